@@ -36,13 +36,42 @@ fn main() -> Result<()> {
     simplelog::SimpleLogger::init(loglevel, logconfig.build())?;
 
     let path = std::path::Path::new(&cfg.data_dir);
+    let compression: storage::Compression = cfg.compression.parse()?;
+    let lsm_opts = storage::LsmOptions { compression, ..Default::default() };
+    let keys = match cfg.encryption_key_file.as_str() {
+        "" => None,
+        file => Some(storage::Keyring::load(file.as_ref())?),
+    };
+    for engine in [&cfg.storage_raft, &cfg.storage_sql] {
+        if compression != storage::Compression::None
+            && !matches!(engine.as_str(), "bitcask" | "" | "lsm")
+        {
+            return Err(Error::Config(format!(
+                "Compression is not supported by the {} storage engine",
+                engine
+            )));
+        }
+        if keys.is_some() && !matches!(engine.as_str(), "bitcask" | "" | "memory") {
+            return Err(Error::Config(format!(
+                "Encryption is not supported by the {} storage engine",
+                engine
+            )));
+        }
+    }
     let raft_log = match cfg.storage_raft.as_str() {
-        "bitcask" | "" => raft::Log::new(
-            storage::BitCask::new_compact(
+        "bitcask" | "" => {
+            let mut engine = storage::BitCask::new_compact(
                 path.join("log"),
                 cfg.compact_threshold,
                 COMPACT_MIN_BYTES,
-            )?,
+                keys.clone(),
+            )?;
+            engine.set_compression(compression);
+            raft::Log::new(engine, cfg.sync)?
+        }
+        "btree" => raft::Log::new(storage::BTree::new(path.join("log"))?, cfg.sync)?,
+        "lsm" => raft::Log::new(
+            storage::Lsm::new_with_options(path.join("log"), lsm_opts.clone())?,
             cfg.sync,
         )?,
         "memory" => raft::Log::new(storage::Memory::new(), false)?,
@@ -50,11 +79,21 @@ fn main() -> Result<()> {
     };
     let raft_state: Box<dyn raft::State> = match cfg.storage_sql.as_str() {
         "bitcask" | "" => {
-            let engine = storage::BitCask::new_compact(
+            let mut engine = storage::BitCask::new_compact(
                 path.join("state"),
                 cfg.compact_threshold,
                 COMPACT_MIN_BYTES,
+                keys.clone(),
             )?;
+            engine.set_compression(compression);
+            Box::new(sql::engine::Raft::new_state(engine)?)
+        }
+        "btree" => {
+            let engine = storage::BTree::new(path.join("state"))?;
+            Box::new(sql::engine::Raft::new_state(engine)?)
+        }
+        "lsm" => {
+            let engine = storage::Lsm::new_with_options(path.join("state"), lsm_opts)?;
             Box::new(sql::engine::Raft::new_state(engine)?)
         }
         "memory" => {
@@ -64,7 +103,24 @@ fn main() -> Result<()> {
         name => return Err(Error::Config(format!("Unknown SQL storage engine {}", name))),
     };
 
-    Server::new(cfg.id, cfg.peers, raft_log, raft_state)?.serve(&cfg.listen_raft, &cfg.listen_sql)
+    let raft_opts = raft::Options {
+        snapshot_threshold: cfg.snapshot_threshold,
+        lease_reads: cfg.lease_reads,
+        max_inflight: cfg.max_inflight,
+        ..Default::default()
+    };
+
+    let mut server = Server::new(cfg.id, cfg.peers, raft_log, raft_state, raft_opts)?;
+    server.set_gc_retention(match cfg.gc_retention {
+        0 => None,
+        secs => Some(std::time::Duration::from_secs(secs)),
+    });
+    server.set_lock_timeout(std::time::Duration::from_millis(cfg.lock_timeout));
+    server.set_txn_timeout(match cfg.txn_timeout {
+        0 => None,
+        secs => Some(std::time::Duration::from_secs(secs)),
+    });
+    server.serve(&cfg.listen_raft, &cfg.listen_sql)
 }
 
 #[derive(Debug, Deserialize)]
@@ -76,9 +132,17 @@ struct Config {
     log_level: String,
     data_dir: String,
     compact_threshold: f64,
+    snapshot_threshold: raft::Index,
+    lease_reads: bool,
+    max_inflight: usize,
     sync: bool,
     storage_raft: String,
     storage_sql: String,
+    compression: String,
+    encryption_key_file: String,
+    gc_retention: u64,
+    lock_timeout: u64,
+    txn_timeout: u64,
 }
 
 impl Config {
@@ -90,9 +154,17 @@ impl Config {
             .set_default("log_level", "info")?
             .set_default("data_dir", "data")?
             .set_default("compact_threshold", 0.2)?
+            .set_default("snapshot_threshold", raft::Options::default().snapshot_threshold)?
+            .set_default("lease_reads", raft::Options::default().lease_reads)?
+            .set_default("max_inflight", raft::Options::default().max_inflight as u64)?
             .set_default("sync", true)?
             .set_default("storage_raft", "bitcask")?
             .set_default("storage_sql", "bitcask")?
+            .set_default("compression", "none")?
+            .set_default("encryption_key_file", "")?
+            .set_default("gc_retention", 86400)?
+            .set_default("lock_timeout", 5000)?
+            .set_default("txn_timeout", 30)?
             .add_source(config::File::with_name(file))
             .add_source(config::Environment::with_prefix("RADB"))
             .build()?
@@ -1,4 +1,4 @@
-//! radump is a debug tool that prints a raDB BitCask database in
+//! radump is a debug tool that prints a raDB BitCask database directory in
 //! human-readable form. It only prints live BitCask data, not garbage entries.
 #![warn(clippy::all)]
 
@@ -9,11 +9,11 @@ use radb::storage::{BitCask, Engine};
 fn main() -> Result<()> {
     let args = clap::command!()
         .about("Prints raDB BitCask contents in human-readable form.")
-        .args([clap::Arg::new("file").required(true)])
+        .args([clap::Arg::new("dir").required(true)])
         .get_matches();
-    let file: &String = args.get_one("file").unwrap();
+    let dir: &String = args.get_one("dir").unwrap();
 
-    let mut engine = BitCask::new(file.into())?;
+    let mut engine = BitCask::new(dir.into())?;
     let mut scan = engine.scan(..);
     while let Some((key, value)) = scan.next().transpose()? {
         let (fkey, Some(fvalue)) = debug::format_key_value(&key, &Some(value)) else {
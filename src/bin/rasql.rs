@@ -13,6 +13,7 @@ use rustyline::history::DefaultHistory;
 use rustyline::validate::{ValidationContext, ValidationResult, Validator};
 use rustyline::{error::ReadlineError, Editor, Modifiers};
 use rustyline_derive::{Completer, Helper, Highlighter, Hinter};
+use std::time::Duration;
 
 fn main() -> Result<()> {
     let opts = clap::command!()
@@ -90,6 +91,22 @@ impl RaSQL {
         };
 
         match command {
+            "!add-learner" => {
+                let args = getargs(2)?;
+                let id = args[0]
+                    .parse()
+                    .map_err(|_| Error::Parse(format!("Invalid node ID {}", args[0])))?;
+                self.client.add_learner(id, args[1])?;
+                println!("Added learner {}", id);
+            }
+            "!add-node" => {
+                let args = getargs(2)?;
+                let id = args[0]
+                    .parse()
+                    .map_err(|_| Error::Parse(format!("Invalid node ID {}", args[0])))?;
+                self.client.add_node(id, args[1])?;
+                println!("Added node {}", id);
+            }
             "!headers" => match getargs(1)?[0] {
                 "on" => {
                     self.show_headers = true;
@@ -106,42 +123,76 @@ impl RaSQL {
 Enter a SQL statement terminated by a semicolon (;) to execute it and display the result.
 The following commands are also available:
 
-    !headers <on|off>  Enable or disable column headers
-    !help              This help message
-    !status            Display server status
-    !table [table]     Display table schema, if it exists
-    !tables            List tables
+    !add-learner <id> <addr>  Add a non-voting learner to the cluster, with the given Raft address
+    !add-node <id> <addr>     Add a node to the cluster, or promote a learner
+    !headers <on|off>         Enable or disable column headers
+    !help                     This help message
+    !max-staleness <ms|off>   Allow read-only transactions to read from this node with bounded staleness
+    !remove-node <id>         Remove a node or learner from the cluster
+    !status                   Display server status
+    !table [table]            Display table schema, if it exists
+    !tables                   List tables
+    !transactions             List active read-write transactions, with their age and idle time
+    !transfer-leader <id>     Transfer Raft leadership to the given node
 "#
             ),
+            "!max-staleness" => match getargs(1)?[0] {
+                "off" => {
+                    self.client.set_max_staleness(None)?;
+                    println!("Follower reads disabled");
+                }
+                v => {
+                    let ms = v.parse().map_err(|_| {
+                        Error::Parse(format!("Invalid value {}, expected milliseconds or off", v))
+                    })?;
+                    self.client.set_max_staleness(Some(Duration::from_millis(ms)))?;
+                    println!("Follower reads enabled with max staleness {}ms", ms);
+                }
+            },
+            "!remove-node" => {
+                let args = getargs(1)?;
+                let id = args[0]
+                    .parse()
+                    .map_err(|_| Error::Parse(format!("Invalid node ID {}", args[0])))?;
+                self.client.remove_node(id)?;
+                println!("Removed node {}", id);
+            }
             "!status" => {
                 let status = self.client.status()?;
                 let mut node_logs = status
                     .raft
                     .last_index
                     .iter()
-                    .map(|(id, index)| format!("{}:{}", id, index))
+                    .map(|(id, index)| match status.raft.learners.contains(id) {
+                        true => format!("{}:{} (learner)", id, index),
+                        false => format!("{}:{}", id, index),
+                    })
                     .collect::<Vec<_>>();
                 node_logs.sort();
                 println!(
                     r#"
-Server:    {server} (leader {leader} in term {term} with {nodes} nodes)
-Raft log:  {committed} committed, {applied} applied, {raft_size} MB ({raft_storage} storage)
+Server:    {server} (leader {leader} in term {term} with {nodes} nodes, {learners} learners)
+Raft log:  {committed} committed, {applied} applied, {snapshot} snapshot, {raft_size} MB ({raft_storage} storage)
 Node logs: {logs}
-MVCC:      {active_txns} active txns, {versions} versions
-Storage:   {keys} keys, {logical_size} MB logical, {nodes}x {disk_size} MB disk, {garbage_percent}% garbage ({sql_storage} engine)
+MVCC:      {active_txns} active txns, {versions} versions, {low_water} GC low-water mark
+Storage:   {keys} keys, {logical_size} MB logical, {replicas}x {disk_size} MB disk, {garbage_percent}% garbage, {compression_ratio}x compression ({sql_storage} engine)
 "#,
                     server = status.server,
                     leader = status.raft.leader,
                     term = status.raft.term,
-                    nodes = status.raft.last_index.len(),
+                    nodes = status.raft.last_index.len() - status.raft.learners.len(),
+                    learners = status.raft.learners.len(),
+                    replicas = status.raft.last_index.len(),
                     committed = status.raft.commit_index,
                     applied = status.raft.apply_index,
+                    snapshot = status.raft.snapshot_index,
                     raft_storage = status.raft.storage.name,
                     raft_size =
                         format_args!("{:.3}", status.raft.storage.size as f64 / 1000.0 / 1000.0),
                     logs = node_logs.join(" "),
                     versions = status.mvcc.versions,
                     active_txns = status.mvcc.active_txns,
+                    low_water = status.mvcc.low_water,
                     keys = status.mvcc.storage.keys,
                     logical_size =
                         format_args!("{:.3}", status.mvcc.storage.size as f64 / 1000.0 / 1000.0),
@@ -159,6 +210,8 @@ Storage:   {keys} keys, {logical_size} MB logical, {nodes}x {disk_size} MB disk,
                         "{:.3}",
                         status.mvcc.storage.total_disk_size as f64 / 1000.0 / 1000.0
                     ),
+                    compression_ratio =
+                        format_args!("{:.2}", status.mvcc.storage.compression_ratio),
                     sql_storage = status.mvcc.storage.name,
                 )
             }
@@ -172,6 +225,29 @@ Storage:   {keys} keys, {logical_size} MB logical, {nodes}x {disk_size} MB disk,
                     println!("{}", table)
                 }
             }
+            "!transactions" => {
+                getargs(0)?;
+                let txns = self.client.list_transactions()?;
+                if txns.is_empty() {
+                    println!("No active transactions");
+                }
+                for txn in txns {
+                    println!(
+                        "{} age={:.1}s idle={:.1}s",
+                        txn.version,
+                        txn.age.as_secs_f64(),
+                        txn.idle.as_secs_f64()
+                    );
+                }
+            }
+            "!transfer-leader" => {
+                let args = getargs(1)?;
+                let id = args[0]
+                    .parse()
+                    .map_err(|_| Error::Parse(format!("Invalid node ID {}", args[0])))?;
+                self.client.transfer_leadership(id)?;
+                println!("Transferring leadership to node {}", id);
+            }
             c => return Err(Error::Parse(format!("Unknown command {}", c))),
         }
         Ok(())
@@ -186,6 +262,9 @@ Storage:   {keys} keys, {logical_size} MB logical, {nodes}x {disk_size} MB disk,
             },
             ResultSet::Commit { version: id } => println!("Committed transaction {}", id),
             ResultSet::Rollback { version: id } => println!("Rolled back transaction {}", id),
+            ResultSet::Savepoint { name } => println!("Created savepoint {}", name),
+            ResultSet::RollbackTo { name } => println!("Rolled back to savepoint {}", name),
+            ResultSet::Release { name } => println!("Released savepoint {}", name),
             ResultSet::Create { count } => println!("Created {} rows", count),
             ResultSet::Delete { count } => println!("Deleted {} rows", count),
             ResultSet::Update { count } => println!("Updated {} rows", count),
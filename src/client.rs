@@ -1,11 +1,14 @@
 use crate::encoding::bincode;
 use crate::error::{Error, Result};
+use crate::raft::{MembershipChange, NodeID};
 use crate::server::{Request, Response, Status};
+use crate::sql::engine::ActiveTransaction;
 use crate::sql::execution::ResultSet;
 use crate::sql::schema::Table;
 
 use rand::Rng;
 use std::io::Write as _;
+use std::time::Duration;
 
 /// raDB client
 pub struct Client {
@@ -32,9 +35,18 @@ impl Client {
 
     /// Executes a query
     pub fn execute(&mut self, query: &str) -> Result<ResultSet> {
-        let mut resultset = match self.call(Request::Execute(query.into()))? {
-            Response::Execute(rs) => rs,
-            resp => return Err(Error::Internal(format!("Unexpected response {:?}", resp))),
+        let mut resultset = match self.call(Request::Execute(query.into())) {
+            Ok(Response::Execute(rs)) => rs,
+            Ok(resp) => return Err(Error::Internal(format!("Unexpected response {:?}", resp))),
+            Err(err) => {
+                // A failed commit (e.g. a serialization failure) or rollback (e.g.
+                // of an expired txn) discards the txn.
+                let query = query.trim().trim_end_matches(';').trim_end();
+                if query.eq_ignore_ascii_case("COMMIT") || query.eq_ignore_ascii_case("ROLLBACK") {
+                    self.txn = None;
+                }
+                return Err(err);
+            }
         };
         if let ResultSet::Query { columns, .. } = resultset {
             // FIXME We buffer rows for now to avoid lifetime hassles
@@ -83,6 +95,54 @@ impl Client {
         }
     }
 
+    /// Lists active read-write transactions
+    pub fn list_transactions(&mut self) -> Result<Vec<ActiveTransaction>> {
+        match self.call(Request::ListTransactions)? {
+            Response::ListTransactions(t) => Ok(t),
+            resp => Err(Error::Value(format!("Unexpected response: {:?}", resp))),
+        }
+    }
+
+    /// Adds a node to the Raft cluster, or promotes a learner to a voter
+    pub fn add_node(&mut self, id: NodeID, addr: &str) -> Result<()> {
+        self.change_membership(MembershipChange::AddNode { id, addr: addr.into() })
+    }
+
+    /// Adds a non-voting learner to the Raft cluster
+    pub fn add_learner(&mut self, id: NodeID, addr: &str) -> Result<()> {
+        self.change_membership(MembershipChange::AddLearner { id, addr: addr.into() })
+    }
+
+    /// Removes a node from the Raft cluster
+    pub fn remove_node(&mut self, id: NodeID) -> Result<()> {
+        self.change_membership(MembershipChange::RemoveNode { id })
+    }
+
+    /// Transfers Raft leadership to the given node
+    pub fn transfer_leadership(&mut self, id: NodeID) -> Result<()> {
+        match self.call(Request::TransferLeadership(id))? {
+            Response::TransferLeadership => Ok(()),
+            resp => Err(Error::Value(format!("Unexpected response: {:?}", resp))),
+        }
+    }
+
+    /// Allows read-only transactions to be served by the connected node with
+    /// the given maximum staleness, or disables follower reads if None
+    pub fn set_max_staleness(&mut self, max_staleness: Option<Duration>) -> Result<()> {
+        match self.call(Request::SetMaxStaleness(max_staleness))? {
+            Response::SetMaxStaleness => Ok(()),
+            resp => Err(Error::Value(format!("Unexpected response: {:?}", resp))),
+        }
+    }
+
+    /// Changes the Raft cluster membership
+    fn change_membership(&mut self, change: MembershipChange) -> Result<()> {
+        match self.call(Request::ChangeMembership(change))? {
+            Response::ChangeMembership => Ok(()),
+            resp => Err(Error::Value(format!("Unexpected response: {:?}", resp))),
+        }
+    }
+
     /// Returns the version and read-only state of the txn
     pub fn txn(&self) -> Option<(u64, bool)> {
         self.txn
@@ -24,6 +24,7 @@
 //! Tuple:  Concatenation of elements, with no surrounding structure.
 //! Array:  Like tuple.
 //! Vec:    Like tuple.
+//! Struct: Like tuple, with the fields in declaration order.
 //! Enum:   The variant's enum index as a single u8 byte.
 //!
 //! SQL Value enums are encoded according to the above scheme, i.e. a single
@@ -83,7 +84,7 @@ impl<'a> serde::Serializer for &'a mut Serializer {
     type SerializeTupleVariant = Self;
     type SerializeTupleStruct = ser::Impossible<(), Error>;
     type SerializeMap = ser::Impossible<(), Error>;
-    type SerializeStruct = ser::Impossible<(), Error>;
+    type SerializeStruct = Self;
     type SerializeStructVariant = ser::Impossible<(), Error>;
 
     /// bool simply uses 1 for true and 0 for false.
@@ -255,8 +256,9 @@ impl<'a> serde::Serializer for &'a mut Serializer {
         unimplemented!()
     }
 
+    // Structs are serialized as the concatenation of the serialized fields.
     fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeStruct> {
-        unimplemented!()
+        Ok(self)
     }
 
     fn serialize_struct_variant(
@@ -312,6 +314,24 @@ impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
     }
 }
 
+// Structs, like tuples, simply concatenate the serialized fields.
+impl ser::SerializeStruct for &mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: serde::Serialize + ?Sized>(
+        &mut self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
 // Deserializes keys from byte slices into a given type. The format is not
 // self-describing, so the caller must provide a concrete type to deserialize
 // into.
@@ -499,9 +519,9 @@ impl<'de, 'a> serde::Deserializer<'de> for &'a mut Deserializer<'de> {
         self,
         _: &'static str,
         _: &'static [&'static str],
-        _: V,
+        visitor: V,
     ) -> Result<V::Value> {
-        unimplemented!()
+        visitor.visit_seq(self)
     }
 
     fn deserialize_enum<V: de::Visitor<'de>>(
@@ -580,7 +600,7 @@ impl<'de> de::VariantAccess<'de> for &mut Deserializer<'de> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::sql::types::Value;
+    use crate::sql::types::{decimal, temporal, Interval, Value};
     use hex;
     use paste::paste;
     use serde::{Deserialize, Serialize};
@@ -588,6 +608,12 @@ mod tests {
     use std::borrow::Cow;
     use std::f64::consts::PI;
 
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct Struct {
+        a: bool,
+        b: u64,
+    }
+
     #[derive(Debug, Deserialize, Serialize, PartialEq)]
     enum Key<'a> {
         Unit,
@@ -696,6 +722,7 @@ mod tests {
         array_bool: [false, true, false] => "000100",
         vec_bool: vec![false, true, false] => "000100",
         vec_u64: vec![u64::MIN, u64::MAX, 65535_u64] => "0000000000000000ffffffffffffffff000000000000ffff",
+        struct_fields: Struct { a: true, b: 65535 } => "01000000000000ffff",
 
         enum_unit: Key::Unit => "00",
         enum_newtype: Key::NewType("foo".to_string()) => "01666f6f0000",
@@ -708,6 +735,13 @@ mod tests {
         value_int: Value::Integer(-1) => "027fffffffffffffff",
         value_float: Value::Float(PI) => "03c00921fb54442d18",
         value_string: Value::String("foo".to_string()) => "04666f6f0000",
+        value_date: Value::Date(temporal::parse_date("1969-12-31").unwrap()) => "057fffffffffffffff",
+        value_time: Value::Time(temporal::parse_time("00:00:01").unwrap()) => "0680000000000f4240",
+        value_timestamp: Value::Timestamp(temporal::parse_timestamp("1970-01-01 00:00:01").unwrap()) => "0780000000000f4240",
+        value_timestamptz: Value::TimestampTz(temporal::parse_timestamptz("1970-01-01 01:00:01+01:00").unwrap()) => "0880000000000f4240",
+        value_interval: Value::Interval(Interval::new(1, -1, 0)) => "0980000000000000017fffffffffffffff8000000000000000",
+        value_decimal: Value::Decimal(decimal::parse("-1.5").unwrap()) => "0a7fffffffffffffffffffffffffffffff7fffffffefd818d0e0ed7ecf78000000",
+        value_bytes: Value::Bytes(vec![0x00, 0x01]) => "0b00ff010000",
     }
 
     test_serialize_error! {
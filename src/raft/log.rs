@@ -1,15 +1,21 @@
 use crate::encoding::{bincode, keycode};
 use crate::error::{Error, Result};
-use crate::storage;
+use crate::storage::{self, WriteBatch};
 
 use ::log::debug;
 use serde::{Deserialize, Serialize};
 
 use super::{NodeID, Term};
+use std::collections::{BTreeMap, BTreeSet};
 
 /// A log index.
 pub type Index = u64;
 
+/// The log storage format version, stored under Key::Version. Logs without a
+/// version (version 0) store entries as (term, command), and are upgraded on
+/// startup. Version 1 adds entry memberships and snapshots.
+const FORMAT_VERSION: u32 = 1;
+
 /// A log entry.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Entry {
@@ -19,6 +25,26 @@ pub struct Entry {
     pub term: Term,
     /// The state machine command. None is used to commit noops during leader election.
     pub command: Option<Vec<u8>>,
+    /// A new cluster membership. This takes effect as soon as the entry is
+    /// appended to a node's log, before it is committed. The command is always
+    /// None for these entries, i.e. they're noops for the state machine.
+    pub membership: Option<Membership>,
+}
+
+/// A cluster membership configuration. Changes are replicated through the log
+/// one node at a time (single-server changes, see section 4.1 in the Raft
+/// dissertation), which ensures that the majorities of the old and new
+/// configurations overlap without needing joint consensus.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Membership {
+    /// The voting nodes in the cluster.
+    pub voters: BTreeSet<NodeID>,
+    /// Non-voting learners. These receive and apply the replicated log, but
+    /// don't vote in elections or count towards the commit quorum.
+    pub learners: BTreeSet<NodeID>,
+    /// The Raft addresses of nodes that were added via membership changes.
+    /// Nodes from the initial configuration use the server's peer config.
+    pub addrs: BTreeMap<NodeID, String>,
 }
 
 /// A log key, encoded using KeyCode.
@@ -30,6 +56,16 @@ pub enum Key {
     TermVote,
     /// Stores the current commit index (if any).
     CommitIndex,
+    /// Stores the index and term of the last entry included in the latest
+    /// state machine snapshot (if any), along with the cluster membership as
+    /// of that entry. Entries up to and including it have been removed from
+    /// the log.
+    SnapshotIndex,
+    /// Stores the log storage format version.
+    Version,
+    /// Stores the index, term, and membership of a snapshot that's being
+    /// restored into the state machine, until the log has been reset for it.
+    PendingSnapshot,
 }
 
 impl Key {
@@ -48,6 +84,9 @@ enum KeyPrefix {
     Entry,
     TermVote,
     CommitIndex,
+    SnapshotIndex,
+    Version,
+    PendingSnapshot,
 }
 
 impl KeyPrefix {
@@ -69,13 +108,27 @@ pub struct Log {
     commit_index: Index,
     /// The term of the last committed entry.
     commit_term: Term,
+    /// The index of the last entry included in the latest snapshot.
+    snapshot_index: Index,
+    /// The term of the last entry included in the latest snapshot.
+    snapshot_term: Term,
+    /// The latest cluster membership in the log or snapshot, and its index.
+    membership: Option<(Index, Membership)>,
     /// Whether to sync writes to disk.
     sync: bool,
+    /// Whether there are appended entries that haven't been flushed yet.
+    dirty: bool,
 }
 
 impl Log {
     /// Creates a new log, using the given storage engine.
     pub fn new(mut engine: impl storage::Engine + 'static, sync: bool) -> Result<Self> {
+        Self::upgrade(&mut engine)?;
+        let (snapshot_index, snapshot_term, snapshot_membership) = engine
+            .get(&Key::SnapshotIndex.encode()?)?
+            .map(|v| bincode::deserialize(&v))
+            .transpose()?
+            .unwrap_or((0, 0, None));
         let (last_index, last_term) = engine
             .scan_prefix(&KeyPrefix::Entry.encode()?)
             .last()
@@ -83,20 +136,56 @@ impl Log {
             .map(|(k, v)| Self::decode_entry(&k, &v))
             .transpose()?
             .map(|e| (e.index, e.term))
-            .unwrap_or((0, 0));
+            .unwrap_or((snapshot_index, snapshot_term));
         let (commit_index, commit_term) = engine
             .get(&Key::CommitIndex.encode()?)?
             .map(|v| bincode::deserialize(&v))
             .transpose()?
             .unwrap_or((0, 0));
-        Ok(Self {
+        let mut log = Self {
             engine: Box::new(engine),
             last_index,
             last_term,
             commit_index,
             commit_term,
+            snapshot_index,
+            snapshot_term,
+            membership: None,
             sync,
-        })
+            dirty: false,
+        };
+        log.membership = log.load_membership(last_index, snapshot_membership)?;
+        Ok(log)
+    }
+
+    /// Upgrades the log storage format to the current version, if necessary.
+    fn upgrade(engine: &mut impl storage::Engine) -> Result<()> {
+        let version: Option<u32> =
+            engine.get(&Key::Version.encode()?)?.map(|v| bincode::deserialize(&v)).transpose()?;
+        let mut batch = WriteBatch::new();
+        match version {
+            Some(FORMAT_VERSION) => return Ok(()),
+            Some(version) => {
+                return Err(Error::Internal(format!(
+                    "Unsupported Raft log format version {}, expected {}",
+                    version, FORMAT_VERSION
+                )))
+            }
+            // A new log.
+            None if engine.scan(..).next().is_none() => {}
+            // Version 0 entries don't have a membership.
+            None => {
+                debug!("Upgrading Raft log format from version 0 to {}", FORMAT_VERSION);
+                for item in engine.scan_prefix(&KeyPrefix::Entry.encode()?) {
+                    let (key, value) = item?;
+                    let (term, command): (Term, Option<Vec<u8>>) = bincode::deserialize(&value)?;
+                    batch.set(&key, bincode::serialize(&(term, command, None::<Membership>))?);
+                }
+            }
+        }
+        batch.set(&Key::Version.encode()?, bincode::serialize(&FORMAT_VERSION)?);
+        engine.write_batch(batch)?;
+        engine.flush()
     }
 
     /// Decodes an entry from a log key/value pair.
@@ -110,8 +199,8 @@ impl Log {
 
     /// Decodes an entry from a value at a given index.
     fn decode_entry_value(index: Index, value: &[u8]) -> Result<Entry> {
-        let (term, command) = bincode::deserialize(value)?;
-        Ok(Entry { index, term, command })
+        let (term, command, membership) = bincode::deserialize(value)?;
+        Ok(Entry { index, term, command, membership })
     }
 
     /// Returns log engine name and status.
@@ -129,6 +218,63 @@ impl Log {
         (self.last_index, self.last_term)
     }
 
+    /// Returns the index and term of the last entry included in the latest
+    /// snapshot, or (0, 0) if the log has never been compacted.
+    pub fn get_snapshot_index(&self) -> (Index, Term) {
+        (self.snapshot_index, self.snapshot_term)
+    }
+
+    /// Returns the latest cluster membership in the log, along with the index
+    /// of the entry that introduced it, or None if there is none.
+    pub fn get_membership(&self) -> Option<(Index, &Membership)> {
+        self.membership.as_ref().map(|(index, membership)| (*index, membership))
+    }
+
+    /// Returns the cluster membership as of the given index, i.e. the latest
+    /// membership at or before it. The index must not be compacted.
+    pub fn get_membership_at(&mut self, index: Index) -> Result<Option<Membership>> {
+        if index < self.snapshot_index {
+            return Err(Error::Internal(format!("Index {} has been compacted", index)));
+        }
+        match &self.membership {
+            Some((i, membership)) if *i <= index => return Ok(Some(membership.clone())),
+            _ => {}
+        }
+        let snapshot_membership = self.get_snapshot_membership()?;
+        self.load_membership(index, snapshot_membership).map(|m| m.map(|(_, m)| m))
+    }
+
+    /// Finds the latest membership at or before the given index, scanning the
+    /// log entries after the snapshot, or falling back to the given snapshot
+    /// membership.
+    fn load_membership(
+        &mut self,
+        index: Index,
+        snapshot_membership: Option<Membership>,
+    ) -> Result<Option<(Index, Membership)>> {
+        let mut membership = snapshot_membership.map(|m| (self.snapshot_index, m));
+        if index <= self.snapshot_index {
+            return Ok(membership);
+        }
+        let mut scan = self.scan((self.snapshot_index + 1)..=index)?;
+        while let Some(entry) = scan.next().transpose()? {
+            if let Some(m) = entry.membership {
+                membership = Some((entry.index, m));
+            }
+        }
+        Ok(membership)
+    }
+
+    /// Returns the membership stored with the snapshot, if any.
+    fn get_snapshot_membership(&mut self) -> Result<Option<Membership>> {
+        Ok(self
+            .engine
+            .get(&Key::SnapshotIndex.encode()?)?
+            .map(|v| bincode::deserialize::<(Index, Term, Option<Membership>)>(&v))
+            .transpose()?
+            .and_then(|(_, _, membership)| membership))
+    }
+
     /// Returns the last known term (0 if none), and cast vote (if any).
     pub fn get_term(&mut self) -> Result<(Term, Option<NodeID>)> {
         let (term, voted_for) = self
@@ -152,17 +298,51 @@ impl Log {
         if self.sync {
             self.engine.flush()?;
         }
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Flushes appended entries to stable storage, if any and if enabled.
+    pub fn flush(&mut self) -> Result<()> {
+        if self.dirty {
+            self.maybe_flush()?;
+        }
         Ok(())
     }
 
     /// Appends a command to the log, returning its index. None implies a noop
-    /// command, typically after Raft leader changes.
+    /// command, typically after Raft leader changes. The entry isn't flushed
+    /// to stable storage until flush() is called (or another write flushes
+    /// the log), so that multiple appends can share a single flush.
     pub fn append(&mut self, term: Term, command: Option<Vec<u8>>) -> Result<Index> {
+        self.append_entry(term, command, None)
+    }
+
+    /// Appends a cluster membership change to the log, returning its index.
+    /// The new membership takes effect immediately. Like append(), the entry
+    /// isn't flushed.
+    pub fn append_membership(&mut self, term: Term, membership: Membership) -> Result<Index> {
+        self.append_entry(term, None, Some(membership))
+    }
+
+    /// Appends an entry to the log, returning its index.
+    fn append_entry(
+        &mut self,
+        term: Term,
+        command: Option<Vec<u8>>,
+        membership: Option<Membership>,
+    ) -> Result<Index> {
         let index = self.last_index + 1;
-        self.engine.set(&Key::Entry(index).encode()?, bincode::serialize(&(term, command))?)?;
-        self.maybe_flush()?;
+        self.engine.set(
+            &Key::Entry(index).encode()?,
+            bincode::serialize(&(term, &command, &membership))?,
+        )?;
+        self.dirty = true;
         self.last_index = index;
         self.last_term = term;
+        if let Some(membership) = membership {
+            self.membership = Some((index, membership));
+        }
         Ok(index)
     }
 
@@ -175,14 +355,13 @@ impl Log {
                 self.commit_index, index
             )));
         }
-        let Some(entry) = self.get(index)? else {
+        let Some(term) = self.term(index)? else {
             return Err(Error::Internal(format!("Can't commit non-existant index {}", index)));
         };
-        self.engine
-            .set(&Key::CommitIndex.encode()?, bincode::serialize(&(entry.index, entry.term))?)?;
+        self.engine.set(&Key::CommitIndex.encode()?, bincode::serialize(&(index, term))?)?;
         self.maybe_flush()?;
-        self.commit_index = entry.index;
-        self.commit_term = entry.term;
+        self.commit_index = index;
+        self.commit_term = term;
         Ok(index)
     }
 
@@ -194,10 +373,26 @@ impl Log {
             .transpose()
     }
 
-    /// Checks if the log contains an entry with the given term.
+    /// Returns the term of the entry at the given index, or None if it does not
+    /// exist. This also includes the last entry in the latest snapshot, even
+    /// though the entry itself has been removed from the log.
+    pub fn term(&mut self, index: Index) -> Result<Option<Term>> {
+        if index > 0 && index == self.snapshot_index {
+            return Ok(Some(self.snapshot_term));
+        }
+        Ok(self.get(index)?.map(|e| e.term))
+    }
+
+    /// Checks if the log contains an entry with the given term. Entries below
+    /// the snapshot index have been removed, but they are committed and thus
+    /// match the entries of any leader (by the Raft log matching property), so
+    /// they are considered to exist.
     pub fn has(&mut self, index: Index, term: Term) -> Result<bool> {
-        match self.get(index)? {
-            Some(entry) => Ok(entry.term == term),
+        if index < self.snapshot_index {
+            return Ok(true);
+        }
+        match self.term(index)? {
+            Some(t) => Ok(t == term),
             None if index == 0 && term == 0 => Ok(true),
             None => Ok(false),
         }
@@ -230,7 +425,13 @@ impl Log {
     /// and the first entry must be at most last_index+1. If an entry does not
     /// exist, append it. If an existing entry has a term mismatch, replace it
     /// and all following entries.
-    pub fn splice(&mut self, entries: Vec<Entry>) -> Result<Index> {
+    ///
+    /// Entries at or below the snapshot index are committed and have already
+    /// been applied and removed, so they are ignored.
+    pub fn splice(&mut self, mut entries: Vec<Entry>) -> Result<Index> {
+        let compacted =
+            entries.iter().take_while(|e| e.index > 0 && e.index <= self.snapshot_index).count();
+        entries.drain(..compacted);
         if entries.is_empty() {
             return Ok(self.last_index);
         }
@@ -257,27 +458,157 @@ impl Log {
             return Err(Error::Internal("Spliced entries must begin after commit index".into()));
         }
 
-        // Write any entries not already in the log.
+        // Write any entries not already in the log, and remove the remaining
+        // tail of the old log if any, in a single atomic batch. Then update
+        // the index.
+        let mut batch = WriteBatch::new();
         for e in entries {
-            self.engine
-                .set(&Key::Entry(e.index).encode()?, bincode::serialize(&(&e.term, &e.command))?)?;
+            batch.set(
+                &Key::Entry(e.index).encode()?,
+                bincode::serialize(&(&e.term, &e.command, &e.membership))?,
+            );
         }
-
-        // Remove the remaining tail of the old log, if any, and update the index.
         for index in (last_index + 1)..=self.last_index {
-            self.engine.delete(&Key::Entry(index).encode()?)?;
+            batch.delete(&Key::Entry(index).encode()?);
         }
+        self.engine.write_batch(batch)?;
         self.maybe_flush()?;
         self.last_index = last_index;
         self.last_term = last_term;
+
+        // Update the membership if it was replaced or truncated.
+        let replaced_from = entries.first().map_or(last_index + 1, |e| e.index);
+        let replaced = self.membership.as_ref().is_some_and(|(i, _)| *i >= replaced_from);
+        if replaced || entries.iter().any(|e| e.membership.is_some()) {
+            let snapshot_membership = self.get_snapshot_membership()?;
+            self.membership = self.load_membership(last_index, snapshot_membership)?;
+        }
         Ok(self.last_index)
     }
+
+    /// Compacts the log by removing all entries up to and including the given
+    /// index, which must be committed. The caller must make sure the entries
+    /// have been applied to a durable state machine, which now becomes the
+    /// only copy of them.
+    pub fn compact(&mut self, index: Index) -> Result<Index> {
+        if index <= self.snapshot_index {
+            return Ok(self.snapshot_index);
+        }
+        if index > self.commit_index {
+            return Err(Error::Internal(format!(
+                "Can't compact uncommitted index {} (committed {})",
+                index, self.commit_index
+            )));
+        }
+        let Some(term) = self.term(index)? else {
+            return Err(Error::Internal(format!("Can't compact non-existant index {}", index)));
+        };
+        let membership = self.get_membership_at(index)?;
+        self.set_snapshot_index(index, term, membership)?;
+        for i in (self.snapshot_index + 1)..=index {
+            self.engine.delete(&Key::Entry(i).encode()?)?;
+        }
+        self.maybe_flush()?;
+        debug!("Compacted log up to index {}", index);
+        self.snapshot_index = index;
+        self.snapshot_term = term;
+        Ok(index)
+    }
+
+    /// Resets the log following installation of a state machine snapshot with
+    /// the given last index, term, and membership. If the log contains a
+    /// matching entry, the entries following it are retained. Otherwise, the
+    /// entire log is discarded.
+    pub fn install_snapshot(
+        &mut self,
+        index: Index,
+        term: Term,
+        membership: Option<Membership>,
+    ) -> Result<()> {
+        if index <= self.commit_index {
+            return Err(Error::Internal(format!(
+                "Snapshot index {} at or below commit index {}",
+                index, self.commit_index
+            )));
+        }
+        if self.has(index, term)? {
+            self.commit(index)?;
+            self.compact(index)?;
+            self.engine.delete(&Key::PendingSnapshot.encode()?)?;
+            return self.maybe_flush();
+        }
+
+        // Write the snapshot index before removing entries, so that Log::new()
+        // doesn't see an empty log with a stale snapshot index.
+        self.set_snapshot_index(index, term, membership.clone())?;
+        self.engine.set(&Key::CommitIndex.encode()?, bincode::serialize(&(index, term))?)?;
+        for i in (self.snapshot_index + 1)..=self.last_index {
+            self.engine.delete(&Key::Entry(i).encode()?)?;
+        }
+        self.engine.delete(&Key::PendingSnapshot.encode()?)?;
+        self.maybe_flush()?;
+        self.snapshot_index = index;
+        self.snapshot_term = term;
+        self.commit_index = index;
+        self.commit_term = term;
+        self.last_index = index;
+        self.last_term = term;
+        self.membership = membership.map(|m| (index, m));
+        Ok(())
+    }
+
+    /// Records that a snapshot with the given last index, term, and membership
+    /// is about to be restored into the state machine. Restoring the state
+    /// machine and resetting the log with install_snapshot() isn't atomic, so
+    /// if we crash in between, this allows finishing the installation on
+    /// restart. The record is flushed to stable storage (if enabled) before
+    /// returning, and removed by install_snapshot().
+    pub fn begin_snapshot(
+        &mut self,
+        index: Index,
+        term: Term,
+        membership: Option<Membership>,
+    ) -> Result<()> {
+        self.engine.set(
+            &Key::PendingSnapshot.encode()?,
+            bincode::serialize(&(index, term, membership))?,
+        )?;
+        self.maybe_flush()
+    }
+
+    /// Returns the index, term, and membership of a snapshot recorded by
+    /// begin_snapshot() that hasn't been installed yet, if any.
+    #[allow(clippy::type_complexity)]
+    pub fn get_pending_snapshot(&mut self) -> Result<Option<(Index, Term, Option<Membership>)>> {
+        self.engine
+            .get(&Key::PendingSnapshot.encode()?)?
+            .map(|v| bincode::deserialize(&v))
+            .transpose()
+    }
+
+    /// Discards a pending snapshot recorded by begin_snapshot(), e.g. if the
+    /// state machine wasn't restored from it before a crash.
+    pub fn clear_pending_snapshot(&mut self) -> Result<()> {
+        self.engine.delete(&Key::PendingSnapshot.encode()?)?;
+        self.maybe_flush()
+    }
+
+    /// Stores the snapshot index, term, and membership.
+    fn set_snapshot_index(
+        &mut self,
+        index: Index,
+        term: Term,
+        membership: Option<Membership>,
+    ) -> Result<()> {
+        self.engine
+            .set(&Key::SnapshotIndex.encode()?, bincode::serialize(&(index, term, membership))?)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::storage::Memory;
+    use crate::storage::{Engine as _, Memory};
     use pretty_assertions::assert_eq;
 
     fn setup() -> Log {
@@ -293,20 +624,51 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    /// Tests that version 0 logs, without entry memberships, are upgraded.
+    fn upgrade() -> Result<()> {
+        let mut engine = Memory::new();
+        engine.set(&Key::TermVote.encode()?, bincode::serialize(&(2u64, Some(1u8)))?)?;
+        engine.set(&Key::Entry(1).encode()?, bincode::serialize(&(1u64, None::<Vec<u8>>))?)?;
+        engine.set(&Key::Entry(2).encode()?, bincode::serialize(&(2u64, Some(vec![0x02u8])))?)?;
+        engine.set(&Key::CommitIndex.encode()?, bincode::serialize(&(1u64, 1u64))?)?;
+
+        let mut l = Log::new(engine, false)?;
+        assert_eq!(l.get_term()?, (2, Some(1)));
+        assert_eq!(l.get_last_index(), (2, 2));
+        assert_eq!(l.get_commit_index(), (1, 1));
+        assert_eq!(
+            l.scan(..)?.collect::<Result<Vec<_>>>()?,
+            vec![
+                Entry { index: 1, term: 1, command: None, membership: None },
+                Entry { index: 2, term: 2, command: Some(vec![0x02]), membership: None },
+            ]
+        );
+
+        // Unknown versions are rejected.
+        let mut engine = Memory::new();
+        engine.set(&Key::Version.encode()?, bincode::serialize(&(FORMAT_VERSION + 1))?)?;
+        assert!(Log::new(engine, false).is_err());
+        Ok(())
+    }
+
     #[test]
     fn append() -> Result<()> {
         let mut l = setup();
         assert_eq!(l.get(1), Ok(None));
 
         assert_eq!(l.append(3, Some(vec![0x01]))?, 1,);
-        assert_eq!(l.get(1)?, Some(Entry { index: 1, term: 3, command: Some(vec![0x01]) }));
+        assert_eq!(
+            l.get(1)?,
+            Some(Entry { index: 1, term: 3, command: Some(vec![0x01]), membership: None })
+        );
         assert_eq!(l.get(2)?, None);
 
         assert_eq!(l.get_last_index(), (1, 3));
         assert_eq!(l.get_commit_index(), (0, 0));
 
         assert_eq!(l.append(3, None)?, 2);
-        assert_eq!(l.get(2)?, Some(Entry { index: 2, term: 3, command: None }));
+        assert_eq!(l.get(2)?, Some(Entry { index: 2, term: 3, command: None, membership: None }));
         assert_eq!(l.get_last_index(), (2, 3));
         assert_eq!(l.get_commit_index(), (0, 0));
         Ok(())
@@ -351,7 +713,10 @@ mod tests {
         assert_eq!(l.get(1)?, None);
 
         l.append(3, Some(vec![0x01]))?;
-        assert_eq!(l.get(1)?, Some(Entry { index: 1, term: 3, command: Some(vec![0x01]) }));
+        assert_eq!(
+            l.get(1)?,
+            Some(Entry { index: 1, term: 3, command: Some(vec![0x01]), membership: None })
+        );
         assert_eq!(l.get(2)?, None);
         Ok(())
     }
@@ -381,14 +746,14 @@ mod tests {
         assert_eq!(
             l.scan(..)?.collect::<Result<Vec<_>>>()?,
             vec![
-                Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                Entry { index: 2, term: 1, command: Some(vec![0x02]) },
-                Entry { index: 3, term: 1, command: Some(vec![0x03]) },
+                Entry { index: 1, term: 1, command: Some(vec![0x01]), membership: None },
+                Entry { index: 2, term: 1, command: Some(vec![0x02]), membership: None },
+                Entry { index: 3, term: 1, command: Some(vec![0x03]), membership: None },
             ],
         );
         assert_eq!(
             l.scan(2..=2)?.collect::<Result<Vec<_>>>()?,
-            vec![Entry { index: 2, term: 1, command: Some(vec![0x02]) },],
+            vec![Entry { index: 2, term: 1, command: Some(vec![0x02]), membership: None },],
         );
         assert!(l.scan(4..)?.collect::<Result<Vec<_>>>()?.is_empty());
         Ok(())
@@ -415,30 +780,34 @@ mod tests {
         assert_eq!(l.splice(vec![])?, 0);
 
         // It should error if the first index is not 1.
-        assert!(l.splice(vec![Entry { index: 0, term: 1, command: None }]).is_err());
-        assert!(l.splice(vec![Entry { index: 2, term: 1, command: None }]).is_err());
+        assert!(l
+            .splice(vec![Entry { index: 0, term: 1, command: None, membership: None }])
+            .is_err());
+        assert!(l
+            .splice(vec![Entry { index: 2, term: 1, command: None, membership: None }])
+            .is_err());
 
         // ...or the entries are not contiguous.
         assert!(l
             .splice(vec![
-                Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                Entry { index: 3, term: 2, command: Some(vec![0x03]) },
+                Entry { index: 1, term: 1, command: Some(vec![0x01]), membership: None },
+                Entry { index: 3, term: 2, command: Some(vec![0x03]), membership: None },
             ])
             .is_err());
 
         // Splicing into an empty log should be fine.
         assert_eq!(
             l.splice(vec![
-                Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                Entry { index: 2, term: 1, command: Some(vec![0x02]) },
+                Entry { index: 1, term: 1, command: Some(vec![0x01]), membership: None },
+                Entry { index: 2, term: 1, command: Some(vec![0x02]), membership: None },
             ])?,
             2
         );
         assert_eq!(
             l.scan(..)?.collect::<Result<Vec<_>>>()?,
             vec![
-                Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                Entry { index: 2, term: 1, command: Some(vec![0x02]) },
+                Entry { index: 1, term: 1, command: Some(vec![0x01]), membership: None },
+                Entry { index: 2, term: 1, command: Some(vec![0x02]), membership: None },
             ]
         );
 
@@ -448,71 +817,71 @@ mod tests {
         assert_eq!(
             l.scan(..)?.collect::<Result<Vec<_>>>()?,
             vec![
-                Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                Entry { index: 2, term: 1, command: Some(vec![0x02]) },
+                Entry { index: 1, term: 1, command: Some(vec![0x01]), membership: None },
+                Entry { index: 2, term: 1, command: Some(vec![0x02]), membership: None },
             ]
         );
 
         // Splicing with a gap after the last_index should error.
         assert!(l
             .splice(vec![
-                Entry { index: 4, term: 1, command: Some(vec![0x04]) },
-                Entry { index: 5, term: 1, command: Some(vec![0x05]) },
+                Entry { index: 4, term: 1, command: Some(vec![0x04]), membership: None },
+                Entry { index: 5, term: 1, command: Some(vec![0x05]), membership: None },
             ])
             .is_err());
 
         // Splicing after the last index should be fine.
         assert_eq!(
             l.splice(vec![
-                Entry { index: 3, term: 1, command: Some(vec![0x03]) },
-                Entry { index: 4, term: 1, command: Some(vec![0x04]) },
+                Entry { index: 3, term: 1, command: Some(vec![0x03]), membership: None },
+                Entry { index: 4, term: 1, command: Some(vec![0x04]), membership: None },
             ])?,
             4
         );
         assert_eq!(
             l.scan(..)?.collect::<Result<Vec<_>>>()?,
             vec![
-                Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                Entry { index: 2, term: 1, command: Some(vec![0x02]) },
-                Entry { index: 3, term: 1, command: Some(vec![0x03]) },
-                Entry { index: 4, term: 1, command: Some(vec![0x04]) },
+                Entry { index: 1, term: 1, command: Some(vec![0x01]), membership: None },
+                Entry { index: 2, term: 1, command: Some(vec![0x02]), membership: None },
+                Entry { index: 3, term: 1, command: Some(vec![0x03]), membership: None },
+                Entry { index: 4, term: 1, command: Some(vec![0x04]), membership: None },
             ]
         );
 
         // Splicing with overlap should be a noop.
         assert_eq!(
             l.splice(vec![
-                Entry { index: 3, term: 1, command: Some(vec![0x03]) },
-                Entry { index: 4, term: 1, command: Some(vec![0x04]) },
+                Entry { index: 3, term: 1, command: Some(vec![0x03]), membership: None },
+                Entry { index: 4, term: 1, command: Some(vec![0x04]), membership: None },
             ])?,
             4
         );
         assert_eq!(
             l.scan(..)?.collect::<Result<Vec<_>>>()?,
             vec![
-                Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                Entry { index: 2, term: 1, command: Some(vec![0x02]) },
-                Entry { index: 3, term: 1, command: Some(vec![0x03]) },
-                Entry { index: 4, term: 1, command: Some(vec![0x04]) },
+                Entry { index: 1, term: 1, command: Some(vec![0x01]), membership: None },
+                Entry { index: 2, term: 1, command: Some(vec![0x02]), membership: None },
+                Entry { index: 3, term: 1, command: Some(vec![0x03]), membership: None },
+                Entry { index: 4, term: 1, command: Some(vec![0x04]), membership: None },
             ]
         );
 
         assert_eq!(
             l.splice(vec![
-                Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                Entry { index: 2, term: 1, command: Some(vec![0x02]) },
-                Entry { index: 3, term: 1, command: Some(vec![0x03]) },
-                Entry { index: 4, term: 1, command: Some(vec![0x04]) },
+                Entry { index: 1, term: 1, command: Some(vec![0x01]), membership: None },
+                Entry { index: 2, term: 1, command: Some(vec![0x02]), membership: None },
+                Entry { index: 3, term: 1, command: Some(vec![0x03]), membership: None },
+                Entry { index: 4, term: 1, command: Some(vec![0x04]), membership: None },
             ])?,
             4
         );
         assert_eq!(
             l.scan(..)?.collect::<Result<Vec<_>>>()?,
             vec![
-                Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                Entry { index: 2, term: 1, command: Some(vec![0x02]) },
-                Entry { index: 3, term: 1, command: Some(vec![0x03]) },
-                Entry { index: 4, term: 1, command: Some(vec![0x04]) },
+                Entry { index: 1, term: 1, command: Some(vec![0x01]), membership: None },
+                Entry { index: 2, term: 1, command: Some(vec![0x02]), membership: None },
+                Entry { index: 3, term: 1, command: Some(vec![0x03]), membership: None },
+                Entry { index: 4, term: 1, command: Some(vec![0x04]), membership: None },
             ]
         );
 
@@ -520,17 +889,17 @@ mod tests {
         // entries match.
         assert_eq!(
             l.splice(vec![
-                Entry { index: 2, term: 1, command: Some(vec![0x02]) },
-                Entry { index: 3, term: 1, command: Some(vec![0x03]) },
+                Entry { index: 2, term: 1, command: Some(vec![0x02]), membership: None },
+                Entry { index: 3, term: 1, command: Some(vec![0x03]), membership: None },
             ])?,
             3
         );
         assert_eq!(
             l.scan(..)?.collect::<Result<Vec<_>>>()?,
             vec![
-                Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                Entry { index: 2, term: 1, command: Some(vec![0x02]) },
-                Entry { index: 3, term: 1, command: Some(vec![0x03]) },
+                Entry { index: 1, term: 1, command: Some(vec![0x01]), membership: None },
+                Entry { index: 2, term: 1, command: Some(vec![0x02]), membership: None },
+                Entry { index: 3, term: 1, command: Some(vec![0x03]), membership: None },
             ]
         );
 
@@ -538,66 +907,274 @@ mod tests {
         // entries match.
         assert_eq!(
             l.splice(vec![
-                Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                Entry { index: 2, term: 1, command: Some(vec![0x02]) },
+                Entry { index: 1, term: 1, command: Some(vec![0x01]), membership: None },
+                Entry { index: 2, term: 1, command: Some(vec![0x02]), membership: None },
             ])?,
             2
         );
         assert_eq!(
             l.scan(..)?.collect::<Result<Vec<_>>>()?,
             vec![
-                Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                Entry { index: 2, term: 1, command: Some(vec![0x02]) },
+                Entry { index: 1, term: 1, command: Some(vec![0x01]), membership: None },
+                Entry { index: 2, term: 1, command: Some(vec![0x02]), membership: None },
             ]
         );
 
         // Splicing a different command does nothing.
-        assert_eq!(l.splice(vec![Entry { index: 2, term: 1, command: Some(vec![0x00]) },])?, 2);
+        assert_eq!(
+            l.splice(vec![Entry {
+                index: 2,
+                term: 1,
+                command: Some(vec![0x00]),
+                membership: None
+            },])?,
+            2
+        );
         assert_eq!(
             l.scan(..)?.collect::<Result<Vec<_>>>()?,
             vec![
-                Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                Entry { index: 2, term: 1, command: Some(vec![0x02]) },
+                Entry { index: 1, term: 1, command: Some(vec![0x01]), membership: None },
+                Entry { index: 2, term: 1, command: Some(vec![0x02]), membership: None },
             ]
         );
 
         // Splicing with overlap beyond the end works.
         assert_eq!(
             l.splice(vec![
-                Entry { index: 2, term: 1, command: Some(vec![0x02]) },
-                Entry { index: 3, term: 1, command: Some(vec![0x03]) },
-                Entry { index: 4, term: 1, command: Some(vec![0x04]) },
+                Entry { index: 2, term: 1, command: Some(vec![0x02]), membership: None },
+                Entry { index: 3, term: 1, command: Some(vec![0x03]), membership: None },
+                Entry { index: 4, term: 1, command: Some(vec![0x04]), membership: None },
             ])?,
             4
         );
         assert_eq!(
             l.scan(..)?.collect::<Result<Vec<_>>>()?,
             vec![
-                Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                Entry { index: 2, term: 1, command: Some(vec![0x02]) },
-                Entry { index: 3, term: 1, command: Some(vec![0x03]) },
-                Entry { index: 4, term: 1, command: Some(vec![0x04]) },
+                Entry { index: 1, term: 1, command: Some(vec![0x01]), membership: None },
+                Entry { index: 2, term: 1, command: Some(vec![0x02]), membership: None },
+                Entry { index: 3, term: 1, command: Some(vec![0x03]), membership: None },
+                Entry { index: 4, term: 1, command: Some(vec![0x04]), membership: None },
             ]
         );
 
         // Splicing with a different term replaces.
         assert_eq!(
             l.splice(vec![
-                Entry { index: 3, term: 2, command: Some(vec![0x03]) },
-                Entry { index: 4, term: 2, command: Some(vec![0x04]) },
+                Entry { index: 3, term: 2, command: Some(vec![0x03]), membership: None },
+                Entry { index: 4, term: 2, command: Some(vec![0x04]), membership: None },
             ])?,
             4
         );
         assert_eq!(
             l.scan(..)?.collect::<Result<Vec<_>>>()?,
             vec![
-                Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                Entry { index: 2, term: 1, command: Some(vec![0x02]) },
-                Entry { index: 3, term: 2, command: Some(vec![0x03]) },
-                Entry { index: 4, term: 2, command: Some(vec![0x04]) },
+                Entry { index: 1, term: 1, command: Some(vec![0x01]), membership: None },
+                Entry { index: 2, term: 1, command: Some(vec![0x02]), membership: None },
+                Entry { index: 3, term: 2, command: Some(vec![0x03]), membership: None },
+                Entry { index: 4, term: 2, command: Some(vec![0x04]), membership: None },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn compact() -> Result<()> {
+        let mut l = setup();
+        l.append(1, Some(vec![0x01]))?;
+        l.append(1, Some(vec![0x02]))?;
+        l.append(2, Some(vec![0x03]))?;
+        l.append(2, Some(vec![0x04]))?;
+        l.commit(3)?;
+
+        // Compacting beyond the commit index should error.
+        assert_eq!(
+            l.compact(4),
+            Err(Error::Internal("Can't compact uncommitted index 4 (committed 3)".to_string()))
+        );
+
+        // Compacting removes entries, but the index and term is retained.
+        assert_eq!(l.compact(2)?, 2);
+        assert_eq!(l.get_snapshot_index(), (2, 1));
+        assert_eq!(l.get(1)?, None);
+        assert_eq!(l.get(2)?, None);
+        assert_eq!(l.term(2)?, Some(1));
+        assert_eq!(
+            l.scan(..)?.collect::<Result<Vec<_>>>()?,
+            vec![
+                Entry { index: 3, term: 2, command: Some(vec![0x03]), membership: None },
+                Entry { index: 4, term: 2, command: Some(vec![0x04]), membership: None },
             ]
         );
 
+        // Compacted entries are considered to exist.
+        assert!(l.has(1, 1)?);
+        assert!(l.has(2, 1)?);
+        assert!(!l.has(2, 2)?);
+
+        // Compacting at or below the snapshot is a noop.
+        assert_eq!(l.compact(1)?, 2);
+        assert_eq!(l.get_snapshot_index(), (2, 1));
+
+        // Splicing compacted entries ignores them.
+        assert_eq!(
+            l.splice(vec![
+                Entry { index: 2, term: 1, command: Some(vec![0x02]), membership: None },
+                Entry { index: 3, term: 2, command: Some(vec![0x03]), membership: None },
+                Entry { index: 4, term: 2, command: Some(vec![0x04]), membership: None },
+                Entry { index: 5, term: 2, command: Some(vec![0x05]), membership: None },
+            ])?,
+            5
+        );
+        assert_eq!(l.get(2)?, None);
+        assert_eq!(l.get_last_index(), (5, 2));
+
+        // Compacting everything retains the last index and term.
+        l.commit(5)?;
+        assert_eq!(l.compact(5)?, 5);
+        assert!(l.scan(..)?.collect::<Result<Vec<_>>>()?.is_empty());
+        assert_eq!(l.get_last_index(), (5, 2));
+        assert_eq!(l.get_commit_index(), (5, 2));
+        assert_eq!(l.append(3, None)?, 6);
+        Ok(())
+    }
+
+    #[test]
+    fn compact_reopen() -> Result<()> {
+        let dir = tempdir::TempDir::new("radb")?;
+        let path = dir.path().join("log");
+        let mut l = Log::new(storage::BitCask::new(path.clone())?, false)?;
+        l.append(1, Some(vec![0x01]))?;
+        l.append(2, Some(vec![0x02]))?;
+        l.commit(2)?;
+        l.compact(2)?;
+        drop(l);
+
+        // Reopening an empty compacted log uses the snapshot as last index.
+        let l = Log::new(storage::BitCask::new(path)?, false)?;
+        assert_eq!(l.get_snapshot_index(), (2, 2));
+        assert_eq!(l.get_last_index(), (2, 2));
+        assert_eq!(l.get_commit_index(), (2, 2));
+        Ok(())
+    }
+
+    #[test]
+    fn install_snapshot() -> Result<()> {
+        let mut l = setup();
+        l.append(1, Some(vec![0x01]))?;
+        l.append(1, Some(vec![0x02]))?;
+        l.append(2, Some(vec![0x03]))?;
+        l.commit(1)?;
+
+        // Installing a snapshot at or below the commit index errors.
+        assert!(l.install_snapshot(1, 1, None).is_err());
+
+        // Installing a snapshot matching an entry retains the following entries.
+        l.install_snapshot(2, 1, None)?;
+        assert_eq!(l.get_snapshot_index(), (2, 1));
+        assert_eq!(l.get_commit_index(), (2, 1));
+        assert_eq!(l.get_last_index(), (3, 2));
+        assert_eq!(
+            l.scan(..)?.collect::<Result<Vec<_>>>()?,
+            vec![Entry { index: 3, term: 2, command: Some(vec![0x03]), membership: None }]
+        );
+
+        // Installing a snapshot that doesn't match discards the entire log.
+        l.install_snapshot(3, 3, None)?;
+        assert_eq!(l.get_snapshot_index(), (3, 3));
+        assert_eq!(l.get_commit_index(), (3, 3));
+        assert_eq!(l.get_last_index(), (3, 3));
+        assert!(l.scan(..)?.collect::<Result<Vec<_>>>()?.is_empty());
+
+        // Installing a snapshot beyond the end of the log also works.
+        l.install_snapshot(7, 4, None)?;
+        assert_eq!(l.get_snapshot_index(), (7, 4));
+        assert_eq!(l.get_last_index(), (7, 4));
+        assert_eq!(l.append(4, None)?, 8);
+        Ok(())
+    }
+
+    #[test]
+    fn pending_snapshot() -> Result<()> {
+        let mut l = setup();
+        l.append(1, Some(vec![0x01]))?;
+        l.commit(1)?;
+        assert_eq!(l.get_pending_snapshot()?, None);
+
+        // A pending snapshot is recorded until it's installed.
+        l.begin_snapshot(3, 2, None)?;
+        assert_eq!(l.get_pending_snapshot()?, Some((3, 2, None)));
+        l.install_snapshot(3, 2, None)?;
+        assert_eq!(l.get_pending_snapshot()?, None);
+        assert_eq!(l.get_snapshot_index(), (3, 2));
+
+        // Or until it's cleared.
+        l.begin_snapshot(4, 2, None)?;
+        l.clear_pending_snapshot()?;
+        assert_eq!(l.get_pending_snapshot()?, None);
+        assert_eq!(l.get_snapshot_index(), (3, 2));
+        Ok(())
+    }
+
+    #[test]
+    fn membership() -> Result<()> {
+        let membership = |voters: &[NodeID]| Membership {
+            voters: voters.iter().copied().collect(),
+            learners: BTreeSet::new(),
+            addrs: BTreeMap::new(),
+        };
+
+        let mut l = setup();
+        assert_eq!(l.get_membership(), None);
+
+        // Appending a membership makes it take effect immediately.
+        l.append(1, Some(vec![0x01]))?;
+        assert_eq!(l.append_membership(1, membership(&[1, 2, 3]))?, 2);
+        l.append(1, Some(vec![0x03]))?;
+        assert_eq!(l.get_membership(), Some((2, &membership(&[1, 2, 3]))));
+        assert_eq!(
+            l.get(2)?,
+            Some(Entry {
+                index: 2,
+                term: 1,
+                command: None,
+                membership: Some(membership(&[1, 2, 3]))
+            })
+        );
+
+        // Splicing in a new membership replaces it.
+        l.splice(vec![Entry {
+            index: 4,
+            term: 2,
+            command: None,
+            membership: Some(membership(&[1, 2, 3, 4])),
+        }])?;
+        assert_eq!(l.get_membership(), Some((4, &membership(&[1, 2, 3, 4]))));
+        assert_eq!(l.get_membership_at(3)?, Some(membership(&[1, 2, 3])));
+        assert_eq!(l.get_membership_at(1)?, None);
+
+        // Truncating the membership entry reverts to the previous one.
+        l.splice(vec![Entry { index: 4, term: 3, command: None, membership: None }])?;
+        assert_eq!(l.get_membership(), Some((2, &membership(&[1, 2, 3]))));
+
+        // Compaction retains the membership, even across reopens.
+        let dir = tempdir::TempDir::new("radb")?;
+        let path = dir.path().join("log");
+        let mut l = Log::new(storage::BitCask::new(path.clone())?, false)?;
+        l.append_membership(1, membership(&[1, 2, 3]))?;
+        l.append(1, None)?;
+        l.commit(2)?;
+        l.compact(2)?;
+        assert_eq!(l.get_membership(), Some((1, &membership(&[1, 2, 3]))));
+        drop(l);
+
+        let mut l = Log::new(storage::BitCask::new(path)?, false)?;
+        assert_eq!(l.get_membership(), Some((2, &membership(&[1, 2, 3]))));
+        assert_eq!(l.get_membership_at(2)?, Some(membership(&[1, 2, 3])));
+
+        // Installing a snapshot uses its membership.
+        l.install_snapshot(5, 2, Some(membership(&[1, 2])))?;
+        assert_eq!(l.get_membership(), Some((5, &membership(&[1, 2]))));
         Ok(())
     }
 }
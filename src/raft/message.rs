@@ -1,9 +1,9 @@
-use super::{Entry, Index, NodeID, Term};
+use super::{Entry, Index, Membership, NodeID, Term, Ticks};
 use crate::error::Result;
 use crate::storage;
 
 use serde_derive::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 /// A message envelope sent between Raft nodes.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -56,6 +56,24 @@ pub enum Message {
         vote: bool,
     },
 
+    /// Candidates check whether they could win an election before campaigning,
+    /// by soliciting pre-votes for the next term. The envelope carries the
+    /// proposed term, but neither the candidate nor the recipients change
+    /// their term or vote.
+    PreVote {
+        /// The index of the candidate's last stored log entry
+        last_index: Index,
+        /// The term of the candidate's last stored log entry
+        last_term: Term,
+    },
+
+    /// Nodes grant pre-votes if they haven't heard from a leader and the
+    /// candidate's log is up-to-date. Granted pre-votes carry the proposed term.
+    PreVoteResponse {
+        /// If true, the sender would vote for the candidate.
+        vote: bool,
+    },
+
     /// Leaders replicate log entries to followers by appending to their logs.
     Append {
         /// The index of the log entry immediately preceding the submitted commands.
@@ -70,12 +88,46 @@ pub enum Message {
     AppendResponse {
         /// If true, the follower rejected the leader's entries.
         reject: bool,
-        /// The index of the follower's last log entry.
+        /// The index of the follower's last log entry. When rejecting, this
+        /// is at most the entry before the rejected base entry, i.e. the last
+        /// entry that may still match the leader's log.
         last_index: Index,
-        /// The term of the follower's last log entry.
+        /// The term of the follower's last log entry, or the entry at
+        /// last_index when rejecting.
         last_term: Term,
     },
 
+    /// Leaders send state machine snapshots to followers whose next entry has
+    /// been compacted out of the leader's log. The snapshot is sent in chunks,
+    /// each of which must be acknowledged before the next is sent.
+    InstallSnapshot {
+        /// The index of the last log entry included in the snapshot.
+        index: Index,
+        /// The term of the last log entry included in the snapshot.
+        term: Term,
+        /// The cluster membership as of the snapshot index.
+        membership: Option<Membership>,
+        /// The byte offset of this chunk in the snapshot.
+        offset: u64,
+        /// The chunk data.
+        data: Vec<u8>,
+        /// If true, this is the last chunk.
+        done: bool,
+    },
+
+    /// Followers acknowledge snapshot chunks. Once the final chunk has been
+    /// installed, the follower responds with an AppendResponse instead.
+    InstallSnapshotResponse {
+        /// The index of the snapshot being installed.
+        index: Index,
+        /// The byte offset of the next chunk the follower expects.
+        offset: u64,
+    },
+
+    /// Leaders tell a caught-up follower to campaign immediately, without
+    /// waiting for an election timeout, when transferring leadership to it.
+    TimeoutNow,
+
     /// A client request. This can be submitted to the leader, or to a follower
     /// which will forward it to its leader. If there is no leader, or the
     /// leader or term changes, the request is aborted with an Error::Abort
@@ -109,11 +161,40 @@ pub enum Request {
     /// A state machine read command. This is not replicated, and only evaluted
     /// on the leader.
     Read(Vec<u8>),
+    /// A state machine read command evaluated against the local state
+    /// machine, without confirming leadership. The result may be stale. If
+    /// max_staleness is given, a follower only serves the read if it has
+    /// applied the leader's commit index as of a heartbeat received within
+    /// that many ticks, and otherwise returns Error::Abort. The state machine
+    /// must ensure it can serve the read, e.g. that it has applied the
+    /// requested version.
+    ReadLocal { command: Vec<u8>, max_staleness: Option<Ticks> },
     /// A state machine write command. This is replicated across all nodes, and
     /// must result in a deterministic response.
     Write(Vec<u8>),
     /// Requests Raft cluster status from the leader.
     Status,
+    /// Changes the cluster membership. Only a single change can be in
+    /// progress at a time, and it takes effect once appended to the leader's
+    /// log. The response is returned once the change is committed.
+    ChangeMembership(MembershipChange),
+    /// Transfers leadership to the given voter. The leader first catches the
+    /// target up, then tells it to campaign immediately. New writes are
+    /// aborted during the transfer. The response is returned once the target
+    /// has been told to campaign, which it will usually win.
+    TransferLeadership(NodeID),
+}
+
+/// A cluster membership change.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum MembershipChange {
+    /// Adds a voting node with the given ID and Raft address. If the node is
+    /// already a learner, it's promoted to a voter.
+    AddNode { id: NodeID, addr: String },
+    /// Adds a non-voting learner with the given ID and Raft address.
+    AddLearner { id: NodeID, addr: String },
+    /// Removes a voter or learner. The leader can't remove itself.
+    RemoveNode { id: NodeID },
 }
 
 /// A client response. This will be wrapped in a Result to handle errors.
@@ -129,6 +210,8 @@ pub enum Response {
     Write(Vec<u8>),
     /// The current Raft leader status.
     Status(Status),
+    /// A leadership transfer was initiated.
+    TransferLeadership,
 }
 
 /// Raft cluster status.
@@ -140,10 +223,15 @@ pub struct Status {
     pub term: Term,
     /// The last log indexes of all nodes.
     pub last_index: HashMap<NodeID, Index>,
+    /// The non-voting learners. All other nodes are voters.
+    pub learners: HashSet<NodeID>,
     /// The current commit index.
     pub commit_index: Index,
     /// The current applied index.
     pub apply_index: Index,
+    /// The index of the last entry included in the latest log snapshot, i.e.
+    /// the log has been compacted up to and including it.
+    pub snapshot_index: Index,
     /// The log storage engine status.
     pub storage: storage::engine::Status,
 }
@@ -3,9 +3,11 @@ mod message;
 mod node;
 mod state;
 
-pub use log::{Entry, Index, Log};
-pub use message::{Envelope, Message, ReadSequence, Request, RequestID, Response, Status};
-pub use node::{Node, NodeID, Term, Ticks};
+pub use log::{Entry, Index, Log, Membership};
+pub use message::{
+    Envelope, MembershipChange, Message, ReadSequence, Request, RequestID, Response, Status,
+};
+pub use node::{Node, NodeID, Options, Term, Ticks};
 pub use state::State;
 
 /// The interval between Raft ticks. This is the unit of time for heartbeats and
@@ -18,3 +20,12 @@ pub const HEARTBEAT_INTERVAL: Ticks = 3;
 /// The election timeout range, in ticks. This is randomized per node in this
 /// interval, to avoid ties.
 const ELECTION_TIMEOUT_RANGE: std::ops::Range<Ticks> = 10..20;
+
+/// The clock drift margin for leader leases, in ticks. Leases expire this long
+/// before the minimum election timeout, to tolerate nodes' clocks advancing at
+/// slightly different rates.
+const LEASE_CLOCK_DRIFT: Ticks = 2;
+
+/// The maximum size of a snapshot chunk sent in a single InstallSnapshot
+/// message, in bytes.
+const SNAPSHOT_CHUNK_SIZE: usize = 1024 * 1024;
@@ -1,14 +1,18 @@
-use super::super::{Envelope, Message};
+use super::super::{Envelope, Message, Request};
 use super::{rand_election_timeout, Follower, Leader, Node, NodeID, RawNode, Role, Term, Ticks};
 use crate::error::{Error, Result};
 
 use ::log::{debug, info};
+use itertools::Itertools as _;
 use std::collections::HashSet;
 
 /// A candidate is campaigning to become a leader.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Candidate {
-    /// Votes received (including ourself).
+    /// If true, we're running a pre-vote for the next term, and haven't
+    /// increased our term or voted yet.
+    pub(super) prevote: bool,
+    /// Votes (or pre-votes) received (including ourself).
     votes: HashSet<NodeID>,
     /// Ticks elapsed since election start.
     election_duration: Ticks,
@@ -20,6 +24,7 @@ impl Candidate {
     /// Creates a new candidate role.
     pub fn new() -> Self {
         Self {
+            prevote: false,
             votes: HashSet::new(),
             election_duration: 0,
             election_timeout: rand_election_timeout(),
@@ -34,9 +39,11 @@ impl RawNode<Candidate> {
     fn assert(&mut self) -> Result<()> {
         self.assert_node()?;
 
-        assert_ne!(self.term, 0, "Candidates can't have term 0");
         assert!(self.role.votes.contains(&self.id), "Candidate did not vote for self");
-        debug_assert_eq!(Some(self.id), self.log.get_term()?.1, "Log vote does not match self");
+        if !self.role.prevote {
+            assert_ne!(self.term, 0, "Candidates can't have term 0");
+            debug_assert_eq!(Some(self.id), self.log.get_term()?.1, "Log vote does not match self");
+        }
 
         assert!(
             self.role.election_duration < self.role.election_timeout,
@@ -57,10 +64,11 @@ impl RawNode<Candidate> {
         assert!(term >= self.term, "Term regression {} -> {}", self.term, term);
 
         if let Some(leader) = leader {
-            // We lost the election, follow the winner.
+            // We lost the election, follow the winner. If we were running a
+            // pre-vote, we haven't necessarily voted in this term.
             assert_eq!(term, self.term, "Can't follow leader in different term");
             info!("Lost election, following leader {} in term {}", leader, term);
-            let voted_for = Some(self.id); // by definition
+            let (_, voted_for) = self.log.get_term()?;
             Ok(self.into_role(Follower::new(Some(leader), voted_for)))
         } else {
             // We found a new term, but we don't necessarily know who the leader
@@ -76,14 +84,21 @@ impl RawNode<Candidate> {
     /// Transitions the candidate to a leader. We won the election.
     pub(super) fn into_leader(self) -> Result<RawNode<Leader>> {
         info!("Won election for term {}, becoming leader", self.term);
-        let peers = self.peers.clone();
+        let peers = self.peers.union(&self.learners).copied().collect();
         let (last_index, _) = self.log.get_last_index();
         let mut node = self.into_role(Leader::new(peers, last_index));
         node.heartbeat()?;
 
         // Propose an empty command when assuming leadership, to disambiguate
-        // previous entries in the log. See section 8 in the Raft paper.
-        node.propose(None)?;
+        // previous entries in the log. See section 8 in the Raft paper. If the
+        // log doesn't contain a cluster membership yet, i.e. when bootstrapping
+        // the cluster, propose the initial membership instead.
+        if node.log.get_membership().is_none() {
+            let membership = node.bootstrap_membership();
+            node.propose_membership(membership)?;
+        } else {
+            node.propose(None)?;
+        }
         Ok(node)
     }
 
@@ -91,6 +106,31 @@ impl RawNode<Candidate> {
     pub fn step(mut self, msg: Envelope) -> Result<Node> {
         self.assert()?;
         self.assert_step(&msg);
+        if !self.is_known_sender(&msg) {
+            return Ok(self.into());
+        }
+
+        // Pre-votes are for the next term, and don't change our term. Record
+        // granted pre-votes, and campaign for real if we have a quorum.
+        match msg.message {
+            Message::PreVote { .. } => {
+                self.step_prevote(&msg, false)?;
+                return Ok(self.into());
+            }
+            Message::PreVoteResponse { vote: true } => {
+                if self.role.prevote
+                    && msg.term == self.term + 1
+                    && !self.learners.contains(&msg.from)
+                {
+                    self.role.votes.insert(msg.from);
+                    if self.role.votes.len() as u8 >= self.quorum_size() {
+                        self.campaign()?;
+                    }
+                }
+                return Ok(self.into());
+            }
+            _ => {}
+        }
 
         // Drop messages from past terms.
         if msg.term < self.term {
@@ -109,9 +149,16 @@ impl RawNode<Candidate> {
             // Ignore other candidates when we're also campaigning.
             Message::Campaign { .. } => {}
 
+            // We're already campaigning.
+            Message::TimeoutNow => {}
+
             // If we received a vote, record it. If the vote gives us quorum,
-            // assume leadership.
+            // assume leadership. Learners don't vote, but may not know they're
+            // learners yet, so ignore their votes.
             Message::CampaignResponse { vote: true } => {
+                if self.role.prevote || self.learners.contains(&msg.from) {
+                    return Ok(self.into());
+                }
                 self.role.votes.insert(msg.from);
                 if self.role.votes.len() as u8 >= self.quorum_size() {
                     return Ok(self.into_leader()?.into());
@@ -120,14 +167,27 @@ impl RawNode<Candidate> {
 
             // We didn't get a vote. :(
             Message::CampaignResponse { vote: false } => {}
-
-            // If we receive a heartbeat or entries in this term, we lost the
-            // election and have a new leader. Follow it and step the message.
-            Message::Heartbeat { .. } | Message::Append { .. } => {
+            Message::PreVoteResponse { .. } => {}
+
+            // If we receive a heartbeat, entries, or a snapshot in this term, we
+            // lost the election and have a new leader. Follow it and step the
+            // message.
+            Message::Heartbeat { .. }
+            | Message::Append { .. }
+            | Message::InstallSnapshot { .. } => {
                 return self.into_follower(msg.term, Some(msg.from))?.step(msg);
             }
 
-            // Abort any inbound client requests while candidate.
+            // Serve unbounded local reads, since these don't depend on a
+            // leader.
+            Message::ClientRequest {
+                id,
+                request: Request::ReadLocal { command, max_staleness: None },
+            } => {
+                self.read_local(id, command)?;
+            }
+
+            // Abort any other inbound client requests while candidate.
             Message::ClientRequest { id, .. } => {
                 self.send(msg.from, Message::ClientResponse { id, response: Err(Error::Abort) })?;
             }
@@ -136,6 +196,8 @@ impl RawNode<Candidate> {
             // so we shouldn't see these.
             Message::HeartbeatResponse { .. }
             | Message::AppendResponse { .. }
+            | Message::InstallSnapshotResponse { .. }
+            | Message::PreVote { .. }
             | Message::ClientResponse { .. } => panic!("Received unexpected message {:?}", msg),
         }
         Ok(self.into())
@@ -147,11 +209,36 @@ impl RawNode<Candidate> {
 
         self.role.election_duration += 1;
         if self.role.election_duration >= self.role.election_timeout {
-            self.campaign()?;
+            match self.opts.prevote {
+                true => self.prevote()?,
+                false => self.campaign()?,
+            }
         }
         Ok(self.into())
     }
 
+    /// Runs a pre-vote for the next term, by soliciting pre-votes from all
+    /// peers without increasing our term or voting. If a quorum would vote for
+    /// us, we campaign for real. This prevents nodes that can't win an
+    /// election, e.g. because they're partitioned, from disrupting the cluster
+    /// by increasing their term. See section 9.6 in the Raft dissertation.
+    pub(super) fn prevote(&mut self) -> Result<()> {
+        let term = self.term + 1;
+        info!("Starting pre-vote for term {}", term);
+        self.role = Candidate::new();
+        self.role.prevote = true;
+        self.role.votes.insert(self.id); // vote for ourself
+        if self.role.votes.len() as u8 >= self.quorum_size() {
+            return self.campaign();
+        }
+
+        let (last_index, last_term) = self.log.get_last_index();
+        for id in self.peers.iter().copied().sorted() {
+            self.send_term(id, term, Message::PreVote { last_index, last_term })?;
+        }
+        Ok(())
+    }
+
     /// Campaign for leadership by increasing the term, voting for ourself, and
     /// soliciting votes from all peers.
     pub(super) fn campaign(&mut self) -> Result<()> {
@@ -171,8 +258,9 @@ impl RawNode<Candidate> {
 #[cfg(test)]
 mod tests {
     use super::super::super::state::tests::TestState;
-    use super::super::super::{Entry, Log, Request};
+    use super::super::super::{Entry, Log, Membership, Response};
     use super::super::tests::{assert_messages, assert_node};
+    use super::super::Options;
     use super::*;
     use crate::storage;
     use itertools::Itertools as _;
@@ -192,10 +280,12 @@ mod tests {
         let mut node = RawNode {
             id: 1,
             peers: HashSet::from([2, 3, 4, 5]),
+            learners: HashSet::new(),
             term: 3,
             log,
             state,
             node_tx,
+            opts: Options::default(),
             role: Candidate::new(),
         };
         node.role.votes.insert(1);
@@ -300,7 +390,7 @@ mod tests {
             );
         }
 
-        for to in peers.iter().copied() {
+        for to in peers.iter().copied().sorted() {
             assert_eq!(
                 node_rx.try_recv()?,
                 Envelope {
@@ -310,7 +400,16 @@ mod tests {
                     message: Message::Append {
                         base_index: 3,
                         base_term: 2,
-                        entries: vec![Entry { index: 4, term: 3, command: None }],
+                        entries: vec![Entry {
+                            index: 4,
+                            term: 3,
+                            command: None,
+                            membership: Some(Membership {
+                                voters: [1, 2, 3, 4, 5].into(),
+                                learners: Default::default(),
+                                addrs: Default::default(),
+                            }),
+                        }],
                     },
                 }
             )
@@ -342,12 +441,39 @@ mod tests {
                 message: Message::ClientResponse { id: vec![0x01], response: Err(Error::Abort) },
             }],
         );
+
+        // Unbounded local reads are served, bounded ones are aborted.
+        for (max_staleness, response) in
+            [(None, Ok(Response::Read(vec![0xaf]))), (Some(10), Err(Error::Abort))]
+        {
+            node = node.step(Envelope {
+                from: 1,
+                to: 1,
+                term: 3,
+                message: Message::ClientRequest {
+                    id: vec![0x02],
+                    request: Request::ReadLocal { command: vec![0xaf], max_staleness },
+                },
+            })?;
+            assert_node(&mut node).is_candidate().term(3);
+            assert_messages(
+                &mut node_rx,
+                vec![Envelope {
+                    from: 1,
+                    to: 1,
+                    term: 3,
+                    message: Message::ClientResponse { id: vec![0x02], response },
+                }],
+            );
+        }
         Ok(())
     }
 
     #[test]
+    // An election timeout starts a pre-vote for the next term, without
+    // changing our term. Once a quorum grants it, we campaign for real.
     fn tick() -> Result<()> {
-        let (candidate, node_rx) = setup()?;
+        let (candidate, mut node_rx) = setup()?;
         let peers = candidate.peers.clone();
         let timeout = candidate.role.election_timeout;
         let mut node = Node::Candidate(candidate);
@@ -357,7 +483,7 @@ mod tests {
             assert_node(&mut node).is_candidate().term(3);
             node = node.tick()?;
         }
-        assert_node(&mut node).is_candidate().term(4);
+        assert_node(&mut node).is_candidate().term(3);
 
         for to in peers.iter().copied().sorted() {
             assert_eq!(
@@ -366,10 +492,77 @@ mod tests {
                     from: 1,
                     to,
                     term: 4,
-                    message: Message::Campaign { last_index: 3, last_term: 2 },
+                    message: Message::PreVote { last_index: 3, last_term: 2 },
                 },
             );
         }
+
+        // Rejections and stale votes are ignored.
+        node = node.step(Envelope {
+            from: 2,
+            to: 1,
+            term: 3,
+            message: Message::PreVoteResponse { vote: false },
+        })?;
+        node = node.step(Envelope {
+            from: 3,
+            to: 1,
+            term: 3,
+            message: Message::CampaignResponse { vote: true },
+        })?;
+        node = node.step(Envelope {
+            from: 4,
+            to: 1,
+            term: 4,
+            message: Message::PreVoteResponse { vote: true },
+        })?;
+        assert_node(&mut node).is_candidate().term(3);
+        assert_messages(&mut node_rx, vec![]);
+
+        node = node.step(Envelope {
+            from: 5,
+            to: 1,
+            term: 4,
+            message: Message::PreVoteResponse { vote: true },
+        })?;
+        assert_node(&mut node).is_candidate().term(4);
+        let mut expect = Vec::new();
+        for to in peers.iter().copied().sorted() {
+            expect.push(Envelope {
+                from: 1,
+                to,
+                term: 4,
+                message: Message::Campaign { last_index: 3, last_term: 2 },
+            });
+        }
+        assert_messages(&mut node_rx, expect);
+        Ok(())
+    }
+
+    #[test]
+    // Without pre-votes, an election timeout campaigns in the next term.
+    fn tick_no_prevote() -> Result<()> {
+        let (mut candidate, mut node_rx) = setup()?;
+        candidate.opts.prevote = false;
+        let peers = candidate.peers.clone();
+        let timeout = candidate.role.election_timeout;
+        let mut node = Node::Candidate(candidate);
+
+        for _ in 0..timeout {
+            assert_node(&mut node).is_candidate().term(3);
+            node = node.tick()?;
+        }
+        assert_node(&mut node).is_candidate().term(4);
+        let mut expect = Vec::new();
+        for to in peers.iter().copied().sorted() {
+            expect.push(Envelope {
+                from: 1,
+                to,
+                term: 4,
+                message: Message::Campaign { last_index: 3, last_term: 2 },
+            });
+        }
+        assert_messages(&mut node_rx, expect);
         Ok(())
     }
 }
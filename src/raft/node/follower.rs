@@ -1,5 +1,7 @@
-use super::super::{Envelope, Log, Message, RequestID, State};
-use super::{rand_election_timeout, Candidate, Node, NodeID, RawNode, Role, Term, Ticks};
+use super::super::{Envelope, Index, Log, Message, Request, RequestID, State};
+use super::{
+    rand_election_timeout, Candidate, Node, NodeID, Options, RawNode, Role, Snapshot, Term, Ticks,
+};
 use crate::error::{Error, Result};
 
 use ::log::{debug, info};
@@ -19,6 +21,16 @@ pub struct Follower {
     // Local client requests that have been forwarded to the leader. These are
     // aborted on leader/term changes.
     pub(super) forwarded: HashSet<RequestID>,
+    /// A partially received snapshot from the leader, if any.
+    pub(super) snapshot: Option<Snapshot>,
+    /// The number of ticks since we received the latest leader heartbeat whose
+    /// commit index we've applied, if any. This bounds the staleness of local
+    /// reads.
+    applied_heartbeat: Option<Ticks>,
+    /// The oldest leader heartbeat whose commit index we haven't applied yet,
+    /// if any, as its commit index and the number of ticks since we received
+    /// it. Once applied, it becomes applied_heartbeat unless that's newer.
+    pending_heartbeat: Option<(Index, Ticks)>,
 }
 
 impl Follower {
@@ -30,6 +42,9 @@ impl Follower {
             leader_seen: 0,
             election_timeout: rand_election_timeout(),
             forwarded: HashSet::new(),
+            snapshot: None,
+            applied_heartbeat: None,
+            pending_heartbeat: None,
         }
     }
 }
@@ -37,17 +52,34 @@ impl Follower {
 impl Role for Follower {}
 
 impl RawNode<Follower> {
-    /// Creates a new node as a leaderless follower.
+    /// Creates a new node as a leaderless follower. The given peers are only
+    /// used until the log contains a cluster membership.
+    ///
+    /// If we crashed while installing a snapshot, the installation is finished
+    /// if the state machine was restored from it, otherwise it's discarded.
     pub fn new(
         id: NodeID,
         peers: HashSet<NodeID>,
         mut log: Log,
         state: Box<dyn State>,
         node_tx: crossbeam::channel::Sender<Envelope>,
+        opts: Options,
     ) -> Result<Self> {
+        if let Some((index, term, membership)) = log.get_pending_snapshot()? {
+            if state.get_applied_index() >= index && log.get_commit_index().0 < index {
+                info!("Finishing installation of snapshot at index {} term {}", index, term);
+                log.install_snapshot(index, term, membership)?;
+            } else {
+                info!("Discarding pending snapshot at index {} term {}", index, term);
+                log.clear_pending_snapshot()?;
+            }
+        }
         let (term, voted_for) = log.get_term()?;
         let role = Follower::new(None, voted_for);
-        Ok(Self { id, peers, term, log, state, node_tx, role })
+        let learners = HashSet::new();
+        let mut node = Self { id, peers, learners, term, log, state, node_tx, opts, role };
+        node.update_peers();
+        Ok(node)
     }
 
     /// Asserts internal invariants.
@@ -74,16 +106,32 @@ impl RawNode<Follower> {
 
     /// Transitions the follower into a candidate, by campaigning for
     /// leadership in a new term.
-    pub(super) fn into_candidate(mut self) -> Result<RawNode<Candidate>> {
+    pub(super) fn into_candidate(self) -> Result<RawNode<Candidate>> {
+        let mut node = self.into_candidate_role()?;
+        node.campaign()?;
+        Ok(node)
+    }
+
+    /// Transitions the follower into a candidate running a pre-vote for the
+    /// next term, or campaigning directly if pre-votes are disabled.
+    fn into_prevote_candidate(self) -> Result<RawNode<Candidate>> {
+        if !self.opts.prevote {
+            return self.into_candidate();
+        }
+        let mut node = self.into_candidate_role()?;
+        node.prevote()?;
+        Ok(node)
+    }
+
+    /// Helper for candidate transitions.
+    fn into_candidate_role(mut self) -> Result<RawNode<Candidate>> {
         // Abort any forwarded requests. These must be retried with new leader.
         self.abort_forwarded()?;
 
         // Apply any pending log entries, so that we're caught up if we win.
         self.maybe_apply()?;
 
-        let mut node = self.into_role(Candidate::new());
-        node.campaign()?;
-        Ok(node)
+        Ok(self.into_role(Candidate::new()))
     }
 
     /// Transitions the candidate into a follower, either a leaderless follower
@@ -121,6 +169,20 @@ impl RawNode<Follower> {
     pub fn step(mut self, msg: Envelope) -> Result<Node> {
         self.assert()?;
         self.assert_step(&msg);
+        if !self.is_known_sender(&msg) {
+            return Ok(self.into());
+        }
+
+        // Pre-votes are for the next term, and don't change our term. We're
+        // not running a pre-vote, so ignore granted pre-votes.
+        match msg.message {
+            Message::PreVote { .. } => {
+                self.step_prevote(&msg, self.role.leader.is_some())?;
+                return Ok(self.into());
+            }
+            Message::PreVoteResponse { vote: true } => return Ok(self.into()),
+            _ => {}
+        }
 
         // Drop messages from past terms.
         if msg.term < self.term {
@@ -165,6 +227,16 @@ impl RawNode<Follower> {
                     self.log.commit(commit_index)?;
                     self.maybe_apply()?;
                 }
+
+                // Track when we've applied the leader's commit index, to bound
+                // the staleness of local reads. If we're behind, remember the
+                // oldest heartbeat we haven't caught up with yet.
+                if commit_index <= self.state.get_applied_index() {
+                    self.role.applied_heartbeat = Some(0);
+                    self.role.pending_heartbeat = None;
+                } else if self.role.pending_heartbeat.is_none() {
+                    self.role.pending_heartbeat = Some((commit_index, 0));
+                }
             }
 
             // Replicate entries from the leader. If we don't have a leader in
@@ -177,17 +249,116 @@ impl RawNode<Follower> {
                     None => self = self.into_follower(Some(from), msg.term)?,
                 }
 
-                // Append the entries, if possible.
+                // Append the entries, if possible. If we reject them, respond
+                // with the last entry that may still match the leader's log,
+                // i.e. before the base entry, for the leader to probe from.
                 let reject = base_index > 0 && !self.log.has(base_index, base_term)?;
                 if !reject {
                     self.log.splice(entries)?;
+                    self.update_peers();
+                }
+                let (mut last_index, mut last_term) = self.log.get_last_index();
+                if reject && last_index >= base_index {
+                    last_index = base_index - 1;
+                    last_term = self.log.term(last_index)?.unwrap_or(0);
                 }
-                let (last_index, last_term) = self.log.get_last_index();
                 self.send(msg.from, Message::AppendResponse { reject, last_index, last_term })?;
             }
 
+            // The leader is transferring leadership to us, and we're caught
+            // up. Campaign immediately. If we don't know the leader yet, e.g.
+            // because we restarted in the same term or just discovered the
+            // term, ignore it. The leader cancels the transfer after a timeout.
+            Message::TimeoutNow => {
+                if !self.is_leader(msg.from) {
+                    debug!("Ignoring TimeoutNow from non-leader {}", msg.from);
+                    return Ok(self.into());
+                }
+                if self.is_voter() {
+                    info!("Leadership transferred to us, campaigning");
+                    return Ok(self.into_candidate()?.into());
+                }
+            }
+
+            // The leader sends a snapshot in chunks when our log is too far
+            // behind its compacted log. Buffer the chunks, and install the
+            // snapshot once the final chunk is received.
+            Message::InstallSnapshot { index, term, membership, offset, data, done } => {
+                // Check that the snapshot is from our leader.
+                let from = msg.from;
+                match self.role.leader {
+                    Some(leader) => assert_eq!(from, leader, "Multiple leaders in term"),
+                    None => self = self.into_follower(Some(from), msg.term)?,
+                }
+
+                // If we've already committed the snapshot index, we don't need
+                // it. Respond with our commit index to resume replication.
+                let (commit_index, commit_term) = self.log.get_commit_index();
+                if index <= commit_index {
+                    self.role.snapshot = None;
+                    self.send(
+                        from,
+                        Message::AppendResponse {
+                            reject: false,
+                            last_index: commit_index,
+                            last_term: commit_term,
+                        },
+                    )?;
+                    return Ok(self.into());
+                }
+
+                // Append the chunk to the buffer. A chunk at offset 0 starts a
+                // new snapshot. If the chunk is beyond the end of the buffer
+                // (e.g. due to a lost message), ask for the missing data.
+                if offset == 0 {
+                    self.role.snapshot =
+                        Some(Snapshot { index, term, membership, data: Vec::new() });
+                }
+                let received = match &self.role.snapshot {
+                    Some(s) if s.index == index && s.term == term => s.data.len() as u64,
+                    _ => 0,
+                };
+                if offset > received {
+                    self.send(from, Message::InstallSnapshotResponse { index, offset: received })?;
+                    return Ok(self.into());
+                }
+                let snapshot = self.role.snapshot.as_mut().expect("no snapshot buffer");
+                snapshot.data.truncate(offset as usize);
+                snapshot.data.extend(data);
+                if !done {
+                    let offset = snapshot.data.len() as u64;
+                    self.send(from, Message::InstallSnapshotResponse { index, offset })?;
+                    return Ok(self.into());
+                }
+
+                // Install the snapshot. The state machine is restored before
+                // the log is reset, since the log would otherwise claim to have
+                // applied entries that the state machine is missing. This isn't
+                // atomic, so the snapshot is recorded in the log first, which
+                // allows finishing the installation on restart if we crash in
+                // between (see RawNode::new).
+                let snapshot = self.role.snapshot.take().unwrap();
+                info!("Installing snapshot at index {} term {}", index, term);
+                self.log.begin_snapshot(
+                    snapshot.index,
+                    snapshot.term,
+                    snapshot.membership.clone(),
+                )?;
+                self.state.restore(snapshot.index, snapshot.data)?;
+                self.log.install_snapshot(snapshot.index, snapshot.term, snapshot.membership)?;
+                self.update_peers();
+                let (last_index, last_term) = self.log.get_last_index();
+                self.send(from, Message::AppendResponse { reject: false, last_index, last_term })?;
+            }
+
             // A candidate in this term is requesting our vote.
             Message::Campaign { last_index, last_term } => {
+                // Don't vote if we're a learner or have been removed.
+                if !self.is_voter() {
+                    self.send(msg.from, Message::CampaignResponse { vote: false })?;
+                    return Ok(self.into());
+                }
+
                 // Don't vote if we already voted for someone else in this term.
                 if let Some(voted_for) = self.role.voted_for {
                     if msg.from != voted_for {
@@ -212,7 +383,33 @@ impl RawNode<Follower> {
 
             // We may receive a vote after we lost an election and followed a
             // different leader. Ignore it.
-            Message::CampaignResponse { .. } => {}
+            Message::CampaignResponse { .. } | Message::PreVoteResponse { .. } => {}
+
+            // Serve local reads if we've heard from the leader recently
+            // enough, otherwise abort them (the client can fall back to the
+            // leader).
+            Message::ClientRequest {
+                id,
+                request: Request::ReadLocal { command, max_staleness },
+            } => {
+                assert_eq!(msg.from, self.id, "Client request from other node");
+
+                let fresh = match max_staleness {
+                    Some(ticks) => {
+                        self.role.leader.is_some()
+                            && self.applied_heartbeat().is_some_and(|seen| seen <= ticks)
+                    }
+                    None => true,
+                };
+                if fresh {
+                    self.read_local(id, command)?;
+                } else {
+                    self.send(
+                        msg.from,
+                        Message::ClientResponse { id, response: Err(Error::Abort) },
+                    )?;
+                }
+            }
 
             // Forward client requests to the leader, or abort them if there is
             // none (the client must retry).
@@ -242,7 +439,10 @@ impl RawNode<Follower> {
             }
 
             // We're not a leader nor candidate in this term, so we shoudn't see these.
-            Message::HeartbeatResponse { .. } | Message::AppendResponse { .. } => {
+            Message::HeartbeatResponse { .. }
+            | Message::AppendResponse { .. }
+            | Message::InstallSnapshotResponse { .. }
+            | Message::PreVote { .. } => {
                 panic!("Received unexpected message {msg:?}")
             }
         };
@@ -254,12 +454,39 @@ impl RawNode<Follower> {
         self.assert()?;
 
         self.role.leader_seen += 1;
+        if let Some(seen) = self.role.applied_heartbeat.as_mut() {
+            *seen += 1;
+        }
+        if let Some((_, seen)) = self.role.pending_heartbeat.as_mut() {
+            *seen += 1;
+        }
         if self.role.leader_seen >= self.role.election_timeout {
-            return Ok(self.into_candidate()?.into());
+            // Nodes that have been removed from the cluster don't campaign.
+            if !self.is_voter() {
+                self.role.leader_seen = 0;
+                return Ok(self.into());
+            }
+            return Ok(self.into_prevote_candidate()?.into());
         }
         Ok(self.into())
     }
 
+    /// Returns the number of ticks since we received the latest leader
+    /// heartbeat whose commit index we've applied, if any. A pending heartbeat
+    /// that we've since caught up with counts as applied, although newer
+    /// heartbeats received while it was pending are not tracked, so this may
+    /// overestimate the staleness until the next heartbeat.
+    fn applied_heartbeat(&mut self) -> Option<Ticks> {
+        if let Some((index, seen)) = self.role.pending_heartbeat {
+            if index <= self.state.get_applied_index() {
+                self.role.applied_heartbeat =
+                    Some(self.role.applied_heartbeat.map_or(seen, |s| s.min(seen)));
+                self.role.pending_heartbeat = None;
+            }
+        }
+        self.role.applied_heartbeat
+    }
+
     /// Aborts all forwarded requests.
     fn abort_forwarded(&mut self) -> Result<()> {
         for id in std::mem::take(&mut self.role.forwarded) {
@@ -278,9 +505,10 @@ impl RawNode<Follower> {
 #[cfg(test)]
 pub mod tests {
     use super::super::super::state::tests::TestState;
-    use super::super::super::{Entry, Log, Request, Response};
+    use super::super::super::{Entry, Log, Membership, Request, Response};
     use super::super::tests::{assert_messages, assert_node};
     use super::*;
+    use crate::encoding::bincode;
     use crate::error::Error;
     use crate::storage;
     use itertools::Itertools as _;
@@ -299,10 +527,12 @@ pub mod tests {
         let node = RawNode {
             id: 1,
             peers: HashSet::from([2, 3, 4, 5]),
+            learners: HashSet::new(),
             term: 3,
             log,
             state,
             node_tx,
+            opts: Options::default(),
             role: Follower::new(Some(2), None),
         };
         Ok((node, node_rx))
@@ -619,10 +849,12 @@ pub mod tests {
         let follower = RawNode {
             id: 1,
             peers: HashSet::from([2, 3, 4, 5]),
+            learners: HashSet::new(),
             term: 1,
             log,
             state: Box::new(TestState::new(0)),
             node_tx,
+            opts: Options::default(),
             role: Follower::new(Some(2), None),
         };
 
@@ -634,14 +866,14 @@ pub mod tests {
                 base_index: 0,
                 base_term: 0,
                 entries: vec![
-                    Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-                    Entry { index: 2, term: 1, command: Some(vec![0x02]) },
+                    Entry { index: 1, term: 1, command: Some(vec![0x01]), membership: None },
+                    Entry { index: 2, term: 1, command: Some(vec![0x02]), membership: None },
                 ],
             },
         })?;
         assert_node(&mut node).is_follower().term(3).entries(vec![
-            Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-            Entry { index: 2, term: 1, command: Some(vec![0x02]) },
+            Entry { index: 1, term: 1, command: Some(vec![0x01]), membership: None },
+            Entry { index: 2, term: 1, command: Some(vec![0x02]), membership: None },
         ]);
         assert_messages(
             &mut node_rx,
@@ -667,17 +899,17 @@ pub mod tests {
                 base_index: 3,
                 base_term: 2,
                 entries: vec![
-                    Entry { index: 4, term: 3, command: Some(vec![0x04]) },
-                    Entry { index: 5, term: 3, command: Some(vec![0x05]) },
+                    Entry { index: 4, term: 3, command: Some(vec![0x04]), membership: None },
+                    Entry { index: 5, term: 3, command: Some(vec![0x05]), membership: None },
                 ],
             },
         })?;
         assert_node(&mut node).is_follower().term(3).entries(vec![
-            Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-            Entry { index: 2, term: 1, command: Some(vec![0x02]) },
-            Entry { index: 3, term: 2, command: Some(vec![0x03]) },
-            Entry { index: 4, term: 3, command: Some(vec![0x04]) },
-            Entry { index: 5, term: 3, command: Some(vec![0x05]) },
+            Entry { index: 1, term: 1, command: Some(vec![0x01]), membership: None },
+            Entry { index: 2, term: 1, command: Some(vec![0x02]), membership: None },
+            Entry { index: 3, term: 2, command: Some(vec![0x03]), membership: None },
+            Entry { index: 4, term: 3, command: Some(vec![0x04]), membership: None },
+            Entry { index: 5, term: 3, command: Some(vec![0x05]), membership: None },
         ]);
         assert_messages(
             &mut node_rx,
@@ -703,16 +935,16 @@ pub mod tests {
                 base_index: 1,
                 base_term: 1,
                 entries: vec![
-                    Entry { index: 3, term: 2, command: Some(vec![0x03]) },
-                    Entry { index: 4, term: 3, command: Some(vec![0x04]) },
+                    Entry { index: 3, term: 2, command: Some(vec![0x03]), membership: None },
+                    Entry { index: 4, term: 3, command: Some(vec![0x04]), membership: None },
                 ],
             },
         })?;
         assert_node(&mut node).is_follower().term(3).entries(vec![
-            Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-            Entry { index: 2, term: 1, command: Some(vec![0x02]) },
-            Entry { index: 3, term: 2, command: Some(vec![0x03]) },
-            Entry { index: 4, term: 3, command: Some(vec![0x04]) },
+            Entry { index: 1, term: 1, command: Some(vec![0x01]), membership: None },
+            Entry { index: 2, term: 1, command: Some(vec![0x02]), membership: None },
+            Entry { index: 3, term: 2, command: Some(vec![0x03]), membership: None },
+            Entry { index: 4, term: 3, command: Some(vec![0x04]), membership: None },
         ]);
         assert_messages(
             &mut node_rx,
@@ -738,16 +970,16 @@ pub mod tests {
                 base_index: 2,
                 base_term: 1,
                 entries: vec![
-                    Entry { index: 3, term: 3, command: Some(vec![0x04]) },
-                    Entry { index: 4, term: 3, command: Some(vec![0x05]) },
+                    Entry { index: 3, term: 3, command: Some(vec![0x04]), membership: None },
+                    Entry { index: 4, term: 3, command: Some(vec![0x05]), membership: None },
                 ],
             },
         })?;
         assert_node(&mut node).is_follower().term(3).entries(vec![
-            Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-            Entry { index: 2, term: 1, command: Some(vec![0x02]) },
-            Entry { index: 3, term: 3, command: Some(vec![0x04]) },
-            Entry { index: 4, term: 3, command: Some(vec![0x05]) },
+            Entry { index: 1, term: 1, command: Some(vec![0x01]), membership: None },
+            Entry { index: 2, term: 1, command: Some(vec![0x02]), membership: None },
+            Entry { index: 3, term: 3, command: Some(vec![0x04]), membership: None },
+            Entry { index: 4, term: 3, command: Some(vec![0x05]), membership: None },
         ]);
         assert_messages(
             &mut node_rx,
@@ -773,16 +1005,16 @@ pub mod tests {
                 base_index: 2,
                 base_term: 1,
                 entries: vec![
-                    Entry { index: 3, term: 2, command: Some(vec![0x03]) },
-                    Entry { index: 4, term: 3, command: Some(vec![0x04]) },
+                    Entry { index: 3, term: 2, command: Some(vec![0x03]), membership: None },
+                    Entry { index: 4, term: 3, command: Some(vec![0x04]), membership: None },
                 ],
             },
         })?;
         assert_node(&mut node).is_follower().term(3).entries(vec![
-            Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-            Entry { index: 2, term: 1, command: Some(vec![0x02]) },
-            Entry { index: 3, term: 2, command: Some(vec![0x03]) },
-            Entry { index: 4, term: 3, command: Some(vec![0x04]) },
+            Entry { index: 1, term: 1, command: Some(vec![0x01]), membership: None },
+            Entry { index: 2, term: 1, command: Some(vec![0x02]), membership: None },
+            Entry { index: 3, term: 2, command: Some(vec![0x03]), membership: None },
+            Entry { index: 4, term: 3, command: Some(vec![0x04]), membership: None },
         ]);
         assert_messages(
             &mut node_rx,
@@ -807,13 +1039,18 @@ pub mod tests {
             message: Message::Append {
                 base_index: 5,
                 base_term: 2,
-                entries: vec![Entry { index: 6, term: 3, command: Some(vec![0x04]) }],
+                entries: vec![Entry {
+                    index: 6,
+                    term: 3,
+                    command: Some(vec![0x04]),
+                    membership: None,
+                }],
             },
         })?;
         assert_node(&mut node).is_follower().term(3).entries(vec![
-            Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-            Entry { index: 2, term: 1, command: Some(vec![0x02]) },
-            Entry { index: 3, term: 2, command: Some(vec![0x03]) },
+            Entry { index: 1, term: 1, command: Some(vec![0x01]), membership: None },
+            Entry { index: 2, term: 1, command: Some(vec![0x02]), membership: None },
+            Entry { index: 3, term: 2, command: Some(vec![0x03]), membership: None },
         ]);
         assert_messages(
             &mut node_rx,
@@ -838,13 +1075,18 @@ pub mod tests {
             message: Message::Append {
                 base_index: 1,
                 base_term: 2,
-                entries: vec![Entry { index: 2, term: 3, command: Some(vec![0x04]) }],
+                entries: vec![Entry {
+                    index: 2,
+                    term: 3,
+                    command: Some(vec![0x04]),
+                    membership: None,
+                }],
             },
         })?;
         assert_node(&mut node).is_follower().term(3).entries(vec![
-            Entry { index: 1, term: 1, command: Some(vec![0x01]) },
-            Entry { index: 2, term: 1, command: Some(vec![0x02]) },
-            Entry { index: 3, term: 2, command: Some(vec![0x03]) },
+            Entry { index: 1, term: 1, command: Some(vec![0x01]), membership: None },
+            Entry { index: 2, term: 1, command: Some(vec![0x02]), membership: None },
+            Entry { index: 3, term: 2, command: Some(vec![0x03]), membership: None },
         ]);
         assert_messages(
             &mut node_rx,
@@ -852,7 +1094,229 @@ pub mod tests {
                 from: 1,
                 to: 2,
                 term: 3,
-                message: Message::AppendResponse { reject: true, last_index: 3, last_term: 2 },
+                message: Message::AppendResponse { reject: true, last_index: 0, last_term: 0 },
+            }],
+        );
+        Ok(())
+    }
+
+    #[test]
+    // InstallSnapshot buffers chunks, and installs the snapshot once done.
+    fn step_installsnapshot() -> Result<()> {
+        let (follower, mut node_rx) = setup()?;
+        let mut node: Node = follower.into();
+        let data = bincode::serialize(&vec![vec![0x01], vec![0x02], vec![0x03], vec![0x04]])?;
+        let (head, tail) = data.split_at(4);
+
+        // The first chunk is buffered and acknowledged.
+        node = node.step(Envelope {
+            from: 2,
+            to: 1,
+            term: 3,
+            message: Message::InstallSnapshot {
+                index: 5,
+                term: 3,
+                membership: None,
+                offset: 0,
+                data: head.to_vec(),
+                done: false,
+            },
+        })?;
+        assert_node(&mut node).is_follower().term(3).leader(Some(2)).committed(2).last(3);
+        assert_messages(
+            &mut node_rx,
+            vec![Envelope {
+                from: 1,
+                to: 2,
+                term: 3,
+                message: Message::InstallSnapshotResponse { index: 5, offset: 4 },
+            }],
+        );
+
+        // A chunk beyond the buffered data asks for the missing data.
+        node = node.step(Envelope {
+            from: 2,
+            to: 1,
+            term: 3,
+            message: Message::InstallSnapshot {
+                index: 5,
+                term: 3,
+                membership: None,
+                offset: 20,
+                data: vec![0xff],
+                done: true,
+            },
+        })?;
+        assert_node(&mut node).committed(2).last(3);
+        assert_messages(
+            &mut node_rx,
+            vec![Envelope {
+                from: 1,
+                to: 2,
+                term: 3,
+                message: Message::InstallSnapshotResponse { index: 5, offset: 4 },
+            }],
+        );
+
+        // The final chunk installs the snapshot, replacing the log.
+        node = node.step(Envelope {
+            from: 2,
+            to: 1,
+            term: 3,
+            message: Message::InstallSnapshot {
+                index: 5,
+                term: 3,
+                membership: None,
+                offset: 4,
+                data: tail.to_vec(),
+                done: true,
+            },
+        })?;
+        assert_node(&mut node)
+            .is_follower()
+            .term(3)
+            .leader(Some(2))
+            .committed(5)
+            .applied(5)
+            .snapshot(5)
+            .last(5)
+            .entries(vec![]);
+        assert_messages(
+            &mut node_rx,
+            vec![Envelope {
+                from: 1,
+                to: 2,
+                term: 3,
+                message: Message::AppendResponse { reject: false, last_index: 5, last_term: 3 },
+            }],
+        );
+        let Node::Follower(follower) = &node else { panic!("expected follower") };
+        assert_eq!(follower.state.snapshot()?, data);
+        Ok(())
+    }
+
+    #[test]
+    // A crash while installing a snapshot is recovered on restart: the
+    // installation is finished if the state machine was restored, otherwise
+    // the snapshot is discarded.
+    fn new_pending_snapshot() -> Result<()> {
+        let data = bincode::serialize(&vec![vec![0x01], vec![0x02], vec![0x03], vec![0x04]])?;
+
+        // The state machine was restored, but the log wasn't reset.
+        let (follower, _node_rx) = setup()?;
+        let RawNode { mut log, mut state, node_tx, .. } = follower;
+        log.begin_snapshot(5, 3, None)?;
+        state.restore(5, data.clone())?;
+        let mut node: Node =
+            RawNode::new(1, HashSet::from([2, 3]), log, state, node_tx, Options::default())?.into();
+        assert_node(&mut node).is_follower().committed(5).applied(5).snapshot(5).last(5);
+        let Node::Follower(follower) = &mut node else { panic!("expected follower") };
+        assert_eq!(follower.log.get_pending_snapshot()?, None);
+        assert_eq!(follower.state.snapshot()?, data);
+
+        // The state machine wasn't restored.
+        let (follower, _node_rx) = setup()?;
+        let RawNode { mut log, state, node_tx, .. } = follower;
+        log.begin_snapshot(5, 3, None)?;
+        let mut node: Node =
+            RawNode::new(1, HashSet::from([2, 3]), log, state, node_tx, Options::default())?.into();
+        assert_node(&mut node).is_follower().committed(2).applied(0).snapshot(0).last(3);
+        let Node::Follower(follower) = &mut node else { panic!("expected follower") };
+        assert_eq!(follower.log.get_pending_snapshot()?, None);
+        Ok(())
+    }
+
+    #[test]
+    // InstallSnapshot at or below the commit index is ignored.
+    fn step_installsnapshot_committed() -> Result<()> {
+        let (follower, mut node_rx) = setup()?;
+        let mut node = follower.step(Envelope {
+            from: 2,
+            to: 1,
+            term: 3,
+            message: Message::InstallSnapshot {
+                index: 2,
+                term: 1,
+                membership: None,
+                offset: 0,
+                data: vec![0x01],
+                done: true,
+            },
+        })?;
+        assert_node(&mut node).is_follower().term(3).committed(2).applied(0).snapshot(0).last(3);
+        assert_messages(
+            &mut node_rx,
+            vec![Envelope {
+                from: 1,
+                to: 2,
+                term: 3,
+                message: Message::AppendResponse { reject: false, last_index: 2, last_term: 1 },
+            }],
+        );
+        Ok(())
+    }
+
+    #[test]
+    // Membership changes in appended entries take effect immediately, and
+    // messages from unknown senders are dropped.
+    fn step_appendentries_membership() -> Result<()> {
+        let (follower, mut node_rx) = setup()?;
+        let mut node: Node = follower.into();
+
+        node = node.step(Envelope {
+            from: 6,
+            to: 1,
+            term: 4,
+            message: Message::Campaign { last_index: 3, last_term: 2 },
+        })?;
+        assert_node(&mut node).is_follower().term(3).leader(Some(2));
+        assert_messages(&mut node_rx, vec![]);
+
+        let membership = Membership {
+            voters: [1, 2, 3, 4, 5, 6].into(),
+            learners: [].into(),
+            addrs: [(6, "addr6".to_string())].into(),
+        };
+        node = node.step(Envelope {
+            from: 2,
+            to: 1,
+            term: 3,
+            message: Message::Append {
+                base_index: 3,
+                base_term: 2,
+                entries: vec![Entry {
+                    index: 4,
+                    term: 3,
+                    command: None,
+                    membership: Some(membership.clone()),
+                }],
+            },
+        })?;
+        assert_eq!(node.membership(), Some(&membership));
+        assert_messages(
+            &mut node_rx,
+            vec![Envelope {
+                from: 1,
+                to: 2,
+                term: 3,
+                message: Message::AppendResponse { reject: false, last_index: 4, last_term: 3 },
+            }],
+        );
+
+        node = node.step(Envelope {
+            from: 6,
+            to: 1,
+            term: 4,
+            message: Message::Campaign { last_index: 3, last_term: 2 },
+        })?;
+        assert_node(&mut node).is_follower().term(4).leader(None).voted_for(None);
+        assert_messages(
+            &mut node_rx,
+            vec![Envelope {
+                from: 1,
+                to: 6,
+                term: 4,
+                message: Message::CampaignResponse { vote: false },
             }],
         );
         Ok(())
@@ -935,6 +1399,95 @@ pub mod tests {
         Ok(())
     }
 
+    #[test]
+    // ReadLocal is served locally if we've applied the commit index of a leader
+    // heartbeat received within max_staleness, and aborted otherwise. Unbounded
+    // reads are always served.
+    fn step_clientrequest_read_local() -> Result<()> {
+        let (follower, mut node_rx) = setup()?;
+        let mut node = Node::Follower(follower);
+        let read_local = |max_staleness| Envelope {
+            from: 1,
+            to: 1,
+            term: 3,
+            message: Message::ClientRequest {
+                id: vec![0x01],
+                request: Request::ReadLocal { command: vec![0xaf], max_staleness },
+            },
+        };
+        let response = |response| Envelope {
+            from: 1,
+            to: 1,
+            term: 3,
+            message: Message::ClientResponse { id: vec![0x01], response },
+        };
+        let heartbeat = |commit_index, commit_term| Envelope {
+            from: 2,
+            to: 1,
+            term: 3,
+            message: Message::Heartbeat { commit_index, commit_term, read_seq: 7 },
+        };
+
+        // Bounded reads are aborted until we've had a heartbeat.
+        node = node.step(read_local(Some(2)))?;
+        assert_messages(&mut node_rx, vec![response(Err(Error::Abort))]);
+
+        node = node.step(heartbeat(3, 2))?;
+        node_rx.try_iter().for_each(drop);
+        node = node.step(read_local(Some(2)))?;
+        assert_node(&mut node).is_follower().term(3).leader(Some(2)).forwarded(vec![]);
+        assert_messages(&mut node_rx, vec![response(Ok(Response::Read(vec![0xaf])))]);
+
+        for _ in 0..3 {
+            node = node.tick()?;
+        }
+        node = node.step(read_local(Some(2)))?;
+        assert_messages(&mut node_rx, vec![response(Err(Error::Abort))]);
+
+        node = node.step(read_local(None))?;
+        assert_messages(&mut node_rx, vec![response(Ok(Response::Read(vec![0xaf])))]);
+
+        // A heartbeat with a commit index we haven't applied doesn't help, even
+        // though we've just heard from the leader.
+        node = node.step(heartbeat(5, 3))?;
+        node_rx.try_iter().for_each(drop);
+        node = node.step(read_local(Some(2)))?;
+        assert_messages(&mut node_rx, vec![response(Err(Error::Abort))]);
+        node = node.step(read_local(Some(3)))?;
+        assert_messages(&mut node_rx, vec![response(Ok(Response::Read(vec![0xaf])))]);
+
+        // Once we catch up, reads are served again.
+        node = node.step(Envelope {
+            from: 2,
+            to: 1,
+            term: 3,
+            message: Message::Append {
+                base_index: 3,
+                base_term: 2,
+                entries: vec![
+                    Entry { index: 4, term: 3, command: Some(vec![0x04]), membership: None },
+                    Entry { index: 5, term: 3, command: Some(vec![0x05]), membership: None },
+                ],
+            },
+        })?;
+        node = node.step(heartbeat(5, 3))?;
+        node_rx.try_iter().for_each(drop);
+        node = node.step(read_local(Some(0)))?;
+        assert_node(&mut node).applied(5);
+        assert_messages(&mut node_rx, vec![response(Ok(Response::Read(vec![0xaf])))]);
+
+        // Without a leader, bounded reads are aborted.
+        let Node::Follower(mut follower) = node else { panic!("expected follower") };
+        follower.role = Follower::new(None, None);
+        node = Node::Follower(follower);
+        node = node.step(read_local(Some(10)))?;
+        assert_messages(&mut node_rx, vec![response(Err(Error::Abort))]);
+        node = node.step(read_local(None))?;
+        assert_node(&mut node).is_follower().leader(None);
+        assert_messages(&mut node_rx, vec![response(Ok(Response::Read(vec![0xaf])))]);
+        Ok(())
+    }
+
     // ClientRequest is forwarded, but aborted when a new leader appears.
     #[test]
     fn step_clientrequest_aborted() -> Result<()> {
@@ -1033,7 +1586,7 @@ pub mod tests {
             assert_node(&mut node).is_follower().term(3).leader(Some(2));
             node = node.tick()?;
         }
-        assert_node(&mut node).is_candidate().term(4);
+        assert_node(&mut node).is_candidate().term(3);
 
         for to in peers.iter().copied().sorted() {
             assert_eq!(
@@ -1042,10 +1595,170 @@ pub mod tests {
                     from: 1,
                     to,
                     term: 4,
-                    message: Message::Campaign { last_index: 3, last_term: 2 },
+                    message: Message::PreVote { last_index: 3, last_term: 2 },
                 },
             );
         }
         Ok(())
     }
+
+    #[test]
+    // Pre-votes are only granted if we don't have a leader and the candidate's
+    // log is up-to-date, and don't change our term or vote.
+    fn step_prevote() -> Result<()> {
+        let (follower, mut node_rx) = setup()?;
+        let mut node: Node = follower.into();
+        let prevote = |from, term, last_index, last_term| Envelope {
+            from,
+            to: 1,
+            term,
+            message: Message::PreVote { last_index, last_term },
+        };
+        let response = |to, term, vote| Envelope {
+            from: 1,
+            to,
+            term,
+            message: Message::PreVoteResponse { vote },
+        };
+
+        // We have a leader, so reject.
+        node = node.step(prevote(3, 4, 3, 2))?;
+        assert_node(&mut node).is_follower().term(3).leader(Some(2)).voted_for(None);
+        assert_messages(&mut node_rx, vec![response(3, 3, false)]);
+
+        // Once leaderless, we grant pre-votes for up-to-date logs only.
+        let Node::Follower(follower) = &mut node else { panic!("expected follower") };
+        follower.role.leader = None;
+        node = node.step(prevote(3, 4, 2, 2))?;
+        node = node.step(prevote(3, 4, 5, 1))?;
+        node = node.step(prevote(3, 3, 3, 2))?;
+        node = node.step(prevote(3, 4, 3, 2))?;
+        node = node.step(prevote(4, 5, 4, 2))?;
+        assert_node(&mut node).is_follower().term(3).leader(None).voted_for(None);
+        assert_messages(
+            &mut node_rx,
+            vec![
+                response(3, 3, false),
+                response(3, 3, false),
+                response(3, 3, false),
+                response(3, 4, true),
+                response(4, 5, true),
+            ],
+        );
+
+        // Granted pre-votes for a later term are ignored, without changing
+        // our term.
+        node = node.step(Envelope {
+            from: 4,
+            to: 1,
+            term: 5,
+            message: Message::PreVoteResponse { vote: true },
+        })?;
+        assert_node(&mut node).is_follower().term(3);
+        assert_messages(&mut node_rx, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    // Nodes removed from the cluster don't campaign.
+    fn tick_removed() -> Result<()> {
+        let (mut follower, mut node_rx) = setup()?;
+        follower.log.append_membership(
+            3,
+            Membership {
+                voters: [2, 3, 4, 5].into(),
+                learners: Default::default(),
+                addrs: Default::default(),
+            },
+        )?;
+        follower.update_peers();
+        let timeout = follower.role.election_timeout;
+        let mut node = Node::Follower(follower);
+
+        for _ in 0..(3 * timeout) {
+            node = node.tick()?;
+            assert_node(&mut node).is_follower().term(3);
+        }
+        assert_messages(&mut node_rx, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    // TimeoutNow from the leader makes us campaign immediately.
+    fn step_timeoutnow() -> Result<()> {
+        let (follower, mut node_rx) = setup()?;
+        let peers = follower.peers.clone();
+        let mut node: Node = follower.into();
+
+        node = node.step(Envelope { from: 2, to: 1, term: 3, message: Message::TimeoutNow })?;
+        assert_node(&mut node).is_candidate().term(4);
+        let mut expect = Vec::new();
+        for to in peers.iter().copied().sorted() {
+            expect.push(Envelope {
+                from: 1,
+                to,
+                term: 4,
+                message: Message::Campaign { last_index: 3, last_term: 2 },
+            });
+        }
+        assert_messages(&mut node_rx, expect);
+        Ok(())
+    }
+
+    #[test]
+    // TimeoutNow is ignored if we don't know the leader, e.g. after a restart
+    // in the same term, or if it comes with a new term.
+    fn step_timeoutnow_unknown_leader() -> Result<()> {
+        let (mut follower, mut node_rx) = setup()?;
+        follower.role = Follower::new(None, None);
+        let mut node: Node = follower.into();
+
+        node = node.step(Envelope { from: 2, to: 1, term: 3, message: Message::TimeoutNow })?;
+        assert_node(&mut node).is_follower().term(3).leader(None);
+        node = node.step(Envelope { from: 3, to: 1, term: 4, message: Message::TimeoutNow })?;
+        assert_node(&mut node).is_follower().term(4).leader(None);
+        assert_messages(&mut node_rx, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    // Learners neither campaign nor vote.
+    fn learner() -> Result<()> {
+        let (mut follower, mut node_rx) = setup()?;
+        follower.log.append_membership(
+            3,
+            Membership {
+                voters: [2, 3, 4, 5].into(),
+                learners: [1].into(),
+                addrs: Default::default(),
+            },
+        )?;
+        follower.update_peers();
+        let timeout = follower.role.election_timeout;
+        let mut node = Node::Follower(follower);
+
+        for _ in 0..(3 * timeout) {
+            node = node.tick()?;
+            assert_node(&mut node).is_follower().term(3);
+        }
+        assert_messages(&mut node_rx, vec![]);
+
+        node = node.step(Envelope {
+            from: 3,
+            to: 1,
+            term: 4,
+            message: Message::Campaign { last_index: 4, last_term: 3 },
+        })?;
+        assert_node(&mut node).is_follower().term(4).voted_for(None);
+        assert_messages(
+            &mut node_rx,
+            vec![Envelope {
+                from: 1,
+                to: 3,
+                term: 4,
+                message: Message::CampaignResponse { vote: false },
+            }],
+        );
+        Ok(())
+    }
 }
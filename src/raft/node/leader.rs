@@ -1,10 +1,12 @@
 use super::super::{
-    Envelope, Index, Message, ReadSequence, Request, RequestID, Response, Status,
-    HEARTBEAT_INTERVAL,
+    Envelope, Index, Membership, MembershipChange, Message, ReadSequence, Request, RequestID,
+    Response, Status, ELECTION_TIMEOUT_RANGE, HEARTBEAT_INTERVAL, LEASE_CLOCK_DRIFT,
+    SNAPSHOT_CHUNK_SIZE,
 };
-use super::{Follower, Node, NodeID, RawNode, Role, Term, Ticks};
+use super::{Follower, Node, NodeID, RawNode, Role, Snapshot, Term, Ticks};
 use crate::error::{Error, Result};
 
+use itertools::Itertools as _;
 use log::{debug, info};
 use std::collections::{HashMap, HashSet, VecDeque};
 
@@ -17,6 +19,38 @@ struct Progress {
     last: Index,
     /// The last read sequence number confirmed by the peer.
     read_seq: ReadSequence,
+    /// An in-flight snapshot transfer to the peer, if any, as the snapshot
+    /// index and the byte offset of the next chunk to send.
+    snapshot: Option<(Index, u64)>,
+    /// Whether we've heard from the peer since the last quorum check.
+    active: bool,
+    /// The last index and send time (leader clock) of in-flight Append
+    /// messages that haven't been acknowledged yet, oldest first. At most
+    /// Options.max_inflight appends are in flight, and new entries are batched
+    /// up until the peer acknowledges one.
+    inflight: VecDeque<(Index, u64)>,
+}
+
+impl Progress {
+    /// Creates a new progress, replicating from the given index.
+    fn new(next: Index) -> Self {
+        Self {
+            next,
+            last: 0,
+            read_seq: 0,
+            snapshot: None,
+            active: false,
+            inflight: VecDeque::new(),
+        }
+    }
+
+    /// Resumes replication from the given index, or the index after the last
+    /// acknowledged entry if later, and drops any in-flight appends. Used when
+    /// the peer rejects an append, or in-flight appends may have been lost.
+    fn rewind(&mut self, next: Index) {
+        self.next = std::cmp::max(next, self.last + 1);
+        self.inflight.clear();
+    }
 }
 
 /// A pending client write request.
@@ -41,6 +75,19 @@ struct Read {
     command: Vec<u8>,
 }
 
+/// An in-progress leadership transfer.
+#[derive(Clone, Debug, PartialEq)]
+struct Transfer {
+    /// The node to transfer leadership to.
+    target: NodeID,
+    /// Number of ticks since the transfer started. It's cancelled if the
+    /// target hasn't taken over within an election timeout.
+    ticks: Ticks,
+    /// The node and request ID of the transfer request. Taken once the
+    /// target has been told to campaign and the client has been responded to.
+    request: Option<(NodeID, RequestID)>,
+}
+
 // A leader serves requests and replicates the log to followers.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Leader {
@@ -68,20 +115,45 @@ pub struct Leader {
     read_seq: ReadSequence,
     /// Number of ticks since last periodic heartbeat.
     since_heartbeat: Ticks,
+    /// Number of ticks since the last quorum check.
+    since_quorum_check: Ticks,
+    /// Number of ticks since we became leader. Used as the clock for leases
+    /// and in-flight appends.
+    clock: u64,
+    /// The clock time at which the read lease expires. Only used with
+    /// lease reads.
+    lease: u64,
+    /// The read sequence numbers of heartbeats that haven't been confirmed by
+    /// a quorum yet, and the clock time they were sent. Only used with lease
+    /// reads, where every heartbeat has a new read sequence number.
+    heartbeats: VecDeque<(ReadSequence, u64)>,
+    /// A state machine snapshot, taken when a peer needs entries that have
+    /// been compacted out of the log. Shared by all snapshot transfers, and
+    /// kept until the log is compacted past it, so that peers that fall behind
+    /// later reuse it instead of taking a new snapshot each.
+    snapshot: Option<Snapshot>,
+    /// An in-progress leadership transfer, if any. New writes are aborted
+    /// while it's in progress, so that the target can catch up.
+    transfer: Option<Transfer>,
 }
 
 impl Leader {
     /// Creates a new leader role.
     pub fn new(peers: HashSet<NodeID>, last_index: Index) -> Self {
         let next = last_index + 1;
-        let progress =
-            peers.into_iter().map(|p| (p, Progress { next, last: 0, read_seq: 0 })).collect();
+        let progress = peers.into_iter().map(|p| (p, Progress::new(next))).collect();
         Self {
             progress,
             writes: HashMap::new(),
             reads: VecDeque::new(),
             read_seq: 0,
             since_heartbeat: 0,
+            since_quorum_check: 0,
+            clock: 0,
+            lease: 0,
+            heartbeats: VecDeque::new(),
+            snapshot: None,
+            transfer: None,
         }
     }
 }
@@ -89,6 +161,9 @@ impl Leader {
 impl Role for Leader {}
 
 impl RawNode<Leader> {
+    /// The duration of a read lease, in ticks.
+    const LEASE_DURATION: u64 = (ELECTION_TIMEOUT_RANGE.start - LEASE_CLOCK_DRIFT) as u64;
+
     /// Asserts internal invariants.
     fn assert(&mut self) -> Result<()> {
         self.assert_node()?;
@@ -99,14 +174,12 @@ impl RawNode<Leader> {
         Ok(())
     }
 
-    /// Transitions the leader into a follower. This can only happen if we
-    /// discover a new term, so we become a leaderless follower. Subsequently
-    /// stepping the received message may discover the leader, if there is one.
+    /// Transitions the leader into a leaderless follower. This happens if we
+    /// discover a new term, in which case subsequently stepping the received
+    /// message may discover the leader, if there is one. It also happens in
+    /// the current term if we lose contact with a quorum (see check_quorum).
     pub(super) fn into_follower(mut self, term: Term) -> Result<RawNode<Follower>> {
         assert!(term >= self.term, "Term regression {} -> {}", self.term, term);
-        assert!(term > self.term, "Can only become follower in later term");
-
-        info!("Discovered new term {}", term);
 
         // Cancel in-flight requests.
         for write in std::mem::take(&mut self.role.writes).into_values() {
@@ -121,7 +194,15 @@ impl RawNode<Leader> {
                 Message::ClientResponse { id: read.id, response: Err(Error::Abort) },
             )?;
         }
+        if let Some((from, id)) = self.role.transfer.take().and_then(|t| t.request) {
+            self.send(from, Message::ClientResponse { id, response: Err(Error::Abort) })?;
+        }
 
+        if term == self.term {
+            let voted_for = Some(self.id); // by definition
+            return Ok(self.into_role(Follower::new(None, voted_for)));
+        }
+        info!("Discovered new term {}", term);
         self.term = term;
         self.log.set_term(term, None)?;
         Ok(self.into_role(Follower::new(None, None)))
@@ -131,6 +212,25 @@ impl RawNode<Leader> {
     pub fn step(mut self, msg: Envelope) -> Result<Node> {
         self.assert()?;
         self.assert_step(&msg);
+        if !self.is_known_sender(&msg) {
+            return Ok(self.into());
+        }
+
+        // Pre-votes are for the next term, and don't change our term. We're
+        // the leader, so we never grant them, and ignore granted pre-votes.
+        match msg.message {
+            Message::PreVote { .. } => {
+                self.step_prevote(&msg, true)?;
+                return Ok(self.into());
+            }
+            Message::PreVoteResponse { vote: true } => return Ok(self.into()),
+            _ => {}
+        }
+
+        // Record that the peer is alive, for quorum checks.
+        if let Some(progress) = self.role.progress.get_mut(&msg.from) {
+            progress.active = true;
+        }
 
         // Drop messages from past terms.
         if msg.term < self.term {
@@ -147,10 +247,15 @@ impl RawNode<Leader> {
 
         match msg.message {
             // There can't be two leaders in the same term.
-            Message::Heartbeat { .. } | Message::Append { .. } => {
+            Message::Heartbeat { .. }
+            | Message::Append { .. }
+            | Message::InstallSnapshot { .. }
+            | Message::TimeoutNow => {
                 panic!("Saw other leader {} in term {}", msg.from, msg.term);
             }
 
+            Message::PreVote { .. } => panic!("Unexpected message {:?}", msg),
+
             // A follower received one of our heartbeats and confirms that we
             // are its leader. If its log is incomplete, append entries. If the
             // peer's read sequence number increased, process any pending reads.
@@ -160,13 +265,28 @@ impl RawNode<Leader> {
                 let progress = self.role.progress.get_mut(&msg.from).unwrap();
                 if read_seq > progress.read_seq {
                     progress.read_seq = read_seq;
+                    self.maybe_extend_lease();
                     self.maybe_read()?;
                 }
 
+                // If the peer's log is incomplete and no appends are in
+                // flight, send it the missing entries. If the oldest in-flight
+                // append was sent more than a heartbeat interval ago, it may
+                // have been lost (e.g. dropped by a full peer channel), so
+                // resend from the peer's last index.
                 if last_index < self.log.get_last_index().0
                     || !self.log.has(last_index, last_term)?
                 {
-                    self.send_log(msg.from)?;
+                    let clock = self.role.clock;
+                    let progress = self.role.progress.get_mut(&msg.from).unwrap();
+                    match progress.inflight.front() {
+                        None => self.send_log(msg.from)?,
+                        Some((_, sent)) if sent + HEARTBEAT_INTERVAL as u64 <= clock => {
+                            progress.rewind(std::cmp::min(progress.next, last_index + 1));
+                            self.send_log(msg.from)?;
+                        }
+                        Some(_) => {}
+                    }
                 }
             }
 
@@ -183,35 +303,88 @@ impl RawNode<Leader> {
                 );
 
                 let progress = self.role.progress.get_mut(&msg.from).unwrap();
+                while progress.inflight.front().is_some_and(|(index, _)| *index <= last_index) {
+                    progress.inflight.pop_front();
+                }
                 if last_index > progress.last {
                     progress.last = last_index;
-                    progress.next = last_index + 1;
+                    progress.next = std::cmp::max(progress.next, last_index + 1);
                     self.maybe_commit_and_apply()?;
+                    self.maybe_transfer()?;
+                }
+
+                // If this completed a snapshot transfer, resume replication
+                // from the snapshot.
+                self.role.progress.get_mut(&msg.from).unwrap().snapshot = None;
+
+                // Send any entries that were batched up while the in-flight
+                // window was full, or that followed the snapshot.
+                let progress = &self.role.progress[&msg.from];
+                if progress.snapshot.is_none() && progress.next <= self.log.get_last_index().0 {
+                    self.send_log(msg.from)?;
+                }
+            }
+
+            // A follower received a snapshot chunk we sent it. Send the next.
+            Message::InstallSnapshotResponse { index, offset } => {
+                let progress = self.role.progress.get_mut(&msg.from).unwrap();
+                match progress.snapshot {
+                    Some((i, _)) if i == index => progress.snapshot = Some((index, offset)),
+                    _ => return Ok(self.into()), // stale response
                 }
+                self.send_snapshot(msg.from)?;
             }
 
             // A follower rejected log entries we sent it, typically because it
-            // does not have the base index in its log. Try to replicate from
-            // the previous entry.
+            // does not have the base index in its log. It responds with the
+            // last entry that may match our log (at most the one before the
+            // base), so try to replicate from there, dropping any other
+            // in-flight appends (which will be rejected too). Rejections of
+            // earlier in-flight appends may rewind us further than necessary,
+            // but that's harmless.
             //
             // This linear probing, as described in the Raft paper, can be very
             // slow with long divergent logs, but we keep it simple.
             //
-            // TODO: make use of last_index and last_term here.
-            Message::AppendResponse { reject: true, last_index: _, last_term: _ } => {
-                self.role.progress.entry(msg.from).and_modify(|p| {
-                    if p.next > 1 {
-                        p.next -= 1
-                    }
-                });
+            // TODO: make use of last_term here.
+            Message::AppendResponse { reject: true, last_index, last_term: _ } => {
+                let progress = self.role.progress.get_mut(&msg.from).unwrap();
+                progress.rewind(last_index + 1);
                 self.send_log(msg.from)?;
             }
 
+            // Unbounded local reads are served immediately.
+            Message::ClientRequest {
+                id,
+                request: Request::ReadLocal { command, max_staleness: None },
+            } => {
+                self.read_local(id, command)?;
+            }
+
             // A client submitted a read command. To ensure linearizability, we
             // must confirm that we are still the leader by sending a heartbeat
             // with the read's sequence number and wait for confirmation from a
             // quorum before executing the read.
-            Message::ClientRequest { id, request: Request::Read(command) } => {
+            //
+            // If we hold a lease, we know that no other leader can have been
+            // elected, and serve the read immediately.
+            //
+            // Local reads with a staleness bound are treated as regular reads,
+            // since we may have been deposed without knowing it.
+            Message::ClientRequest {
+                id,
+                request:
+                    Request::Read(command) | Request::ReadLocal { command, max_staleness: Some(_) },
+            } if self.has_lease() => {
+                let response = self.state.read(command).map(Response::Read);
+                self.send(msg.from, Message::ClientResponse { id, response })?;
+            }
+
+            Message::ClientRequest {
+                id,
+                request:
+                    Request::Read(command) | Request::ReadLocal { command, max_staleness: Some(_) },
+            } => {
                 self.role.read_seq += 1;
                 self.role.reads.push_back(Read {
                     seq: self.role.read_seq,
@@ -225,6 +398,16 @@ impl RawNode<Leader> {
                 self.heartbeat()?;
             }
 
+            // Abort new writes and membership changes during a leadership
+            // transfer, to allow the target to catch up. The client must
+            // retry, typically against the new leader.
+            Message::ClientRequest {
+                id,
+                request: Request::Write(_) | Request::ChangeMembership(_),
+            } if self.role.transfer.is_some() => {
+                self.send(msg.from, Message::ClientResponse { id, response: Err(Error::Abort) })?;
+            }
+
             // A client submitted a write command. Propose it, and track it
             // until it's applied and the response is returned to the client.
             Message::ClientRequest { id, request: Request::Write(command) } => {
@@ -235,6 +418,43 @@ impl RawNode<Leader> {
                 }
             }
 
+            // A client requested a membership change. It takes effect
+            // immediately, and is tracked like a write until it's applied.
+            Message::ClientRequest { id, request: Request::ChangeMembership(change) } => {
+                match self.change_membership(change) {
+                    Ok(index) => {
+                        self.role.writes.insert(index, Write { from: msg.from, id });
+                        self.maybe_commit_and_apply()?;
+                    }
+                    Err(err) => {
+                        self.send(msg.from, Message::ClientResponse { id, response: Err(err) })?
+                    }
+                }
+            }
+
+            // A client requested a leadership transfer. Catch up the target
+            // and tell it to campaign.
+            Message::ClientRequest { id, request: Request::TransferLeadership(target) } => {
+                if target == self.id {
+                    let response = Ok(Response::TransferLeadership);
+                    self.send(msg.from, Message::ClientResponse { id, response })?;
+                } else if self.role.transfer.is_some() {
+                    let response =
+                        Err(Error::Value("A leadership transfer is already in progress".into()));
+                    self.send(msg.from, Message::ClientResponse { id, response })?;
+                } else if !self.peers.contains(&target) {
+                    let response = Err(Error::Value(format!("Node {} is not a voter", target)));
+                    self.send(msg.from, Message::ClientResponse { id, response })?;
+                } else {
+                    info!("Transferring leadership to {}", target);
+                    let request = Some((msg.from, id));
+                    self.role.transfer = Some(Transfer { target, ticks: 0, request });
+                    if !self.maybe_transfer()? && self.role.progress[&target].snapshot.is_none() {
+                        self.send_log(target)?;
+                    }
+                }
+            }
+
             Message::ClientRequest { id, request: Request::Status } => {
                 let status = Status {
                     leader: self.id,
@@ -246,8 +466,10 @@ impl RawNode<Leader> {
                         .map(|(id, p)| (*id, p.last))
                         .chain(std::iter::once((self.id, self.log.get_last_index().0)))
                         .collect(),
+                    learners: self.learners.clone(),
                     commit_index: self.log.get_commit_index().0,
                     apply_index: self.state.get_applied_index(),
+                    snapshot_index: self.log.get_snapshot_index().0,
                     storage: self.log.status()?,
                 };
                 self.send(
@@ -257,7 +479,9 @@ impl RawNode<Leader> {
             }
 
             // Votes can come in after we won the election, ignore them.
-            Message::Campaign { .. } | Message::CampaignResponse { .. } => {}
+            Message::Campaign { .. }
+            | Message::CampaignResponse { .. }
+            | Message::PreVoteResponse { .. } => {}
 
             // Leaders never proxy client requests, so we don't expect to see
             // responses from other nodes.
@@ -271,19 +495,94 @@ impl RawNode<Leader> {
     pub fn tick(mut self) -> Result<Node> {
         self.assert()?;
 
+        self.role.clock += 1;
         self.role.since_heartbeat += 1;
         if self.role.since_heartbeat >= HEARTBEAT_INTERVAL {
             self.heartbeat()?;
             self.role.since_heartbeat = 0;
         }
+
+        // Step down if we haven't heard from a quorum in an election timeout.
+        // We may be partitioned away from the rest of the cluster, which
+        // will elect a new leader, and clients should find it.
+        if self.opts.check_quorum {
+            self.role.since_quorum_check += 1;
+            if self.role.since_quorum_check >= ELECTION_TIMEOUT_RANGE.start {
+                self.role.since_quorum_check = 0;
+                if !self.check_quorum() {
+                    info!("Lost contact with quorum, stepping down in term {}", self.term);
+                    let term = self.term;
+                    return Ok(self.into_follower(term)?.into());
+                }
+            }
+        }
+
+        // Cancel the leadership transfer if the target hasn't taken over
+        // within an election timeout, and resume accepting writes.
+        if let Some(transfer) = self.role.transfer.as_mut() {
+            transfer.ticks += 1;
+            if transfer.ticks >= ELECTION_TIMEOUT_RANGE.end {
+                info!("Leadership transfer to {} timed out", transfer.target);
+                if let Some((from, id)) = self.role.transfer.take().and_then(|t| t.request) {
+                    self.send(from, Message::ClientResponse { id, response: Err(Error::Abort) })?;
+                }
+            }
+        }
         Ok(self.into())
     }
 
-    /// Broadcasts a heartbeat to all peers.
+    /// Checks whether we've heard from a quorum of voters since the last
+    /// check, and resets the activity tracking for the next check.
+    fn check_quorum(&mut self) -> bool {
+        let peers = &self.peers;
+        let active = self
+            .role
+            .progress
+            .iter_mut()
+            .filter(|(id, _)| peers.contains(id))
+            .map(|(_, p)| std::mem::replace(&mut p.active, false))
+            .filter(|active| *active)
+            .count();
+        active as u8 + 1 >= self.quorum_size()
+    }
+
+    /// Tells the leadership transfer target to campaign, if it has caught up
+    /// with our log, and responds to the client. Returns true if it did.
+    fn maybe_transfer(&mut self) -> Result<bool> {
+        let Some(transfer) = self.role.transfer.as_mut() else {
+            return Ok(false);
+        };
+        if transfer.request.is_none()
+            || self.role.progress[&transfer.target].last < self.log.get_last_index().0
+        {
+            return Ok(false);
+        }
+        let target = transfer.target;
+        let (from, id) = transfer.request.take().unwrap();
+        self.send(
+            from,
+            Message::ClientResponse { id, response: Ok(Response::TransferLeadership) },
+        )?;
+        self.send(target, Message::TimeoutNow)?;
+        Ok(true)
+    }
+
+    /// Broadcasts a heartbeat to all peers and learners.
     pub(super) fn heartbeat(&mut self) -> Result<()> {
+        // With lease reads, every heartbeat gets a new read sequence number,
+        // so that we know when the heartbeat confirmed by a quorum was sent.
+        // Heartbeats that are too old to extend the lease are dropped.
+        if self.opts.lease_reads && !self.peers.is_empty() {
+            let clock = self.role.clock;
+            self.role.heartbeats.retain(|(_, sent)| sent + Self::LEASE_DURATION > clock);
+            self.role.read_seq += 1;
+            self.role.heartbeats.push_back((self.role.read_seq, clock));
+        }
         let (commit_index, commit_term) = self.log.get_commit_index();
         let read_seq = self.role.read_seq;
-        self.broadcast(Message::Heartbeat { commit_index, commit_term, read_seq })?;
+        for peer in self.replicas() {
+            self.send(peer, Message::Heartbeat { commit_index, commit_term, read_seq })?;
+        }
         // NB: We don't reset self.since_heartbeat here, because we want to send
         // periodic heartbeats regardless of any on-demand heartbeats.
         Ok(())
@@ -294,21 +593,118 @@ impl RawNode<Leader> {
     /// and applied to the state machine.
     pub(super) fn propose(&mut self, command: Option<Vec<u8>>) -> Result<Index> {
         let index = self.log.append(self.term, command)?;
-        for peer in self.peers.clone() {
-            self.send_log(peer)?;
-        }
+        self.replicate()?;
         Ok(index)
     }
 
+    /// Proposes a new cluster membership. Unlike regular commands, it takes
+    /// effect as soon as it's appended to the log, so the peers and
+    /// replication progress are updated before replicating it.
+    pub(super) fn propose_membership(&mut self, membership: Membership) -> Result<Index> {
+        let index = self.log.append_membership(self.term, membership)?;
+        self.update_progress();
+        self.replicate()?;
+        Ok(index)
+    }
+
+    /// Validates and proposes a membership change. Only a single change can
+    /// be in progress at a time, to ensure that the majorities of consecutive
+    /// memberships overlap.
+    fn change_membership(&mut self, change: MembershipChange) -> Result<Index> {
+        let (index, mut membership) = match self.log.get_membership() {
+            Some((index, membership)) => (index, membership.clone()),
+            None => (0, self.bootstrap_membership()),
+        };
+        if index > self.log.get_commit_index().0 {
+            return Err(Error::Value("A membership change is already in progress".into()));
+        }
+        match change {
+            MembershipChange::AddNode { id, addr } => {
+                // Promoting a learner is a single-server change too, since
+                // learners aren't part of the voting configuration.
+                if !membership.voters.insert(id) {
+                    return Err(Error::Value(format!("Node {} is already a member", id)));
+                }
+                membership.learners.remove(&id);
+                membership.addrs.insert(id, addr);
+            }
+            MembershipChange::AddLearner { id, addr } => {
+                if membership.voters.contains(&id) || !membership.learners.insert(id) {
+                    return Err(Error::Value(format!("Node {} is already a member", id)));
+                }
+                membership.addrs.insert(id, addr);
+            }
+            MembershipChange::RemoveNode { id } => {
+                if id == self.id {
+                    return Err(Error::Value(format!("Can't remove the leader {}", id)));
+                }
+                if !membership.voters.remove(&id) && !membership.learners.remove(&id) {
+                    return Err(Error::Value(format!("Node {} is not a member", id)));
+                }
+                membership.addrs.remove(&id);
+            }
+        }
+        info!(
+            "Changing cluster membership to voters {:?} learners {:?}",
+            membership.voters, membership.learners
+        );
+        self.propose_membership(membership)
+    }
+
+    /// Returns the initial cluster membership, consisting of the local node
+    /// and the peers it was started with.
+    pub(super) fn bootstrap_membership(&self) -> Membership {
+        Membership {
+            voters: self.peers.iter().copied().chain(std::iter::once(self.id)).collect(),
+            learners: Default::default(),
+            addrs: Default::default(),
+        }
+    }
+
+    /// Updates the peers and learners from the latest membership, and adds or
+    /// removes their replication progress. New nodes are replicated from the
+    /// start of the log, since they're likely to be empty.
+    fn update_progress(&mut self) {
+        self.update_peers();
+        let replicas = self.replicas();
+        self.role.progress.retain(|id, _| replicas.contains(id));
+        for peer in replicas {
+            self.role.progress.entry(peer).or_insert(Progress::new(1));
+        }
+    }
+
+    /// Returns all nodes that the log is replicated to, i.e. the peers and
+    /// learners, in sorted order for test determinism.
+    fn replicas(&self) -> Vec<NodeID> {
+        self.peers.iter().chain(self.learners.iter()).copied().sorted().collect()
+    }
+
+    /// Sends pending log entries to all peers and learners.
+    fn replicate(&mut self) -> Result<()> {
+        for peer in self.replicas() {
+            // Peers receiving a snapshot will get the entries once it's done.
+            if self.role.progress[&peer].snapshot.is_none() {
+                self.send_log(peer)?;
+            }
+        }
+        Ok(())
+    }
+
     /// Commits any new log entries that have been replicated to a quorum, and
-    /// applies them to the state machine.
+    /// applies them to the state machine. Learners don't count towards quorum.
     fn maybe_commit_and_apply(&mut self) -> Result<Index> {
+        // Flush our appended entries before counting them towards a quorum.
+        // This is a group commit: a single flush covers all entries proposed
+        // since the last one, which have already been sent to peers.
+        self.log.flush()?;
+
         // Determine the new commit index.
         let quorum_index = self.quorum_value(
             self.role
                 .progress
-                .values()
-                .map(|p| p.last)
+                .iter()
+                .filter(|(id, _)| self.peers.contains(id))
+                .map(|(_, p)| p.last)
                 .chain(std::iter::once(self.log.get_last_index().0))
                 .collect(),
         );
@@ -348,10 +744,56 @@ impl RawNode<Leader> {
             }
             Ok(())
         })?;
+        self.maybe_compact()?;
 
         Ok(commit_index)
     }
 
+    /// Returns the maximum read sequence number confirmed by a quorum of
+    /// voters.
+    fn quorum_read_seq(&self) -> ReadSequence {
+        self.quorum_value(
+            self.role
+                .progress
+                .iter()
+                .filter(|(id, _)| self.peers.contains(id))
+                .map(|(_, p)| p.read_seq)
+                .chain(std::iter::once(self.role.read_seq))
+                .collect(),
+        )
+    }
+
+    /// Returns true if we hold a read lease. Followers don't grant pre-votes
+    /// until an election timeout after they last heard from us, so once a
+    /// quorum has confirmed a heartbeat, no other leader can be elected until
+    /// an election timeout after it was sent. We allow for clock drift, and
+    /// only use leases once we've committed an entry in our term, since we
+    /// may not have applied all committed entries before then. Leases aren't
+    /// used during leadership transfers, since the target campaigns
+    /// immediately.
+    fn has_lease(&self) -> bool {
+        self.opts.lease_reads
+            && self.opts.prevote
+            && self.role.clock < self.role.lease
+            && self.role.transfer.is_none()
+            && self.log.get_commit_index().1 == self.term
+    }
+
+    /// Extends the read lease if a quorum has confirmed a new heartbeat.
+    fn maybe_extend_lease(&mut self) {
+        if !self.opts.lease_reads {
+            return;
+        }
+        let read_seq = self.quorum_read_seq();
+        while let Some((seq, sent)) = self.role.heartbeats.front().copied() {
+            if seq > read_seq {
+                break;
+            }
+            self.role.heartbeats.pop_front();
+            self.role.lease = std::cmp::max(self.role.lease, sent + Self::LEASE_DURATION);
+        }
+    }
+
     /// Executes any pending read requests that are now ready after quorum
     /// confirmation of their sequence number.
     fn maybe_read(&mut self) -> Result<()> {
@@ -359,15 +801,8 @@ impl RawNode<Leader> {
             return Ok(());
         }
 
-        // Determine the maximum read sequence confirmed by quorum.
-        let read_seq = self.quorum_value(
-            self.role
-                .progress
-                .values()
-                .map(|p| p.read_seq)
-                .chain(std::iter::once(self.role.read_seq))
-                .collect(),
-        );
+        // Determine the maximum read sequence confirmed by a quorum of voters.
+        let read_seq = self.quorum_read_seq();
 
         // Execute the ready reads.
         while let Some(read) = self.role.reads.front() {
@@ -385,22 +820,82 @@ impl RawNode<Leader> {
         Ok(())
     }
 
-    /// Sends pending log entries to a peer.
+    /// Sends pending log entries to a peer, unless its in-flight window is
+    /// full. The entries are assumed to be received, and replication continues
+    /// after them, unless the peer rejects them or they appear to be lost. If
+    /// the entries have been compacted out of the log, a snapshot is sent
+    /// instead.
     fn send_log(&mut self, peer: NodeID) -> Result<()> {
-        let (base_index, base_term) = match self.role.progress.get(&peer) {
-            Some(Progress { next, .. }) if *next > 1 => match self.log.get(next - 1)? {
-                Some(entry) => (entry.index, entry.term),
-                None => panic!("Missing base entry {}", next - 1),
+        let Some(progress) = self.role.progress.get(&peer) else {
+            panic!("Unknown peer {}", peer);
+        };
+        let next = progress.next;
+        if next <= self.log.get_snapshot_index().0 {
+            return self.send_snapshot(peer);
+        }
+        if progress.inflight.len() >= std::cmp::max(self.opts.max_inflight, 1) {
+            debug!("In-flight window full for {}, batching entries", peer);
+            return Ok(());
+        }
+
+        let (base_index, base_term) = match next - 1 {
+            0 => (0, 0),
+            index => match self.log.term(index)? {
+                Some(term) => (index, term),
+                None => panic!("Missing base entry {}", index),
             },
-            Some(_) => (0, 0),
-            None => panic!("Unknown peer {}", peer),
         };
 
         let entries = self.log.scan((base_index + 1)..)?.collect::<Result<Vec<_>>>()?;
         debug!("Replicating {} entries at base {} to {}", entries.len(), base_index, peer);
+        if let Some(last) = entries.last() {
+            let clock = self.role.clock;
+            let progress = self.role.progress.get_mut(&peer).unwrap();
+            progress.next = last.index + 1;
+            progress.inflight.push_back((last.index, clock));
+        }
         self.send(peer, Message::Append { base_index, base_term, entries })?;
         Ok(())
     }
+
+    /// Sends the next snapshot chunk to a peer, taking a new snapshot of the
+    /// state machine if necessary. The snapshot is taken at the applied index,
+    /// which is at or after the log's snapshot index.
+    fn send_snapshot(&mut self, peer: NodeID) -> Result<()> {
+        let (snapshot_index, _) = self.log.get_snapshot_index();
+        if !matches!(&self.role.snapshot, Some(s) if s.index >= snapshot_index) {
+            let index = self.state.get_applied_index();
+            let term = self.log.term(index)?.expect("applied entry missing");
+            let membership = self.log.get_membership_at(index)?;
+            let data = self.state.snapshot()?;
+            info!("Took snapshot at index {} ({} bytes)", index, data.len());
+            self.role.snapshot = Some(Snapshot { index, term, membership, data });
+        }
+        let snapshot = self.role.snapshot.as_ref().unwrap();
+
+        // Resume an in-flight transfer of this snapshot, or start a new one.
+        let progress = self.role.progress.get_mut(&peer).unwrap();
+        let offset = match progress.snapshot {
+            Some((index, offset)) if index == snapshot.index => offset,
+            _ => 0,
+        };
+        progress.snapshot = Some((snapshot.index, offset));
+
+        let start = std::cmp::min(offset as usize, snapshot.data.len());
+        let end = std::cmp::min(start + SNAPSHOT_CHUNK_SIZE, snapshot.data.len());
+        debug!("Sending snapshot {} bytes {}-{} to {}", snapshot.index, start, end, peer);
+        self.send(
+            peer,
+            Message::InstallSnapshot {
+                index: snapshot.index,
+                term: snapshot.term,
+                membership: snapshot.membership.clone(),
+                offset: start as u64,
+                data: snapshot.data[start..end].to_vec(),
+                done: end == snapshot.data.len(),
+            },
+        )
+    }
 }
 
 #[cfg(test)]
@@ -408,6 +903,7 @@ mod tests {
     use super::super::super::state::tests::TestState;
     use super::super::super::{Entry, Log};
     use super::super::tests::{assert_messages, assert_node};
+    use super::super::Options;
     use super::*;
     use crate::storage;
     use itertools::Itertools as _;
@@ -430,11 +926,13 @@ mod tests {
         let node = RawNode {
             id: 1,
             peers: peers.clone(),
+            learners: HashSet::new(),
             term: 3,
             role: Leader::new(peers, log.get_last_index().0),
             log,
             state,
             node_tx,
+            opts: Options::default(),
         };
         Ok((node, node_rx))
     }
@@ -617,15 +1115,21 @@ mod tests {
         let entries = leader.log.scan(0..)?.collect::<Result<Vec<_>>>()?;
         let mut node: Node = leader.into();
 
+        // The follower rejects each base entry, responding with the entry
+        // before it, until it reaches the start of the log.
         for i in 0..(entries.len() + 3) {
+            let index = if i >= entries.len() { 0 } else { entries.len() - i - 1 };
             node = node.step(Envelope {
                 from: 2,
                 to: 1,
                 term: 3,
-                message: Message::AppendResponse { reject: true, last_index: 0, last_term: 3 },
+                message: Message::AppendResponse {
+                    reject: true,
+                    last_index: index as Index,
+                    last_term: 1,
+                },
             })?;
             assert_node(&mut node).is_leader().term(3).committed(2);
-            let index = if i >= entries.len() { 0 } else { entries.len() - i - 1 };
             let replicate = entries.get(index..).unwrap().to_vec();
             assert_messages(
                 &mut node_rx,
@@ -648,6 +1152,180 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    // A follower needing compacted entries is sent a snapshot, which is
+    // resumed from the offset it acknowledges.
+    fn step_rejectentries_snapshot() -> Result<()> {
+        let (mut leader, mut node_rx) = setup()?;
+        leader.log.commit(5)?;
+        leader.maybe_apply()?;
+        leader.log.compact(4)?;
+        let data = leader.state.snapshot()?;
+        let mut node: Node = leader.into();
+
+        // The base entry 4 was compacted, but its term is known.
+        node = node.step(Envelope {
+            from: 2,
+            to: 1,
+            term: 3,
+            message: Message::AppendResponse { reject: true, last_index: 4, last_term: 3 },
+        })?;
+        assert_messages(
+            &mut node_rx,
+            vec![Envelope {
+                from: 1,
+                to: 2,
+                term: 3,
+                message: Message::Append {
+                    base_index: 4,
+                    base_term: 3,
+                    entries: vec![Entry {
+                        index: 5,
+                        term: 3,
+                        command: Some(vec![0x05]),
+                        membership: None,
+                    }],
+                },
+            }],
+        );
+
+        // Entry 4 is needed, so a snapshot is sent at the applied index.
+        node = node.step(Envelope {
+            from: 2,
+            to: 1,
+            term: 3,
+            message: Message::AppendResponse { reject: true, last_index: 3, last_term: 3 },
+        })?;
+        assert_node(&mut node).is_leader().snapshot(4);
+        assert_messages(
+            &mut node_rx,
+            vec![Envelope {
+                from: 1,
+                to: 2,
+                term: 3,
+                message: Message::InstallSnapshot {
+                    index: 5,
+                    term: 3,
+                    membership: None,
+                    offset: 0,
+                    data: data.clone(),
+                    done: true,
+                },
+            }],
+        );
+
+        // New proposals are not sent while the snapshot is in flight.
+        node = node.step(Envelope {
+            from: 1,
+            to: 1,
+            term: 3,
+            message: Message::ClientRequest { id: vec![0x01], request: Request::Write(vec![0x06]) },
+        })?;
+        assert!(node_rx.try_iter().all(|e| e.to != 2));
+
+        // The follower asks for a later offset, which is resent.
+        node = node.step(Envelope {
+            from: 2,
+            to: 1,
+            term: 3,
+            message: Message::InstallSnapshotResponse { index: 5, offset: 4 },
+        })?;
+        assert_messages(
+            &mut node_rx,
+            vec![Envelope {
+                from: 1,
+                to: 2,
+                term: 3,
+                message: Message::InstallSnapshot {
+                    index: 5,
+                    term: 3,
+                    membership: None,
+                    offset: 4,
+                    data: data[4..].to_vec(),
+                    done: true,
+                },
+            }],
+        );
+
+        // Once installed, replication resumes after the snapshot.
+        node = node.step(Envelope {
+            from: 2,
+            to: 1,
+            term: 3,
+            message: Message::AppendResponse { reject: false, last_index: 5, last_term: 3 },
+        })?;
+        assert_messages(
+            &mut node_rx,
+            vec![Envelope {
+                from: 1,
+                to: 2,
+                term: 3,
+                message: Message::Append {
+                    base_index: 5,
+                    base_term: 3,
+                    entries: vec![Entry {
+                        index: 6,
+                        term: 3,
+                        command: Some(vec![0x06]),
+                        membership: None,
+                    }],
+                },
+            }],
+        );
+        let Node::Leader(leader) = &node else { panic!("expected leader") };
+        assert_eq!(leader.role.snapshot.as_ref().map(|s| s.index), Some(5));
+        assert_eq!(leader.role.progress[&2].snapshot, None);
+
+        // Another follower needing compacted entries reuses the snapshot.
+        node.step(Envelope {
+            from: 3,
+            to: 1,
+            term: 3,
+            message: Message::AppendResponse { reject: true, last_index: 3, last_term: 3 },
+        })?;
+        assert_messages(
+            &mut node_rx,
+            vec![Envelope {
+                from: 1,
+                to: 3,
+                term: 3,
+                message: Message::InstallSnapshot {
+                    index: 5,
+                    term: 3,
+                    membership: None,
+                    offset: 0,
+                    data,
+                    done: true,
+                },
+            }],
+        );
+        Ok(())
+    }
+
+    #[test]
+    // Applying past the snapshot threshold compacts the log, retaining the
+    // last half threshold of entries.
+    fn compact() -> Result<()> {
+        let (mut leader, _node_rx) = setup()?;
+        leader.opts.snapshot_threshold = 3;
+        let mut node: Node = leader.into();
+        for peer in [2, 3] {
+            node = node.step(Envelope {
+                from: peer,
+                to: 1,
+                term: 3,
+                message: Message::AppendResponse { reject: false, last_index: 5, last_term: 3 },
+            })?;
+        }
+        assert_node(&mut node).committed(5).applied(5).snapshot(4).last(5).entries(vec![Entry {
+            index: 5,
+            term: 3,
+            command: Some(vec![0x05]),
+            membership: None,
+        }]);
+        Ok(())
+    }
+
     #[test]
     // Sending a client query request will pass it to the state machine and trigger heartbeats.
     fn step_clientrequest_query() -> Result<()> {
@@ -675,6 +1353,105 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    // Unbounded local reads are served immediately, bounded local reads are
+    // treated as regular reads.
+    fn step_clientrequest_read_local() -> Result<()> {
+        let (leader, node_rx) = setup()?;
+        let mut node: Node = leader.into();
+        let read_local = |max_staleness| Envelope {
+            from: 1,
+            to: 1,
+            term: 3,
+            message: Message::ClientRequest {
+                id: vec![0x01],
+                request: Request::ReadLocal { command: vec![0xaf], max_staleness },
+            },
+        };
+
+        node = node.step(read_local(None))?;
+        assert_eq!(
+            node_rx.try_iter().collect_vec(),
+            vec![Envelope {
+                from: 1,
+                to: 1,
+                term: 3,
+                message: Message::ClientResponse {
+                    id: vec![0x01],
+                    response: Ok(Response::Read(vec![0xaf])),
+                },
+            }]
+        );
+
+        node = node.step(read_local(Some(10)))?;
+        assert_node(&mut node).is_leader().term(3);
+        let msgs = node_rx.try_iter().map(|e| e.message).collect_vec();
+        assert_eq!(msgs.len(), 4);
+        assert!(msgs.iter().all(|m| matches!(m, Message::Heartbeat { read_seq: 1, .. })));
+        Ok(())
+    }
+
+    #[test]
+    // With lease reads, reads are served immediately once a quorum has
+    // confirmed a heartbeat and we've committed an entry in our term, until
+    // the lease expires.
+    fn step_clientrequest_read_lease() -> Result<()> {
+        let (mut leader, node_rx) = setup()?;
+        leader.opts.lease_reads = true;
+        let node: Node = leader.into();
+
+        // Steps a message into the node and returns the sent messages.
+        let step = |node: Node, from: NodeID, message: Message| -> Result<(Node, Vec<Message>)> {
+            let node = node.step(Envelope { from, to: 1, term: 3, message })?;
+            Ok((node, node_rx.try_iter().map(|e| e.message).collect()))
+        };
+        let read =
+            |id: u8| Message::ClientRequest { id: vec![id], request: Request::Read(vec![id]) };
+        let response = |id: u8| Message::ClientResponse {
+            id: vec![id],
+            response: Ok(Response::Read(vec![id])),
+        };
+        let heartbeat =
+            |read_seq| Message::HeartbeatResponse { last_index: 5, last_term: 3, read_seq };
+
+        // The first read requires a heartbeat round trip. Once confirmed by a
+        // quorum, the read is served and the lease acquired.
+        let (n, msgs) = step(node, 1, read(0x01))?;
+        assert_eq!(msgs.len(), 4);
+        assert!(msgs.iter().all(|m| matches!(m, Message::Heartbeat { read_seq: 2, .. })));
+        let (n, msgs) = step(n, 2, heartbeat(2))?;
+        assert_eq!(msgs, vec![]);
+        let (n, msgs) = step(n, 3, heartbeat(2))?;
+        assert_eq!(msgs, vec![response(0x01)]);
+
+        // We haven't committed an entry in our term yet, so we can't use the
+        // lease.
+        let (n, msgs) = step(n, 1, read(0x02))?;
+        assert_eq!(msgs.len(), 4);
+        assert!(msgs.iter().all(|m| matches!(m, Message::Heartbeat { read_seq: 4, .. })));
+        let (n, _) = step(n, 2, heartbeat(4))?;
+        let (n, msgs) = step(n, 3, heartbeat(4))?;
+        assert_eq!(msgs, vec![response(0x02)]);
+
+        // Once we commit an entry in our term, reads are served immediately.
+        let append = Message::AppendResponse { reject: false, last_index: 5, last_term: 3 };
+        let (n, _) = step(n, 2, append.clone())?;
+        let (mut n, _) = step(n, 3, append)?;
+        assert_node(&mut n).committed(5);
+        let (mut n, msgs) = step(n, 1, read(0x03))?;
+        assert_eq!(msgs, vec![response(0x03)]);
+
+        // When the lease expires, we fall back to heartbeat round trips.
+        for _ in 0..RawNode::<Leader>::LEASE_DURATION {
+            n = n.tick()?;
+        }
+        node_rx.try_iter().for_each(drop);
+        let (_, msgs) = step(n, 1, read(0x04))?;
+        assert_eq!(msgs.len(), 4);
+        assert!(msgs.iter().all(|m| matches!(m, Message::Heartbeat { .. })));
+        Ok(())
+    }
+
     #[test]
     // Sending a mutate request should append it to log, replicate it to peers, and register notification.
     fn step_clientrequest_mutate() -> Result<()> {
@@ -692,9 +1469,10 @@ mod tests {
             index: 6,
             term: 3,
             command: Some(vec![0xaf]),
+            membership: None,
         });
 
-        for peer in peers.iter().cloned() {
+        for peer in peers.iter().copied().sorted() {
             assert_eq!(
                 node_rx.try_recv()?,
                 Envelope {
@@ -704,7 +1482,12 @@ mod tests {
                     message: Message::Append {
                         base_index: 5,
                         base_term: 3,
-                        entries: vec![Entry { index: 6, term: 3, command: Some(vec![0xaf]) },]
+                        entries: vec![Entry {
+                            index: 6,
+                            term: 3,
+                            command: Some(vec![0xaf]),
+                            membership: None
+                        },]
                     },
                 }
             )
@@ -712,6 +1495,440 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    // Appends are pipelined up to the in-flight window, after which new
+    // entries are batched until the peer acknowledges an append. Appends
+    // that appear to be lost are resent.
+    fn step_clientrequest_mutate_pipelined() -> Result<()> {
+        let (mut leader, node_rx) = setup()?;
+        leader.opts.max_inflight = 2;
+        let mut node: Node = leader.into();
+
+        let write = |id: u8| Envelope {
+            from: 1,
+            to: 1,
+            term: 3,
+            message: Message::ClientRequest { id: vec![id], request: Request::Write(vec![id]) },
+        };
+        let entry = |index: Index| Entry {
+            index,
+            term: 3,
+            command: Some(vec![index as u8]),
+            membership: None,
+        };
+        // Returns the Appends sent to node 2, as (base_index, entry indexes).
+        let appends = || {
+            node_rx
+                .try_iter()
+                .filter(|e| e.to == 2)
+                .filter_map(|e| match e.message {
+                    Message::Append { base_index, entries, .. } => {
+                        Some((base_index, entries.iter().map(|e| e.index).collect_vec()))
+                    }
+                    _ => None,
+                })
+                .collect_vec()
+        };
+
+        // The first two writes are sent immediately, the next two are held
+        // back since the window is full.
+        for id in 6..=9 {
+            node = node.step(write(id))?;
+        }
+        assert_node(&mut node).last(9).entry(entry(9));
+        assert_eq!(appends(), vec![(5, vec![6]), (6, vec![7])]);
+
+        // When the first append is acknowledged, the held back entries are
+        // sent in a single append.
+        node = node.step(Envelope {
+            from: 2,
+            to: 1,
+            term: 3,
+            message: Message::AppendResponse { reject: false, last_index: 6, last_term: 3 },
+        })?;
+        assert_eq!(appends(), vec![(7, vec![8, 9])]);
+
+        // If the peer hasn't received the remaining appends a heartbeat
+        // interval later, they're assumed lost and resent.
+        let heartbeat_response = Envelope {
+            from: 2,
+            to: 1,
+            term: 3,
+            message: Message::HeartbeatResponse { last_index: 6, last_term: 3, read_seq: 0 },
+        };
+        node = node.step(heartbeat_response.clone())?;
+        assert_eq!(appends(), vec![]);
+        for _ in 0..HEARTBEAT_INTERVAL {
+            node = node.tick()?;
+        }
+        appends();
+        node.step(heartbeat_response)?;
+        assert_eq!(appends(), vec![(6, vec![7, 8, 9])]);
+        Ok(())
+    }
+
+    #[test]
+    // Membership changes take effect immediately, and respond once applied.
+    fn step_clientrequest_change_membership() -> Result<()> {
+        let (leader, mut node_rx) = setup()?;
+        let mut node: Node = leader.into();
+        let request = |change| Envelope {
+            from: 1,
+            to: 1,
+            term: 3,
+            message: Message::ClientRequest {
+                id: vec![0x01],
+                request: Request::ChangeMembership(change),
+            },
+        };
+        let error = |msg: &str| Envelope {
+            from: 1,
+            to: 1,
+            term: 3,
+            message: Message::ClientResponse {
+                id: vec![0x01],
+                response: Err(Error::Value(msg.to_string())),
+            },
+        };
+
+        // Adding a node appends the membership, and replicates the entire log
+        // to the new node.
+        node = node.step(request(MembershipChange::AddNode { id: 6, addr: "addr6".into() }))?;
+        let membership = Membership {
+            voters: [1, 2, 3, 4, 5, 6].into(),
+            learners: [].into(),
+            addrs: [(6, "addr6".to_string())].into(),
+        };
+        let entry =
+            Entry { index: 6, term: 3, command: None, membership: Some(membership.clone()) };
+        assert_node(&mut node).is_leader().term(3).committed(2).last(6).entry(entry.clone());
+        let Node::Leader(leader) = &mut node else { panic!("expected leader") };
+        assert_eq!(leader.peers, HashSet::from([2, 3, 4, 5, 6]));
+        let entries = leader.log.scan(..)?.collect::<Result<Vec<_>>>()?;
+        let mut expect = Vec::new();
+        for to in [2, 3, 4, 5] {
+            expect.push(Envelope {
+                from: 1,
+                to,
+                term: 3,
+                message: Message::Append {
+                    base_index: 5,
+                    base_term: 3,
+                    entries: vec![entry.clone()],
+                },
+            });
+        }
+        expect.push(Envelope {
+            from: 1,
+            to: 6,
+            term: 3,
+            message: Message::Append { base_index: 0, base_term: 0, entries },
+        });
+        assert_messages(&mut node_rx, expect);
+
+        // Another change can't be made until the first one commits.
+        node = node.step(request(MembershipChange::AddNode { id: 7, addr: "addr7".into() }))?;
+        assert_messages(&mut node_rx, vec![error("A membership change is already in progress")]);
+
+        // The new membership requires 4 nodes for quorum.
+        for peer in [2, 3] {
+            node = node.step(Envelope {
+                from: peer,
+                to: 1,
+                term: 3,
+                message: Message::AppendResponse { reject: false, last_index: 6, last_term: 3 },
+            })?;
+        }
+        assert_node(&mut node).committed(2);
+        assert_messages(&mut node_rx, vec![]);
+
+        node = node.step(Envelope {
+            from: 6,
+            to: 1,
+            term: 3,
+            message: Message::AppendResponse { reject: false, last_index: 6, last_term: 3 },
+        })?;
+        assert_node(&mut node).committed(6).applied(6);
+        assert_messages(
+            &mut node_rx,
+            vec![Envelope {
+                from: 1,
+                to: 1,
+                term: 3,
+                message: Message::ClientResponse {
+                    id: vec![0x01],
+                    response: Ok(Response::Write(vec![])),
+                },
+            }],
+        );
+
+        // Invalid changes error.
+        node = node.step(request(MembershipChange::AddNode { id: 2, addr: "addr2".into() }))?;
+        assert_messages(&mut node_rx, vec![error("Node 2 is already a member")]);
+        node = node.step(request(MembershipChange::RemoveNode { id: 7 }))?;
+        assert_messages(&mut node_rx, vec![error("Node 7 is not a member")]);
+        node = node.step(request(MembershipChange::RemoveNode { id: 1 }))?;
+        assert_messages(&mut node_rx, vec![error("Can't remove the leader 1")]);
+
+        // Removing a node stops replication to it.
+        node = node.step(request(MembershipChange::RemoveNode { id: 6 }))?;
+        assert_node(&mut node).last(7);
+        let Node::Leader(leader) = &mut node else { panic!("expected leader") };
+        assert_eq!(leader.peers, HashSet::from([2, 3, 4, 5]));
+        assert!(!leader.role.progress.contains_key(&6));
+        assert!(node_rx.try_iter().all(|msg| msg.to != 6));
+        Ok(())
+    }
+
+    #[test]
+    // Leadership transfers catch up the target, then tell it to campaign.
+    fn step_clientrequest_transfer_leadership() -> Result<()> {
+        let (leader, mut node_rx) = setup()?;
+        let mut node: Node = leader.into();
+        let request = |id: u8, request| Envelope {
+            from: 1,
+            to: 1,
+            term: 3,
+            message: Message::ClientRequest { id: vec![id], request },
+        };
+        let response = |id: u8, response| Envelope {
+            from: 1,
+            to: 1,
+            term: 3,
+            message: Message::ClientResponse { id: vec![id], response },
+        };
+
+        // Transfers to non-voters error, and transfers to self are a noop.
+        node = node.step(request(0x01, Request::TransferLeadership(6)))?;
+        assert_messages(
+            &mut node_rx,
+            vec![response(0x01, Err(Error::Value("Node 6 is not a voter".into())))],
+        );
+        node = node.step(request(0x01, Request::TransferLeadership(1)))?;
+        assert_messages(&mut node_rx, vec![response(0x01, Ok(Response::TransferLeadership))]);
+
+        // Transferring to a lagging node replicates the log to it.
+        node = node.step(request(0x01, Request::TransferLeadership(2)))?;
+        assert_messages(
+            &mut node_rx,
+            vec![Envelope {
+                from: 1,
+                to: 2,
+                term: 3,
+                message: Message::Append { base_index: 5, base_term: 3, entries: vec![] },
+            }],
+        );
+
+        // Writes, membership changes, and other transfers are rejected while
+        // the transfer is in progress.
+        node = node.step(request(0x02, Request::Write(vec![0x06])))?;
+        node = node.step(request(
+            0x03,
+            Request::ChangeMembership(MembershipChange::RemoveNode { id: 5 }),
+        ))?;
+        node = node.step(request(0x04, Request::TransferLeadership(3)))?;
+        assert_node(&mut node).is_leader().last(5);
+        assert_messages(
+            &mut node_rx,
+            vec![
+                response(0x02, Err(Error::Abort)),
+                response(0x03, Err(Error::Abort)),
+                response(
+                    0x04,
+                    Err(Error::Value("A leadership transfer is already in progress".into())),
+                ),
+            ],
+        );
+
+        // Once the target catches up, it's told to campaign.
+        node = node.step(Envelope {
+            from: 2,
+            to: 1,
+            term: 3,
+            message: Message::AppendResponse { reject: false, last_index: 5, last_term: 3 },
+        })?;
+        assert_messages(
+            &mut node_rx,
+            vec![
+                response(0x01, Ok(Response::TransferLeadership)),
+                Envelope { from: 1, to: 2, term: 3, message: Message::TimeoutNow },
+            ],
+        );
+
+        // When it campaigns, we step down.
+        node = node.step(Envelope {
+            from: 2,
+            to: 1,
+            term: 4,
+            message: Message::Campaign { last_index: 5, last_term: 3 },
+        })?;
+        assert_node(&mut node).is_follower().term(4).voted_for(Some(2));
+        Ok(())
+    }
+
+    #[test]
+    // Leadership transfers are cancelled after an election timeout.
+    fn tick_transfer_leadership_timeout() -> Result<()> {
+        let (mut leader, node_rx) = setup()?;
+        leader.opts.check_quorum = false;
+        let mut node: Node = leader.into();
+
+        node = node.step(Envelope {
+            from: 1,
+            to: 1,
+            term: 3,
+            message: Message::ClientRequest {
+                id: vec![0x01],
+                request: Request::TransferLeadership(2),
+            },
+        })?;
+        for _ in 0..ELECTION_TIMEOUT_RANGE.end {
+            node = node.tick()?;
+        }
+        assert_node(&mut node).is_leader().term(3);
+        let responses = node_rx
+            .try_iter()
+            .filter(|msg| matches!(msg.message, Message::ClientResponse { .. }))
+            .collect_vec();
+        assert_eq!(
+            responses,
+            vec![Envelope {
+                from: 1,
+                to: 1,
+                term: 3,
+                message: Message::ClientResponse { id: vec![0x01], response: Err(Error::Abort) },
+            }]
+        );
+
+        // Writes are accepted again.
+        node = node.step(Envelope {
+            from: 1,
+            to: 1,
+            term: 3,
+            message: Message::ClientRequest { id: vec![0x02], request: Request::Write(vec![0x06]) },
+        })?;
+        assert_node(&mut node).is_leader().last(6);
+        Ok(())
+    }
+
+    #[test]
+    // Learners are replicated to, but don't count towards quorum, and can be
+    // promoted to voters.
+    fn step_clientrequest_add_learner() -> Result<()> {
+        let (leader, mut node_rx) = setup()?;
+        let mut node: Node = leader.into();
+        let request = |change| Envelope {
+            from: 1,
+            to: 1,
+            term: 3,
+            message: Message::ClientRequest {
+                id: vec![0x01],
+                request: Request::ChangeMembership(change),
+            },
+        };
+        let response = Envelope {
+            from: 1,
+            to: 1,
+            term: 3,
+            message: Message::ClientResponse {
+                id: vec![0x01],
+                response: Ok(Response::Write(vec![])),
+            },
+        };
+
+        node = node.step(request(MembershipChange::AddLearner { id: 6, addr: "addr6".into() }))?;
+        assert_node(&mut node).is_leader().term(3).committed(2).last(6);
+        let Node::Leader(leader) = &mut node else { panic!("expected leader") };
+        assert_eq!(leader.peers, HashSet::from([2, 3, 4, 5]));
+        assert_eq!(leader.learners, HashSet::from([6]));
+        assert_eq!(node_rx.try_iter().map(|msg| msg.to).collect_vec(), vec![2, 3, 4, 5, 6],);
+
+        // Adding it again errors, both as a learner and a voter.
+        node = node.step(request(MembershipChange::AddLearner { id: 6, addr: "addr6".into() }))?;
+        node = node.step(request(MembershipChange::AddLearner { id: 2, addr: "addr2".into() }))?;
+        assert_eq!(node_rx.try_iter().count(), 2);
+
+        // Voters alone form a quorum.
+        for peer in [2, 3] {
+            node = node.step(Envelope {
+                from: peer,
+                to: 1,
+                term: 3,
+                message: Message::AppendResponse { reject: false, last_index: 6, last_term: 3 },
+            })?;
+        }
+        assert_node(&mut node).committed(6).applied(6);
+        assert_messages(&mut node_rx, vec![response.clone()]);
+
+        // Heartbeats are sent to the learner, and it's visible in the status.
+        for _ in 0..HEARTBEAT_INTERVAL {
+            node = node.tick()?;
+        }
+        assert_eq!(node_rx.try_iter().map(|msg| msg.to).collect_vec(), vec![2, 3, 4, 5, 6],);
+        node = node.step(Envelope {
+            from: 1,
+            to: 1,
+            term: 3,
+            message: Message::ClientRequest { id: vec![0x02], request: Request::Status },
+        })?;
+        match node_rx.try_recv()?.message {
+            Message::ClientResponse { response: Ok(Response::Status(status)), .. } => {
+                assert_eq!(status.learners, HashSet::from([6]));
+                assert_eq!(status.last_index.len(), 6);
+            }
+            message => panic!("unexpected message {:?}", message),
+        }
+
+        // The learner's own progress doesn't commit anything, even when the
+        // voters lag behind.
+        node = node.step(Envelope {
+            from: 1,
+            to: 1,
+            term: 3,
+            message: Message::ClientRequest { id: vec![0x03], request: Request::Write(vec![0x07]) },
+        })?;
+        node_rx.try_iter().count();
+        node = node.step(Envelope {
+            from: 6,
+            to: 1,
+            term: 3,
+            message: Message::AppendResponse { reject: false, last_index: 7, last_term: 3 },
+        })?;
+        node = node.step(Envelope {
+            from: 2,
+            to: 1,
+            term: 3,
+            message: Message::AppendResponse { reject: false, last_index: 7, last_term: 3 },
+        })?;
+        assert_node(&mut node).committed(6);
+
+        // Promoting the learner makes it a voter.
+        node = node.step(Envelope {
+            from: 3,
+            to: 1,
+            term: 3,
+            message: Message::AppendResponse { reject: false, last_index: 7, last_term: 3 },
+        })?;
+        assert_node(&mut node).committed(7);
+        node_rx.try_iter().count();
+        node = node.step(request(MembershipChange::AddNode { id: 6, addr: "addr6".into() }))?;
+        assert_node(&mut node).last(8).entry(Entry {
+            index: 8,
+            term: 3,
+            command: None,
+            membership: Some(Membership {
+                voters: [1, 2, 3, 4, 5, 6].into(),
+                learners: [].into(),
+                addrs: [(6, "addr6".to_string())].into(),
+            }),
+        });
+        let Node::Leader(leader) = &mut node else { panic!("expected leader") };
+        assert_eq!(leader.peers, HashSet::from([2, 3, 4, 5, 6]));
+        assert!(leader.learners.is_empty());
+        assert_eq!(leader.role.progress[&6].last, 7);
+        Ok(())
+    }
+
     #[test]
     // Sending a status request should pass it on to state machine, to add status.
     fn step_clientrequest_status() -> Result<()> {
@@ -737,15 +1954,18 @@ mod tests {
                         leader: 1,
                         term: 3,
                         last_index: HashMap::from([(1, 5), (2, 0), (3, 0), (4, 0), (5, 0)]),
+                        learners: HashSet::new(),
                         commit_index: 2,
                         apply_index: 0,
+                        snapshot_index: 0,
                         storage: storage::engine::Status {
                             name: "memory".to_string(),
-                            keys: 7,
-                            size: 72,
+                            keys: 8,
+                            size: 79,
                             total_disk_size: 0,
                             live_disk_size: 0,
                             garbage_disk_size: 0,
+                            compression_ratio: 1.0,
                         },
                     })),
                 },
@@ -782,7 +2002,48 @@ mod tests {
                     }
                 );
             }
+
+            // A quorum responds, so we remain leader.
+            for from in [2, 3] {
+                node = node.step(Envelope {
+                    from,
+                    to: 1,
+                    term: 3,
+                    message: Message::HeartbeatResponse {
+                        last_index: 5,
+                        last_term: 3,
+                        read_seq: 0,
+                    },
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    // A leader that doesn't hear from a quorum within an election timeout
+    // steps down. Learners don't count.
+    fn tick_check_quorum() -> Result<()> {
+        let (mut leader, _node_rx) = setup()?;
+        leader.learners.insert(6);
+        leader.role.progress.insert(6, Progress::new(6));
+        let mut node: Node = leader.into();
+
+        // Hearing from a single voter and the learner isn't sufficient.
+        for _ in 1..ELECTION_TIMEOUT_RANGE.start {
+            node = node.tick()?;
+            assert_node(&mut node).is_leader();
+        }
+        for from in [2, 6] {
+            node = node.step(Envelope {
+                from,
+                to: 1,
+                term: 3,
+                message: Message::HeartbeatResponse { last_index: 5, last_term: 3, read_seq: 0 },
+            })?;
         }
+        node = node.tick()?;
+        assert_node(&mut node).is_follower().term(3).leader(None).voted_for(Some(1));
         Ok(())
     }
 }
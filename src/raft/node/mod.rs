@@ -2,7 +2,9 @@ mod candidate;
 mod follower;
 mod leader;
 
-use super::{Envelope, Index, Log, Message, State, ELECTION_TIMEOUT_RANGE};
+use super::{
+    Envelope, Index, Log, Membership, Message, RequestID, Response, State, ELECTION_TIMEOUT_RANGE,
+};
 use crate::error::{Error, Result};
 use candidate::Candidate;
 use follower::Follower;
@@ -22,6 +24,58 @@ pub type Term = u64;
 /// A logical clock interval as number of ticks.
 pub type Ticks = u8;
 
+/// Raft node options.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    /// The number of applied log entries beyond the last snapshot after which
+    /// the log is compacted. Half of this is retained in the log, for lagging
+    /// followers. 0 disables compaction.
+    pub snapshot_threshold: Index,
+    /// Whether to run a pre-vote before campaigning, to check that we could
+    /// win an election without increasing our term. This prevents partitioned
+    /// nodes from disrupting the cluster when they rejoin.
+    pub prevote: bool,
+    /// Whether leaders step down if they haven't heard from a quorum within an
+    /// election timeout.
+    pub check_quorum: bool,
+    /// Whether leaders serve reads locally while they hold a lease, i.e. within
+    /// an election timeout (minus a clock drift margin) of a heartbeat
+    /// confirmed by a quorum. Otherwise, every read is confirmed by a quorum.
+    /// Requires pre-votes, since followers would otherwise vote for a new
+    /// leader while the lease is held.
+    pub lease_reads: bool,
+    /// The maximum number of unacknowledged Append messages in flight to each
+    /// peer. Once reached, new entries are batched up until the peer
+    /// acknowledges an append. Must be at least 1.
+    pub max_inflight: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            snapshot_threshold: 10000,
+            prevote: true,
+            check_quorum: true,
+            lease_reads: false,
+            max_inflight: 16,
+        }
+    }
+}
+
+/// A state machine snapshot, used to bring lagging followers up to date once
+/// the entries they need have been compacted out of the leader's log.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    /// The index of the last log entry included in the snapshot.
+    pub index: Index,
+    /// The term of the last log entry included in the snapshot.
+    pub term: Term,
+    /// The cluster membership as of the snapshot index.
+    pub membership: Option<Membership>,
+    /// The snapshot data.
+    pub data: Vec<u8>,
+}
+
 /// Generates a randomized election timeout.
 fn rand_election_timeout() -> Ticks {
     rand::thread_rng().gen_range(ELECTION_TIMEOUT_RANGE)
@@ -50,9 +104,10 @@ impl Node {
         log: Log,
         state: Box<dyn State>,
         node_tx: crossbeam::channel::Sender<Envelope>,
+        opts: Options,
     ) -> Result<Self> {
-        let node = RawNode::new(id, peers, log, state, node_tx)?;
-        if node.peers.is_empty() {
+        let node = RawNode::new(id, peers, log, state, node_tx, opts)?;
+        if node.peers.is_empty() && node.is_voter() {
             // If there are no peers, become leader immediately.
             return Ok(node.into_candidate()?.into_leader()?.into());
         }
@@ -77,6 +132,17 @@ impl Node {
         }
     }
 
+    /// Returns the current cluster membership, or None if the log does not
+    /// contain one yet (i.e. the initial peers given to Node::new are used).
+    pub fn membership(&self) -> Option<&Membership> {
+        let log = match self {
+            Node::Candidate(n) => &n.log,
+            Node::Follower(n) => &n.log,
+            Node::Leader(n) => &n.log,
+        };
+        log.get_membership().map(|(_, membership)| membership)
+    }
+
     /// Processes a message from a peer.
     pub fn step(self, msg: Envelope) -> Result<Self> {
         debug!("Stepping {:?}", msg);
@@ -125,10 +191,12 @@ pub trait Role: Clone + std::fmt::Debug + PartialEq {}
 pub struct RawNode<R: Role = Follower> {
     id: NodeID,
     peers: HashSet<NodeID>,
+    learners: HashSet<NodeID>,
     term: Term,
     log: Log,
     state: Box<dyn State>,
     node_tx: crossbeam::channel::Sender<Envelope>,
+    opts: Options,
     role: R,
 }
 
@@ -138,10 +206,12 @@ impl<R: Role> RawNode<R> {
         RawNode {
             id: self.id,
             peers: self.peers,
+            learners: self.learners,
             term: self.term,
             log: self.log,
             state: self.state,
             node_tx: self.node_tx,
+            opts: self.opts,
             role,
         }
     }
@@ -149,7 +219,8 @@ impl<R: Role> RawNode<R> {
     /// Applies any pending, committed entries to the state machine. The command
     /// responses are discarded, use maybe_apply_with() instead to access them.
     fn maybe_apply(&mut self) -> Result<()> {
-        Self::maybe_apply_with(&mut self.log, &mut self.state, |_, _| Ok(()))
+        Self::maybe_apply_with(&mut self.log, &mut self.state, |_, _| Ok(()))?;
+        self.maybe_compact()
     }
 
     /// Like maybe_apply(), but calls the given closure with the result of every
@@ -177,6 +248,47 @@ impl<R: Role> RawNode<R> {
         Ok(())
     }
 
+    /// Compacts the log if the number of applied entries since the last
+    /// snapshot exceeds the snapshot threshold. The state machine is flushed
+    /// first, since it becomes the only copy of the compacted entries.
+    ///
+    /// The last half threshold of applied entries is retained, such that
+    /// followers that are only slightly behind can catch up from the log
+    /// instead of requiring a full state machine snapshot.
+    fn maybe_compact(&mut self) -> Result<()> {
+        let threshold = self.opts.snapshot_threshold;
+        let applied_index = self.state.get_applied_index();
+        let (snapshot_index, _) = self.log.get_snapshot_index();
+        if threshold == 0 || applied_index < snapshot_index + threshold {
+            return Ok(());
+        }
+        let compact_index = applied_index - threshold / 2;
+        debug!("Compacting log up to index {} (applied {})", compact_index, applied_index);
+        self.state.flush()?;
+        self.log.compact(compact_index)?;
+        Ok(())
+    }
+
+    /// Updates the voting peers and learners from the latest membership in the
+    /// log. If there is none, the initial peers are retained.
+    fn update_peers(&mut self) {
+        if let Some((_, membership)) = self.log.get_membership() {
+            self.peers = membership.voters.iter().copied().filter(|id| *id != self.id).collect();
+            self.learners =
+                membership.learners.iter().copied().filter(|id| *id != self.id).collect();
+        }
+    }
+
+    /// Returns true if the local node is a voter in the latest membership.
+    /// Learners and nodes that have been removed from the cluster never
+    /// campaign nor vote.
+    fn is_voter(&self) -> bool {
+        match self.log.get_membership() {
+            Some((_, membership)) => membership.voters.contains(&self.id),
+            None => true,
+        }
+    }
+
     /// Returns the size of the cluster.
     fn cluster_size(&self) -> u8 {
         self.peers.len() as u8 + 1
@@ -196,7 +308,13 @@ impl<R: Role> RawNode<R> {
 
     /// Sends a message.
     fn send(&self, to: NodeID, message: Message) -> Result<()> {
-        let msg = Envelope { from: self.id, to, term: self.term, message };
+        self.send_term(to, self.term, message)
+    }
+
+    /// Sends a message for the given term. Only pre-votes use a term other
+    /// than our own.
+    fn send_term(&self, to: NodeID, term: Term, message: Message) -> Result<()> {
+        let msg = Envelope { from: self.id, to, term, message };
         debug!("Sending {msg:?}");
         Ok(self.node_tx.send(msg)?)
     }
@@ -210,6 +328,31 @@ impl<R: Role> RawNode<R> {
         Ok(())
     }
 
+    /// Responds to a pre-vote request for a future term, without changing our
+    /// term or vote. The vote is granted if we're a voter, we don't have a
+    /// leader, and the candidate's log is at least as up-to-date as ours. A
+    /// granted vote is sent in the candidate's proposed term. See section 9.6
+    /// in the Raft dissertation.
+    fn step_prevote(&self, msg: &Envelope, has_leader: bool) -> Result<()> {
+        let Message::PreVote { last_index, last_term } = msg.message else {
+            panic!("Expected PreVote, got {:?}", msg.message);
+        };
+        let (log_index, log_term) = self.log.get_last_index();
+        let vote = msg.term > self.term
+            && !has_leader
+            && self.is_voter()
+            && (last_term > log_term || last_term == log_term && last_index >= log_index);
+        let term = if vote { msg.term } else { self.term };
+        self.send_term(msg.from, term, Message::PreVoteResponse { vote })
+    }
+
+    /// Serves a local read request from the local state machine, without
+    /// confirming leadership. The caller must enforce any staleness bound.
+    fn read_local(&self, id: RequestID, command: Vec<u8>) -> Result<()> {
+        let response = self.state.read(command).map(Response::Read);
+        self.send(self.id, Message::ClientResponse { id, response })
+    }
+
     /// Asserts common node invariants.
     fn assert_node(&mut self) -> Result<()> {
         debug_assert_eq!(self.term, self.log.get_term()?.0, "Term does not match log");
@@ -220,13 +363,21 @@ impl<R: Role> RawNode<R> {
     fn assert_step(&self, msg: &Envelope) {
         // Messages must be addressed to the local node.
         assert_eq!(msg.to, self.id, "Message to other node");
+    }
 
-        // Senders must be known.
-        assert!(
-            msg.from == self.id || self.peers.contains(&msg.from),
-            "Unknown sender {}",
-            msg.from
-        );
+    /// Checks whether a message sender is a known peer. Messages from unknown
+    /// senders are dropped, since they may come from a node that has been
+    /// removed from the cluster (but doesn't know it), or from a new node
+    /// whose membership change we haven't seen yet.
+    fn is_known_sender(&self, msg: &Envelope) -> bool {
+        if msg.from != self.id
+            && !self.peers.contains(&msg.from)
+            && !self.learners.contains(&msg.from)
+        {
+            debug!("Dropping message from unknown sender {}: {:?}", msg.from, msg);
+            return false;
+        }
+        true
     }
 }
 
@@ -250,7 +401,7 @@ mod tests {
     use super::*;
     use crate::storage;
     use pretty_assertions::assert_eq;
-    use std::collections::HashSet;
+    use std::collections::{BTreeMap, HashSet};
 
     #[track_caller]
     pub fn assert_messages<T: std::fmt::Debug + PartialEq>(
@@ -308,6 +459,12 @@ mod tests {
             self
         }
 
+        #[track_caller]
+        pub fn snapshot(mut self, index: Index) -> Self {
+            assert_eq!(index, self.log().get_snapshot_index().0, "Unexpected snapshot index");
+            self
+        }
+
         #[track_caller]
         pub fn entry(mut self, entry: Entry) -> Self {
             assert!(entry.index <= self.log().get_last_index().0, "Index beyond last entry");
@@ -394,6 +551,8 @@ mod tests {
             assert_eq!(
                 saved_voted_for,
                 match self.node {
+                    // Candidates running a pre-vote haven't voted in the term.
+                    Node::Candidate(n) if n.role.prevote => saved_voted_for,
                     Node::Candidate(n) => Some(n.id),
                     Node::Follower(n) => n.role.voted_for,
                     Node::Leader(n) => Some(n.id),
@@ -436,10 +595,12 @@ mod tests {
             role: Follower::new(None, None),
             id: 1,
             peers: HashSet::from_iter(peers),
+            learners: HashSet::new(),
             term: 1,
             log: Log::new(storage::Memory::new(), false)?,
             state: Box::new(TestState::new(0)),
             node_tx,
+            opts: Options::default(),
         };
         Ok((node, node_rx))
     }
@@ -453,6 +614,7 @@ mod tests {
             Log::new(storage::Memory::new(), false)?,
             Box::new(TestState::new(0)),
             node_tx,
+            Options::default(),
         )?;
         match node {
             Node::Follower(rolenode) => {
@@ -474,6 +636,7 @@ mod tests {
             Log::new(storage::Memory::new(), false)?,
             Box::new(TestState::new(0)),
             node_tx,
+            Options::default(),
         )?;
         match node {
             Node::Leader(rolenode) => {
@@ -529,4 +692,182 @@ mod tests {
         assert_eq!(super::quorum_value(vec![1, 1, 1, 2, 2]), 1);
         assert_eq!(super::quorum_value(vec![1, 1, 2, 2, 2]), 2);
     }
+
+    /// A simulated cluster of Raft nodes, with messages delivered in-process.
+    /// Partitioned nodes can't send messages to or receive messages from any
+    /// other node.
+    struct Cluster {
+        nodes: BTreeMap<NodeID, Node>,
+        node_rxs: BTreeMap<NodeID, crossbeam::channel::Receiver<Envelope>>,
+        partitioned: HashSet<NodeID>,
+    }
+
+    impl Cluster {
+        /// Creates a new cluster with the given number of nodes, and ticks it
+        /// until a leader is elected.
+        fn new(size: u8, opts: Options) -> Result<Self> {
+            let mut cluster = Self {
+                nodes: BTreeMap::new(),
+                node_rxs: BTreeMap::new(),
+                partitioned: HashSet::new(),
+            };
+            for id in 1..=size {
+                let (node_tx, node_rx) = crossbeam::channel::unbounded();
+                let peers = (1..=size).filter(|p| *p != id).collect();
+                let log = Log::new(storage::Memory::new(), false)?;
+                let state = Box::new(TestState::new(0));
+                let node = Node::new(id, peers, log, state, node_tx, opts.clone())?;
+                cluster.nodes.insert(id, node);
+                cluster.node_rxs.insert(id, node_rx);
+            }
+            cluster.tick_until(|c| c.leader().is_some())?;
+            Ok(cluster)
+        }
+
+        /// Returns the leader among the non-partitioned nodes, if any.
+        fn leader(&self) -> Option<NodeID> {
+            let mut leaders = self
+                .nodes
+                .iter()
+                .filter(|(id, node)| {
+                    !self.partitioned.contains(id) && matches!(node, Node::Leader(_))
+                })
+                .map(|(id, _)| *id);
+            let leader = leaders.next();
+            assert_eq!(leaders.next(), None, "multiple leaders");
+            leader
+        }
+
+        /// Returns the given node.
+        fn node(&mut self, id: NodeID) -> &mut Node {
+            self.nodes.get_mut(&id).unwrap()
+        }
+
+        /// Delivers pending messages until there are none left, dropping
+        /// messages to or from partitioned nodes.
+        fn deliver(&mut self) -> Result<()> {
+            loop {
+                let msgs: Vec<Envelope> =
+                    self.node_rxs.values().flat_map(|rx| rx.try_iter()).collect();
+                if msgs.is_empty() {
+                    return Ok(());
+                }
+                for msg in msgs {
+                    if self.partitioned.contains(&msg.from) || self.partitioned.contains(&msg.to) {
+                        continue;
+                    }
+                    let node = self.nodes.remove(&msg.to).unwrap();
+                    self.nodes.insert(msg.to, node.step(msg)?);
+                }
+            }
+        }
+
+        /// Ticks all nodes and delivers the resulting messages.
+        fn tick(&mut self) -> Result<()> {
+            for id in self.nodes.keys().copied().collect_vec() {
+                let node = self.nodes.remove(&id).unwrap();
+                self.nodes.insert(id, node.tick()?);
+            }
+            self.deliver()
+        }
+
+        /// Ticks the cluster until the given condition holds, or panics after
+        /// 10 election timeouts.
+        fn tick_until(&mut self, condition: impl Fn(&Self) -> bool) -> Result<()> {
+            for _ in 0..10 * ELECTION_TIMEOUT_RANGE.end as usize {
+                if condition(self) {
+                    return Ok(());
+                }
+                self.tick()?;
+            }
+            panic!("condition not reached")
+        }
+
+        /// Ticks the cluster for the given number of election timeouts.
+        fn tick_timeouts(&mut self, timeouts: usize) -> Result<()> {
+            for _ in 0..timeouts * ELECTION_TIMEOUT_RANGE.end as usize {
+                self.tick()?;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    // A partitioned follower doesn't increase its term when pre-voting, and
+    // doesn't disrupt the leader when the partition heals.
+    fn partition_follower_prevote() -> Result<()> {
+        let mut cluster = Cluster::new(3, Options::default())?;
+        let leader = cluster.leader().unwrap();
+        let term = cluster.node(leader).term();
+        let follower = (1..=3).find(|id| *id != leader).unwrap();
+
+        cluster.partitioned.insert(follower);
+        cluster.tick_timeouts(5)?;
+        assert_node(cluster.node(follower)).is_candidate().term(term);
+        assert_eq!(cluster.leader(), Some(leader));
+
+        cluster.partitioned.clear();
+        cluster.tick_timeouts(2)?;
+        assert_eq!(cluster.leader(), Some(leader));
+        assert_node(cluster.node(leader)).term(term);
+        assert_node(cluster.node(follower)).is_follower().term(term).leader(Some(leader));
+        Ok(())
+    }
+
+    #[test]
+    // Without pre-votes, a partitioned follower keeps increasing its term, and
+    // forces the leader to step down when the partition heals.
+    fn partition_follower_no_prevote() -> Result<()> {
+        let opts = Options { prevote: false, ..Default::default() };
+        let mut cluster = Cluster::new(3, opts)?;
+        let leader = cluster.leader().unwrap();
+        let term = cluster.node(leader).term();
+        let follower = (1..=3).find(|id| *id != leader).unwrap();
+
+        cluster.partitioned.insert(follower);
+        cluster.tick_timeouts(5)?;
+        assert!(cluster.node(follower).term() > term);
+
+        cluster.partitioned.clear();
+        cluster.tick_until(|c| c.leader().is_some_and(|id| c.nodes[&id].term() > term))?;
+        Ok(())
+    }
+
+    #[test]
+    // A partitioned leader steps down once it loses contact with the quorum,
+    // while the majority elects a new leader. When the partition heals, the
+    // old leader follows the new one.
+    fn partition_leader_check_quorum() -> Result<()> {
+        let mut cluster = Cluster::new(3, Options::default())?;
+        let old_leader = cluster.leader().unwrap();
+        let term = cluster.node(old_leader).term();
+
+        cluster.partitioned.insert(old_leader);
+        cluster.tick_timeouts(1)?;
+        assert!(!matches!(cluster.node(old_leader), Node::Leader(_)));
+        cluster.tick_until(|c| c.leader().is_some())?;
+        assert_eq!(cluster.node(old_leader).term(), term);
+        let leader = cluster.leader().unwrap();
+        let new_term = cluster.node(leader).term();
+        assert!(new_term > term);
+
+        cluster.partitioned.clear();
+        cluster.tick_timeouts(2)?;
+        assert_eq!(cluster.leader(), Some(leader));
+        assert_node(cluster.node(old_leader)).is_follower().term(new_term).leader(Some(leader));
+        Ok(())
+    }
+
+    #[test]
+    // Without check-quorum, a partitioned leader remains leader.
+    fn partition_leader_no_check_quorum() -> Result<()> {
+        let opts = Options { check_quorum: false, ..Default::default() };
+        let mut cluster = Cluster::new(3, opts)?;
+        let old_leader = cluster.leader().unwrap();
+
+        cluster.partitioned.insert(old_leader);
+        cluster.tick_timeouts(2)?;
+        assert!(matches!(cluster.node(old_leader), Node::Leader(_)));
+        Ok(())
+    }
 }
@@ -19,11 +19,25 @@ pub trait State: Send {
 
     /// Reads from the state machine. All errors are propagated to the caller.
     fn read(&self, command: Vec<u8>) -> Result<Vec<u8>>;
+
+    /// Takes a snapshot of the state machine as of the last applied index.
+    /// This is sent to followers that lag behind the leader's compacted log.
+    fn snapshot(&self) -> Result<Vec<u8>>;
+
+    /// Replaces the entire state machine with the given snapshot, taken at the
+    /// given applied index. The snapshot must be durable when this returns.
+    fn restore(&mut self, index: Index, snapshot: Vec<u8>) -> Result<()>;
+
+    /// Flushes the state machine to durable storage. This is called before
+    /// compacting the Raft log, since the state machine then becomes the only
+    /// copy of the compacted entries.
+    fn flush(&mut self) -> Result<()>;
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::encoding::bincode;
     use std::sync::{Arc, Mutex};
 
     #[derive(Clone, Debug)]
@@ -60,5 +74,21 @@ pub mod tests {
             self.commands.lock()?.push(command.clone());
             Ok(command)
         }
+
+        // Serializes the internal command list.
+        fn snapshot(&self) -> Result<Vec<u8>> {
+            bincode::serialize(&*self.commands.lock()?)
+        }
+
+        // Replaces the internal command list.
+        fn restore(&mut self, index: Index, snapshot: Vec<u8>) -> Result<()> {
+            *self.commands.lock()? = bincode::deserialize(&snapshot)?;
+            *self.applied_index.lock()? = index;
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
     }
 }
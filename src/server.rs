@@ -2,7 +2,7 @@ use crate::encoding::bincode;
 use crate::error::{Error, Result};
 use crate::raft;
 use crate::sql;
-use crate::sql::engine::Engine as _;
+use crate::sql::engine::{ActiveTransaction, Engine as _, Transaction as _};
 use crate::sql::execution::ResultSet;
 use crate::sql::schema::{Catalog as _, Table};
 use crate::sql::types::Row;
@@ -11,9 +11,11 @@ use crate::storage;
 use crossbeam::channel::{Receiver, Sender};
 use log::{debug, error, info};
 use serde_derive::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::Write as _;
 use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
 /// The outbound peer channel capacity. This buffers messages when a Raft
 /// peer is slow or unavailable. Beyond this, messages will be dropped.
@@ -22,6 +24,17 @@ const RAFT_PEER_CHANNEL_CAPACITY: usize = 1000;
 /// The retry interval when connecting to a Raft peer.
 const RAFT_PEER_RETRY_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
 
+/// The interval between MVCC garbage collection runs.
+const GC_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// The interval between heartbeats for read-write transactions of SQL
+/// sessions, and between checks for abandoned transactions.
+const HEARTBEAT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// The versions of the read-write transactions of a node's SQL sessions,
+/// which are kept alive by heartbeats.
+type SessionTxns = Arc<Mutex<HashSet<u64>>>;
+
 /// A Radb server. Routes messages to/from an inner Raft node.
 ///
 /// - Listens for inbound Raft connections via TCP and passes messages to the
@@ -32,6 +45,13 @@ const RAFT_PEER_RETRY_INTERVAL: std::time::Duration = std::time::Duration::from_
 ///
 /// - Listens for inbound SQL connections via TCP and passes requests to
 ///   the local Raft node.
+///
+/// - Periodically garbage collects old MVCC versions via Raft, if enabled
+///   and the local node is the leader.
+///
+/// - Periodically sends heartbeats for the read-write transactions of local
+///   SQL sessions, and rolls back transactions without recent heartbeats if
+///   the local node is the leader, if enabled.
 pub struct Server {
     /// The inner Raft node.
     node: raft::Node,
@@ -39,6 +59,14 @@ pub struct Server {
     node_rx: Receiver<raft::Envelope>,
     /// Raft peer IDs and addresses.
     peers: HashMap<raft::NodeID, String>,
+    /// The MVCC history retention period for time-travel queries, or None to
+    /// disable garbage collection.
+    gc_retention: Option<std::time::Duration>,
+    /// The time SQL sessions wait for row locks.
+    lock_timeout: std::time::Duration,
+    /// The time after which read-write transactions without a heartbeat are
+    /// rolled back, or None to disable this.
+    txn_timeout: Option<std::time::Duration>,
 }
 
 impl Server {
@@ -48,6 +76,7 @@ impl Server {
         peers: HashMap<raft::NodeID, String>,
         raft_log: raft::Log,
         raft_state: Box<dyn raft::State>,
+        raft_opts: raft::Options,
     ) -> Result<Self> {
         let (node_tx, node_rx) = crossbeam::channel::unbounded();
         Ok(Self {
@@ -57,12 +86,37 @@ impl Server {
                 raft_log,
                 raft_state,
                 node_tx,
+                raft_opts,
             )?,
             peers,
             node_rx,
+            gc_retention: None,
+            lock_timeout: sql::engine::LOCK_TIMEOUT,
+            txn_timeout: None,
         })
     }
 
+    /// Enables periodic garbage collection of old MVCC versions, retaining the
+    /// given period of history for time-travel queries, or disables it if None.
+    pub fn set_gc_retention(&mut self, retention: Option<std::time::Duration>) {
+        self.gc_retention = retention;
+    }
+
+    /// Sets the time SQL sessions wait for row locks (SELECT ... FOR UPDATE)
+    /// before giving up with a serialization error.
+    pub fn set_lock_timeout(&mut self, lock_timeout: std::time::Duration) {
+        self.lock_timeout = lock_timeout;
+    }
+
+    /// Enables rolling back read-write transactions that haven't had a
+    /// heartbeat within the given timeout, e.g. because the client's node
+    /// crashed, or disables it if None. Heartbeat times are taken from the
+    /// clock of the session's node, but compared with the leader's clock, so
+    /// the timeout must be well above the clock skew between nodes.
+    pub fn set_txn_timeout(&mut self, timeout: Option<std::time::Duration>) {
+        self.txn_timeout = timeout;
+    }
+
     /// Serves Raft and SQL requests indefinitely. Consumes the server.
     pub fn serve(self, raft_addr: impl ToSocketAddrs, sql_addr: impl ToSocketAddrs) -> Result<()> {
         let raft_listener = TcpListener::bind(raft_addr)?;
@@ -92,6 +146,8 @@ impl Server {
             }
 
             // Route Raft messages between the local node, peers, and clients.
+            let is_leader = Arc::new(AtomicBool::new(false));
+            let route_is_leader = is_leader.clone();
             s.spawn(move || {
                 Self::raft_route(
                     self.node,
@@ -99,11 +155,30 @@ impl Server {
                     raft_step_rx,
                     raft_peers_tx,
                     raft_request_rx,
+                    route_is_leader,
                 )
             });
 
+            // Garbage collect old MVCC versions.
+            if let Some(retention) = self.gc_retention {
+                let raft_request_tx = raft_request_tx.clone();
+                s.spawn(move || Self::gc(id, retention, raft_request_tx));
+            }
+
+            // Send heartbeats for local transactions, and expire abandoned ones.
+            let txns = SessionTxns::default();
+            if let Some(timeout) = self.txn_timeout {
+                let (heartbeat_txns, heartbeat_tx) = (txns.clone(), raft_request_tx.clone());
+                s.spawn(move || Self::heartbeat(heartbeat_txns, heartbeat_tx));
+                let expire_tx = raft_request_tx.clone();
+                s.spawn(move || Self::expire(is_leader, timeout, expire_tx));
+            }
+
             // Serve inbound SQL connections.
-            s.spawn(move || Self::sql_accept(id, sql_listener, raft_request_tx));
+            let lock_timeout = self.lock_timeout;
+            s.spawn(move || {
+                Self::sql_accept(id, sql_listener, raft_request_tx, lock_timeout, txns)
+            });
         });
 
         Ok(())
@@ -188,6 +263,7 @@ impl Server {
         peers_rx: Receiver<raft::Envelope>,
         mut peers_tx: HashMap<raft::NodeID, Sender<raft::Envelope>>,
         request_rx: Receiver<(raft::Request, Sender<Result<raft::Response>>)>,
+        is_leader: Arc<AtomicBool>,
     ) {
         // Track response channels by request ID. The Raft node will emit
         // ClientResponse messages that we forward to the response channel.
@@ -195,6 +271,9 @@ impl Server {
 
         let ticker = crossbeam::channel::tick(raft::TICK_INTERVAL);
         loop {
+            // Publish whether the node is the leader, for background tasks.
+            is_leader.store(matches!(node, raft::Node::Leader(_)), Ordering::Relaxed);
+
             crossbeam::select! {
                 // Periodically tick the node.
                 recv(ticker) -> _ => node = node.tick().expect("tick failed"),
@@ -218,6 +297,18 @@ impl Server {
                             continue
                         }
                     }
+                    // Connect to peers that were added via membership changes.
+                    if let std::collections::hash_map::Entry::Vacant(entry) = peers_tx.entry(msg.to) {
+                        let addr = node.membership().and_then(|m| m.addrs.get(&msg.to)).cloned();
+                        let Some(addr) = addr else {
+                            error!("Unknown Raft peer {}, dropping message", msg.to);
+                            continue
+                        };
+                        let (peer_tx, peer_rx) =
+                            crossbeam::channel::bounded(RAFT_PEER_CHANNEL_CAPACITY);
+                        std::thread::spawn(move || Self::raft_send_peer(addr, peer_rx));
+                        entry.insert(peer_tx);
+                    }
                     let peer_tx = peers_tx.get_mut(&msg.to).expect("unknown peer");
                     match peer_tx.try_send(msg) {
                         Ok(()) => {},
@@ -247,11 +338,85 @@ impl Server {
         }
     }
 
+    /// Periodically garbage collects old MVCC versions, retaining the given
+    /// period of history. GC is replicated via Raft, so only the leader
+    /// submits it, to avoid redundant runs.
+    fn gc(
+        id: raft::NodeID,
+        retention: std::time::Duration,
+        raft_request_tx: Sender<(raft::Request, Sender<Result<raft::Response>>)>,
+    ) {
+        let engine = sql::engine::Raft::new(raft_request_tx);
+        loop {
+            std::thread::sleep(GC_INTERVAL);
+            match engine.status() {
+                Ok(status) if status.raft.leader == id => {}
+                Ok(_) => continue,
+                Err(err) => {
+                    error!("Failed fetching status for garbage collection: {err}");
+                    continue;
+                }
+            }
+            match engine.gc(retention) {
+                Ok(0) => {}
+                Ok(removed) => info!("Garbage collected {removed} MVCC versions"),
+                Err(err) => error!("Garbage collection failed: {err}"),
+            }
+        }
+    }
+
+    /// Periodically records heartbeats for the read-write transactions of
+    /// local SQL sessions, which prevents the leader from expiring them.
+    fn heartbeat(
+        txns: SessionTxns,
+        raft_request_tx: Sender<(raft::Request, Sender<Result<raft::Response>>)>,
+    ) {
+        let engine = sql::engine::Raft::new(raft_request_tx);
+        loop {
+            std::thread::sleep(HEARTBEAT_INTERVAL);
+            let txns: Vec<u64> = txns.lock().expect("lock poisoned").iter().copied().collect();
+            if txns.is_empty() {
+                continue;
+            }
+            if let Err(err) = engine.heartbeat(txns) {
+                error!("Transaction heartbeat failed: {err}");
+            }
+        }
+    }
+
+    /// Periodically rolls back read-write transactions that haven't had a
+    /// heartbeat within the timeout. Like GC, only the leader submits this.
+    /// Since this runs every heartbeat interval, leadership is checked via the
+    /// local Raft node rather than a status request.
+    fn expire(
+        is_leader: Arc<AtomicBool>,
+        timeout: std::time::Duration,
+        raft_request_tx: Sender<(raft::Request, Sender<Result<raft::Response>>)>,
+    ) {
+        let engine = sql::engine::Raft::new(raft_request_tx);
+        loop {
+            std::thread::sleep(HEARTBEAT_INTERVAL);
+            if !is_leader.load(Ordering::Relaxed) {
+                continue;
+            }
+            match engine.expire(timeout) {
+                Ok(expired) => {
+                    for version in expired {
+                        info!("Rolled back abandoned transaction {version}");
+                    }
+                }
+                Err(err) => error!("Transaction expiry failed: {err}"),
+            }
+        }
+    }
+
     /// Accepts new SQL client connections and spawns session threads for them.
     fn sql_accept(
         id: raft::NodeID,
         listener: TcpListener,
         raft_request_tx: Sender<(raft::Request, Sender<Result<raft::Response>>)>,
+        lock_timeout: std::time::Duration,
+        txns: SessionTxns,
     ) {
         std::thread::scope(|s| loop {
             let (socket, peer) = match listener.accept() {
@@ -261,10 +426,10 @@ impl Server {
                     continue;
                 }
             };
-            let raft_request_tx = raft_request_tx.clone();
+            let (raft_request_tx, txns) = (raft_request_tx.clone(), txns.clone());
             s.spawn(move || {
                 debug!("Client {peer} connected");
-                match Self::sql_session(id, socket, raft_request_tx) {
+                match Self::sql_session(id, socket, raft_request_tx, lock_timeout, txns) {
                     Ok(()) => debug!("Client {peer} disconnected"),
                     Err(err) => error!("Client {peer} error: {err}"),
                 }
@@ -278,8 +443,13 @@ impl Server {
         id: raft::NodeID,
         socket: TcpStream,
         raft_request_tx: Sender<(raft::Request, Sender<Result<raft::Response>>)>,
+        lock_timeout: std::time::Duration,
+        txns: SessionTxns,
     ) -> Result<()> {
-        let mut session = sql::engine::Raft::new(raft_request_tx).session();
+        let mut engine = sql::engine::Raft::new(raft_request_tx);
+        engine.set_lock_timeout(lock_timeout);
+        let mut session = engine.session();
+        let mut session_txn = SessionTxn { txns, version: None };
         let mut reader = std::io::BufReader::new(socket.try_clone()?);
         let mut writer = std::io::BufWriter::new(socket);
 
@@ -298,7 +468,19 @@ impl Server {
                     .status()
                     .map(|s| Status { server: id, raft: s.raft, mvcc: s.mvcc })
                     .map(Response::Status),
+                Request::ChangeMembership(change) => {
+                    session.change_membership(change).map(|()| Response::ChangeMembership)
+                }
+                Request::TransferLeadership(id) => {
+                    session.transfer_leadership(id).map(|()| Response::TransferLeadership)
+                }
+                Request::SetMaxStaleness(max_staleness) => {
+                    session.set_max_staleness(max_staleness);
+                    Ok(Response::SetMaxStaleness)
+                }
+                Request::ListTransactions => session.transactions().map(Response::ListTransactions),
             };
+            session_txn.set(session.txn().filter(|t| !t.read_only()).map(|t| t.version()));
 
             // Process response.
             debug!("Returning response {response:?}");
@@ -345,6 +527,17 @@ pub enum Request {
     ListTables,
     /// Returns server status.
     Status,
+    /// Adds or removes a Raft cluster node.
+    ChangeMembership(raft::MembershipChange),
+    /// Transfers Raft leadership to the given node.
+    TransferLeadership(raft::NodeID),
+    /// Allows read-only transactions in this session to be served by the
+    /// local node if it has heard from the Raft leader within the given
+    /// duration, or always uses the leader if None. Historical (AS OF)
+    /// transactions are served locally regardless, if possible.
+    SetMaxStaleness(Option<std::time::Duration>),
+    /// Lists active read-write transactions.
+    ListTransactions,
 }
 
 /// A SQL server response.
@@ -355,6 +548,10 @@ pub enum Response {
     GetTable(Table),
     ListTables(Vec<String>),
     Status(Status),
+    ChangeMembership,
+    TransferLeadership,
+    SetMaxStaleness,
+    ListTransactions(Vec<ActiveTransaction>),
 }
 
 /// SQL server status.
@@ -364,3 +561,33 @@ pub struct Status {
     pub raft: raft::Status,
     pub mvcc: storage::mvcc::Status,
 }
+
+/// Registers a session's read-write transaction, if any, for heartbeats.
+/// Deregisters it when dropped.
+struct SessionTxn {
+    txns: SessionTxns,
+    version: Option<u64>,
+}
+
+impl SessionTxn {
+    /// Sets the session's current read-write transaction version.
+    fn set(&mut self, version: Option<u64>) {
+        if version == self.version {
+            return;
+        }
+        let mut txns = self.txns.lock().expect("lock poisoned");
+        if let Some(old) = self.version {
+            txns.remove(&old);
+        }
+        if let Some(new) = version {
+            txns.insert(new);
+        }
+        self.version = version;
+    }
+}
+
+impl Drop for SessionTxn {
+    fn drop(&mut self) {
+        self.set(None)
+    }
+}
@@ -3,7 +3,7 @@ use super::super::types::{Expression, Row, Value};
 use super::Transaction as _;
 use crate::encoding::{bincode, keycode};
 use crate::error::{Error, Result};
-use crate::storage;
+use crate::storage::{self, mvcc::LockMode};
 
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
@@ -55,6 +55,10 @@ impl<E: storage::Engine> super::Engine for KV<E> {
         Ok(Self::Transaction::new(self.kv.begin()?))
     }
 
+    fn begin_serializable(&self) -> Result<Self::Transaction> {
+        Ok(Self::Transaction::new(self.kv.begin_serializable()?))
+    }
+
     fn begin_read_only(&self) -> Result<Self::Transaction> {
         Ok(Self::Transaction::new(self.kv.begin_read_only()?))
     }
@@ -127,11 +131,29 @@ impl<E: storage::Engine> super::Transaction for Transaction<E> {
     }
 
     fn commit(self) -> Result<()> {
-        self.txn.commit()
+        self.txn.commit()?;
+        super::notify_lock_release()
     }
 
     fn rollback(self) -> Result<()> {
-        self.txn.rollback()
+        self.txn.rollback()?;
+        super::notify_lock_release()
+    }
+
+    fn savepoint(&mut self) -> Result<u64> {
+        Ok(self.txn.savepoint())
+    }
+
+    fn rollback_to(&mut self, savepoint: u64) -> Result<()> {
+        self.txn.rollback_to(savepoint)
+    }
+
+    fn release(&mut self, savepoint: u64) -> Result<()> {
+        self.txn.release(savepoint)
+    }
+
+    fn try_lock(&mut self, table: &str, id: &Value, mode: LockMode, wait: bool) -> Result<bool> {
+        self.txn.lock(&Key::Row(table.into(), id.into()).encode()?, mode, wait)
     }
 
     fn create(&mut self, table: &str, row: Row) -> Result<()> {
@@ -148,9 +170,10 @@ impl<E: storage::Engine> super::Transaction for Transaction<E> {
 
         // Update indexes
         for (i, column) in table.columns.iter().enumerate().filter(|(_, c)| c.index) {
-            let mut index = self.index_load(&table.name, &column.name, &row[i])?;
+            let value = column.index_value(&row[i])?;
+            let mut index = self.index_load(&table.name, &column.name, &value)?;
             index.insert(id.clone());
-            self.index_save(&table.name, &column.name, &row[i], index)?;
+            self.index_save(&table.name, &column.name, &value, index)?;
         }
         Ok(())
     }
@@ -180,9 +203,10 @@ impl<E: storage::Engine> super::Transaction for Transaction<E> {
         if !indexes.is_empty() {
             if let Some(row) = self.read(&table.name, id)? {
                 for (i, column) in indexes {
-                    let mut index = self.index_load(&table.name, &column.name, &row[i])?;
+                    let value = column.index_value(&row[i])?;
+                    let mut index = self.index_load(&table.name, &column.name, &value)?;
                     index.remove(id);
-                    self.index_save(&table.name, &column.name, &row[i], index)?;
+                    self.index_save(&table.name, &column.name, &value, index)?;
                 }
             }
         }
@@ -269,16 +293,18 @@ impl<E: storage::Engine> super::Transaction for Transaction<E> {
         if !indexes.is_empty() {
             let old = self.read(&table.name, id)?.unwrap();
             for (i, column) in indexes {
-                if old[i] == row[i] {
+                let (old_value, new_value) =
+                    (column.index_value(&old[i])?, column.index_value(&row[i])?);
+                if old_value == new_value {
                     continue;
                 }
-                let mut index = self.index_load(&table.name, &column.name, &old[i])?;
+                let mut index = self.index_load(&table.name, &column.name, &old_value)?;
                 index.remove(id);
-                self.index_save(&table.name, &column.name, &old[i], index)?;
+                self.index_save(&table.name, &column.name, &old_value, index)?;
 
-                let mut index = self.index_load(&table.name, &column.name, &row[i])?;
+                let mut index = self.index_load(&table.name, &column.name, &new_value)?;
                 index.insert(id.clone());
-                self.index_save(&table.name, &column.name, &row[i], index)?;
+                self.index_save(&table.name, &column.name, &new_value, index)?;
             }
         }
 
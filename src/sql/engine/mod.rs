@@ -2,7 +2,7 @@
 mod kv;
 pub mod raft;
 pub use kv::KV;
-pub use raft::{Raft, Status};
+pub use raft::{ActiveTransaction, Raft, Status};
 
 use super::execution::ResultSet;
 use super::parser::{ast, Parser};
@@ -10,8 +10,42 @@ use super::plan::Plan;
 use super::schema::Catalog;
 use super::types::{Expression, Row, Value};
 use crate::error::{Error, Result};
+use crate::storage::mvcc::LockMode;
 
 use std::collections::HashSet;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// The default time to wait for a row lock before giving up.
+pub const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Wakes transactions waiting for row locks when locks may have been released,
+/// i.e. when a transaction commits or rolls back, by bumping a generation
+/// counter. This is process-wide, since a process runs a single Raft node whose
+/// state machine applies all lock releases. Releases by other engines in the
+/// same process (e.g. in tests) only cause a spurious retry.
+static LOCK_RELEASES: (Mutex<u64>, Condvar) = (Mutex::new(0), Condvar::new());
+
+/// Notifies transactions waiting for row locks that locks may have been released.
+fn notify_lock_release() -> Result<()> {
+    let (generation, released) = &LOCK_RELEASES;
+    *generation.lock()? += 1;
+    released.notify_all();
+    Ok(())
+}
+
+/// Returns the current lock release generation.
+fn lock_release_generation() -> Result<u64> {
+    Ok(*LOCK_RELEASES.0.lock()?)
+}
+
+/// Waits until locks may have been released since the given generation, or the timeout elapses.
+fn wait_lock_release(generation: u64, timeout: Duration) -> Result<()> {
+    let (current, released) = &LOCK_RELEASES;
+    let guard = current.lock()?;
+    drop(released.wait_timeout_while(guard, timeout, |current| *current == generation)?);
+    Ok(())
+}
 
 /// The SQL engine interface
 pub trait Engine: Clone {
@@ -21,6 +55,9 @@ pub trait Engine: Clone {
     /// Begins a read-write transaction.
     fn begin(&self) -> Result<Self::Transaction>;
 
+    /// Begins a serializable read-write transaction.
+    fn begin_serializable(&self) -> Result<Self::Transaction>;
+
     /// Begins a read-only transaction.
     fn begin_read_only(&self) -> Result<Self::Transaction>;
 
@@ -29,7 +66,7 @@ pub trait Engine: Clone {
 
     /// Begins a session for executing individual statements
     fn session(&self) -> Session<Self> {
-        Session { engine: self.clone(), txn: None }
+        Session { engine: self.clone(), txn: None, savepoints: Vec::new() }
     }
 }
 
@@ -44,6 +81,41 @@ pub trait Transaction: Catalog {
     fn commit(self) -> Result<()>;
     /// Rolls back the transaction
     fn rollback(self) -> Result<()>;
+    /// Creates a savepoint, returning its ID
+    fn savepoint(&mut self) -> Result<u64>;
+    /// Rolls back to a savepoint, undoing later writes and removing later savepoints
+    fn rollback_to(&mut self, savepoint: u64) -> Result<()>;
+    /// Releases a savepoint and all later savepoints, keeping their writes
+    fn release(&mut self, savepoint: u64) -> Result<()>;
+
+    /// Attempts to lock a table row, returning false if another transaction holds a conflicting
+    /// lock. If wait is true and the lock isn't acquired, records that the transaction is waiting
+    /// for it, and returns a serialization error if waiting would deadlock.
+    fn try_lock(&mut self, table: &str, id: &Value, mode: LockMode, wait: bool) -> Result<bool>;
+    /// The time to wait for a row lock before giving up
+    fn lock_timeout(&self) -> Duration {
+        LOCK_TIMEOUT
+    }
+    /// Locks a table row, waiting for conflicting locks to be released. Returns a serialization
+    /// error if the lock isn't acquired within the lock timeout (immediately if nowait is true),
+    /// or if waiting would deadlock. Lock attempts are only retried when a transaction commits
+    /// or rolls back, rather than polling.
+    fn lock(&mut self, table: &str, id: &Value, mode: LockMode, nowait: bool) -> Result<()> {
+        let deadline = Instant::now() + if nowait { Duration::ZERO } else { self.lock_timeout() };
+        loop {
+            // Read the generation before attempting, so a release that happens before we start
+            // waiting isn't missed. Make a final non-waiting attempt at the deadline, which clears
+            // the wait record.
+            let generation = lock_release_generation()?;
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if self.try_lock(table, id, mode, !remaining.is_zero())? {
+                return Ok(());
+            } else if remaining.is_zero() {
+                return Err(Error::Serialization);
+            }
+            wait_lock_release(generation, remaining)?;
+        }
+    }
 
     /// Creates a new table row
     fn create(&mut self, table: &str, row: Row) -> Result<()>;
@@ -67,6 +139,8 @@ pub struct Session<E: Engine + 'static> {
     engine: E,
     /// The current session transaction, if any
     txn: Option<E::Transaction>,
+    /// The savepoints of the current transaction, as names and IDs
+    savepoints: Vec<(String, u64)>,
 }
 
 impl<E: Engine + 'static> Session<E> {
@@ -79,49 +153,77 @@ impl<E: Engine + 'static> Session<E> {
             ast::Statement::Begin { .. } if self.txn.is_some() => {
                 Err(Error::Value("Already in a transaction".into()))
             }
-            ast::Statement::Begin { read_only: true, as_of: None } => {
+            ast::Statement::Begin { read_only: true, as_of: None, .. } => {
                 let txn = self.engine.begin_read_only()?;
                 let result = ResultSet::Begin { version: txn.version(), read_only: true };
                 self.txn = Some(txn);
                 Ok(result)
             }
-            ast::Statement::Begin { read_only: true, as_of: Some(version) } => {
+            ast::Statement::Begin { read_only: true, as_of: Some(version), .. } => {
                 let txn = self.engine.begin_as_of(version)?;
                 let result = ResultSet::Begin { version, read_only: true };
                 self.txn = Some(txn);
                 Ok(result)
             }
-            ast::Statement::Begin { read_only: false, as_of: Some(_) } => {
+            ast::Statement::Begin { read_only: false, as_of: Some(_), .. } => {
                 Err(Error::Value("Can't start read-write transaction in a given version".into()))
             }
-            ast::Statement::Begin { read_only: false, as_of: None } => {
-                let txn = self.engine.begin()?;
+            ast::Statement::Begin { read_only: false, as_of: None, serializable } => {
+                let txn = match serializable {
+                    true => self.engine.begin_serializable()?,
+                    false => self.engine.begin()?,
+                };
                 let result = ResultSet::Begin { version: txn.version(), read_only: false };
                 self.txn = Some(txn);
                 Ok(result)
             }
-            ast::Statement::Commit | ast::Statement::Rollback if self.txn.is_none() => {
+            ast::Statement::Commit
+            | ast::Statement::Rollback
+            | ast::Statement::Savepoint(_)
+            | ast::Statement::RollbackTo(_)
+            | ast::Statement::Release(_)
+                if self.txn.is_none() =>
+            {
                 Err(Error::Value("Not in a transaction".into()))
             }
             ast::Statement::Commit => {
                 let txn = self.txn.take().unwrap();
                 let version = txn.version();
+                self.savepoints.clear();
                 txn.commit()?;
                 Ok(ResultSet::Commit { version })
             }
             ast::Statement::Rollback => {
                 let txn = self.txn.take().unwrap();
                 let version = txn.version();
+                self.savepoints.clear();
                 txn.rollback()?;
                 Ok(ResultSet::Rollback { version })
             }
+            ast::Statement::Savepoint(name) => {
+                let id = self.txn.as_mut().unwrap().savepoint()?;
+                self.savepoints.push((name.clone(), id));
+                Ok(ResultSet::Savepoint { name })
+            }
+            ast::Statement::RollbackTo(name) => {
+                let index = self.find_savepoint(&name)?;
+                self.txn.as_mut().unwrap().rollback_to(self.savepoints[index].1)?;
+                self.savepoints.truncate(index + 1);
+                Ok(ResultSet::RollbackTo { name })
+            }
+            ast::Statement::Release(name) => {
+                let index = self.find_savepoint(&name)?;
+                self.txn.as_mut().unwrap().release(self.savepoints[index].1)?;
+                self.savepoints.truncate(index);
+                Ok(ResultSet::Release { name })
+            }
             ast::Statement::Explain(statement) => self.with_txn_read_only(|txn| {
                 Ok(ResultSet::Explain(Plan::build(*statement, txn)?.optimize(txn)?.0))
             }),
             statement if self.txn.is_some() => Plan::build(statement, self.txn.as_mut().unwrap())?
                 .optimize(self.txn.as_mut().unwrap())?
                 .execute(self.txn.as_mut().unwrap()),
-            statement @ ast::Statement::Select { .. } => {
+            statement @ ast::Statement::Select { lock: None, .. } => {
                 let mut txn = self.engine.begin_read_only()?;
                 let result =
                     Plan::build(statement, &mut txn)?.optimize(&mut txn)?.execute(&mut txn);
@@ -144,6 +246,19 @@ impl<E: Engine + 'static> Session<E> {
         }
     }
 
+    /// Finds the latest savepoint with the given name, returning its index.
+    fn find_savepoint(&self, name: &str) -> Result<usize> {
+        self.savepoints
+            .iter()
+            .rposition(|(n, _)| n == name)
+            .ok_or_else(|| Error::Value(format!("Savepoint {} does not exist", name)))
+    }
+
+    /// Returns the session's current transaction, if any.
+    pub fn txn(&self) -> Option<&E::Transaction> {
+        self.txn.as_ref()
+    }
+
     /// Runs a read-only closure in the session's transaction, or a new
     /// read-only transaction if none is active.
     ///
@@ -166,6 +281,22 @@ impl Session<Raft> {
     pub fn status(&self) -> Result<Status> {
         self.engine.status()
     }
+
+    pub fn change_membership(&self, change: crate::raft::MembershipChange) -> Result<()> {
+        self.engine.change_membership(change)
+    }
+
+    pub fn transfer_leadership(&self, id: crate::raft::NodeID) -> Result<()> {
+        self.engine.transfer_leadership(id)
+    }
+
+    pub fn set_max_staleness(&mut self, max_staleness: Option<std::time::Duration>) {
+        self.engine.set_max_staleness(max_staleness)
+    }
+
+    pub fn transactions(&self) -> Result<Vec<ActiveTransaction>> {
+        self.engine.transactions()
+    }
 }
 
 impl<E: Engine + 'static> Drop for Session<E> {
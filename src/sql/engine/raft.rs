@@ -4,19 +4,24 @@ use super::{Engine as _, IndexScan, Scan, Transaction as _};
 use crate::encoding::bincode;
 use crate::error::{Error, Result};
 use crate::raft::{self, Entry};
-use crate::storage::{self, mvcc::TransactionState};
+use crate::storage::{
+    self,
+    mvcc::{LockMode, TransactionState},
+};
 
 use crossbeam::channel::Sender;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
+use std::time::{Duration, SystemTime};
 
 /// A Raft state machine mutation.
 ///
 /// TODO: use Cows for these.
 #[derive(Clone, Serialize, Deserialize)]
 enum Mutation {
-    /// Begins a read-write transaction
-    Begin,
+    /// Begins a read-write transaction at the given proposer time (in
+    /// milliseconds since the Unix epoch)
+    Begin { time: u64 },
     /// Commits the given transaction
     Commit(TransactionState),
     /// Rolls back the given transaction
@@ -33,6 +38,32 @@ enum Mutation {
     CreateTable { txn: TransactionState, schema: Table },
     /// Deletes a table
     DeleteTable { txn: TransactionState, table: String },
+
+    /// Garbage collects old MVCC versions, retaining the given number of
+    /// seconds of history as of the given proposer time (in seconds since the
+    /// Unix epoch). See State::gc().
+    GC { time: u64, retention: u64 },
+
+    /// Begins a serializable read-write transaction at the given proposer time
+    BeginSerializable { time: u64 },
+    /// Runs a query in a serializable transaction. These record their reads
+    /// for conflict detection, so they must be applied via the Raft log.
+    Read(Query),
+
+    /// Rolls back the given transaction to a savepoint
+    RollbackTo { txn: TransactionState, savepoint: u64 },
+    /// Releases a savepoint in the given transaction
+    Release { txn: TransactionState, savepoint: u64 },
+
+    /// Attempts to lock a row, recording a wait for it if requested
+    Lock { txn: TransactionState, table: String, id: Value, mode: LockMode, wait: bool },
+
+    /// Records heartbeats for the given read-write transactions at the given
+    /// proposer time. See State::expire().
+    Heartbeat { time: u64, txns: Vec<u64> },
+    /// Rolls back read-write transactions without a heartbeat within the
+    /// given timeout (in milliseconds) as of the given proposer time.
+    Expire { time: u64, timeout: u64 },
 }
 
 /// A Raft state machine query.
@@ -58,6 +89,10 @@ enum Query {
     ScanTables { txn: TransactionState },
     /// Reads a table
     ReadTable { txn: TransactionState, table: String },
+
+    /// Lists active read-write transactions, with their begin and last
+    /// heartbeat times
+    Transactions,
 }
 
 /// Status for the Raft SQL engine.
@@ -67,6 +102,25 @@ pub struct Status {
     pub mvcc: storage::mvcc::Status,
 }
 
+/// An active read-write transaction, as listed by Raft::transactions().
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ActiveTransaction {
+    /// The transaction version.
+    pub version: u64,
+    /// The time since the transaction began.
+    pub age: Duration,
+    /// The time since the transaction's last heartbeat.
+    pub idle: Duration,
+}
+
+/// Returns the current time in milliseconds since the Unix epoch.
+fn now_millis() -> Result<u64> {
+    Ok(SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_err(|err| Error::Internal(err.to_string()))?
+        .as_millis() as u64)
+}
+
 /// A client for the local Raft node.
 #[derive(Clone)]
 struct Client {
@@ -104,6 +158,22 @@ impl Client {
         }
     }
 
+    /// Queries the local Raft state machine, which may be stale, deserializing
+    /// the response into the return type. Returns Error::Abort if the local
+    /// node hasn't applied the leader's commit index as of a heartbeat within
+    /// max_staleness.
+    fn query_local<V: DeserializeOwned>(
+        &self,
+        query: Query,
+        max_staleness: Option<raft::Ticks>,
+    ) -> Result<V> {
+        let command = bincode::serialize(&query)?;
+        match self.execute(raft::Request::ReadLocal { command, max_staleness })? {
+            raft::Response::Read(response) => Ok(bincode::deserialize(&response)?),
+            resp => Err(Error::Internal(format!("Unexpected Raft query response {:?}", resp))),
+        }
+    }
+
     /// Fetches Raft node status.
     fn status(&self) -> Result<raft::Status> {
         match self.execute(raft::Request::Status)? {
@@ -111,18 +181,61 @@ impl Client {
             resp => Err(Error::Internal(format!("Unexpected Raft status response {:?}", resp))),
         }
     }
+
+    /// Changes the Raft cluster membership.
+    fn change_membership(&self, change: raft::MembershipChange) -> Result<()> {
+        match self.execute(raft::Request::ChangeMembership(change))? {
+            raft::Response::Write(_) => Ok(()),
+            resp => Err(Error::Internal(format!("Unexpected Raft membership response {:?}", resp))),
+        }
+    }
+
+    /// Transfers Raft leadership to the given node.
+    fn transfer_leadership(&self, id: raft::NodeID) -> Result<()> {
+        match self.execute(raft::Request::TransferLeadership(id))? {
+            raft::Response::TransferLeadership => Ok(()),
+            resp => Err(Error::Internal(format!("Unexpected Raft transfer response {:?}", resp))),
+        }
+    }
 }
 
 /// A SQL engine using a Raft state machine.
+///
+/// Historical read-only transactions (AS OF) are served from the local state
+/// machine when it has applied the requested version, and otherwise by the
+/// leader. Current read-only transactions are served by the leader, unless
+/// bounded-staleness follower reads are enabled via set_max_staleness().
 #[derive(Clone)]
 pub struct Raft {
     client: Client,
+    /// The maximum staleness of local reads for current read-only
+    /// transactions, or None to always read from the leader.
+    max_staleness: Option<raft::Ticks>,
+    /// The time to wait for row locks.
+    lock_timeout: Duration,
 }
 
 impl Raft {
     /// Creates a new Raft-based SQL engine.
     pub fn new(tx: Sender<(raft::Request, Sender<Result<raft::Response>>)>) -> Self {
-        Self { client: Client::new(tx) }
+        Self { client: Client::new(tx), max_staleness: None, lock_timeout: super::LOCK_TIMEOUT }
+    }
+
+    /// Allows current read-only transactions to be served from the local
+    /// state machine if it has applied the leader's commit index as of a
+    /// heartbeat received within the given duration, i.e. if it's at most
+    /// about that stale, or disables follower reads if None. The duration is
+    /// rounded down to Raft ticks.
+    pub fn set_max_staleness(&mut self, max_staleness: Option<Duration>) {
+        self.max_staleness = max_staleness.map(|d| {
+            (d.as_millis() / raft::TICK_INTERVAL.as_millis()).min(raft::Ticks::MAX as u128)
+                as raft::Ticks
+        });
+    }
+
+    /// Sets the time to wait for row locks before giving up.
+    pub fn set_lock_timeout(&mut self, lock_timeout: Duration) {
+        self.lock_timeout = lock_timeout;
     }
 
     /// Creates an underlying state machine for a Raft engine.
@@ -134,21 +247,86 @@ impl Raft {
     pub fn status(&self) -> Result<Status> {
         Ok(Status { raft: self.client.status()?, mvcc: self.client.query(Query::Status)? })
     }
+
+    /// Changes the Raft cluster membership.
+    pub fn change_membership(&self, change: raft::MembershipChange) -> Result<()> {
+        self.client.change_membership(change)
+    }
+
+    /// Garbage collects old MVCC versions that are no longer needed by active
+    /// transactions, retaining at least the given period of history for
+    /// time-travel queries (rounded down to seconds). Returns the number of
+    /// removed versions.
+    pub fn gc(&self, retention: Duration) -> Result<u64> {
+        let time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_err(|err| Error::Internal(err.to_string()))?
+            .as_secs();
+        self.client.mutate(Mutation::GC { time, retention: retention.as_secs() })
+    }
+
+    /// Transfers Raft leadership to the given node.
+    pub fn transfer_leadership(&self, id: raft::NodeID) -> Result<()> {
+        self.client.transfer_leadership(id)
+    }
+
+    /// Records a heartbeat for the given read-write transactions, keeping
+    /// them alive.
+    pub fn heartbeat(&self, txns: Vec<u64>) -> Result<()> {
+        self.client.mutate(Mutation::Heartbeat { time: now_millis()?, txns })
+    }
+
+    /// Rolls back read-write transactions that haven't had a heartbeat within
+    /// the given timeout, e.g. because their session or node has gone away.
+    /// Returns the versions of the rolled back transactions. The transactions
+    /// are first checked with a read-only query, and the expiry is only
+    /// replicated via the Raft log if any of them are overdue.
+    pub fn expire(&self, timeout: Duration) -> Result<Vec<u64>> {
+        if !self.transactions()?.iter().any(|txn| txn.idle >= timeout) {
+            return Ok(Vec::new());
+        }
+        self.client
+            .mutate(Mutation::Expire { time: now_millis()?, timeout: timeout.as_millis() as u64 })
+    }
+
+    /// Lists active read-write transactions, oldest first.
+    pub fn transactions(&self) -> Result<Vec<ActiveTransaction>> {
+        let now = now_millis()?;
+        let txns: Vec<(u64, u64, u64)> = self.client.query(Query::Transactions)?;
+        Ok(txns
+            .into_iter()
+            .map(|(version, begun, heartbeat)| ActiveTransaction {
+                version,
+                age: Duration::from_millis(now.saturating_sub(begun)),
+                idle: Duration::from_millis(now.saturating_sub(heartbeat)),
+            })
+            .collect())
+    }
 }
 
 impl super::Engine for Raft {
     type Transaction = Transaction;
 
     fn begin(&self) -> Result<Self::Transaction> {
-        Transaction::begin(self.client.clone(), false, None)
+        Transaction::begin(self.client.clone(), false, None, None, self.lock_timeout)
+    }
+
+    fn begin_serializable(&self) -> Result<Self::Transaction> {
+        let state = self.client.mutate(Mutation::BeginSerializable { time: now_millis()? })?;
+        Ok(Transaction {
+            client: self.client.clone(),
+            state,
+            local: false,
+            lock_timeout: self.lock_timeout,
+        })
     }
 
     fn begin_read_only(&self) -> Result<Self::Transaction> {
-        Transaction::begin(self.client.clone(), true, None)
+        Transaction::begin(self.client.clone(), true, None, self.max_staleness, self.lock_timeout)
     }
 
     fn begin_as_of(&self, version: u64) -> Result<Self::Transaction> {
-        Transaction::begin(self.client.clone(), true, Some(version))
+        Transaction::begin(self.client.clone(), true, Some(version), None, self.lock_timeout)
     }
 }
 
@@ -157,17 +335,54 @@ impl super::Engine for Raft {
 pub struct Transaction {
     client: Client,
     state: TransactionState,
+    /// If true, reads are served by the local state machine.
+    local: bool,
+    /// The time to wait for row locks.
+    lock_timeout: Duration,
 }
 
 impl Transaction {
     /// Starts a transaction in the given mode.
-    fn begin(client: Client, read_only: bool, as_of: Option<u64>) -> Result<Self> {
+    ///
+    /// Historical transactions are begun on the local state machine, which
+    /// errors if it hasn't applied the version yet. Current read-only
+    /// transactions are begun locally if max_staleness is given, which aborts
+    /// if the local node hasn't heard from the leader recently enough. In
+    /// either case, we fall back to the leader. Once begun, the transaction's
+    /// snapshot is immutable, so subsequent reads can be served by the same
+    /// state machine regardless of staleness.
+    fn begin(
+        client: Client,
+        read_only: bool,
+        as_of: Option<u64>,
+        max_staleness: Option<raft::Ticks>,
+        lock_timeout: Duration,
+    ) -> Result<Self> {
+        if as_of.is_some() || read_only && max_staleness.is_some() {
+            let max_staleness = if as_of.is_some() { None } else { max_staleness };
+            match client.query_local(Query::BeginReadOnly { as_of }, max_staleness) {
+                Ok(state) => return Ok(Self { client, state, local: true, lock_timeout }),
+                Err(Error::Abort | Error::Value(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
         let state = if read_only || as_of.is_some() {
             client.query(Query::BeginReadOnly { as_of })?
         } else {
-            client.mutate(Mutation::Begin)?
+            client.mutate(Mutation::Begin { time: now_millis()? })?
         };
-        Ok(Self { client, state })
+        Ok(Self { client, state, local: false, lock_timeout })
+    }
+
+    /// Queries the state machine, using the local state machine if the
+    /// transaction was begun there. Serializable transactions record their
+    /// reads in the state machine, so their queries go via the Raft log.
+    fn query<V: DeserializeOwned>(&self, query: Query) -> Result<V> {
+        match (self.state.serializable, self.local) {
+            (true, _) => self.client.mutate(Mutation::Read(query)),
+            (false, true) => self.client.query_local(query, None),
+            (false, false) => self.client.query(query),
+        }
     }
 }
 
@@ -194,6 +409,39 @@ impl super::Transaction for Transaction {
         Ok(())
     }
 
+    fn savepoint(&mut self) -> Result<u64> {
+        // A savepoint only exists in the transaction state until the
+        // transaction writes, so creating one doesn't need to go via Raft.
+        self.state.savepoints += 1;
+        Ok(self.state.savepoints - 1)
+    }
+
+    fn rollback_to(&mut self, savepoint: u64) -> Result<()> {
+        self.state =
+            self.client.mutate(Mutation::RollbackTo { txn: self.state.clone(), savepoint })?;
+        Ok(())
+    }
+
+    fn release(&mut self, savepoint: u64) -> Result<()> {
+        self.state =
+            self.client.mutate(Mutation::Release { txn: self.state.clone(), savepoint })?;
+        Ok(())
+    }
+
+    fn try_lock(&mut self, table: &str, id: &Value, mode: LockMode, wait: bool) -> Result<bool> {
+        self.client.mutate(Mutation::Lock {
+            txn: self.state.clone(),
+            table: table.to_string(),
+            id: id.clone(),
+            mode,
+            wait,
+        })
+    }
+
+    fn lock_timeout(&self) -> Duration {
+        self.lock_timeout
+    }
+
     fn create(&mut self, table: &str, row: Row) -> Result<()> {
         self.client.mutate(Mutation::Create {
             txn: self.state.clone(),
@@ -211,7 +459,7 @@ impl super::Transaction for Transaction {
     }
 
     fn read(&self, table: &str, id: &Value) -> Result<Option<Row>> {
-        self.client.query(Query::Read {
+        self.query(Query::Read {
             txn: self.state.clone(),
             table: table.to_string(),
             id: id.clone(),
@@ -219,7 +467,7 @@ impl super::Transaction for Transaction {
     }
 
     fn read_index(&self, table: &str, column: &str, value: &Value) -> Result<HashSet<Value>> {
-        self.client.query(Query::ReadIndex {
+        self.query(Query::ReadIndex {
             txn: self.state.clone(),
             table: table.to_string(),
             column: column.to_string(),
@@ -229,27 +477,25 @@ impl super::Transaction for Transaction {
 
     fn scan(&self, table: &str, filter: Option<Expression>) -> Result<Scan> {
         Ok(Box::new(
-            self.client
-                .query::<Vec<_>>(Query::Scan {
-                    txn: self.state.clone(),
-                    table: table.to_string(),
-                    filter,
-                })?
-                .into_iter()
-                .map(Ok),
+            self.query::<Vec<_>>(Query::Scan {
+                txn: self.state.clone(),
+                table: table.to_string(),
+                filter,
+            })?
+            .into_iter()
+            .map(Ok),
         ))
     }
 
     fn scan_index(&self, table: &str, column: &str) -> Result<IndexScan> {
         Ok(Box::new(
-            self.client
-                .query::<Vec<_>>(Query::ScanIndex {
-                    txn: self.state.clone(),
-                    table: table.to_string(),
-                    column: column.to_string(),
-                })?
-                .into_iter()
-                .map(Ok),
+            self.query::<Vec<_>>(Query::ScanIndex {
+                txn: self.state.clone(),
+                table: table.to_string(),
+                column: column.to_string(),
+            })?
+            .into_iter()
+            .map(Ok),
         ))
     }
 
@@ -274,12 +520,12 @@ impl Catalog for Transaction {
     }
 
     fn read_table(&self, table: &str) -> Result<Option<Table>> {
-        self.client.query(Query::ReadTable { txn: self.state.clone(), table: table.to_string() })
+        self.query(Query::ReadTable { txn: self.state.clone(), table: table.to_string() })
     }
 
     fn scan_tables(&self) -> Result<Tables> {
         Ok(Box::new(
-            self.client.query::<Vec<_>>(Query::ScanTables { txn: self.state.clone() })?.into_iter(),
+            self.query::<Vec<_>>(Query::ScanTables { txn: self.state.clone() })?.into_iter(),
         ))
     }
 }
@@ -306,9 +552,21 @@ impl<E: storage::Engine> State<E> {
     /// Mutates the state machine.
     fn mutate(&mut self, mutation: Mutation) -> Result<Vec<u8>> {
         match mutation {
-            Mutation::Begin => bincode::serialize(&self.engine.begin()?.state()),
-            Mutation::Commit(txn) => bincode::serialize(&self.engine.resume(txn)?.commit()?),
-            Mutation::Rollback(txn) => bincode::serialize(&self.engine.resume(txn)?.rollback()?),
+            Mutation::Begin { time } => {
+                let txn = self.engine.begin()?;
+                self.heartbeat(time, vec![txn.version()], true)?;
+                bincode::serialize(&txn.state())
+            }
+            Mutation::Commit(txn) => {
+                let version = txn.version;
+                self.engine.resume(txn)?.commit()?;
+                bincode::serialize(&self.remove_heartbeat(version)?)
+            }
+            Mutation::Rollback(txn) => {
+                let version = txn.version;
+                self.engine.resume(txn)?.rollback()?;
+                bincode::serialize(&self.remove_heartbeat(version)?)
+            }
 
             Mutation::Create { txn, table, row } => {
                 bincode::serialize(&self.engine.resume(txn)?.create(&table, row)?)
@@ -326,32 +584,41 @@ impl<E: storage::Engine> State<E> {
             Mutation::DeleteTable { txn, table } => {
                 bincode::serialize(&self.engine.resume(txn)?.delete_table(&table)?)
             }
-        }
-    }
-}
 
-impl<E: storage::Engine> raft::State for State<E> {
-    fn get_applied_index(&self) -> u64 {
-        self.applied_index
-    }
+            Mutation::GC { time, retention } => bincode::serialize(&self.gc(time, retention)?),
 
-    fn apply(&mut self, entry: Entry) -> Result<Vec<u8>> {
-        assert_eq!(entry.index, self.applied_index + 1, "entry index not after applied index");
+            Mutation::BeginSerializable { time } => {
+                let txn = self.engine.begin_serializable()?;
+                self.heartbeat(time, vec![txn.version()], true)?;
+                bincode::serialize(&txn.state())
+            }
+            Mutation::Read(query) => self.query(query),
 
-        let result = match &entry.command {
-            Some(command) => match self.mutate(bincode::deserialize(command)?) {
-                error @ Err(Error::Internal(_)) => return error, // don't record as applied
-                result => result,
-            },
-            None => Ok(Vec::new()),
-        };
-        self.applied_index = entry.index;
-        self.engine.set_metadata(b"applied_index", bincode::serialize(&entry.index)?)?;
-        result
+            Mutation::RollbackTo { txn, savepoint } => {
+                let mut txn = self.engine.resume(txn)?;
+                txn.rollback_to(savepoint)?;
+                bincode::serialize(&txn.state())
+            }
+            Mutation::Release { txn, savepoint } => {
+                let mut txn = self.engine.resume(txn)?;
+                txn.release(savepoint)?;
+                bincode::serialize(&txn.state())
+            }
+
+            Mutation::Lock { txn, table, id, mode, wait } => {
+                bincode::serialize(&self.engine.resume(txn)?.try_lock(&table, &id, mode, wait)?)
+            }
+
+            Mutation::Heartbeat { time, txns } => {
+                bincode::serialize(&self.heartbeat(time, txns, false)?)
+            }
+            Mutation::Expire { time, timeout } => bincode::serialize(&self.expire(time, timeout)?),
+        }
     }
 
-    fn read(&self, command: Vec<u8>) -> Result<Vec<u8>> {
-        match bincode::deserialize(&command)? {
+    /// Queries the state machine.
+    fn query(&self, query: Query) -> Result<Vec<u8>> {
+        match query {
             Query::BeginReadOnly { as_of } => {
                 let txn = if let Some(version) = as_of {
                     self.engine.begin_as_of(version)?
@@ -385,6 +652,322 @@ impl<E: storage::Engine> raft::State for State<E> {
             Query::ScanTables { txn } => {
                 bincode::serialize(&self.engine.resume(txn)?.scan_tables()?.collect::<Vec<_>>())
             }
+
+            Query::Transactions => {
+                let active = self.engine.kv.active()?;
+                let mut txns: Vec<(u64, u64, u64)> = self
+                    .get_heartbeats()?
+                    .into_iter()
+                    .filter(|(version, _)| active.contains(version))
+                    .map(|(version, (begun, heartbeat))| (version, begun, heartbeat))
+                    .collect();
+                txns.sort_by_key(|(version, begun, _)| (*begun, *version));
+                bincode::serialize(&txns)
+            }
+        }
+    }
+
+    /// Fetches the begin and last heartbeat times of read-write transactions,
+    /// by version. Records are removed when transactions commit or roll back,
+    /// but this may include transactions that were completed outside of the
+    /// Raft log, which are removed by expire().
+    fn get_heartbeats(&self) -> Result<BTreeMap<u64, (u64, u64)>> {
+        self.engine
+            .get_metadata(b"txn_heartbeats")?
+            .map(|b| bincode::deserialize(&b))
+            .unwrap_or(Ok(BTreeMap::new()))
+    }
+
+    /// Records heartbeats for the given read-write transactions at the given
+    /// time (in milliseconds since the Unix epoch). If begin is true, the
+    /// transactions have just begun, otherwise heartbeats are only recorded
+    /// for known transactions, since they may have completed already.
+    fn heartbeat(&mut self, time: u64, txns: Vec<u64>, begin: bool) -> Result<()> {
+        let mut heartbeats = self.get_heartbeats()?;
+        for version in txns {
+            match heartbeats.get_mut(&version) {
+                Some((_, heartbeat)) => *heartbeat = time.max(*heartbeat),
+                None if begin => _ = heartbeats.insert(version, (time, time)),
+                None => {}
+            }
+        }
+        self.engine.set_metadata(b"txn_heartbeats", bincode::serialize(&heartbeats)?)
+    }
+
+    /// Removes the heartbeat record of a completed transaction, if any, so that
+    /// the records only grow with the number of active transactions.
+    fn remove_heartbeat(&mut self, version: u64) -> Result<()> {
+        let mut heartbeats = self.get_heartbeats()?;
+        if heartbeats.remove(&version).is_some() {
+            self.engine.set_metadata(b"txn_heartbeats", bincode::serialize(&heartbeats)?)?;
+        }
+        Ok(())
+    }
+
+    /// Rolls back read-write transactions whose last heartbeat is older than
+    /// the timeout (in milliseconds) as of the given time, returning their
+    /// versions. Transactions are kept alive by heartbeats from the node
+    /// running their SQL session, so these have been abandoned, e.g. because
+    /// the node crashed. Like GC, the time is provided by the proposer.
+    ///
+    /// Heartbeat times are provided by the nodes running the sessions, and
+    /// compared with the expiring leader's time, so clock skew between them
+    /// shifts the timeout: a session node whose clock lags the leader's by
+    /// more than the timeout has its transactions rolled back, while one whose
+    /// clock is ahead keeps abandoned transactions alive for longer. The
+    /// timeout must therefore be well above the expected clock skew.
+    ///
+    /// Active transactions without a heartbeat record, e.g. ones begun before
+    /// heartbeats were recorded, are considered to have had a heartbeat now.
+    /// Records for completed transactions are removed.
+    fn expire(&mut self, time: u64, timeout: u64) -> Result<Vec<u64>> {
+        let active = self.engine.kv.active()?;
+        let mut heartbeats = self.get_heartbeats()?;
+        heartbeats.retain(|version, _| active.contains(version));
+        for version in active {
+            heartbeats.entry(version).or_insert((time, time));
+        }
+        let expired: Vec<u64> = heartbeats
+            .iter()
+            .filter(|(_, (_, heartbeat))| time.saturating_sub(*heartbeat) >= timeout)
+            .map(|(version, _)| *version)
+            .collect();
+        for version in &expired {
+            self.engine.kv.rollback(*version)?;
+            heartbeats.remove(version);
+        }
+        self.engine.set_metadata(b"txn_heartbeats", bincode::serialize(&heartbeats)?)?;
+        if !expired.is_empty() {
+            super::notify_lock_release()?;
+        }
+        Ok(expired)
+    }
+
+    /// Garbage collects MVCC versions older than the retention period (in
+    /// seconds) as of the given time (in seconds since the Unix epoch).
+    ///
+    /// MVCC versions are logical timestamps, so to map times to versions each
+    /// GC run records a checkpoint of the time and the next version. The
+    /// low-water mark is then the version of the latest checkpoint that is
+    /// outside the retention period, since all versions below it began before
+    /// that time. The time is provided by the proposer via the Raft log rather
+    /// than read from the local clock, so that all nodes remove the same
+    /// versions.
+    fn gc(&mut self, time: u64, retention: u64) -> Result<u64> {
+        let mut checkpoints: Vec<(u64, u64)> = self
+            .engine
+            .get_metadata(b"gc_checkpoints")?
+            .map(|b| bincode::deserialize(&b))
+            .unwrap_or(Ok(Vec::new()))?;
+
+        // Leaders may have different clocks, so don't let time go backwards.
+        let time = checkpoints.last().map_or(time, |(last, _)| time.max(*last));
+        checkpoints.push((time, self.engine.kv.status()?.versions + 1));
+
+        // Find the latest checkpoint outside the retention period, and discard
+        // older checkpoints which are no longer needed.
+        let cutoff = time.saturating_sub(retention);
+        let low_water = checkpoints.iter().rposition(|(t, _)| *t <= cutoff).map(|i| {
+            checkpoints.drain(..i);
+            checkpoints[0].1
+        });
+        self.engine.set_metadata(b"gc_checkpoints", bincode::serialize(&checkpoints)?)?;
+
+        match low_water {
+            Some(version) => self.engine.kv.gc(version),
+            None => Ok(0),
+        }
+    }
+}
+
+impl<E: storage::Engine> raft::State for State<E> {
+    fn get_applied_index(&self) -> u64 {
+        self.applied_index
+    }
+
+    fn apply(&mut self, entry: Entry) -> Result<Vec<u8>> {
+        assert_eq!(entry.index, self.applied_index + 1, "entry index not after applied index");
+
+        let result = match &entry.command {
+            Some(command) => match self.mutate(bincode::deserialize(command)?) {
+                error @ Err(Error::Internal(_)) => return error, // don't record as applied
+                result => result,
+            },
+            None => Ok(Vec::new()),
+        };
+        self.applied_index = entry.index;
+        self.engine.set_metadata(b"applied_index", bincode::serialize(&entry.index)?)?;
+        result
+    }
+
+    fn read(&self, command: Vec<u8>) -> Result<Vec<u8>> {
+        self.query(bincode::deserialize(&command)?)
+    }
+
+    fn snapshot(&self) -> Result<Vec<u8>> {
+        bincode::serialize(&self.engine.kv.export()?)
+    }
+
+    fn restore(&mut self, index: raft::Index, snapshot: Vec<u8>) -> Result<()> {
+        self.engine.kv.import(
+            bincode::deserialize(&snapshot)?,
+            vec![(b"applied_index", bincode::serialize(&index)?)],
+        )?;
+        self.applied_index = index;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.engine.kv.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raft::State as _;
+    use crate::storage::Memory;
+
+    /// Applies a mutation to the state machine at the next index.
+    fn apply<E: storage::Engine>(state: &mut State<E>, mutation: Mutation) -> Result<Vec<u8>> {
+        let entry = Entry {
+            index: state.get_applied_index() + 1,
+            term: 1,
+            command: Some(bincode::serialize(&mutation)?),
+            membership: None,
+        };
+        state.apply(entry)
+    }
+
+    /// Runs GC at the given time with a retention period of 10 seconds,
+    /// returning the number of removed versions.
+    fn gc<E: storage::Engine>(state: &mut State<E>, time: u64) -> Result<u64> {
+        bincode::deserialize(&apply(state, Mutation::GC { time, retention: 10 })?)
+    }
+
+    #[test]
+    /// Tests that restoring a snapshot replaces the entire state, including the
+    /// applied index.
+    fn snapshot_restore() -> Result<()> {
+        let write = |state: &State<Memory>, key: &[u8]| -> Result<()> {
+            let txn = state.engine.kv.begin()?;
+            txn.set(key, vec![1])?;
+            txn.commit()
+        };
+        let mut source = Raft::new_state(Memory::new())?;
+        write(&source, b"a")?;
+        apply(&mut source, Mutation::GC { time: 1, retention: 10 })?;
+        let snapshot = source.snapshot()?;
+
+        let mut target = Raft::new_state(Memory::new())?;
+        write(&target, b"b")?;
+        target.restore(7, snapshot)?;
+        assert_eq!(target.get_applied_index(), 7);
+        assert_eq!(target.engine.get_metadata(b"applied_index")?, Some(bincode::serialize(&7u64)?));
+
+        let txn = target.engine.kv.begin_read_only()?;
+        assert_eq!(txn.get(b"a")?, Some(vec![1]));
+        assert_eq!(txn.get(b"b")?, None);
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that GC retains the given period of history, using checkpoints of
+    /// the proposer's time in the Raft log.
+    fn gc_retention() -> Result<()> {
+        let mut state = Raft::new_state(Memory::new())?;
+        let write = |state: &State<Memory>, value: u8| -> Result<()> {
+            let txn = state.engine.kv.begin()?;
+            txn.set(b"key", vec![value])?;
+            txn.commit()
+        };
+
+        // Write a version between each GC run. Nothing is removed until the
+        // first checkpoint is outside the retention period.
+        write(&state, 1)?;
+        assert_eq!(gc(&mut state, 100)?, 0);
+        write(&state, 2)?;
+        assert_eq!(gc(&mut state, 105)?, 0);
+        write(&state, 3)?;
+        assert_eq!(gc(&mut state, 110)?, 0);
+        assert_eq!(state.engine.kv.status()?.low_water, 2);
+
+        // Once the checkpoint at 105 is outside the retention period, versions
+        // before it are removed.
+        assert_eq!(gc(&mut state, 115)?, 1);
+        assert_eq!(state.engine.kv.status()?.low_water, 3);
+
+        // Time going backwards is ignored.
+        assert_eq!(gc(&mut state, 50)?, 0);
+        assert_eq!(state.engine.kv.status()?.low_water, 3);
+
+        // Time-travel queries below the low-water mark error.
+        let query = |as_of| bincode::serialize(&Query::BeginReadOnly { as_of: Some(as_of) });
+        assert!(matches!(state.read(query(2)?), Err(Error::Value(_))));
+        assert!(state.read(query(3)?).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that heartbeat records are removed when transactions complete,
+    /// even if expiry never runs.
+    fn heartbeat_complete() -> Result<()> {
+        let mut state = Raft::new_state(Memory::new())?;
+        let begin = |state: &mut State<Memory>, time| -> Result<TransactionState> {
+            bincode::deserialize(&apply(state, Mutation::Begin { time })?)
+        };
+
+        let t1 = begin(&mut state, 100)?;
+        let t2 = begin(&mut state, 101)?;
+        for time in 102..110 {
+            let txn = begin(&mut state, time)?;
+            apply(&mut state, Mutation::Commit(txn))?;
         }
+        assert_eq!(
+            state.get_heartbeats()?.keys().copied().collect::<Vec<_>>(),
+            vec![t1.version, t2.version]
+        );
+
+        apply(&mut state, Mutation::Commit(t1))?;
+        apply(&mut state, Mutation::Rollback(t2))?;
+        assert!(state.get_heartbeats()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that transactions without a heartbeat within the timeout are
+    /// rolled back, using the proposer's time in the Raft log.
+    fn expire() -> Result<()> {
+        let mut state = Raft::new_state(Memory::new())?;
+        let begin = |state: &mut State<Memory>, time| -> Result<TransactionState> {
+            bincode::deserialize(&apply(state, Mutation::Begin { time })?)
+        };
+        let expire = |state: &mut State<Memory>, time| -> Result<Vec<u64>> {
+            bincode::deserialize(&apply(state, Mutation::Expire { time, timeout: 10 })?)
+        };
+        let transactions = |state: &State<Memory>| -> Result<Vec<(u64, u64, u64)>> {
+            bincode::deserialize(&state.read(bincode::serialize(&Query::Transactions)?)?)
+        };
+
+        // t3 is begun outside of the Raft log, so it has no heartbeat record
+        // until the first expiry run.
+        let t1 = begin(&mut state, 100)?;
+        let t2 = begin(&mut state, 105)?;
+        let t3 = state.engine.kv.begin()?.state().clone();
+        assert_eq!(expire(&mut state, 108)?, Vec::<u64>::new());
+
+        // Heartbeats keep t1 alive, and are ignored for unknown transactions.
+        apply(&mut state, Mutation::Heartbeat { time: 109, txns: vec![t1.version, 99] })?;
+        assert_eq!(expire(&mut state, 115)?, vec![t2.version]);
+        assert_eq!(transactions(&state)?, vec![(t1.version, 100, 109), (t3.version, 108, 108)]);
+
+        // Using an expired transaction is a user error, which doesn't halt
+        // the state machine.
+        assert!(matches!(apply(&mut state, Mutation::Commit(t2)), Err(Error::Value(_))));
+
+        assert_eq!(expire(&mut state, 120)?, vec![t1.version, t3.version]);
+        assert_eq!(transactions(&state)?, vec![]);
+        assert_eq!(state.engine.kv.status()?.active_txns, 0);
+        Ok(())
     }
 }
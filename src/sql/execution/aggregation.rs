@@ -4,8 +4,10 @@ use super::super::types::{Column, Value};
 use super::{Executor, ResultSet};
 use crate::error::{Error, Result};
 
+use rust_decimal::Decimal;
 use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::mem::discriminant;
 
 /// An aggregation executor
 pub struct Aggregation<T: Transaction> {
@@ -129,6 +131,9 @@ impl Accumulator for Average {
         match (self.sum.aggregate(), self.count.aggregate()) {
             (Value::Integer(s), Value::Integer(c)) => Value::Integer(s / c),
             (Value::Float(s), Value::Integer(c)) => Value::Float(s / c as f64),
+            (Value::Decimal(s), Value::Integer(c)) => {
+                Value::Decimal((s / Decimal::from(c)).normalize())
+            }
             _ => Value::Null,
         }
     }
@@ -150,7 +155,7 @@ impl Accumulator for Max {
     fn accumulate(&mut self, value: &Value) -> Result<()> {
         if let Some(max) = &mut self.max {
             match value.partial_cmp(max) {
-                _ if max.datatype() != value.datatype() => *max = Value::Null,
+                _ if discriminant(max) != discriminant(value) => *max = Value::Null,
                 None => *max = Value::Null,
                 Some(Ordering::Greater) => *max = value.clone(),
                 Some(Ordering::Equal) | Some(Ordering::Less) => {}
@@ -185,7 +190,7 @@ impl Accumulator for Min {
     fn accumulate(&mut self, value: &Value) -> Result<()> {
         if let Some(min) = &mut self.min {
             match value.partial_cmp(min) {
-                _ if min.datatype() != value.datatype() => *min = Value::Null,
+                _ if discriminant(min) != discriminant(value) => *min = Value::Null,
                 None => *min = Value::Null,
                 Some(Ordering::Less) => *min = value.clone(),
                 Some(Ordering::Equal) | Some(Ordering::Greater) => {}
@@ -221,8 +226,12 @@ impl Accumulator for Sum {
         self.sum = match (&self.sum, value) {
             (Some(Value::Integer(s)), Value::Integer(i)) => Some(Value::Integer(s + i)),
             (Some(Value::Float(s)), Value::Float(f)) => Some(Value::Float(s + f)),
+            (Some(Value::Decimal(s)), Value::Decimal(d)) => Some(Value::Decimal(
+                s.checked_add(*d).ok_or_else(|| Error::Value("Decimal overflow".into()))?,
+            )),
             (None, Value::Integer(i)) => Some(Value::Integer(*i)),
             (None, Value::Float(f)) => Some(Value::Float(*f)),
+            (None, Value::Decimal(d)) => Some(Value::Decimal(*d)),
             _ => Some(Value::Null),
         };
         Ok(())
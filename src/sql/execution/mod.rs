@@ -7,7 +7,7 @@ mod source;
 
 use aggregation::Aggregation;
 use join::{HashJoin, NestedLoopJoin};
-use mutation::{Delete, Insert, Update};
+use mutation::{Delete, Insert, Lock, Update};
 use query::{Filter, Limit, Offset, Order, Projection};
 use schema::{CreateTable, DropTable};
 use source::{IndexLookup, KeyLookup, Nothing, Scan};
@@ -52,6 +52,9 @@ impl<T: Transaction + 'static> dyn Executor<T> {
             }
             Node::KeyLookup { table, alias: _, keys } => KeyLookup::new(table, keys),
             Node::Limit { source, limit } => Limit::new(Self::build(*source), limit),
+            Node::Lock { source, table, mode, nowait } => {
+                Lock::new(table, Self::build(*source), mode, nowait)
+            }
             Node::NestedLoopJoin { left, left_size: _, right, predicate, outer } => {
                 NestedLoopJoin::new(Self::build(*left), Self::build(*right), predicate, outer)
             }
@@ -88,6 +91,18 @@ pub enum ResultSet {
     Rollback {
         version: u64,
     },
+    // Savepoint created
+    Savepoint {
+        name: String,
+    },
+    // Transaction rolled back to savepoint
+    RollbackTo {
+        name: String,
+    },
+    // Savepoint released
+    Release {
+        name: String,
+    },
     // Rows created
     Create {
         count: u64,
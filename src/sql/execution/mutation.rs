@@ -3,8 +3,10 @@ use super::super::schema::Table;
 use super::super::types::{Expression, Row, Value};
 use super::{Executor, ResultSet};
 use crate::error::{Error, Result};
+use crate::storage::mvcc::LockMode;
 
 use std::collections::{HashMap, HashSet};
+use std::mem::replace;
 
 /// An INSERT executor
 pub struct Insert {
@@ -68,6 +70,9 @@ impl<T: Transaction> Executor<T> for Insert {
             } else {
                 row = Self::make_row(&table, &self.columns, row)?;
             }
+            for (value, column) in row.iter_mut().zip(&table.columns) {
+                *value = column.convert_value(replace(value, Value::Null))?;
+            }
             txn.create(&table.name, row)?;
             count += 1;
         }
@@ -113,7 +118,8 @@ impl<T: Transaction> Executor<T> for Update<T> {
                     }
                     let mut new = row.clone();
                     for (field, expr) in &self.expressions {
-                        new[*field] = expr.evaluate(Some(&row))?;
+                        new[*field] =
+                            table.columns[*field].convert_value(expr.evaluate(Some(&row))?)?;
                     }
                     txn.update(&table.name, &id, new)?;
                     updated.insert(id);
@@ -153,3 +159,42 @@ impl<T: Transaction> Executor<T> for Delete<T> {
         }
     }
 }
+
+/// A row lock executor, for SELECT ... FOR UPDATE/SHARE. Locks all source rows, waiting for
+/// conflicting locks, and then returns their latest committed versions. Rows deleted while
+/// waiting are skipped.
+pub struct Lock<T: Transaction> {
+    table: String,
+    source: Box<dyn Executor<T>>,
+    mode: LockMode,
+    nowait: bool,
+}
+
+impl<T: Transaction> Lock<T> {
+    pub fn new(
+        table: String,
+        source: Box<dyn Executor<T>>,
+        mode: LockMode,
+        nowait: bool,
+    ) -> Box<Self> {
+        Box::new(Self { table, source, mode, nowait })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Lock<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let table = txn.must_read_table(&self.table)?;
+        match self.source.execute(txn)? {
+            ResultSet::Query { columns, rows } => {
+                let ids = rows.map(|r| table.get_row_key(&r?)).collect::<Result<Vec<_>>>()?;
+                let mut locked = Vec::with_capacity(ids.len());
+                for id in ids {
+                    txn.lock(&table.name, &id, self.mode, self.nowait)?;
+                    locked.extend(txn.read(&table.name, &id)?);
+                }
+                Ok(ResultSet::Query { columns, rows: Box::new(locked.into_iter().map(Ok)) })
+            }
+            r => Err(Error::Internal(format!("Unexpected result {:?}", r))),
+        }
+    }
+}
@@ -1,6 +1,9 @@
-use super::super::types::DataType;
+use super::super::types::{DataType, Interval};
 use crate::error::Result;
+use crate::storage::mvcc::LockMode;
 
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use rust_decimal::Decimal;
 use std::collections::BTreeMap;
 use std::mem::replace;
 
@@ -11,9 +14,13 @@ pub enum Statement {
     Begin {
         read_only: bool,
         as_of: Option<u64>,
+        serializable: bool,
     },
     Commit,
     Rollback,
+    Savepoint(String),
+    RollbackTo(String),
+    Release(String),
     Explain(Box<Statement>),
 
     CreateTable {
@@ -49,9 +56,17 @@ pub enum Statement {
         order: Vec<(Expression, Order)>,
         offset: Option<Expression>,
         limit: Option<Expression>,
+        lock: Option<Lock>,
     },
 }
 
+/// A row locking clause (FOR UPDATE or FOR SHARE)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lock {
+    pub mode: LockMode,
+    pub nowait: bool,
+}
+
 /// A FROM item
 #[derive(Clone, Debug, PartialEq)]
 pub enum FromItem {
@@ -86,6 +101,7 @@ pub struct Column {
     pub default: Option<Expression>,
     pub unique: bool,
     pub index: bool,
+    pub index_path: Option<String>,
     pub references: Option<String>,
 }
 
@@ -126,6 +142,14 @@ pub enum Literal {
     Integer(i64),
     Float(f64),
     String(String),
+    Date(NaiveDate),
+    Time(NaiveTime),
+    Timestamp(NaiveDateTime),
+    TimestampTz(DateTime<Utc>),
+    Interval(Interval),
+    Decimal(Decimal),
+    Bytes(Vec<u8>),
+    Json(String),
 }
 
 /// Operations (done by operators)
@@ -158,6 +182,11 @@ pub enum Operation {
 
     // String operators
     Like(Box<Expression>, Box<Expression>),
+
+    // JSON operators
+    JsonContains(Box<Expression>, Box<Expression>),
+    JsonGet(Box<Expression>, Box<Expression>),
+    JsonGetText(Box<Expression>, Box<Expression>),
 }
 
 impl Expression {
@@ -192,6 +221,9 @@ impl Expression {
             | Self::Operation(Exponentiate(lhs, rhs))
             | Self::Operation(GreaterThan(lhs, rhs))
             | Self::Operation(GreaterThanOrEqual(lhs, rhs))
+            | Self::Operation(JsonContains(lhs, rhs))
+            | Self::Operation(JsonGet(lhs, rhs))
+            | Self::Operation(JsonGetText(lhs, rhs))
             | Self::Operation(LessThan(lhs, rhs))
             | Self::Operation(LessThanOrEqual(lhs, rhs))
             | Self::Operation(Like(lhs, rhs))
@@ -244,6 +276,9 @@ impl Expression {
                 | Self::Operation(Exponentiate(lhs, rhs))
                 | Self::Operation(GreaterThan(lhs, rhs))
                 | Self::Operation(GreaterThanOrEqual(lhs, rhs))
+                | Self::Operation(JsonContains(lhs, rhs))
+                | Self::Operation(JsonGet(lhs, rhs))
+                | Self::Operation(JsonGetText(lhs, rhs))
                 | Self::Operation(LessThan(lhs, rhs))
                 | Self::Operation(LessThanOrEqual(lhs, rhs))
                 | Self::Operation(Like(lhs, rhs))
@@ -8,6 +8,7 @@ use std::str::Chars;
 pub enum Token {
     Number(String),
     String(String),
+    HexString(String),
     Ident(String),
     Keyword(Keyword),
     Period,
@@ -19,6 +20,10 @@ pub enum Token {
     LessOrGreaterThan,
     Plus,
     Minus,
+    Arrow,
+    DoubleArrow,
+    At,
+    AtGreaterThan,
     Asterisk,
     Slash,
     Caret,
@@ -37,6 +42,7 @@ impl std::fmt::Display for Token {
         f.write_str(match self {
             Token::Number(n) => n,
             Token::String(s) => s,
+            Token::HexString(s) => s,
             Token::Ident(s) => s,
             Token::Keyword(k) => k.to_str(),
             Token::Period => ".",
@@ -48,6 +54,10 @@ impl std::fmt::Display for Token {
             Token::LessOrGreaterThan => "<>",
             Token::Plus => "+",
             Token::Minus => "-",
+            Token::Arrow => "->",
+            Token::DoubleArrow => "->>",
+            Token::At => "@",
+            Token::AtGreaterThan => "@>",
             Token::Asterisk => "*",
             Token::Slash => "/",
             Token::Caret => "^",
@@ -76,13 +86,17 @@ pub enum Keyword {
     As,
     Asc,
     Begin,
+    Blob,
     Bool,
     Boolean,
     By,
+    Bytea,
     Char,
     Commit,
     Create,
     Cross,
+    Date,
+    Decimal,
     Default,
     Delete,
     Desc,
@@ -92,6 +106,7 @@ pub enum Keyword {
     Explain,
     False,
     Float,
+    For,
     From,
     Group,
     Having,
@@ -102,16 +117,22 @@ pub enum Keyword {
     Insert,
     Int,
     Integer,
+    Interval,
     Into,
     Is,
+    Isolation,
     Join,
+    Json,
     Key,
     Left,
+    Level,
     Like,
     Limit,
     NaN,
     Not,
+    Nowait,
     Null,
+    Numeric,
     Of,
     Offset,
     On,
@@ -122,15 +143,22 @@ pub enum Keyword {
     Primary,
     Read,
     References,
+    Release,
     Right,
     Rollback,
+    Savepoint,
     Select,
+    Serializable,
     Set,
+    Share,
+    Snapshot,
     String,
     System,
     Table,
     Text,
     Time,
+    Timestamp,
+    To,
     Transaction,
     True,
     Unique,
@@ -138,7 +166,10 @@ pub enum Keyword {
     Values,
     Varchar,
     Where,
+    With,
+    Without,
     Write,
+    Zone,
 }
 
 impl Keyword {
@@ -149,13 +180,17 @@ impl Keyword {
             "ASC" => Self::Asc,
             "AND" => Self::And,
             "BEGIN" => Self::Begin,
+            "BLOB" => Self::Blob,
             "BOOL" => Self::Bool,
             "BOOLEAN" => Self::Boolean,
             "BY" => Self::By,
+            "BYTEA" => Self::Bytea,
             "CHAR" => Self::Char,
             "COMMIT" => Self::Commit,
             "CREATE" => Self::Create,
             "CROSS" => Self::Cross,
+            "DATE" => Self::Date,
+            "DECIMAL" => Self::Decimal,
             "DEFAULT" => Self::Default,
             "DELETE" => Self::Delete,
             "DESC" => Self::Desc,
@@ -165,6 +200,7 @@ impl Keyword {
             "EXPLAIN" => Self::Explain,
             "FALSE" => Self::False,
             "FLOAT" => Self::Float,
+            "FOR" => Self::For,
             "FROM" => Self::From,
             "GROUP" => Self::Group,
             "HAVING" => Self::Having,
@@ -175,16 +211,22 @@ impl Keyword {
             "INSERT" => Self::Insert,
             "INT" => Self::Int,
             "INTEGER" => Self::Integer,
+            "INTERVAL" => Self::Interval,
             "INTO" => Self::Into,
             "IS" => Self::Is,
+            "ISOLATION" => Self::Isolation,
             "JOIN" => Self::Join,
+            "JSON" => Self::Json,
             "KEY" => Self::Key,
             "LEFT" => Self::Left,
+            "LEVEL" => Self::Level,
             "LIKE" => Self::Like,
             "LIMIT" => Self::Limit,
             "NAN" => Self::NaN,
             "NOT" => Self::Not,
+            "NOWAIT" => Self::Nowait,
             "NULL" => Self::Null,
+            "NUMERIC" => Self::Numeric,
             "OF" => Self::Of,
             "OFFSET" => Self::Offset,
             "ON" => Self::On,
@@ -195,15 +237,22 @@ impl Keyword {
             "PRIMARY" => Self::Primary,
             "READ" => Self::Read,
             "REFERENCES" => Self::References,
+            "RELEASE" => Self::Release,
             "RIGHT" => Self::Right,
             "ROLLBACK" => Self::Rollback,
+            "SAVEPOINT" => Self::Savepoint,
             "SELECT" => Self::Select,
+            "SERIALIZABLE" => Self::Serializable,
             "SET" => Self::Set,
+            "SHARE" => Self::Share,
+            "SNAPSHOT" => Self::Snapshot,
             "STRING" => Self::String,
             "SYSTEM" => Self::System,
             "TABLE" => Self::Table,
             "TEXT" => Self::Text,
             "TIME" => Self::Time,
+            "TIMESTAMP" => Self::Timestamp,
+            "TO" => Self::To,
             "TRANSACTION" => Self::Transaction,
             "TRUE" => Self::True,
             "UNIQUE" => Self::Unique,
@@ -211,7 +260,10 @@ impl Keyword {
             "VALUES" => Self::Values,
             "VARCHAR" => Self::Varchar,
             "WHERE" => Self::Where,
+            "WITH" => Self::With,
+            "WITHOUT" => Self::Without,
             "WRITE" => Self::Write,
+            "ZONE" => Self::Zone,
             _ => return None,
         })
     }
@@ -222,13 +274,17 @@ impl Keyword {
             Self::Asc => "ASC",
             Self::And => "AND",
             Self::Begin => "BEGIN",
+            Self::Blob => "BLOB",
             Self::Bool => "BOOL",
             Self::Boolean => "BOOLEAN",
             Self::By => "BY",
+            Self::Bytea => "BYTEA",
             Self::Char => "CHAR",
             Self::Commit => "COMMIT",
             Self::Create => "CREATE",
             Self::Cross => "CROSS",
+            Self::Date => "DATE",
+            Self::Decimal => "DECIMAL",
             Self::Default => "DEFAULT",
             Self::Delete => "DELETE",
             Self::Desc => "DESC",
@@ -238,6 +294,7 @@ impl Keyword {
             Self::Explain => "EXPLAIN",
             Self::False => "FALSE",
             Self::Float => "FLOAT",
+            Self::For => "FOR",
             Self::From => "FROM",
             Self::Group => "GROUP",
             Self::Having => "HAVING",
@@ -248,16 +305,22 @@ impl Keyword {
             Self::Insert => "INSERT",
             Self::Int => "INT",
             Self::Integer => "INTEGER",
+            Self::Interval => "INTERVAL",
             Self::Into => "INTO",
             Self::Is => "IS",
+            Self::Isolation => "ISOLATION",
             Self::Join => "JOIN",
+            Self::Json => "JSON",
             Self::Key => "KEY",
             Self::Left => "LEFT",
+            Self::Level => "LEVEL",
             Self::Like => "LIKE",
             Self::Limit => "LIMIT",
             Self::NaN => "NAN",
             Self::Not => "NOT",
+            Self::Nowait => "NOWAIT",
             Self::Null => "NULL",
+            Self::Numeric => "NUMERIC",
             Self::Of => "OF",
             Self::Offset => "OFFSET",
             Self::On => "ON",
@@ -268,15 +331,22 @@ impl Keyword {
             Self::Primary => "PRIMARY",
             Self::Read => "READ",
             Self::References => "REFERENCES",
+            Self::Release => "RELEASE",
             Self::Right => "RIGHT",
             Self::Rollback => "ROLLBACK",
+            Self::Savepoint => "SAVEPOINT",
             Self::Select => "SELECT",
+            Self::Serializable => "SERIALIZABLE",
             Self::Set => "SET",
+            Self::Share => "SHARE",
+            Self::Snapshot => "SNAPSHOT",
             Self::String => "STRING",
             Self::System => "SYSTEM",
             Self::Table => "TABLE",
             Self::Text => "TEXT",
             Self::Time => "TIME",
+            Self::Timestamp => "TIMESTAMP",
+            Self::To => "TO",
             Self::Transaction => "TRANSACTION",
             Self::True => "TRUE",
             Self::Unique => "UNIQUE",
@@ -284,7 +354,10 @@ impl Keyword {
             Self::Values => "VALUES",
             Self::Varchar => "VARCHAR",
             Self::Where => "WHERE",
+            Self::With => "WITH",
+            Self::Without => "WITHOUT",
             Self::Write => "WRITE",
+            Self::Zone => "ZONE",
         }
     }
 }
@@ -351,8 +424,9 @@ impl<'a> Lexer<'a> {
     /// Scans the input for the next token if any, ignoring leading whitespace
     fn scan(&mut self) -> Result<Option<Token>> {
         self.consume_whitespace();
-        match self.iter.peek() {
+        match self.iter.peek().copied() {
             Some('\'') => self.scan_string(),
+            Some('x' | 'X') if self.iter.clone().nth(1) == Some('\'') => self.scan_hex_string(),
             Some('"') => self.scan_ident_quoted(),
             Some(c) if c.is_ascii_digit() => Ok(self.scan_number()),
             Some(c) if c.is_alphabetic() => Ok(self.scan_ident()),
@@ -427,6 +501,18 @@ impl<'a> Lexer<'a> {
         Ok(Some(Token::String(s)))
     }
 
+    /// Scans the input for the next hex string literal, e.g. x'01ff', if any.
+    /// The hex digits are validated by the parser.
+    fn scan_hex_string(&mut self) -> Result<Option<Token>> {
+        if self.next_if(|c| c == 'x' || c == 'X').is_none() {
+            return Ok(None);
+        }
+        Ok(match self.scan_string()? {
+            Some(Token::String(s)) => Some(Token::HexString(s)),
+            token => token,
+        })
+    }
+
     /// Scans the input for the next symbol token, if any, and
     /// handle any multi-symbol tokens
     fn scan_symbol(&mut self) -> Option<Token> {
@@ -437,6 +523,7 @@ impl<'a> Lexer<'a> {
             '<' => Some(Token::LessThan),
             '+' => Some(Token::Plus),
             '-' => Some(Token::Minus),
+            '@' => Some(Token::At),
             '*' => Some(Token::Asterisk),
             '/' => Some(Token::Slash),
             '^' => Some(Token::Caret),
@@ -473,6 +560,24 @@ impl<'a> Lexer<'a> {
                     token
                 }
             }
+            Token::Minus => {
+                if self.next_if(|c| c == '>').is_some() {
+                    if self.next_if(|c| c == '>').is_some() {
+                        Token::DoubleArrow
+                    } else {
+                        Token::Arrow
+                    }
+                } else {
+                    token
+                }
+            }
+            Token::At => {
+                if self.next_if(|c| c == '>').is_some() {
+                    Token::AtGreaterThan
+                } else {
+                    token
+                }
+            }
             _ => token,
         })
     }
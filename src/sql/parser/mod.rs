@@ -2,8 +2,9 @@ pub mod ast;
 mod lexer;
 pub use lexer::{Keyword, Lexer, Token};
 
-use super::types::DataType;
+use super::types::{decimal, json, temporal, DataType};
 use crate::error::{Error, Result};
+use crate::storage::mvcc::LockMode;
 
 use regex::Regex;
 use std::collections::BTreeMap;
@@ -57,6 +58,24 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Grabs the next string literal, or errors if not found
+    fn next_string(&mut self) -> Result<String> {
+        match self.next()? {
+            Token::String(s) => Ok(s),
+            token => Err(Error::Parse(format!("Expected string, got {}", token))),
+        }
+    }
+
+    /// Grabs the next number as an unsigned integer, or errors if not found
+    fn next_unsigned(&mut self) -> Result<u32> {
+        match self.next()? {
+            Token::Number(n) => {
+                n.parse().map_err(|_| Error::Parse(format!("Expected unsigned integer, got {}", n)))
+            }
+            token => Err(Error::Parse(format!("Expected unsigned integer, got {}", token))),
+        }
+    }
+
     /// Grabs the next lexer token if it satisfies the predicate function
     fn next_if<F: Fn(&Token) -> bool>(&mut self, predicate: F) -> Option<Token> {
         self.peek().unwrap_or(None).filter(|t| predicate(t))?;
@@ -101,6 +120,8 @@ impl<'a> Parser<'a> {
             Some(Token::Keyword(Keyword::Begin)) => self.parse_transaction(),
             Some(Token::Keyword(Keyword::Commit)) => self.parse_transaction(),
             Some(Token::Keyword(Keyword::Rollback)) => self.parse_transaction(),
+            Some(Token::Keyword(Keyword::Savepoint)) => self.parse_transaction(),
+            Some(Token::Keyword(Keyword::Release)) => self.parse_transaction(),
 
             Some(Token::Keyword(Keyword::Create)) => self.parse_ddl(),
             Some(Token::Keyword(Keyword::Drop)) => self.parse_ddl(),
@@ -176,6 +197,18 @@ impl<'a> Parser<'a> {
                 Token::Keyword(Keyword::String) => DataType::String,
                 Token::Keyword(Keyword::Text) => DataType::String,
                 Token::Keyword(Keyword::Varchar) => DataType::String,
+                Token::Keyword(Keyword::Date) => DataType::Date,
+                Token::Keyword(Keyword::Time) => DataType::Time,
+                Token::Keyword(Keyword::Timestamp) if self.parse_time_zone()? => {
+                    DataType::TimestampTz
+                }
+                Token::Keyword(Keyword::Timestamp) => DataType::Timestamp,
+                Token::Keyword(Keyword::Interval) => DataType::Interval,
+                Token::Keyword(Keyword::Decimal) => self.parse_decimal_spec()?,
+                Token::Keyword(Keyword::Numeric) => self.parse_decimal_spec()?,
+                Token::Keyword(Keyword::Blob) => DataType::Bytes,
+                Token::Keyword(Keyword::Bytea) => DataType::Bytes,
+                Token::Keyword(Keyword::Json) => DataType::Json,
                 token => return Err(Error::Parse(format!("Unexpected token {}", token))),
             },
             primary_key: false,
@@ -183,6 +216,7 @@ impl<'a> Parser<'a> {
             default: None,
             unique: false,
             index: false,
+            index_path: None,
             references: None,
         };
         while let Some(Token::Keyword(keyword)) = self.next_if_keyword() {
@@ -212,7 +246,12 @@ impl<'a> Parser<'a> {
                 }
                 Keyword::Default => column.default = Some(self.parse_expression(0)?),
                 Keyword::Unique => column.unique = true,
-                Keyword::Index => column.index = true,
+                Keyword::Index => {
+                    column.index = true;
+                    if self.next_if_token(Keyword::On.into()).is_some() {
+                        column.index_path = Some(self.next_string()?);
+                    }
+                }
                 Keyword::References => column.references = Some(self.next_ident()?),
                 keyword => return Err(Error::Parse(format!("Unexpected keyword {}", keyword))),
             }
@@ -299,6 +338,7 @@ impl<'a> Parser<'a> {
             } else {
                 None
             },
+            lock: self.parse_clause_lock()?,
         })
     }
 
@@ -331,7 +371,16 @@ impl<'a> Parser<'a> {
             Token::Keyword(Keyword::Begin) => {
                 let mut readonly = false;
                 let mut version = None;
+                let mut serializable = false;
                 self.next_if_token(Keyword::Transaction.into());
+                if self.next_if_token(Keyword::Isolation.into()).is_some() {
+                    self.next_expect(Some(Keyword::Level.into()))?;
+                    match self.next()? {
+                        Token::Keyword(Keyword::Snapshot) => serializable = false,
+                        Token::Keyword(Keyword::Serializable) => serializable = true,
+                        token => return Err(Error::Parse(format!("Unexpected token {}", token))),
+                    }
+                }
                 if self.next_if_token(Keyword::Read.into()).is_some() {
                     match self.next()? {
                         Token::Keyword(Keyword::Only) => readonly = true,
@@ -353,10 +402,21 @@ impl<'a> Parser<'a> {
                         }
                     }
                 }
-                Ok(ast::Statement::Begin { read_only: readonly, as_of: version })
+                Ok(ast::Statement::Begin { read_only: readonly, as_of: version, serializable })
             }
             Token::Keyword(Keyword::Commit) => Ok(ast::Statement::Commit),
-            Token::Keyword(Keyword::Rollback) => Ok(ast::Statement::Rollback),
+            Token::Keyword(Keyword::Rollback) => {
+                if self.next_if_token(Keyword::To.into()).is_none() {
+                    return Ok(ast::Statement::Rollback);
+                }
+                self.next_if_token(Keyword::Savepoint.into());
+                Ok(ast::Statement::RollbackTo(self.next_ident()?))
+            }
+            Token::Keyword(Keyword::Savepoint) => Ok(ast::Statement::Savepoint(self.next_ident()?)),
+            Token::Keyword(Keyword::Release) => {
+                self.next_if_token(Keyword::Savepoint.into());
+                Ok(ast::Statement::Release(self.next_ident()?))
+            }
             token => Err(Error::Parse(format!("Unexpected token {}", token))),
         }
     }
@@ -455,6 +515,20 @@ impl<'a> Parser<'a> {
         Ok(Some(self.parse_expression(0)?))
     }
 
+    /// Parses a row locking clause
+    fn parse_clause_lock(&mut self) -> Result<Option<ast::Lock>> {
+        if self.next_if_token(Keyword::For.into()).is_none() {
+            return Ok(None);
+        }
+        let mode = match self.next()? {
+            Token::Keyword(Keyword::Update) => LockMode::Exclusive,
+            Token::Keyword(Keyword::Share) => LockMode::Shared,
+            token => return Err(Error::Parse(format!("Unexpected token {}", token))),
+        };
+        let nowait = self.next_if_token(Keyword::Nowait.into()).is_some();
+        Ok(Some(ast::Lock { mode, nowait }))
+    }
+
     /// Parses an order clause
     fn parse_clause_order(&mut self) -> Result<Vec<(ast::Expression, ast::Order)>> {
         if self.next_if_token(Keyword::Order.into()).is_none() {
@@ -523,11 +597,15 @@ impl<'a> Parser<'a> {
         } else {
             self.parse_expression_atom()?
         };
-        while let Some(postfix) = self.next_if_operator::<PostfixOperator>(min_prec)? {
-            lhs = postfix.build(lhs)
-        }
-        while let Some(infix) = self.next_if_operator::<InfixOperator>(min_prec)? {
-            lhs = infix.build(lhs, self.parse_expression(infix.prec() + infix.assoc())?)
+        // Postfix operators may follow infix operators which bind tighter, e.g. a -> 'b' IS NULL.
+        loop {
+            if let Some(postfix) = self.next_if_operator::<PostfixOperator>(min_prec)? {
+                lhs = postfix.build(lhs)
+            } else if let Some(infix) = self.next_if_operator::<InfixOperator>(min_prec)? {
+                lhs = infix.build(lhs, self.parse_expression(infix.prec() + infix.assoc())?)
+            } else {
+                break;
+            }
         }
         Ok(lhs)
     }
@@ -537,6 +615,9 @@ impl<'a> Parser<'a> {
         Ok(match self.next()? {
             Token::Ident(i) => {
                 if self.next_if_token(Token::OpenParen).is_some() {
+                    if i == "extract" {
+                        return Ok(ast::Expression::Function(i, self.parse_expression_extract()?));
+                    }
                     let mut args = Vec::new();
                     while self.next_if_token(Token::CloseParen).is_none() {
                         if !args.is_empty() {
@@ -573,14 +654,82 @@ impl<'a> Parser<'a> {
                 expr
             }
             Token::String(s) => ast::Literal::String(s).into(),
+            Token::HexString(s) => ast::Literal::Bytes(
+                hex::decode(&s)
+                    .map_err(|_| Error::Parse(format!("Invalid hex string x'{}'", s)))?,
+            )
+            .into(),
             Token::Keyword(Keyword::False) => ast::Literal::Boolean(false).into(),
             Token::Keyword(Keyword::Infinity) => ast::Literal::Float(f64::INFINITY).into(),
             Token::Keyword(Keyword::NaN) => ast::Literal::Float(f64::NAN).into(),
             Token::Keyword(Keyword::Null) => ast::Literal::Null.into(),
             Token::Keyword(Keyword::True) => ast::Literal::Boolean(true).into(),
+            Token::Keyword(Keyword::Date) => {
+                ast::Literal::Date(temporal::parse_date(&self.next_string()?)?).into()
+            }
+            Token::Keyword(Keyword::Time) => {
+                ast::Literal::Time(temporal::parse_time(&self.next_string()?)?).into()
+            }
+            Token::Keyword(Keyword::Timestamp) if self.parse_time_zone()? => {
+                ast::Literal::TimestampTz(temporal::parse_timestamptz(&self.next_string()?)?).into()
+            }
+            Token::Keyword(Keyword::Timestamp) => {
+                ast::Literal::Timestamp(temporal::parse_timestamp(&self.next_string()?)?).into()
+            }
+            Token::Keyword(Keyword::Interval) => {
+                ast::Literal::Interval(self.next_string()?.parse()?).into()
+            }
+            Token::Keyword(Keyword::Decimal) | Token::Keyword(Keyword::Numeric) => {
+                ast::Literal::Decimal(decimal::parse(&self.next_string()?)?).into()
+            }
+            Token::Keyword(Keyword::Json) => {
+                ast::Literal::Json(json::parse(&self.next_string()?)?).into()
+            }
             t => return Err(Error::Parse(format!("Expected expression atom, found {}", t))),
         })
     }
+
+    /// Parses an optional (precision[, scale]) clause after DECIMAL. The
+    /// precision defaults to the maximum, and the scale to 0.
+    fn parse_decimal_spec(&mut self) -> Result<DataType> {
+        let (mut precision, mut scale) = (decimal::MAX_PRECISION, 0);
+        if self.next_if_token(Token::OpenParen).is_some() {
+            precision = self.next_unsigned()?;
+            if self.next_if_token(Token::Comma).is_some() {
+                scale = self.next_unsigned()?;
+            }
+            self.next_expect(Some(Token::CloseParen))?;
+        }
+        Ok(DataType::Decimal(precision, scale))
+    }
+
+    /// Parses the arguments of EXTRACT(field FROM expr), after the opening
+    /// parenthesis. The field is passed as a string argument.
+    fn parse_expression_extract(&mut self) -> Result<Vec<ast::Expression>> {
+        let field = match self.next()? {
+            Token::Ident(field) | Token::String(field) => field,
+            token => return Err(Error::Parse(format!("Expected field, got {}", token))),
+        };
+        self.next_expect(Some(Keyword::From.into()))?;
+        let expr = self.parse_expression(0)?;
+        self.next_expect(Some(Token::CloseParen))?;
+        Ok(vec![ast::Literal::String(field).into(), expr])
+    }
+
+    /// Parses an optional WITH TIME ZONE or WITHOUT TIME ZONE clause after
+    /// TIMESTAMP, returning true if the timestamp has a time zone.
+    fn parse_time_zone(&mut self) -> Result<bool> {
+        let with_zone = if self.next_if_token(Keyword::With.into()).is_some() {
+            true
+        } else if self.next_if_token(Keyword::Without.into()).is_some() {
+            false
+        } else {
+            return Ok(false);
+        };
+        self.next_expect(Some(Keyword::Time.into()))?;
+        self.next_expect(Some(Keyword::Zone.into()))?;
+        Ok(with_zone)
+    }
 }
 
 /// An operator trait, to help with parsing of operators
@@ -648,6 +797,9 @@ enum InfixOperator {
     GreaterThanOrEqual,
     LessThan,
     LessThanOrEqual,
+    JsonContains,
+    JsonGet,
+    JsonGetText,
     Like,
     Modulo,
     Multiply,
@@ -669,6 +821,9 @@ impl InfixOperator {
             Self::GreaterThanOrEqual => ast::Operation::GreaterThanOrEqual(lhs, rhs),
             Self::LessThan => ast::Operation::LessThan(lhs, rhs),
             Self::LessThanOrEqual => ast::Operation::LessThanOrEqual(lhs, rhs),
+            Self::JsonContains => ast::Operation::JsonContains(lhs, rhs),
+            Self::JsonGet => ast::Operation::JsonGet(lhs, rhs),
+            Self::JsonGetText => ast::Operation::JsonGetText(lhs, rhs),
             Self::Like => ast::Operation::Like(lhs, rhs),
             Self::Modulo => ast::Operation::Modulo(lhs, rhs),
             Self::Multiply => ast::Operation::Multiply(lhs, rhs),
@@ -683,8 +838,11 @@ impl InfixOperator {
 impl Operator for InfixOperator {
     fn from(token: &Token) -> Option<Self> {
         Some(match token {
+            Token::Arrow => Self::JsonGet,
             Token::Asterisk => Self::Multiply,
+            Token::AtGreaterThan => Self::JsonContains,
             Token::Caret => Self::Exponentiate,
+            Token::DoubleArrow => Self::JsonGetText,
             Token::Equal => Self::Equal,
             Token::GreaterThan => Self::GreaterThan,
             Token::GreaterThanOrEqual => Self::GreaterThanOrEqual,
@@ -721,11 +879,13 @@ impl Operator for InfixOperator {
             Self::Equal | Self::NotEqual | Self::Like => 3,
             Self::GreaterThan
             | Self::GreaterThanOrEqual
+            | Self::JsonContains
             | Self::LessThan
             | Self::LessThanOrEqual => 4,
             Self::Add | Self::Subtract => 5,
             Self::Multiply | Self::Divide | Self::Modulo => 6,
             Self::Exponentiate => 7,
+            Self::JsonGet | Self::JsonGetText => 8,
         }
     }
 }
@@ -796,3 +956,8 @@ pub(super) fn format_ident(ident: &str) -> String {
         format!("\"{}\"", ident.replace('\"', "\"\""))
     }
 }
+
+// Formats a string literal by quoting it and escaping quotes
+pub(super) fn format_string(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
@@ -9,6 +9,7 @@ use super::parser::ast;
 use super::schema::{Catalog, Table};
 use super::types::{Expression, Value};
 use crate::error::Result;
+use crate::storage::mvcc::LockMode;
 
 use serde_derive::{Deserialize, Serialize};
 use std::fmt::{self, Display};
@@ -95,6 +96,12 @@ pub enum Node {
         source: Box<Node>,
         limit: u64,
     },
+    Lock {
+        source: Box<Node>,
+        table: String,
+        mode: LockMode,
+        nowait: bool,
+    },
     NestedLoopJoin {
         left: Box<Node>,
         left_size: usize,
@@ -163,6 +170,9 @@ impl Node {
             Self::Limit { source, limit } => {
                 Self::Limit { source: source.transform(before, after)?.into(), limit }
             }
+            Self::Lock { source, table, mode, nowait } => {
+                Self::Lock { source: source.transform(before, after)?.into(), table, mode, nowait }
+            }
             Self::NestedLoopJoin { left, left_size, right, predicate, outer } => {
                 Self::NestedLoopJoin {
                     left: left.transform(before, after)?.into(),
@@ -203,6 +213,7 @@ impl Node {
             | n @ Self::IndexLookup { .. }
             | n @ Self::KeyLookup { .. }
             | n @ Self::Limit { .. }
+            | n @ Self::Lock { .. }
             | n @ Self::NestedLoopJoin { predicate: None, .. }
             | n @ Self::Nothing
             | n @ Self::Offset { .. }
@@ -344,6 +355,15 @@ impl Node {
                 s += &format!("Limit: {}\n", limit);
                 s += &source.format(indent, false, true);
             }
+            Self::Lock { source, table, mode, nowait } => {
+                s += &format!(
+                    "Lock: {} for {}{}\n",
+                    table,
+                    mode,
+                    if *nowait { " nowait" } else { "" }
+                );
+                s += &source.format(indent, false, true);
+            }
             Self::NestedLoopJoin { left, left_size: _, right, predicate, outer } => {
                 s += &format!("NestedLoopJoin: {}", if *outer { "outer" } else { "inner" });
                 if let Some(expr) = predicate {
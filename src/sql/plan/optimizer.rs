@@ -1,5 +1,5 @@
-use super::super::schema::Catalog;
-use super::super::types::{Expression, Value};
+use super::super::schema::{Catalog, Column};
+use super::super::types::{json, Expression, Function, Value};
 use super::Node;
 use crate::error::Result;
 
@@ -25,12 +25,39 @@ impl Optimizer for ConstantFolder {
                         Ok(e)
                     }
                 },
-                &Ok,
+                &|e| match e {
+                    Expression::Function(function, args) => Ok(self.fold_function(function, args)),
+                    e => Ok(e),
+                },
             )
         })
     }
 }
 
+impl ConstantFolder {
+    /// Folds a function call with constant arguments that determine the result, even
+    /// though other arguments aren't constant.
+    fn fold_function(&self, function: Function, mut args: Vec<Expression>) -> Expression {
+        let is_null = |arg: &Expression| matches!(arg, Expression::Constant(Value::Null));
+        if function.propagates_null() && args.iter().any(is_null) {
+            return Expression::Constant(Value::Null);
+        }
+        if function == Function::Coalesce {
+            // Skip NULL arguments, and any arguments after a non-NULL constant.
+            args.retain(|arg| !is_null(arg));
+            if let Some(i) = args.iter().position(|arg| matches!(arg, Expression::Constant(_))) {
+                args.truncate(i + 1);
+            }
+            match args.len() {
+                0 => return Expression::Constant(Value::Null),
+                1 => return args.remove(0),
+                _ => {}
+            }
+        }
+        Expression::Function(function, args)
+    }
+}
+
 /// A filter pushdown optimizer, which moves filter predicates into or closer to the source node.
 pub struct FilterPushdown;
 
@@ -189,12 +216,17 @@ impl<'a, C: Catalog> Optimizer for IndexLookup<'a, C> {
                 // apply the remaining conjunctions as a filter node, if any.
                 let mut cnf = filter.clone().into_cnf_vec();
                 for i in 0..cnf.len() {
-                    if let Some(keys) = cnf[i].as_lookup(pk) {
+                    let keys = cnf[i].as_lookup(pk);
+                    if let Some(keys) = keys.and_then(|k| convert_lookup(&columns[pk], k)) {
                         cnf.remove(i);
                         return Ok(self.wrap_cnf(Node::KeyLookup { table, alias, keys }, cnf));
                     }
                     for (ci, column) in columns.iter().enumerate().filter(|(_, c)| c.index) {
-                        if let Some(values) = cnf[i].as_lookup(ci) {
+                        let lookup = match &column.index_path {
+                            Some(path) => cnf[i].as_lookup_of(&|e| is_path_extract(e, ci, path)),
+                            None => cnf[i].as_lookup(ci).and_then(|v| convert_lookup(column, v)),
+                        };
+                        if let Some(values) = lookup {
                             cnf.remove(i);
                             return Ok(self.wrap_cnf(
                                 Node::IndexLookup {
@@ -215,6 +247,35 @@ impl<'a, C: Catalog> Optimizer for IndexLookup<'a, C> {
     }
 }
 
+// Converts lookup values to the column's type, since they must match the stored values exactly,
+// e.g. integers must be widened to decimals for decimal columns. Returns None if a value can't be
+// converted, in which case the table must be scanned instead.
+fn convert_lookup(column: &Column, values: Vec<Value>) -> Option<Vec<Value>> {
+    values
+        .into_iter()
+        .map(|value| {
+            let value = column.convert_value(value).ok()?;
+            match value.datatype() {
+                Some(datatype) if !column.datatype.accepts(&datatype) => None,
+                _ => Some(value),
+            }
+        })
+        .collect()
+}
+
+// Checks if an expression extracts the given JSON path from a field, i.e. json_extract(field, path).
+fn is_path_extract(expr: &Expression, field: usize, path: &str) -> bool {
+    match expr {
+        Expression::Function(Function::JsonExtract, args) => match args.as_slice() {
+            [Expression::Field(i, _), Expression::Constant(Value::String(p))] => {
+                *i == field && json::normalize_path(p).is_ok_and(|p| p == path)
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
 /// Cleans up noops, e.g. filters with constant true/false predicates.
 /// FIXME This should perhaps replace nodes that can never return anything with a Nothing node,
 /// but that requires propagating the column names.
@@ -1,21 +1,24 @@
 use super::super::parser::ast;
 use super::super::schema::{Catalog, Column, Table};
-use super::super::types::{Expression, Value};
+use super::super::types::{self, json, temporal, Expression, Value};
 use super::{Aggregate, Direction, Node, Plan};
 use crate::error::{Error, Result};
 
+use chrono::{DateTime, Utc};
 use std::collections::{HashMap, HashSet};
 use std::mem::replace;
 
 /// A query plan builder.
 pub struct Planner<'a, C: Catalog> {
     catalog: &'a mut C,
+    /// The statement time, returned by now().
+    now: DateTime<Utc>,
 }
 
 impl<'a, C: Catalog> Planner<'a, C> {
     /// Creates a new planner.
     pub fn new(catalog: &'a mut C) -> Self {
-        Self { catalog }
+        Self { catalog, now: temporal::now() }
     }
 
     /// Builds a plan for an AST statement.
@@ -27,7 +30,12 @@ impl<'a, C: Catalog> Planner<'a, C> {
     fn build_statement(&self, statement: ast::Statement) -> Result<Node> {
         Ok(match statement {
             // Transaction control and explain statements should have been handled by session.
-            ast::Statement::Begin { .. } | ast::Statement::Commit | ast::Statement::Rollback => {
+            ast::Statement::Begin { .. }
+            | ast::Statement::Commit
+            | ast::Statement::Rollback
+            | ast::Statement::Savepoint(_)
+            | ast::Statement::RollbackTo(_)
+            | ast::Statement::Release(_) => {
                 return Err(Error::Internal(format!(
                     "Unexpected transaction statement {:?}",
                     statement
@@ -51,16 +59,23 @@ impl<'a, C: Catalog> Planner<'a, C> {
                                 None if nullable => Some(Value::Null),
                                 None => None,
                             };
-                            Ok(Column {
+                            let mut column = Column {
                                 name: c.name,
                                 datatype: c.datatype,
                                 primary_key: c.primary_key,
                                 nullable,
-                                default,
+                                default: None,
                                 index: c.index && !c.primary_key,
+                                index_path: c
+                                    .index_path
+                                    .map(|path| json::normalize_path(&path))
+                                    .transpose()?,
                                 unique: c.unique || c.primary_key,
                                 references: c.references,
-                            })
+                            };
+                            column.default =
+                                default.map(|v| column.convert_value(v)).transpose()?;
+                            Ok(column)
                         })
                         .collect::<Result<_>>()?,
                 )?,
@@ -129,9 +144,19 @@ impl<'a, C: Catalog> Planner<'a, C> {
                 mut order,
                 offset,
                 limit,
+                lock,
             } => {
                 let scope = &mut Scope::new();
 
+                // Row locks need a single table to find the locked rows' primary keys.
+                let lock_table = match (&lock, from.as_slice()) {
+                    (None, _) => None,
+                    (Some(_), [ast::FromItem::Table { name, .. }]) => Some(name.clone()),
+                    (Some(_), _) => {
+                        return Err(Error::Value("FOR UPDATE/SHARE requires a single table".into()))
+                    }
+                };
+
                 // Build FROM clause.
                 let mut node = if !from.is_empty() {
                     self.build_from_clause(scope, from)?
@@ -149,6 +174,11 @@ impl<'a, C: Catalog> Planner<'a, C> {
                     };
                 };
 
+                // Lock the rows matched by the WHERE clause.
+                if let (Some(ast::Lock { mode, nowait }), Some(table)) = (lock, lock_table) {
+                    node = Node::Lock { source: Box::new(node), table, mode, nowait };
+                }
+
                 // Build SELECT clause.
                 let mut hidden = 0;
                 if !select.is_empty() {
@@ -532,13 +562,40 @@ impl<'a, C: Catalog> Planner<'a, C> {
                 ast::Literal::Integer(i) => Value::Integer(i),
                 ast::Literal::Float(f) => Value::Float(f),
                 ast::Literal::String(s) => Value::String(s),
+                ast::Literal::Date(d) => Value::Date(d),
+                ast::Literal::Time(t) => Value::Time(t),
+                ast::Literal::Timestamp(ts) => Value::Timestamp(ts),
+                ast::Literal::TimestampTz(ts) => Value::TimestampTz(ts),
+                ast::Literal::Interval(i) => Value::Interval(i),
+                ast::Literal::Decimal(d) => Value::Decimal(d),
+                ast::Literal::Bytes(b) => Value::Bytes(b),
+                ast::Literal::Json(j) => Value::Json(j),
             }),
             ast::Expression::Column(i) => Field(i, scope.get_label(i)?),
             ast::Expression::Field(table, name) => {
                 Field(scope.resolve(table.as_deref(), &name)?, Some((table, name)))
             }
-            ast::Expression::Function(name, _) => {
-                return Err(Error::Value(format!("Unknown function {}", name,)))
+            ast::Expression::Function(name, args) if name == "now" && args.is_empty() => {
+                Constant(Value::TimestampTz(self.now))
+            }
+            ast::Expression::Function(name, args) => {
+                let function = types::Function::from_name(&name)
+                    .ok_or_else(|| Error::Value(format!("Unknown function {}", name)))?;
+                function.check_arity(args.len())?;
+                let args = args
+                    .into_iter()
+                    .map(|arg| self.build_expression(scope, arg))
+                    .collect::<Result<Vec<_>>>()?;
+                // Check constant arguments against the function signature up front, other
+                // arguments are checked during evaluation.
+                let mut constants = Vec::new();
+                for (index, arg) in args.iter().enumerate() {
+                    if let Constant(value) = arg {
+                        function.check_argument(index, value, &constants)?;
+                        constants.push(value.clone());
+                    }
+                }
+                Function(function, args)
             }
             ast::Expression::Operation(op) => match op {
                 // Logical operators
@@ -630,6 +687,20 @@ impl<'a, C: Catalog> Planner<'a, C> {
                     self.build_expression(scope, *lhs)?.into(),
                     self.build_expression(scope, *rhs)?.into(),
                 ),
+
+                // JSON operators
+                ast::Operation::JsonContains(lhs, rhs) => JsonContains(
+                    self.build_expression(scope, *lhs)?.into(),
+                    self.build_expression(scope, *rhs)?.into(),
+                ),
+                ast::Operation::JsonGet(lhs, rhs) => JsonGet(
+                    self.build_expression(scope, *lhs)?.into(),
+                    self.build_expression(scope, *rhs)?.into(),
+                ),
+                ast::Operation::JsonGetText(lhs, rhs) => JsonGetText(
+                    self.build_expression(scope, *lhs)?.into(),
+                    self.build_expression(scope, *rhs)?.into(),
+                ),
             },
         })
     }
@@ -1,6 +1,6 @@
 use super::engine::Transaction;
-use super::parser::format_ident;
-use super::types::{DataType, Value};
+use super::parser::{format_ident, format_string};
+use super::types::{decimal, json, DataType, Value};
 use crate::error::{Error, Result};
 
 use serde_derive::{Deserialize, Serialize};
@@ -153,6 +153,8 @@ pub struct Column {
     pub references: Option<String>,
     /// Whether the column should be indexed
     pub index: bool,
+    /// The JSON path to index for JSON columns, instead of the whole value
+    pub index_path: Option<String>,
 }
 
 impl Column {
@@ -165,11 +167,32 @@ impl Column {
         if self.primary_key && !self.unique {
             return Err(Error::Value(format!("Primary key {} must be unique", self.name)));
         }
+        if self.datatype == DataType::Interval && (self.primary_key || self.index) {
+            return Err(Error::Value(format!(
+                "Interval column {} can't be a primary key or indexed",
+                self.name
+            )));
+        }
+        if self.index_path.is_some() && (self.datatype != DataType::Json || !self.index) {
+            return Err(Error::Value(format!(
+                "Column {} must be an indexed JSON column to index a JSON path",
+                self.name
+            )));
+        }
+        if let DataType::Decimal(precision, scale) = self.datatype {
+            if !(1..=decimal::MAX_PRECISION).contains(&precision) || scale > precision {
+                return Err(Error::Value(format!(
+                    "Decimal column {} must have precision 1 to {} and scale 0 to precision",
+                    self.name,
+                    decimal::MAX_PRECISION
+                )));
+            }
+        }
 
         // Validate default value
         if let Some(default) = &self.default {
             if let Some(datatype) = default.datatype() {
-                if datatype != self.datatype {
+                if !self.datatype.accepts(&datatype) {
                     return Err(Error::Value(format!(
                         "Default value for column {} has datatype {}, must be {}",
                         self.name, datatype, self.datatype
@@ -214,6 +237,26 @@ impl Column {
         Ok(())
     }
 
+    /// Converts a value for storage in the column. Strings are parsed as JSON
+    /// documents for JSON columns, integers are widened to decimals for
+    /// decimal columns, other values are stored as is.
+    pub fn convert_value(&self, value: Value) -> Result<Value> {
+        match (&self.datatype, value) {
+            (DataType::Json, Value::String(s)) => Ok(Value::Json(json::parse(&s)?)),
+            (DataType::Decimal(_, _), Value::Integer(i)) => Ok(Value::Decimal(i.into())),
+            (_, value) => Ok(value),
+        }
+    }
+
+    /// Returns the index entry value for a column value, i.e. the value at the
+    /// index path for JSON path indexes.
+    pub fn index_value(&self, value: &Value) -> Result<Value> {
+        match (&self.index_path, value) {
+            (Some(path), Value::Json(j)) => json::extract(j, path),
+            (_, value) => Ok(value.clone()),
+        }
+    }
+
     /// Validates a column value
     pub fn validate_value(
         &self,
@@ -226,11 +269,17 @@ impl Column {
         match value.datatype() {
             None if self.nullable => Ok(()),
             None => Err(Error::Value(format!("NULL value not allowed for column {}", self.name))),
-            Some(ref datatype) if datatype != &self.datatype => Err(Error::Value(format!(
+            Some(ref datatype) if self.datatype.accepts(datatype) => Ok(()),
+            Some(DataType::Decimal(_, _)) if matches!(self.datatype, DataType::Decimal(_, _)) => {
+                Err(Error::Value(format!(
+                    "Decimal value {} does not fit {} column {}",
+                    value, self.datatype, self.name
+                )))
+            }
+            Some(datatype) => Err(Error::Value(format!(
                 "Invalid datatype {} for {} column {}",
                 datatype, self.datatype, self.name
             ))),
-            _ => Ok(()),
         }?;
 
         // Validate value
@@ -297,6 +346,9 @@ impl Display for Column {
         if self.index {
             sql += " INDEX";
         }
+        if let Some(path) = &self.index_path {
+            sql += &format!(" ON {}", format_string(path));
+        }
         write!(f, "{}", sql)
     }
 }
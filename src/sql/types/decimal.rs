@@ -0,0 +1,122 @@
+//! The exact DECIMAL(precision, scale) data type, using rust_decimal values
+//! with up to 28 significant digits.
+//!
+//! Decimal values don't keep trailing fractional zeros, i.e. 1.50 and 1.5 are
+//! the same value. For storage, they're serialized as their integral part and
+//! their fractional part scaled by 10^28, each as an i128 split into a high
+//! i64 and a low u64. This makes their KeyCode encoding order-preserving, so
+//! they can be used in primary keys and indexes.
+
+use crate::error::{Error, Result};
+
+use rust_decimal::prelude::ToPrimitive as _;
+use rust_decimal::Decimal;
+
+/// The maximum precision (number of significant digits) of a decimal.
+pub const MAX_PRECISION: u32 = 28;
+
+/// Parses a decimal literal.
+pub fn parse(s: &str) -> Result<Decimal> {
+    Decimal::from_str_exact(s.trim()).map_err(|_| Error::Parse(format!("Invalid decimal {}", s)))
+}
+
+/// Returns the precision and scale of a decimal value, i.e. the number of
+/// significant digits and the number of fractional digits.
+pub fn precision_scale(decimal: &Decimal) -> (u32, u32) {
+    let decimal = decimal.normalize();
+    let digits = decimal.mantissa().unsigned_abs().checked_ilog10().unwrap_or(0) + 1;
+    (digits.max(decimal.scale()), decimal.scale())
+}
+
+/// Converts a decimal to a float, for arithmetic with floats.
+pub fn to_f64(decimal: &Decimal) -> f64 {
+    decimal.to_f64().unwrap_or(f64::NAN)
+}
+
+/// Serializes decimals as order-preserving integral and fractional parts.
+pub(super) mod serde_decimal {
+    use super::*;
+    use serde::{de::Error as _, Deserialize as _, Deserializer, Serialize as _, Serializer};
+
+    /// The scale of the serialized fractional part.
+    const FRACTION_SCALE: u32 = 28;
+
+    pub fn serialize<S: Serializer>(
+        decimal: &Decimal,
+        s: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        let integral = decimal.trunc().mantissa();
+        let fraction = decimal.fract();
+        let fraction = fraction.mantissa() * 10i128.pow(FRACTION_SCALE - fraction.scale());
+        ((integral >> 64) as i64, integral as u64, (fraction >> 64) as i64, fraction as u64)
+            .serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> std::result::Result<Decimal, D::Error> {
+        let (integral_hi, integral_lo, fraction_hi, fraction_lo) =
+            <(i64, u64, i64, u64)>::deserialize(d)?;
+        let integral = (integral_hi as i128) << 64 | integral_lo as i128;
+        let fraction = (fraction_hi as i128) << 64 | fraction_lo as i128;
+        Decimal::try_from_i128_with_scale(integral, 0)
+            .ok()
+            .zip(Decimal::try_from_i128_with_scale(fraction, FRACTION_SCALE).ok())
+            .and_then(|(integral, fraction)| integral.checked_add(fraction.normalize()))
+            .map(|decimal| decimal.normalize())
+            .ok_or_else(|| D::Error::custom(format!("Invalid decimal {}.{}", integral, fraction)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::keycode;
+    use crate::sql::types::Value;
+
+    #[test]
+    /// Tests decimal precision and scale.
+    fn precision() -> Result<()> {
+        assert_eq!(precision_scale(&parse("123.45")?), (5, 2));
+        assert_eq!(precision_scale(&parse("-123.4500")?), (5, 2));
+        assert_eq!(precision_scale(&parse("0.05")?), (2, 2));
+        assert_eq!(precision_scale(&parse("1000")?), (4, 0));
+        assert_eq!(precision_scale(&parse("0")?), (1, 0));
+        assert_eq!(precision_scale(&Decimal::MAX), (29, 0));
+        assert!(parse("1.2.3").is_err());
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that decimals round-trip through serialization, and that the
+    /// KeyCode encoding is order-preserving.
+    fn serialize() -> Result<()> {
+        let decimals = [
+            Decimal::MIN,
+            parse("-1000")?,
+            parse("-1.5")?,
+            parse("-1.05")?,
+            parse("-1")?,
+            parse("-0.0000000000000000000000000001")?,
+            parse("0")?,
+            parse("0.5")?,
+            parse("1")?,
+            parse("1.05")?,
+            parse("1.5")?,
+            parse("7922816251426433759354395033.5")?,
+            Decimal::MAX,
+        ];
+        let mut keys = Vec::new();
+        for decimal in decimals {
+            let value = Value::Decimal(decimal);
+            let key = keycode::serialize(&value)?;
+            assert_eq!(keycode::deserialize::<Value>(&key)?, value);
+            assert_eq!(bincode::deserialize::<Value>(&bincode::serialize(&value)?)?, value);
+            keys.push(key);
+        }
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(
+            keycode::serialize(&Value::Decimal(parse("1.50")?))?,
+            keycode::serialize(&Value::Decimal(parse("1.5")?))?
+        );
+        Ok(())
+    }
+}
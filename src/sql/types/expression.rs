@@ -1,8 +1,11 @@
-use super::{Row, Value};
+use super::{decimal, json, temporal, Function, Row, Value};
 use crate::error::{Error, Result};
 
+use chrono::NaiveDate;
 use regex::Regex;
+use rust_decimal::MathematicalOps as _;
 use serde_derive::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::fmt::{self, Display};
 use std::mem::replace;
 
@@ -37,6 +40,14 @@ pub enum Expression {
 
     // String operations
     Like(Box<Expression>, Box<Expression>),
+
+    // JSON operations
+    JsonContains(Box<Expression>, Box<Expression>),
+    JsonGet(Box<Expression>, Box<Expression>),
+    JsonGetText(Box<Expression>, Box<Expression>),
+
+    // Function calls
+    Function(Function, Vec<Expression>),
 }
 
 impl Expression {
@@ -82,10 +93,13 @@ impl Expression {
                 (Float(lhs), Integer(rhs)) => Boolean(lhs == rhs as f64),
                 (Float(lhs), Float(rhs)) => Boolean(lhs == rhs),
                 (String(lhs), String(rhs)) => Boolean(lhs == rhs),
+                (Bytes(lhs), Bytes(rhs)) => Boolean(lhs == rhs),
                 (Null, _) | (_, Null) => Null,
-                (lhs, rhs) => {
-                    return Err(Error::Value(format!("Can't compare {} and {}", lhs, rhs)))
-                }
+                // Decimal and temporal values, which may be of different types.
+                (lhs, rhs) => match lhs.partial_cmp(&rhs) {
+                    Some(ordering) => Boolean(ordering == Ordering::Equal),
+                    None => return Err(Error::Value(format!("Can't compare {} and {}", lhs, rhs))),
+                },
             },
             Self::GreaterThan(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
                 #[allow(clippy::bool_comparison)]
@@ -95,10 +109,13 @@ impl Expression {
                 (Float(lhs), Integer(rhs)) => Boolean(lhs > rhs as f64),
                 (Float(lhs), Float(rhs)) => Boolean(lhs > rhs),
                 (String(lhs), String(rhs)) => Boolean(lhs > rhs),
+                (Bytes(lhs), Bytes(rhs)) => Boolean(lhs > rhs),
                 (Null, _) | (_, Null) => Null,
-                (lhs, rhs) => {
-                    return Err(Error::Value(format!("Can't compare {} and {}", lhs, rhs)))
-                }
+                // Decimal and temporal values, which may be of different types.
+                (lhs, rhs) => match lhs.partial_cmp(&rhs) {
+                    Some(ordering) => Boolean(ordering == Ordering::Greater),
+                    None => return Err(Error::Value(format!("Can't compare {} and {}", lhs, rhs))),
+                },
             },
             Self::LessThan(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
                 #[allow(clippy::bool_comparison)]
@@ -108,10 +125,13 @@ impl Expression {
                 (Float(lhs), Integer(rhs)) => Boolean(lhs < rhs as f64),
                 (Float(lhs), Float(rhs)) => Boolean(lhs < rhs),
                 (String(lhs), String(rhs)) => Boolean(lhs < rhs),
+                (Bytes(lhs), Bytes(rhs)) => Boolean(lhs < rhs),
                 (Null, _) | (_, Null) => Null,
-                (lhs, rhs) => {
-                    return Err(Error::Value(format!("Can't compare {} and {}", lhs, rhs)))
-                }
+                // Decimal and temporal values, which may be of different types.
+                (lhs, rhs) => match lhs.partial_cmp(&rhs) {
+                    Some(ordering) => Boolean(ordering == Ordering::Less),
+                    None => return Err(Error::Value(format!("Can't compare {} and {}", lhs, rhs))),
+                },
             },
             Self::IsNull(expr) => match expr.evaluate(row)? {
                 Null => Boolean(true),
@@ -131,11 +151,42 @@ impl Expression {
                 (Null, Float(_)) => Null,
                 (Null, Integer(_)) => Null,
                 (Null, Null) => Null,
+                (Date(lhs), Integer(rhs)) | (Integer(rhs), Date(lhs)) => {
+                    Date(temporal::add_days(lhs, rhs)?)
+                }
+                (Date(lhs), Time(rhs)) | (Time(rhs), Date(lhs)) => Timestamp(lhs.and_time(rhs)),
+                (Date(lhs), Interval(rhs)) | (Interval(rhs), Date(lhs)) => {
+                    Timestamp(rhs.add_to(lhs.into())?)
+                }
+                (Time(lhs), Interval(rhs)) | (Interval(rhs), Time(lhs)) => {
+                    Time(rhs.add_to_time(lhs))
+                }
+                (Timestamp(lhs), Interval(rhs)) | (Interval(rhs), Timestamp(lhs)) => {
+                    Timestamp(rhs.add_to(lhs)?)
+                }
+                (TimestampTz(lhs), Interval(rhs)) | (Interval(rhs), TimestampTz(lhs)) => {
+                    TimestampTz(rhs.add_to(lhs.naive_utc())?.and_utc())
+                }
+                (Interval(lhs), Interval(rhs)) => Interval(lhs.checked_add(&rhs)?),
+                (Decimal(lhs), Decimal(rhs)) => Decimal(
+                    lhs.checked_add(rhs).ok_or_else(|| Error::Value("Decimal overflow".into()))?,
+                ),
+                (Decimal(lhs), Integer(rhs)) | (Integer(rhs), Decimal(lhs)) => Decimal(
+                    lhs.checked_add(rhs.into())
+                        .ok_or_else(|| Error::Value("Decimal overflow".into()))?,
+                ),
+                (Decimal(lhs), Float(rhs)) | (Float(rhs), Decimal(lhs)) => {
+                    Float(decimal::to_f64(&lhs) + rhs)
+                }
+                (Decimal(_), Null) | (Null, Decimal(_)) => Null,
+                (Date(_) | Time(_) | Timestamp(_) | TimestampTz(_) | Interval(_), Null) => Null,
+                (Null, Date(_) | Time(_) | Timestamp(_) | TimestampTz(_) | Interval(_)) => Null,
                 (lhs, rhs) => return Err(Error::Value(format!("Can't add {} and {}", lhs, rhs))),
             },
             Self::Assert(expr) => match expr.evaluate(row)? {
                 Float(f) => Float(f),
                 Integer(i) => Integer(i),
+                Decimal(d) => Decimal(d),
                 Null => Null,
                 expr => return Err(Error::Value(format!("Can't take the positive of {}", expr))),
             },
@@ -152,6 +203,30 @@ impl Expression {
                 (Null, Float(_)) => Null,
                 (Null, Integer(_)) => Null,
                 (Null, Null) => Null,
+                (Interval(lhs), Integer(rhs)) => Interval(lhs.checked_div(rhs as f64)?),
+                (Interval(lhs), Float(rhs)) => Interval(lhs.checked_div(rhs)?),
+                (Interval(_), Null) | (Null, Interval(_)) => Null,
+                (Decimal(_), Integer(0)) => {
+                    return Err(Error::Value("Can't divide by zero".into()))
+                }
+                (Decimal(_) | Integer(_), Decimal(rhs)) if rhs.is_zero() => {
+                    return Err(Error::Value("Can't divide by zero".into()))
+                }
+                (Decimal(lhs), Decimal(rhs)) => Decimal(
+                    lhs.checked_div(rhs).ok_or_else(|| Error::Value("Decimal overflow".into()))?,
+                ),
+                (Decimal(lhs), Integer(rhs)) => Decimal(
+                    lhs.checked_div(rhs.into())
+                        .ok_or_else(|| Error::Value("Decimal overflow".into()))?,
+                ),
+                (Integer(lhs), Decimal(rhs)) => Decimal(
+                    rust_decimal::Decimal::from(lhs)
+                        .checked_div(rhs)
+                        .ok_or_else(|| Error::Value("Decimal overflow".into()))?,
+                ),
+                (Decimal(lhs), Float(rhs)) => Float(decimal::to_f64(&lhs) / rhs),
+                (Float(lhs), Decimal(rhs)) => Float(lhs / decimal::to_f64(&rhs)),
+                (Decimal(_), Null) | (Null, Decimal(_)) => Null,
                 (lhs, rhs) => {
                     return Err(Error::Value(format!("Can't divide {} and {}", lhs, rhs)))
                 }
@@ -170,6 +245,16 @@ impl Expression {
                 (Null, Float(_)) => Null,
                 (Null, Integer(_)) => Null,
                 (Null, Null) => Null,
+                (Decimal(lhs), Integer(rhs)) => Decimal(
+                    lhs.checked_powi(rhs).ok_or_else(|| Error::Value("Decimal overflow".into()))?,
+                ),
+                (Decimal(lhs), Decimal(rhs)) => {
+                    Float(decimal::to_f64(&lhs).powf(decimal::to_f64(&rhs)))
+                }
+                (Decimal(lhs), Float(rhs)) => Float(decimal::to_f64(&lhs).powf(rhs)),
+                (Integer(lhs), Decimal(rhs)) => Float((lhs as f64).powf(decimal::to_f64(&rhs))),
+                (Float(lhs), Decimal(rhs)) => Float(lhs.powf(decimal::to_f64(&rhs))),
+                (Decimal(_), Null) | (Null, Decimal(_)) => Null,
                 (lhs, rhs) => {
                     return Err(Error::Value(format!("Can't exponentiate {} and {}", lhs, rhs)))
                 }
@@ -196,6 +281,27 @@ impl Expression {
                 (Null, Float(_)) => Null,
                 (Null, Integer(_)) => Null,
                 (Null, Null) => Null,
+                (Decimal(_), Integer(0)) => {
+                    return Err(Error::Value("Can't divide by zero".into()))
+                }
+                (Decimal(_) | Integer(_), Decimal(rhs)) if rhs.is_zero() => {
+                    return Err(Error::Value("Can't divide by zero".into()))
+                }
+                (Decimal(lhs), Decimal(rhs)) => Decimal(
+                    lhs.checked_rem(rhs).ok_or_else(|| Error::Value("Decimal overflow".into()))?,
+                ),
+                (Decimal(lhs), Integer(rhs)) => Decimal(
+                    lhs.checked_rem(rhs.into())
+                        .ok_or_else(|| Error::Value("Decimal overflow".into()))?,
+                ),
+                (Integer(lhs), Decimal(rhs)) => Decimal(
+                    rust_decimal::Decimal::from(lhs)
+                        .checked_rem(rhs)
+                        .ok_or_else(|| Error::Value("Decimal overflow".into()))?,
+                ),
+                (Decimal(lhs), Float(rhs)) => Float(decimal::to_f64(&lhs) % rhs),
+                (Float(lhs), Decimal(rhs)) => Float(lhs % decimal::to_f64(&rhs)),
+                (Decimal(_), Null) | (Null, Decimal(_)) => Null,
                 (lhs, rhs) => {
                     return Err(Error::Value(format!("Can't take modulo of {} and {}", lhs, rhs)))
                 }
@@ -212,6 +318,24 @@ impl Expression {
                 (Null, Float(_)) => Null,
                 (Null, Integer(_)) => Null,
                 (Null, Null) => Null,
+                (Interval(lhs), Integer(rhs)) | (Integer(rhs), Interval(lhs)) => {
+                    Interval(lhs.checked_mul(rhs as f64)?)
+                }
+                (Interval(lhs), Float(rhs)) | (Float(rhs), Interval(lhs)) => {
+                    Interval(lhs.checked_mul(rhs)?)
+                }
+                (Interval(_), Null) | (Null, Interval(_)) => Null,
+                (Decimal(lhs), Decimal(rhs)) => Decimal(
+                    lhs.checked_mul(rhs).ok_or_else(|| Error::Value("Decimal overflow".into()))?,
+                ),
+                (Decimal(lhs), Integer(rhs)) | (Integer(rhs), Decimal(lhs)) => Decimal(
+                    lhs.checked_mul(rhs.into())
+                        .ok_or_else(|| Error::Value("Decimal overflow".into()))?,
+                ),
+                (Decimal(lhs), Float(rhs)) | (Float(rhs), Decimal(lhs)) => {
+                    Float(decimal::to_f64(&lhs) * rhs)
+                }
+                (Decimal(_), Null) | (Null, Decimal(_)) => Null,
                 (lhs, rhs) => {
                     return Err(Error::Value(format!("Can't multiply {} and {}", lhs, rhs)))
                 }
@@ -219,6 +343,8 @@ impl Expression {
             Self::Negate(expr) => match expr.evaluate(row)? {
                 Integer(i) => Integer(-i),
                 Float(f) => Float(-f),
+                Interval(i) => Interval(i.checked_neg()?),
+                Decimal(d) => Decimal(-d),
                 Null => Null,
                 value => return Err(Error::Value(format!("Can't negate {}", value))),
             },
@@ -234,6 +360,45 @@ impl Expression {
                 (Null, Float(_)) => Null,
                 (Null, Integer(_)) => Null,
                 (Null, Null) => Null,
+                (Date(lhs), Integer(rhs)) => Date(temporal::add_days(
+                    lhs,
+                    rhs.checked_neg().ok_or_else(|| Error::Value("Integer overflow".into()))?,
+                )?),
+                (Date(lhs), Date(rhs)) => Integer(lhs.signed_duration_since(rhs).num_days()),
+                (Date(lhs), Interval(rhs)) => Timestamp(rhs.checked_neg()?.add_to(lhs.into())?),
+                (Time(lhs), Time(rhs)) => Interval(temporal::Interval::between(
+                    NaiveDate::default().and_time(lhs),
+                    NaiveDate::default().and_time(rhs),
+                )?),
+                (Time(lhs), Interval(rhs)) => Time(rhs.checked_neg()?.add_to_time(lhs)),
+                (Timestamp(lhs), Timestamp(rhs)) => {
+                    Interval(temporal::Interval::between(lhs, rhs)?)
+                }
+                (Timestamp(lhs), Interval(rhs)) => Timestamp(rhs.checked_neg()?.add_to(lhs)?),
+                (TimestampTz(lhs), TimestampTz(rhs)) => {
+                    Interval(temporal::Interval::between(lhs.naive_utc(), rhs.naive_utc())?)
+                }
+                (TimestampTz(lhs), Interval(rhs)) => {
+                    TimestampTz(rhs.checked_neg()?.add_to(lhs.naive_utc())?.and_utc())
+                }
+                (Interval(lhs), Interval(rhs)) => Interval(lhs.checked_sub(&rhs)?),
+                (Decimal(lhs), Decimal(rhs)) => Decimal(
+                    lhs.checked_sub(rhs).ok_or_else(|| Error::Value("Decimal overflow".into()))?,
+                ),
+                (Decimal(lhs), Integer(rhs)) => Decimal(
+                    lhs.checked_sub(rhs.into())
+                        .ok_or_else(|| Error::Value("Decimal overflow".into()))?,
+                ),
+                (Integer(lhs), Decimal(rhs)) => Decimal(
+                    rust_decimal::Decimal::from(lhs)
+                        .checked_sub(rhs)
+                        .ok_or_else(|| Error::Value("Decimal overflow".into()))?,
+                ),
+                (Decimal(lhs), Float(rhs)) => Float(decimal::to_f64(&lhs) - rhs),
+                (Float(lhs), Decimal(rhs)) => Float(lhs - decimal::to_f64(&rhs)),
+                (Decimal(_), Null) | (Null, Decimal(_)) => Null,
+                (Date(_) | Time(_) | Timestamp(_) | TimestampTz(_) | Interval(_), Null) => Null,
+                (Null, Date(_) | Time(_) | Timestamp(_) | TimestampTz(_) | Interval(_)) => Null,
                 (lhs, rhs) => {
                     return Err(Error::Value(format!("Can't subtract {} and {}", lhs, rhs)))
                 }
@@ -256,6 +421,29 @@ impl Expression {
                 (Null, String(_)) => Null,
                 (lhs, rhs) => return Err(Error::Value(format!("Can't LIKE {} and {}", lhs, rhs))),
             },
+
+            // JSON operations
+            Self::JsonContains(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
+                (Json(lhs), Json(rhs)) => Boolean(json::contains(&lhs, &rhs)?),
+                (Json(_) | Null, Null) | (Null, Json(_)) => Null,
+                (lhs, rhs) => {
+                    return Err(Error::Value(format!("Can't check if {} contains {}", lhs, rhs)))
+                }
+            },
+            Self::JsonGet(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
+                (Json(_), Null) | (Null, _) => Null,
+                (Json(lhs), rhs) => json::get(&lhs, rhs)?,
+                (lhs, rhs) => return Err(Error::Value(format!("Can't get {} from {}", rhs, lhs))),
+            },
+            Self::JsonGetText(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
+                (Json(_), Null) | (Null, _) => Null,
+                (Json(lhs), rhs) => json::get_text(&lhs, rhs)?,
+                (lhs, rhs) => return Err(Error::Value(format!("Can't get {} from {}", rhs, lhs))),
+            },
+
+            // Function calls
+            Self::Function(function, args) => function
+                .evaluate(args.iter().map(|arg| arg.evaluate(row)).collect::<Result<_>>()?)?,
         })
     }
 
@@ -288,6 +476,9 @@ impl Expression {
             | Self::Equal(lhs, rhs)
             | Self::Exponentiate(lhs, rhs)
             | Self::GreaterThan(lhs, rhs)
+            | Self::JsonContains(lhs, rhs)
+            | Self::JsonGet(lhs, rhs)
+            | Self::JsonGetText(lhs, rhs)
             | Self::LessThan(lhs, rhs)
             | Self::Like(lhs, rhs)
             | Self::Modulo(lhs, rhs)
@@ -304,6 +495,12 @@ impl Expression {
             | Self::Negate(expr)
             | Self::Not(expr) => Self::replace_with(expr, |e| e.transform(before, after))?,
 
+            Self::Function(_, args) => {
+                for arg in args {
+                    Self::replace_with(arg, |e| e.transform(before, after))?;
+                }
+            }
+
             Self::Constant(_) | Self::Field(_, _) => {}
         };
         after(self)
@@ -319,6 +516,9 @@ impl Expression {
                 | Self::Equal(lhs, rhs)
                 | Self::Exponentiate(lhs, rhs)
                 | Self::GreaterThan(lhs, rhs)
+                | Self::JsonContains(lhs, rhs)
+                | Self::JsonGet(lhs, rhs)
+                | Self::JsonGetText(lhs, rhs)
                 | Self::LessThan(lhs, rhs)
                 | Self::Like(lhs, rhs)
                 | Self::Modulo(lhs, rhs)
@@ -332,6 +532,8 @@ impl Expression {
                 | Self::Negate(expr)
                 | Self::Not(expr) => expr.walk(visitor),
 
+                Self::Function(_, args) => args.iter().all(|arg| arg.walk(visitor)),
+
                 Self::Constant(_) | Self::Field(_, _) => true,
             }
     }
@@ -466,20 +668,23 @@ impl Expression {
     // Checks if the expression is a field lookup, and returns the list of values looked up.
     // Expressions must be a combination of =, IS NULL, OR to be converted.
     pub fn as_lookup(&self, field: usize) -> Option<Vec<Value>> {
+        self.as_lookup_of(&|e| matches!(e, Expression::Field(i, _) if i == &field))
+    }
+
+    // Checks if the expression is a lookup of an arbitrary target expression, e.g. a JSON path
+    // extraction, and returns the list of values looked up. See as_lookup().
+    pub fn as_lookup_of<F: Fn(&Expression) -> bool>(&self, target: &F) -> Option<Vec<Value>> {
         use Expression::*;
         // FIXME This should use a single match level, but since the child expressions are boxed
         // that would require box patterns, which are unstable.
         match &self {
             Equal(lhs, rhs) => match (&**lhs, &**rhs) {
-                (Field(i, _), Constant(v)) if i == &field => Some(vec![v.clone()]),
-                (Constant(v), Field(i, _)) if i == &field => Some(vec![v.clone()]),
+                (e, Constant(v)) if target(e) => Some(vec![v.clone()]),
+                (Constant(v), e) if target(e) => Some(vec![v.clone()]),
                 (_, _) => None,
             },
-            IsNull(e) => match &**e {
-                Field(i, _) if i == &field => Some(vec![Value::Null]),
-                _ => None,
-            },
-            Or(lhs, rhs) => match (lhs.as_lookup(field), rhs.as_lookup(field)) {
+            IsNull(e) if target(e) => Some(vec![Value::Null]),
+            Or(lhs, rhs) => match (lhs.as_lookup_of(target), rhs.as_lookup_of(target)) {
                 (Some(mut lvalues), Some(mut rvalues)) => {
                     lvalues.append(&mut rvalues);
                     Some(lvalues)
@@ -545,6 +750,16 @@ impl Display for Expression {
             Self::Subtract(lhs, rhs) => format!("{} - {}", lhs, rhs),
 
             Self::Like(lhs, rhs) => format!("{} LIKE {}", lhs, rhs),
+
+            Self::JsonContains(lhs, rhs) => format!("{} @> {}", lhs, rhs),
+            Self::JsonGet(lhs, rhs) => format!("{} -> {}", lhs, rhs),
+            Self::JsonGetText(lhs, rhs) => format!("{} ->> {}", lhs, rhs),
+
+            Self::Function(function, args) => format!(
+                "{}({})",
+                function,
+                args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>().join(", ")
+            ),
         };
         write!(f, "{}", s)
     }
@@ -0,0 +1,502 @@
+use super::{json, temporal, Expression, Value};
+use crate::error::{Error, Result};
+
+use rust_decimal::MathematicalOps as _;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde_derive::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+
+/// A scalar function, which is evaluated for each row. Functions return NULL
+/// if any of their arguments are NULL, except coalesce(), concat() and
+/// nullif().
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Function {
+    /// date_trunc(field, value): truncates a date or timestamp to the given
+    /// precision, e.g. 'day' or 'month'.
+    DateTrunc,
+    /// extract(field FROM value): extracts a field, e.g. year or hour, from a
+    /// temporal value.
+    Extract,
+    /// length(value): the number of characters in a string, or bytes in a
+    /// byte string.
+    Length,
+    /// substring(value, start[, count]): the characters or bytes of a string or
+    /// byte string from the 1-based start position, up to count of them.
+    Substring,
+    /// json_extract(json, path): the value at the given JSON path, as an SQL
+    /// value for JSON scalars and as JSON for arrays and objects.
+    JsonExtract,
+    /// json_array_length(json): the number of elements in a JSON array.
+    JsonArrayLength,
+    /// lower(string): the string in lowercase.
+    Lower,
+    /// upper(string): the string in uppercase.
+    Upper,
+    /// trim(string): the string without leading and trailing whitespace.
+    Trim,
+    /// replace(string, from, to): the string with all occurrences of from
+    /// replaced by to.
+    Replace,
+    /// concat(value, ...): the values concatenated as a string, skipping NULLs.
+    Concat,
+    /// abs(number): the absolute value of a number.
+    Abs,
+    /// round(number[, digits]): the number rounded half away from zero to the
+    /// given number of fractional digits, or to an integer if omitted.
+    Round,
+    /// floor(number): the largest integer not greater than the number.
+    Floor,
+    /// ceil(number): the smallest integer not less than the number.
+    Ceil,
+    /// sqrt(number): the square root of a number, as a float.
+    Sqrt,
+    /// power(base, exponent): the base raised to the exponent, like ^.
+    Power,
+    /// coalesce(value, ...): the first non-NULL value, or NULL if all are NULL.
+    /// The values must have a common type, and numbers are widened to it.
+    Coalesce,
+    /// nullif(value, other): NULL if the values are equal, otherwise value.
+    NullIf,
+}
+
+impl Function {
+    /// Looks up a function by name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "date_trunc" => Some(Self::DateTrunc),
+            "extract" => Some(Self::Extract),
+            "length" => Some(Self::Length),
+            "substring" => Some(Self::Substring),
+            "json_extract" => Some(Self::JsonExtract),
+            "json_array_length" => Some(Self::JsonArrayLength),
+            "lower" => Some(Self::Lower),
+            "upper" => Some(Self::Upper),
+            "trim" => Some(Self::Trim),
+            "replace" => Some(Self::Replace),
+            "concat" => Some(Self::Concat),
+            "abs" => Some(Self::Abs),
+            "round" => Some(Self::Round),
+            "floor" => Some(Self::Floor),
+            "ceil" | "ceiling" => Some(Self::Ceil),
+            "sqrt" => Some(Self::Sqrt),
+            "power" | "pow" => Some(Self::Power),
+            "coalesce" => Some(Self::Coalesce),
+            "nullif" => Some(Self::NullIf),
+            "substr" => Some(Self::Substring),
+            _ => None,
+        }
+    }
+
+    /// Returns the function name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::DateTrunc => "date_trunc",
+            Self::Extract => "extract",
+            Self::Length => "length",
+            Self::Substring => "substring",
+            Self::JsonExtract => "json_extract",
+            Self::JsonArrayLength => "json_array_length",
+            Self::Lower => "lower",
+            Self::Upper => "upper",
+            Self::Trim => "trim",
+            Self::Replace => "replace",
+            Self::Concat => "concat",
+            Self::Abs => "abs",
+            Self::Round => "round",
+            Self::Floor => "floor",
+            Self::Ceil => "ceil",
+            Self::Sqrt => "sqrt",
+            Self::Power => "power",
+            Self::Coalesce => "coalesce",
+            Self::NullIf => "nullif",
+        }
+    }
+
+    /// Returns the function signature.
+    pub fn signature(&self) -> Signature {
+        use ParamType::*;
+        match self {
+            Self::DateTrunc | Self::Extract => Signature::new(&[String, Temporal]),
+            Self::Length => Signature::new(&[Text]),
+            Self::Substring => Signature::new(&[Text, Integer, Integer]).optional(1),
+            Self::JsonExtract => Signature::new(&[Json, String]),
+            Self::JsonArrayLength => Signature::new(&[Json]),
+            Self::Lower | Self::Upper | Self::Trim => Signature::new(&[String]),
+            Self::Replace => Signature::new(&[String, String, String]),
+            Self::Concat => Signature::new(&[Any]).variadic(),
+            Self::Coalesce => Signature::new(&[Common]).variadic(),
+            Self::Abs | Self::Floor | Self::Ceil | Self::Sqrt => Signature::new(&[Number]),
+            Self::Round => Signature::new(&[Number, Integer]).optional(1),
+            Self::Power => Signature::new(&[Number, Number]),
+            Self::NullIf => Signature::new(&[Any, Any]),
+        }
+    }
+
+    /// Returns whether the function returns NULL if any argument is NULL.
+    pub fn propagates_null(&self) -> bool {
+        !matches!(self, Self::Coalesce | Self::Concat | Self::NullIf)
+    }
+
+    /// Checks that the function can be called with the given number of
+    /// arguments.
+    pub fn check_arity(&self, count: usize) -> Result<()> {
+        let signature = self.signature();
+        let (min, max) = (signature.required, signature.params.len());
+        if count >= min && (count <= max || signature.variadic) {
+            return Ok(());
+        }
+        let expected = match (min, max) {
+            (min, _) if signature.variadic => format!("at least {}", min),
+            (min, max) if min == max => min.to_string(),
+            (min, max) => format!("{} to {}", min, max),
+        };
+        Err(Error::Value(format!("Function {} takes {} arguments, got {}", self, expected, count)))
+    }
+
+    /// Checks that the given value can be used as the argument at the given
+    /// 0-based position, given the known previous arguments (e.g. only the
+    /// constant ones when planning). Assumes the arity has been checked.
+    pub fn check_argument(&self, index: usize, value: &Value, previous: &[Value]) -> Result<()> {
+        let params = self.signature().params;
+        let param = params[index.min(params.len() - 1)];
+        let datatype = |value: &Value| value.datatype().map(|d| d.to_string()).unwrap_or_default();
+        if !param.accepts(value) {
+            return Err(Error::Value(format!(
+                "Function {} expects {} for argument {}, got {}",
+                self,
+                param,
+                index + 1,
+                datatype(value),
+            )));
+        }
+        if param == ParamType::Common {
+            if let Some(other) = previous.iter().find(|other| !have_common_type(value, other)) {
+                return Err(Error::Value(format!(
+                    "Function {} expects arguments of a common type, got {} and {}",
+                    self,
+                    datatype(other),
+                    datatype(value),
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluates the function with the given arguments.
+    pub fn evaluate(&self, mut args: Vec<Value>) -> Result<Value> {
+        self.check_arity(args.len())?;
+        for (index, arg) in args.iter().enumerate() {
+            self.check_argument(index, arg, &args[..index])?;
+        }
+        if self.propagates_null() && args.iter().any(|arg| matches!(arg, Value::Null)) {
+            return Ok(Value::Null);
+        }
+        match self {
+            Self::DateTrunc => {
+                let value = args.remove(1);
+                temporal::date_trunc(&args.remove(0).string()?, value)
+            }
+            Self::Extract => {
+                let value = args.remove(1);
+                temporal::extract(&args.remove(0).string()?, value)
+            }
+            Self::Length => match args.remove(0) {
+                Value::String(s) => Ok(Value::Integer(s.chars().count() as i64)),
+                Value::Bytes(b) => Ok(Value::Integer(b.len() as i64)),
+                value => Err(Error::Value(format!("Can't take length of {}", value))),
+            },
+            Self::Substring => {
+                let count = args.get(2).cloned().map(|count| count.integer()).transpose()?;
+                let start = args.remove(1).integer()?;
+                match args.remove(0) {
+                    Value::String(s) => Ok(Value::String(
+                        substring(&s.chars().collect::<Vec<_>>(), start, count)?.iter().collect(),
+                    )),
+                    Value::Bytes(b) => Ok(Value::Bytes(substring(&b, start, count)?.to_vec())),
+                    value => Err(Error::Value(format!("Can't take substring of {}", value))),
+                }
+            }
+            Self::JsonExtract => match (args.remove(0), args.remove(0)) {
+                (Value::Json(j), Value::String(path)) => json::extract(&j, &path),
+                (j, path) => Err(Error::Value(format!("Can't extract path {} from {}", path, j))),
+            },
+            Self::JsonArrayLength => match args.remove(0) {
+                Value::Json(j) => json::array_length(&j),
+                value => Err(Error::Value(format!("Can't take array length of {}", value))),
+            },
+            Self::Lower => Ok(Value::String(args.remove(0).string()?.to_lowercase())),
+            Self::Upper => Ok(Value::String(args.remove(0).string()?.to_uppercase())),
+            Self::Trim => Ok(Value::String(args.remove(0).string()?.trim().to_string())),
+            Self::Replace => {
+                let (to, from) = (args.remove(2).string()?, args.remove(1).string()?);
+                let string = args.remove(0).string()?;
+                match from.is_empty() {
+                    true => Ok(Value::String(string)),
+                    false => Ok(Value::String(string.replace(&from, &to))),
+                }
+            }
+            Self::Concat => Ok(Value::String(
+                args.iter()
+                    .filter(|arg| !matches!(arg, Value::Null))
+                    .map(|arg| arg.to_string())
+                    .collect(),
+            )),
+            Self::Abs => match args.remove(0) {
+                Value::Integer(i) => Ok(Value::Integer(
+                    i.checked_abs().ok_or_else(|| Error::Value("Integer overflow".into()))?,
+                )),
+                Value::Float(f) => Ok(Value::Float(f.abs())),
+                Value::Decimal(d) => Ok(Value::Decimal(d.abs())),
+                value => Err(Error::Value(format!("Can't take absolute value of {}", value))),
+            },
+            Self::Round => {
+                let digits = args.get(1).cloned().map(|digits| digits.integer()).transpose()?;
+                round(args.remove(0), digits.unwrap_or(0))
+            }
+            Self::Floor => match args.remove(0) {
+                Value::Integer(i) => Ok(Value::Integer(i)),
+                Value::Float(f) => Ok(Value::Float(f.floor())),
+                Value::Decimal(d) => Ok(Value::Decimal(d.floor())),
+                value => Err(Error::Value(format!("Can't take floor of {}", value))),
+            },
+            Self::Ceil => match args.remove(0) {
+                Value::Integer(i) => Ok(Value::Integer(i)),
+                Value::Float(f) => Ok(Value::Float(f.ceil())),
+                Value::Decimal(d) => Ok(Value::Decimal(d.ceil())),
+                value => Err(Error::Value(format!("Can't take ceiling of {}", value))),
+            },
+            Self::Sqrt => {
+                let f = match args.remove(0) {
+                    Value::Integer(i) => i as f64,
+                    Value::Float(f) => f,
+                    Value::Decimal(d) => super::decimal::to_f64(&d),
+                    value => {
+                        return Err(Error::Value(format!("Can't take square root of {}", value)))
+                    }
+                };
+                if f < 0.0 {
+                    return Err(Error::Value(format!(
+                        "Can't take square root of negative number {}",
+                        f
+                    )));
+                }
+                Ok(Value::Float(f.sqrt()))
+            }
+            Self::Power => {
+                let exponent = Expression::Constant(args.remove(1));
+                Expression::Exponentiate(
+                    Expression::Constant(args.remove(0)).into(),
+                    exponent.into(),
+                )
+                .evaluate(None)
+            }
+            Self::Coalesce => {
+                let mut args = args.into_iter().filter(|arg| !matches!(arg, Value::Null));
+                let value = args.next().unwrap_or(Value::Null);
+                Ok(args.fold(value, |value, arg| widen(value, &arg)))
+            }
+            Self::NullIf => {
+                let (other, value) = (args.remove(1), args.remove(0));
+                let equal = Expression::Equal(
+                    Expression::Constant(value.clone()).into(),
+                    Expression::Constant(other).into(),
+                );
+                match equal.evaluate(None)? {
+                    Value::Boolean(true) => Ok(Value::Null),
+                    _ => Ok(value),
+                }
+            }
+        }
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// A function signature, with the parameter types. Trailing parameters may be
+/// optional, and the last parameter may be repeated for variadic functions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Signature {
+    /// The parameter types.
+    pub params: &'static [ParamType],
+    /// The number of required parameters.
+    pub required: usize,
+    /// Whether the last parameter can be given any number of times.
+    pub variadic: bool,
+}
+
+impl Signature {
+    /// Creates a signature with the given required parameters.
+    const fn new(params: &'static [ParamType]) -> Self {
+        Self { params, required: params.len(), variadic: false }
+    }
+
+    /// Makes the given number of trailing parameters optional.
+    const fn optional(mut self, count: usize) -> Self {
+        self.required -= count;
+        self
+    }
+
+    /// Allows the last parameter to be repeated.
+    const fn variadic(mut self) -> Self {
+        self.variadic = true;
+        self
+    }
+}
+
+/// A function parameter type. NULL is accepted for any parameter.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParamType {
+    /// Any value.
+    Any,
+    /// Any value, but all arguments for the parameter must have a common type,
+    /// where numbers are widened like in arithmetic.
+    Common,
+    /// An INTEGER.
+    Integer,
+    /// A JSON value.
+    Json,
+    /// An INTEGER, FLOAT or DECIMAL.
+    Number,
+    /// A STRING.
+    String,
+    /// A DATE, TIME, TIMESTAMP, TIMESTAMP WITH TIME ZONE or INTERVAL.
+    Temporal,
+    /// A STRING or BYTEA.
+    Text,
+}
+
+impl ParamType {
+    /// Returns whether the parameter accepts the given value.
+    pub fn accepts(&self, value: &Value) -> bool {
+        match (self, value) {
+            (_, Value::Null) | (Self::Any | Self::Common, _) => true,
+            (Self::Integer, Value::Integer(_)) => true,
+            (Self::Json, Value::Json(_)) => true,
+            (Self::Number, Value::Integer(_) | Value::Float(_) | Value::Decimal(_)) => true,
+            (Self::String | Self::Text, Value::String(_)) => true,
+            (Self::Text, Value::Bytes(_)) => true,
+            (
+                Self::Temporal,
+                Value::Date(_)
+                | Value::Time(_)
+                | Value::Timestamp(_)
+                | Value::TimestampTz(_)
+                | Value::Interval(_),
+            ) => true,
+            (_, _) => false,
+        }
+    }
+}
+
+impl Display for ParamType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Any | Self::Common => "any value",
+            Self::Integer => "INTEGER",
+            Self::Json => "JSON",
+            Self::Number => "a number",
+            Self::String => "STRING",
+            Self::Temporal => "a temporal value",
+            Self::Text => "STRING or BYTEA",
+        })
+    }
+}
+
+/// Checks whether two values have a common type, i.e. they have the same type,
+/// either is NULL, or both are numbers.
+fn have_common_type(lhs: &Value, rhs: &Value) -> bool {
+    use Value::*;
+    match (lhs, rhs) {
+        (Null, _) | (_, Null) => true,
+        (Integer(_) | Float(_) | Decimal(_), Integer(_) | Float(_) | Decimal(_)) => true,
+        (lhs, rhs) => std::mem::discriminant(lhs) == std::mem::discriminant(rhs),
+    }
+}
+
+/// Widens a number to the type of another number if that's wider, like in
+/// arithmetic: integers to decimals and floats, and decimals to floats. Other
+/// values are returned as is.
+fn widen(value: Value, to: &Value) -> Value {
+    match (value, to) {
+        (Value::Integer(i), Value::Float(_)) => Value::Float(i as f64),
+        (Value::Integer(i), Value::Decimal(_)) => Value::Decimal(i.into()),
+        (Value::Decimal(d), Value::Float(_)) => Value::Float(super::decimal::to_f64(&d)),
+        (value, _) => value,
+    }
+}
+
+/// Rounds a number half away from zero to the given number of fractional
+/// digits, like SQL round(). Negative digits round to tens, hundreds, etc.
+fn round(value: Value, digits: i64) -> Result<Value> {
+    if let Value::Float(f) = value {
+        return Ok(Value::Float(round_float(f, digits)));
+    }
+    let digits = digits.clamp(-30, 30) as i32;
+    match value {
+        Value::Integer(i) if digits >= 0 => Ok(Value::Integer(i)),
+        Value::Integer(i) => {
+            let factor = 10i128.pow(digits.unsigned_abs());
+            let (quotient, remainder) = (i as i128 / factor, i as i128 % factor);
+            let quotient = match remainder.abs() * 2 >= factor {
+                true => quotient + i.signum() as i128,
+                false => quotient,
+            };
+            Ok(Value::Integer(
+                i64::try_from(quotient * factor)
+                    .map_err(|_| Error::Value("Integer overflow".into()))?,
+            ))
+        }
+        Value::Decimal(d) if digits >= 0 => Ok(Value::Decimal(
+            d.round_dp_with_strategy(digits as u32, RoundingStrategy::MidpointAwayFromZero),
+        )),
+        Value::Decimal(d) => {
+            let overflow = || Error::Value("Decimal overflow".into());
+            let factor = Decimal::TEN.checked_powi(-digits as i64).ok_or_else(overflow)?;
+            let rounded = d
+                .checked_div(factor)
+                .ok_or_else(overflow)?
+                .round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero);
+            Ok(Value::Decimal(rounded.checked_mul(factor).ok_or_else(overflow)?))
+        }
+        value => Err(Error::Value(format!("Can't round {}", value))),
+    }
+}
+
+/// Rounds a float like round(). The value is scaled by a power of ten, which
+/// is only exact enough while the scaled value has fractional digits within
+/// f64 precision. Beyond that there's nothing to round, so the value is
+/// returned unchanged.
+fn round_float(f: f64, digits: i64) -> f64 {
+    let max_digits = f64::MAX_10_EXP as i64;
+    if digits > max_digits {
+        return f;
+    }
+    let factor = 10f64.powi(digits.max(-max_digits) as i32);
+    let scaled = f * factor;
+    if !scaled.is_finite() || scaled.abs() >= (1u64 << (f64::MANTISSA_DIGITS - 1)) as f64 {
+        return f;
+    }
+    match scaled.round() / factor {
+        rounded if rounded.is_finite() => rounded,
+        _ => f,
+    }
+}
+
+/// Returns the items from the 1-based start position, up to count of them,
+/// like SQL substring(). The start position may be before the first item, in
+/// which case fewer items are returned.
+fn substring<T>(items: &[T], start: i64, count: Option<i64>) -> Result<&[T]> {
+    let end = match count {
+        Some(count) if count < 0 => {
+            return Err(Error::Value("Substring length can't be negative".into()))
+        }
+        Some(count) => start.saturating_add(count),
+        None => i64::MAX,
+    };
+    let offset = |position: i64| (position.max(1) - 1).min(items.len() as i64) as usize;
+    Ok(&items[offset(start)..offset(end)])
+}
@@ -0,0 +1,230 @@
+//! The JSON data type, for semi-structured documents.
+//!
+//! JSON values are stored as compact JSON text with object keys in sorted
+//! order, such that equal documents have equal values, and can be used in
+//! indexes. They're parsed on demand when evaluating JSON operators and
+//! functions.
+//!
+//! JSON paths, as used by json_extract() and path indexes, are given as '$'
+//! followed by any number of .key and [index] steps, e.g. '$.items[0].name'.
+
+use super::Value;
+use crate::error::{Error, Result};
+
+use serde_json::Value as Json;
+
+/// Parses and validates a JSON document, returning its canonical text.
+pub fn parse(s: &str) -> Result<String> {
+    Ok(decode(s).map_err(|err| Error::Value(format!("Invalid JSON: {}", err)))?.to_string())
+}
+
+/// Decodes JSON text.
+fn decode(s: &str) -> std::result::Result<Json, serde_json::Error> {
+    serde_json::from_str(s)
+}
+
+/// Decodes a stored JSON value.
+fn decode_value(s: &str) -> Result<Json> {
+    decode(s).map_err(|err| Error::Value(format!("Invalid JSON value {}: {}", s, err)))
+}
+
+/// Converts a JSON value to an SQL value. Strings, numbers, booleans and null
+/// are converted to the corresponding SQL values, while arrays and objects are
+/// kept as JSON.
+fn to_value(json: Json) -> Value {
+    match json {
+        Json::Null => Value::Null,
+        Json::Bool(b) => Value::Boolean(b),
+        Json::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => Value::Float(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Json::String(s) => Value::String(s),
+        json => Value::Json(json.to_string()),
+    }
+}
+
+/// Returns an object member or array element of a JSON value, for the ->
+/// operator. Negative array indexes count from the end. Returns NULL if the
+/// member or element doesn't exist.
+pub fn get(json: &str, key: Value) -> Result<Value> {
+    Ok(lookup(json, key)?.map(|json| Value::Json(json.to_string())).unwrap_or(Value::Null))
+}
+
+/// Like get(), but returns the member or element as a string, for the ->>
+/// operator. JSON strings are returned without quotes, and JSON null as NULL.
+pub fn get_text(json: &str, key: Value) -> Result<Value> {
+    Ok(match lookup(json, key)? {
+        None | Some(Json::Null) => Value::Null,
+        Some(Json::String(s)) => Value::String(s),
+        Some(json) => Value::String(json.to_string()),
+    })
+}
+
+/// Looks up an object member or array element of a JSON value.
+fn lookup(json: &str, key: Value) -> Result<Option<Json>> {
+    Ok(match (decode_value(json)?, key) {
+        (Json::Object(mut object), Value::String(key)) => object.remove(&key),
+        (Json::Array(mut array), Value::Integer(index)) => {
+            let index = if index < 0 { index + array.len() as i64 } else { index };
+            match usize::try_from(index) {
+                Ok(index) if index < array.len() => Some(array.swap_remove(index)),
+                _ => None,
+            }
+        }
+        (_, Value::String(_) | Value::Integer(_)) => None,
+        (_, key) => return Err(Error::Value(format!("Can't use {} as a JSON key", key))),
+    })
+}
+
+/// Returns whether a JSON value contains another, for the @> operator. Objects
+/// contain objects whose members are all contained in their members, arrays
+/// contain arrays whose elements are all contained in one of their elements,
+/// as well as scalars equal to one of their elements, and scalars only contain
+/// equal scalars.
+pub fn contains(json: &str, other: &str) -> Result<bool> {
+    Ok(contains_json(&decode_value(json)?, &decode_value(other)?))
+}
+
+fn contains_json(json: &Json, other: &Json) -> bool {
+    match (json, other) {
+        (Json::Object(object), Json::Object(other)) => other
+            .iter()
+            .all(|(key, value)| object.get(key).is_some_and(|v| contains_json(v, value))),
+        (Json::Array(array), Json::Array(other)) => {
+            other.iter().all(|value| array.iter().any(|v| contains_json(v, value)))
+        }
+        (Json::Array(array), other) if !other.is_object() => array.iter().any(|v| v == other),
+        (json, other) => json == other,
+    }
+}
+
+/// Extracts the value at a JSON path, for json_extract() and path indexes.
+/// Returns NULL if the path doesn't exist.
+pub fn extract(json: &str, path: &str) -> Result<Value> {
+    let mut json = decode_value(json)?;
+    for step in parse_path(path)? {
+        json = match (json, step) {
+            (Json::Object(mut object), Step::Key(key)) => object.remove(&key),
+            (Json::Array(mut array), Step::Index(index)) if index < array.len() => {
+                Some(array.swap_remove(index))
+            }
+            _ => None,
+        }
+        .unwrap_or(Json::Null);
+    }
+    Ok(to_value(json))
+}
+
+/// Returns the length of a JSON array.
+pub fn array_length(json: &str) -> Result<Value> {
+    match decode_value(json)? {
+        Json::Array(array) => Ok(Value::Integer(array.len() as i64)),
+        _ => Err(Error::Value(format!("Can't take array length of {}", json))),
+    }
+}
+
+/// A JSON path step.
+enum Step {
+    /// An object member.
+    Key(String),
+    /// An array element.
+    Index(usize),
+}
+
+/// Parses a JSON path.
+fn parse_path(path: &str) -> Result<Vec<Step>> {
+    let invalid = || Error::Value(format!("Invalid JSON path {}", path));
+    let mut chars = path.strip_prefix('$').ok_or_else(invalid)?.chars().peekable();
+    let mut steps = Vec::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '.' => {
+                let mut key = String::new();
+                while let Some(c) = chars.next_if(|c| *c != '.' && *c != '[') {
+                    key.push(c)
+                }
+                if key.is_empty() {
+                    return Err(invalid());
+                }
+                steps.push(Step::Key(key))
+            }
+            '[' => {
+                let mut index = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+                    index.push(c)
+                }
+                if chars.next() != Some(']') {
+                    return Err(invalid());
+                }
+                steps.push(Step::Index(index.parse().map_err(|_| invalid())?))
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(steps)
+}
+
+/// Validates a JSON path and returns it in normalized form.
+pub fn normalize_path(path: &str) -> Result<String> {
+    let mut normalized = String::from("$");
+    for step in parse_path(path)? {
+        normalized += &match step {
+            Step::Key(key) => format!(".{}", key),
+            Step::Index(index) => format!("[{}]", index),
+        };
+    }
+    Ok(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Tests that JSON documents are canonicalized.
+    fn canonical() -> Result<()> {
+        assert_eq!(parse(r#" { "b": [1, 2.5, null], "a": {"y": true, "x": "s"} } "#)?, {
+            r#"{"a":{"x":"s","y":true},"b":[1,2.5,null]}"#
+        });
+        assert_eq!(parse(r#""text""#)?, r#""text""#);
+        assert!(parse("{'a': 1}").is_err());
+        assert!(parse("[1,]").is_err());
+        Ok(())
+    }
+
+    #[test]
+    /// Tests JSON path parsing.
+    fn path() -> Result<()> {
+        assert_eq!(normalize_path("$")?, "$");
+        assert_eq!(normalize_path("$.items[10].name")?, "$.items[10].name");
+        assert_eq!(normalize_path("$[0][1]")?, "$[0][1]");
+        for invalid in ["", "items", "$.", "$..a", "$[a]", "$[-1]", "$[1", "$a"] {
+            assert!(normalize_path(invalid).is_err(), "{} should be invalid", invalid);
+        }
+        Ok(())
+    }
+
+    #[test]
+    /// Tests JSON containment.
+    fn contains() -> Result<()> {
+        let doc = r#"{"a":1,"tags":["x","y"],"nested":{"b":[{"c":2}]}}"#;
+        for (other, expect) in [
+            ("{}", true),
+            (r#"{"a":1}"#, true),
+            (r#"{"a":2}"#, false),
+            (r#"{"tags":["y"]}"#, true),
+            (r#"{"tags":["y","z"]}"#, false),
+            (r#"{"nested":{"b":[{}]}}"#, true),
+            (r#"{"nested":{"b":[{"c":2}]}}"#, true),
+            (r#"{"missing":null}"#, false),
+            (r#"[1]"#, false),
+        ] {
+            assert_eq!(super::contains(doc, other)?, expect, "{} @> {}", doc, other);
+        }
+        assert!(super::contains(r#"["x","y"]"#, r#""x""#)?);
+        assert!(!super::contains(r#""x""#, r#"["x"]"#)?);
+        assert!(!super::contains(r#"[{"a":1}]"#, r#"{"a":1}"#)?);
+        Ok(())
+    }
+}
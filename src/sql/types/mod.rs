@@ -1,8 +1,16 @@
+pub mod decimal;
 mod expression;
+mod function;
+pub mod json;
+pub mod temporal;
 pub use expression::Expression;
+pub use function::Function;
+pub use temporal::Interval;
 
 use crate::error::{Error, Result};
 
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use rust_decimal::Decimal;
 use serde_derive::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::cmp::Ordering;
@@ -15,6 +23,28 @@ pub enum DataType {
     Integer,
     Float,
     String,
+    Date,
+    Time,
+    Timestamp,
+    TimestampTz,
+    Interval,
+    /// A decimal with the given precision and scale.
+    Decimal(u32, u32),
+    Bytes,
+    Json,
+}
+
+impl DataType {
+    /// Returns whether values of the given datatype can be stored in a column
+    /// of this datatype. Decimals must fit the column's precision and scale.
+    pub fn accepts(&self, datatype: &DataType) -> bool {
+        match (self, datatype) {
+            (Self::Decimal(precision, scale), Self::Decimal(p, s)) => {
+                s <= scale && p - s <= precision - scale
+            }
+            (a, b) => a == b,
+        }
+    }
 }
 
 impl std::fmt::Display for DataType {
@@ -24,6 +54,16 @@ impl std::fmt::Display for DataType {
             Self::Integer => "INTEGER",
             Self::Float => "FLOAT",
             Self::String => "STRING",
+            Self::Date => "DATE",
+            Self::Time => "TIME",
+            Self::Timestamp => "TIMESTAMP",
+            Self::TimestampTz => "TIMESTAMP WITH TIME ZONE",
+            Self::Interval => "INTERVAL",
+            Self::Decimal(precision, scale) => {
+                return write!(f, "DECIMAL({},{})", precision, scale)
+            }
+            Self::Bytes => "BYTEA",
+            Self::Json => "JSON",
         })
     }
 }
@@ -36,6 +76,15 @@ pub enum Value {
     Integer(i64),
     Float(f64),
     String(String),
+    Date(#[serde(with = "temporal::serde_date")] NaiveDate),
+    Time(#[serde(with = "temporal::serde_time")] NaiveTime),
+    Timestamp(#[serde(with = "temporal::serde_timestamp")] NaiveDateTime),
+    TimestampTz(#[serde(with = "temporal::serde_timestamptz")] DateTime<Utc>),
+    Interval(Interval),
+    Decimal(#[serde(with = "decimal::serde_decimal")] Decimal),
+    Bytes(#[serde(with = "serde_bytes")] Vec<u8>),
+    /// A JSON document, as canonical JSON text. See the json module.
+    Json(String),
 }
 
 impl std::cmp::Eq for Value {}
@@ -45,11 +94,19 @@ impl Hash for Value {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.datatype().hash(state);
         match self {
-            Value::Null => self.hash(state),
+            Value::Null => {}
             Value::Boolean(v) => v.hash(state),
             Value::Integer(v) => v.hash(state),
             Value::Float(v) => v.to_be_bytes().hash(state),
             Value::String(v) => v.hash(state),
+            Value::Date(v) => v.hash(state),
+            Value::Time(v) => v.hash(state),
+            Value::Timestamp(v) => v.hash(state),
+            Value::TimestampTz(v) => v.hash(state),
+            Value::Interval(v) => v.hash(state),
+            Value::Decimal(v) => v.hash(state),
+            Value::Bytes(v) => v.hash(state),
+            Value::Json(v) => v.hash(state),
         }
     }
 }
@@ -75,6 +132,17 @@ impl Value {
             Self::Integer(_) => Some(DataType::Integer),
             Self::Float(_) => Some(DataType::Float),
             Self::String(_) => Some(DataType::String),
+            Self::Date(_) => Some(DataType::Date),
+            Self::Time(_) => Some(DataType::Time),
+            Self::Timestamp(_) => Some(DataType::Timestamp),
+            Self::TimestampTz(_) => Some(DataType::TimestampTz),
+            Self::Interval(_) => Some(DataType::Interval),
+            Self::Decimal(d) => {
+                let (precision, scale) = decimal::precision_scale(d);
+                Some(DataType::Decimal(precision, scale))
+            }
+            Self::Bytes(_) => Some(DataType::Bytes),
+            Self::Json(_) => Some(DataType::Json),
         }
     }
 
@@ -121,6 +189,14 @@ impl std::fmt::Display for Value {
                 Self::Integer(i) => i.to_string(),
                 Self::Float(f) => f.to_string(),
                 Self::String(s) => s.clone(),
+                Self::Date(d) => d.to_string(),
+                Self::Time(t) => t.to_string(),
+                Self::Timestamp(ts) => ts.to_string(),
+                Self::TimestampTz(ts) => temporal::format_timestamptz(ts),
+                Self::Interval(i) => i.to_string(),
+                Self::Decimal(d) => d.normalize().to_string(),
+                Self::Bytes(b) => format!("\\x{}", hex::encode(b)),
+                Self::Json(j) => j.clone(),
             }
             .as_ref(),
         )
@@ -139,7 +215,16 @@ impl PartialOrd for Value {
             (Self::Integer(a), Self::Float(b)) => (*a as f64).partial_cmp(b),
             (Self::Integer(a), Self::Integer(b)) => a.partial_cmp(b),
             (Self::String(a), Self::String(b)) => a.partial_cmp(b),
-            (_, _) => None,
+            (Self::Time(a), Self::Time(b)) => a.partial_cmp(b),
+            (Self::Interval(a), Self::Interval(b)) => a.partial_cmp(b),
+            (Self::Decimal(a), Self::Decimal(b)) => a.partial_cmp(b),
+            (Self::Bytes(a), Self::Bytes(b)) => a.partial_cmp(b),
+            (Self::Json(a), Self::Json(b)) => a.partial_cmp(b),
+            (Self::Decimal(a), Self::Integer(b)) => a.partial_cmp(&Decimal::from(*b)),
+            (Self::Integer(a), Self::Decimal(b)) => Decimal::from(*a).partial_cmp(b),
+            (Self::Decimal(a), Self::Float(b)) => decimal::to_f64(a).partial_cmp(b),
+            (Self::Float(a), Self::Decimal(b)) => a.partial_cmp(&decimal::to_f64(b)),
+            (a, b) => temporal::compare(a, b),
         }
     }
 }
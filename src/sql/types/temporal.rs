@@ -0,0 +1,657 @@
+//! Temporal data types: DATE, TIME, TIMESTAMP, TIMESTAMP WITH TIME ZONE and
+//! INTERVAL, along with literal parsing and the temporal functions.
+//!
+//! Dates and times use chrono types, with microsecond precision. Time zones
+//! are only used when parsing literals: TIMESTAMP WITH TIME ZONE values are
+//! stored and displayed in UTC.
+//!
+//! For storage, temporal values (except intervals) are serialized as a single
+//! i64 counting days or microseconds from the Unix epoch or midnight. This
+//! makes their KeyCode encoding order-preserving, so they can be used in
+//! primary keys and indexes.
+
+use super::Value;
+use crate::error::{Error, Result};
+
+use chrono::{
+    DateTime, Datelike as _, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Timelike as _,
+    Utc,
+};
+use serde_derive::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+
+/// The number of microseconds in a day.
+const MICROS_PER_DAY: i64 = 86_400_000_000;
+
+/// The number of microseconds in a second.
+const MICROS_PER_SECOND: i64 = 1_000_000;
+
+/// Returns the Unix epoch date.
+fn epoch() -> NaiveDate {
+    DateTime::UNIX_EPOCH.date_naive()
+}
+
+/// Returns the current time, truncated to microseconds.
+pub fn now() -> DateTime<Utc> {
+    let now = Utc::now();
+    now.with_nanosecond(now.nanosecond() / 1000 * 1000).unwrap_or(now)
+}
+
+/// Truncates a time to microsecond precision.
+fn truncate_nanos(time: NaiveTime) -> NaiveTime {
+    time.with_nanosecond(time.nanosecond() / 1000 * 1000).unwrap_or(time)
+}
+
+/// Parses a DATE literal, e.g. 2024-01-31.
+pub fn parse_date(s: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d")
+        .map_err(|err| Error::Parse(format!("Invalid date {}: {}", s, err)))
+}
+
+/// Parses a TIME literal, e.g. 13:45:00 or 13:45:00.123456.
+pub fn parse_time(s: &str) -> Result<NaiveTime> {
+    ["%H:%M:%S%.f", "%H:%M"]
+        .iter()
+        .find_map(|format| NaiveTime::parse_from_str(s.trim(), format).ok())
+        .map(truncate_nanos)
+        .ok_or_else(|| Error::Parse(format!("Invalid time {}", s)))
+}
+
+/// Parses a TIMESTAMP literal, e.g. 2024-01-31 13:45:00. The time is optional,
+/// and the date and time may be separated by T.
+pub fn parse_timestamp(s: &str) -> Result<NaiveDateTime> {
+    let s = s.trim();
+    ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d").ok().map(|d| d.and_time(NaiveTime::MIN))
+        })
+        .map(|dt| dt.date().and_time(truncate_nanos(dt.time())))
+        .ok_or_else(|| Error::Parse(format!("Invalid timestamp {}", s)))
+}
+
+/// Parses a TIMESTAMP WITH TIME ZONE literal, e.g. 2024-01-31 13:45:00+01:00.
+/// Timestamps without a UTC offset are taken to be in UTC.
+pub fn parse_timestamptz(s: &str) -> Result<DateTime<Utc>> {
+    let s = s.trim();
+    ["%Y-%m-%d %H:%M:%S%.f%#z", "%Y-%m-%dT%H:%M:%S%.f%#z", "%Y-%m-%d %H:%M%#z"]
+        .iter()
+        .find_map(|format| DateTime::parse_from_str(s, format).ok())
+        .or_else(|| DateTime::parse_from_rfc3339(s).ok())
+        .map(|dt| dt.with_timezone(&Utc))
+        .or_else(|| parse_timestamp(s).ok().map(|dt| dt.and_utc()))
+        .map(|dt| dt.with_nanosecond(dt.nanosecond() / 1000 * 1000).unwrap_or(dt))
+        .ok_or_else(|| Error::Parse(format!("Invalid timestamp {}", s)))
+}
+
+/// Formats a TIMESTAMP WITH TIME ZONE value.
+pub fn format_timestamptz(datetime: &DateTime<Utc>) -> String {
+    format!("{}+00:00", datetime.naive_utc())
+}
+
+/// Adds a number of days to a date.
+pub fn add_days(date: NaiveDate, days: i64) -> Result<NaiveDate> {
+    TimeDelta::try_days(days)
+        .and_then(|delta| date.checked_add_signed(delta))
+        .ok_or_else(|| Error::Value("Date out of range".into()))
+}
+
+/// Compares two dates or timestamps, which may be of different types. Dates
+/// are taken to be at midnight, and timestamps without time zone in UTC.
+pub fn compare(lhs: &Value, rhs: &Value) -> Option<Ordering> {
+    let datetime = |v: &Value| match v {
+        Value::Date(date) => Some(date.and_time(NaiveTime::MIN)),
+        Value::Timestamp(datetime) => Some(*datetime),
+        Value::TimestampTz(datetime) => Some(datetime.naive_utc()),
+        _ => None,
+    };
+    datetime(lhs)?.partial_cmp(&datetime(rhs)?)
+}
+
+/// Extracts a field from a temporal value, for the extract() function.
+pub fn extract(field: &str, value: Value) -> Result<Value> {
+    let field = field.to_lowercase();
+    let result = match &value {
+        Value::Date(date) => extract_datetime(&field, date.and_time(NaiveTime::MIN)),
+        Value::Time(time) => extract_time(&field, *time),
+        Value::Timestamp(datetime) => extract_datetime(&field, *datetime),
+        Value::TimestampTz(datetime) => extract_datetime(&field, datetime.naive_utc()),
+        Value::Interval(interval) => interval.extract(&field),
+        value => return Err(Error::Value(format!("Can't extract {} from {}", field, value))),
+    };
+    result.ok_or_else(|| {
+        Error::Value(format!(
+            "Can't extract {} from {}",
+            field,
+            value.datatype().map(|t| t.to_string()).unwrap_or_default()
+        ))
+    })
+}
+
+/// Extracts a field from a timestamp.
+fn extract_datetime(field: &str, datetime: NaiveDateTime) -> Option<Value> {
+    Some(match field {
+        "year" => Value::Integer(datetime.year() as i64),
+        "quarter" => Value::Integer((datetime.month0() / 3 + 1) as i64),
+        "month" => Value::Integer(datetime.month() as i64),
+        "week" => Value::Integer(datetime.iso_week().week() as i64),
+        "day" => Value::Integer(datetime.day() as i64),
+        "dow" => Value::Integer(datetime.weekday().num_days_from_sunday() as i64),
+        "doy" => Value::Integer(datetime.ordinal() as i64),
+        "epoch" => {
+            Value::Float(datetime.and_utc().timestamp_micros() as f64 / MICROS_PER_SECOND as f64)
+        }
+        field => return extract_time(field, datetime.time()),
+    })
+}
+
+/// Extracts a field from a time of day.
+fn extract_time(field: &str, time: NaiveTime) -> Option<Value> {
+    let micros = time_to_micros(time);
+    Some(match field {
+        "hour" => Value::Integer(time.hour() as i64),
+        "minute" => Value::Integer(time.minute() as i64),
+        "second" => Value::Float((micros % 60_000_000) as f64 / MICROS_PER_SECOND as f64),
+        "microseconds" => Value::Integer(micros % 60_000_000),
+        "epoch" => Value::Float(micros as f64 / MICROS_PER_SECOND as f64),
+        _ => return None,
+    })
+}
+
+/// Truncates a date or timestamp to the given precision, for the
+/// date_trunc() function. Dates are truncated to timestamps.
+pub fn date_trunc(field: &str, value: Value) -> Result<Value> {
+    let field = field.to_lowercase();
+    let result = match &value {
+        Value::Date(date) => {
+            truncate_datetime(&field, date.and_time(NaiveTime::MIN)).map(Value::Timestamp)
+        }
+        Value::Timestamp(datetime) => truncate_datetime(&field, *datetime).map(Value::Timestamp),
+        Value::TimestampTz(datetime) => truncate_datetime(&field, datetime.naive_utc())
+            .map(|datetime| Value::TimestampTz(datetime.and_utc())),
+        value => return Err(Error::Value(format!("Can't truncate {}", value))),
+    };
+    result.ok_or_else(|| Error::Value(format!("Can't truncate to {}", field)))
+}
+
+/// Truncates a timestamp to the given precision.
+fn truncate_datetime(field: &str, datetime: NaiveDateTime) -> Option<NaiveDateTime> {
+    let (date, time) = (datetime.date(), datetime.time());
+    let (year, month) = (date.year(), date.month());
+    Some(match field {
+        "second" => date.and_time(time.with_nanosecond(0)?),
+        "minute" => date.and_hms_opt(time.hour(), time.minute(), 0)?,
+        "hour" => date.and_hms_opt(time.hour(), 0, 0)?,
+        "day" => date.and_time(NaiveTime::MIN),
+        "week" => (date - TimeDelta::days(date.weekday().num_days_from_monday() as i64))
+            .and_time(NaiveTime::MIN),
+        "month" => NaiveDate::from_ymd_opt(year, month, 1)?.and_time(NaiveTime::MIN),
+        "quarter" => {
+            NaiveDate::from_ymd_opt(year, month - (month - 1) % 3, 1)?.and_time(NaiveTime::MIN)
+        }
+        "year" => NaiveDate::from_ymd_opt(year, 1, 1)?.and_time(NaiveTime::MIN),
+        _ => return None,
+    })
+}
+
+/// Converts a time of day to microseconds since midnight.
+fn time_to_micros(time: NaiveTime) -> i64 {
+    time.num_seconds_from_midnight() as i64 * MICROS_PER_SECOND + time.nanosecond() as i64 / 1000
+}
+
+/// Converts microseconds since midnight to a time of day.
+fn micros_to_time(micros: i64) -> Option<NaiveTime> {
+    if !(0..MICROS_PER_DAY).contains(&micros) {
+        return None;
+    }
+    NaiveTime::from_num_seconds_from_midnight_opt(
+        (micros / MICROS_PER_SECOND) as u32,
+        (micros % MICROS_PER_SECOND * 1000) as u32,
+    )
+}
+
+/// A time interval, e.g. 1 month 2 days 03:00:00. Like in PostgreSQL, months
+/// and days are kept separate from the time, since their length varies: adding
+/// 1 month to January 31st yields February 29th (or 28th), and adding 1 day
+/// across a daylight saving time change can be 23 or 25 hours.
+///
+/// For comparisons, months are considered 30 days long, so 1 month equals 30
+/// days. The same interval can therefore have several representations, and
+/// their storage encodings differ, so intervals can't be used in primary keys
+/// or indexes.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct Interval {
+    /// The number of months.
+    pub months: i64,
+    /// The number of days.
+    pub days: i64,
+    /// The number of microseconds.
+    pub micros: i64,
+}
+
+impl Interval {
+    /// Creates a new interval.
+    pub fn new(months: i64, days: i64, micros: i64) -> Self {
+        Self { months, days, micros }
+    }
+
+    /// Returns the interval between two timestamps, as days and microseconds.
+    pub fn between(lhs: NaiveDateTime, rhs: NaiveDateTime) -> Result<Self> {
+        let micros = lhs
+            .signed_duration_since(rhs)
+            .num_microseconds()
+            .ok_or_else(|| Error::Value("Interval out of range".into()))?;
+        Ok(Self::new(0, micros / MICROS_PER_DAY, micros % MICROS_PER_DAY))
+    }
+
+    /// Returns the interval as a number of microseconds, with 30-day months.
+    /// Used for comparisons.
+    fn normalized(&self) -> i128 {
+        (self.months as i128 * 30 + self.days as i128) * MICROS_PER_DAY as i128
+            + self.micros as i128
+    }
+
+    /// Adds two intervals.
+    pub fn checked_add(&self, other: &Self) -> Result<Self> {
+        Ok(Self::new(
+            self.months.checked_add(other.months).ok_or_else(Self::out_of_range)?,
+            self.days.checked_add(other.days).ok_or_else(Self::out_of_range)?,
+            self.micros.checked_add(other.micros).ok_or_else(Self::out_of_range)?,
+        ))
+    }
+
+    /// Subtracts two intervals.
+    pub fn checked_sub(&self, other: &Self) -> Result<Self> {
+        self.checked_add(&other.checked_neg()?)
+    }
+
+    /// Negates the interval.
+    pub fn checked_neg(&self) -> Result<Self> {
+        Ok(Self::new(
+            self.months.checked_neg().ok_or_else(Self::out_of_range)?,
+            self.days.checked_neg().ok_or_else(Self::out_of_range)?,
+            self.micros.checked_neg().ok_or_else(Self::out_of_range)?,
+        ))
+    }
+
+    /// Multiplies the interval by a factor. Fractional months are carried
+    /// over to days as 30 days, and fractional days to the time as 24 hours.
+    pub fn checked_mul(&self, factor: f64) -> Result<Self> {
+        let months = self.months as f64 * factor;
+        let days = self.days as f64 * factor + months.fract() * 30.0;
+        let micros = self.micros as f64 * factor + days.fract() * MICROS_PER_DAY as f64;
+        let to_i64 = |f: f64| match f.is_finite() && f.abs() < i64::MAX as f64 {
+            true => Ok(f as i64),
+            false => Err(Self::out_of_range()),
+        };
+        Ok(Self::new(to_i64(months.trunc())?, to_i64(days.trunc())?, to_i64(micros.round())?))
+    }
+
+    /// Divides the interval by a divisor, like checked_mul().
+    pub fn checked_div(&self, divisor: f64) -> Result<Self> {
+        if divisor == 0.0 {
+            return Err(Error::Value("Can't divide by zero".into()));
+        }
+        self.checked_mul(1.0 / divisor)
+    }
+
+    /// Adds the interval to a timestamp. Months are added first, clamping
+    /// the day to the end of the month, then days, then the time.
+    pub fn add_to(&self, datetime: NaiveDateTime) -> Result<NaiveDateTime> {
+        let months = u32::try_from(self.months.unsigned_abs()).ok().map(Months::new);
+        let datetime = match months {
+            Some(months) if self.months >= 0 => datetime.checked_add_months(months),
+            Some(months) => datetime.checked_sub_months(months),
+            None => None,
+        };
+        datetime
+            .and_then(|dt| dt.checked_add_signed(TimeDelta::try_days(self.days)?))
+            .and_then(|dt| dt.checked_add_signed(TimeDelta::microseconds(self.micros)))
+            .ok_or_else(|| Error::Value("Timestamp out of range".into()))
+    }
+
+    /// Adds the interval to a time of day, wrapping around midnight. Months
+    /// and days are ignored.
+    pub fn add_to_time(&self, time: NaiveTime) -> NaiveTime {
+        let micros =
+            (time_to_micros(time) + self.micros % MICROS_PER_DAY).rem_euclid(MICROS_PER_DAY);
+        micros_to_time(micros).expect("time out of range")
+    }
+
+    /// Extracts a field from the interval.
+    fn extract(&self, field: &str) -> Option<Value> {
+        Some(match field {
+            "year" => Value::Integer(self.months / 12),
+            "month" => Value::Integer(self.months % 12),
+            "day" => Value::Integer(self.days),
+            "hour" => Value::Integer(self.micros / 3_600_000_000),
+            "minute" => Value::Integer(self.micros % 3_600_000_000 / 60_000_000),
+            "second" => Value::Float((self.micros % 60_000_000) as f64 / MICROS_PER_SECOND as f64),
+            "microseconds" => Value::Integer(self.micros % 60_000_000),
+            // Like PostgreSQL, years are 365.25 days and months 30 days.
+            "epoch" => Value::Float(
+                (self.months / 12) as f64 * 365.25 * 86_400.0
+                    + (self.months % 12 * 30 + self.days) as f64 * 86_400.0
+                    + self.micros as f64 / MICROS_PER_SECOND as f64,
+            ),
+            _ => return None,
+        })
+    }
+
+    /// Returns an interval out of range error.
+    fn out_of_range() -> Error {
+        Error::Value("Interval out of range".into())
+    }
+}
+
+impl PartialEq for Interval {
+    fn eq(&self, other: &Self) -> bool {
+        self.normalized() == other.normalized()
+    }
+}
+
+impl Eq for Interval {}
+
+impl PartialOrd for Interval {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Interval {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.normalized().cmp(&other.normalized())
+    }
+}
+
+impl Hash for Interval {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.normalized().hash(state)
+    }
+}
+
+impl std::fmt::Display for Interval {
+    /// Formats the interval like PostgreSQL, e.g. 1 year 2 mons 3 days 04:05:06.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        for (value, unit) in
+            [(self.months / 12, "year"), (self.months % 12, "mon"), (self.days, "day")]
+        {
+            match value {
+                0 => {}
+                1 => parts.push(format!("1 {}", unit)),
+                value => parts.push(format!("{} {}s", value, unit)),
+            }
+        }
+        if self.micros != 0 || parts.is_empty() {
+            let micros = self.micros.unsigned_abs();
+            let mut time = format!(
+                "{}{:02}:{:02}:{:02}",
+                if self.micros < 0 { "-" } else { "" },
+                micros / 3_600_000_000,
+                micros / 60_000_000 % 60,
+                micros / 1_000_000 % 60,
+            );
+            let fraction = micros % 1_000_000;
+            if fraction != 0 {
+                time += format!(".{:06}", fraction).trim_end_matches('0');
+            }
+            parts.push(time);
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+impl std::str::FromStr for Interval {
+    type Err = Error;
+
+    /// Parses an interval literal, consisting of numbers followed by units
+    /// (e.g. 1 year 2 months 3.5 days) and an optional [-]HH:MM[:SS] time.
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::Parse(format!("Invalid interval {}", s));
+        let mut interval = Self::default();
+        let mut words = s.split_whitespace().peekable();
+        if words.peek().is_none() {
+            return Err(invalid());
+        }
+        while let Some(word) = words.next() {
+            if word.contains(':') {
+                let (sign, time) = match word.strip_prefix('-') {
+                    Some(time) => (-1.0, time),
+                    None => (1.0, word),
+                };
+                let parts = time.split(':').map(|p| p.parse::<f64>()).collect::<Vec<_>>();
+                let (hours, minutes, seconds) = match parts.as_slice() {
+                    [Ok(h), Ok(m)] => (*h, *m, 0.0),
+                    [Ok(h), Ok(m), Ok(s)] => (*h, *m, *s),
+                    _ => return Err(invalid()),
+                };
+                let seconds = sign * (hours * 3600.0 + minutes * 60.0 + seconds);
+                interval = interval.checked_add(&Self::new(0, 0, 1).checked_mul(seconds * 1e6)?)?;
+                continue;
+            }
+            let number = word.parse::<f64>().map_err(|_| invalid())?;
+            let unit = match words.next().map(|unit| unit.to_lowercase()) {
+                Some(unit) => unit,
+                None => return Err(invalid()),
+            };
+            let unit = match unit.as_str() {
+                "microsecond" | "microseconds" | "us" => Self::new(0, 0, 1),
+                "millisecond" | "milliseconds" | "ms" => Self::new(0, 0, 1_000),
+                "second" | "seconds" | "sec" | "secs" | "s" => Self::new(0, 0, MICROS_PER_SECOND),
+                "minute" | "minutes" | "min" | "mins" => Self::new(0, 0, 60 * MICROS_PER_SECOND),
+                "hour" | "hours" | "h" => Self::new(0, 0, 3600 * MICROS_PER_SECOND),
+                "day" | "days" | "d" => Self::new(0, 1, 0),
+                "week" | "weeks" | "w" => Self::new(0, 7, 0),
+                "month" | "months" | "mon" | "mons" => Self::new(1, 0, 0),
+                "year" | "years" | "y" => Self::new(12, 0, 0),
+                _ => return Err(invalid()),
+            };
+            interval = interval.checked_add(&unit.checked_mul(number)?)?;
+        }
+        Ok(interval)
+    }
+}
+
+/// Serializes dates as the number of days since the Unix epoch.
+pub(super) mod serde_date {
+    use super::*;
+    use serde::{de::Error as _, Deserialize as _, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        date: &NaiveDate,
+        s: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        s.serialize_i64(date.signed_duration_since(epoch()).num_days())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        d: D,
+    ) -> std::result::Result<NaiveDate, D::Error> {
+        add_days(epoch(), i64::deserialize(d)?).map_err(D::Error::custom)
+    }
+}
+
+/// Serializes times as the number of microseconds since midnight.
+pub(super) mod serde_time {
+    use super::*;
+    use serde::{de::Error as _, Deserialize as _, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        time: &NaiveTime,
+        s: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        s.serialize_i64(time_to_micros(*time))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        d: D,
+    ) -> std::result::Result<NaiveTime, D::Error> {
+        let micros = i64::deserialize(d)?;
+        micros_to_time(micros).ok_or_else(|| D::Error::custom(format!("Invalid time {}", micros)))
+    }
+}
+
+/// Serializes timestamps as the number of microseconds since the Unix epoch.
+pub(super) mod serde_timestamp {
+    use super::*;
+    use serde::{de::Error as _, Deserialize as _, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        datetime: &NaiveDateTime,
+        s: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        s.serialize_i64(datetime.and_utc().timestamp_micros())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        d: D,
+    ) -> std::result::Result<NaiveDateTime, D::Error> {
+        let micros = i64::deserialize(d)?;
+        DateTime::from_timestamp_micros(micros)
+            .map(|datetime| datetime.naive_utc())
+            .ok_or_else(|| D::Error::custom(format!("Invalid timestamp {}", micros)))
+    }
+}
+
+/// Serializes timestamps with time zone as the number of microseconds since
+/// the Unix epoch.
+pub(super) mod serde_timestamptz {
+    use super::*;
+    use serde::{de::Error as _, Deserialize as _, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        datetime: &DateTime<Utc>,
+        s: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        s.serialize_i64(datetime.timestamp_micros())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        d: D,
+    ) -> std::result::Result<DateTime<Utc>, D::Error> {
+        let micros = i64::deserialize(d)?;
+        DateTime::from_timestamp_micros(micros)
+            .ok_or_else(|| D::Error::custom(format!("Invalid timestamp {}", micros)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Tests parsing and formatting of temporal literals.
+    fn parse_format() -> Result<()> {
+        assert_eq!(parse_date("2024-02-29")?.to_string(), "2024-02-29");
+        assert!(parse_date("2023-02-29").is_err());
+        assert_eq!(parse_time("13:45")?.to_string(), "13:45:00");
+        assert_eq!(parse_time("13:45:01.5")?.to_string(), "13:45:01.500");
+        assert_eq!(parse_time("13:45:01.1234567")?.to_string(), "13:45:01.123456");
+        assert!(parse_time("25:00").is_err());
+        assert_eq!(parse_timestamp("2024-01-31")?.to_string(), "2024-01-31 00:00:00");
+        assert_eq!(parse_timestamp("2024-01-31T13:45:01")?.to_string(), "2024-01-31 13:45:01");
+        assert!(parse_timestamp("2024-01-31 13").is_err());
+        assert_eq!(
+            format_timestamptz(&parse_timestamptz("2024-01-31 13:45:00+02:00")?),
+            "2024-01-31 11:45:00+00:00"
+        );
+        assert_eq!(
+            format_timestamptz(&parse_timestamptz("2024-01-31T13:45:00Z")?),
+            "2024-01-31 13:45:00+00:00"
+        );
+        assert_eq!(
+            format_timestamptz(&parse_timestamptz("2024-01-31 13:45:00")?),
+            "2024-01-31 13:45:00+00:00"
+        );
+        Ok(())
+    }
+
+    #[test]
+    /// Tests parsing, formatting and comparison of intervals.
+    fn interval() -> Result<()> {
+        let parse = |s: &str| -> Result<String> { Ok(s.parse::<Interval>()?.to_string()) };
+        assert_eq!(parse("1 year 2 months 3 days 04:05:06")?, "1 year 2 mons 3 days 04:05:06");
+        assert_eq!(parse("1 day")?, "1 day");
+        assert_eq!(parse("-2 days -1:30")?, "-2 days -01:30:00");
+        assert_eq!(parse("1.5 months")?, "1 mon 15 days");
+        assert_eq!(parse("1.5 seconds 1 ms")?, "00:00:01.501");
+        assert_eq!(parse("2 weeks 36:00:00")?, "14 days 36:00:00");
+        assert_eq!(parse("0 days")?, "00:00:00");
+        assert!(parse("").is_err());
+        assert!(parse("1").is_err());
+        assert!(parse("1 fortnight").is_err());
+
+        let parse = |s: &str| s.parse::<Interval>();
+        assert_eq!(parse("1 month")?, parse("30 days")?);
+        assert!(parse("1 day")? > parse("23:59:59")?);
+        assert_eq!(parse("1 day")?.checked_mul(1.5)?.to_string(), "1 day 12:00:00");
+        assert_eq!(parse("1 mon")?.checked_div(4.0)?.to_string(), "7 days 12:00:00");
+        assert!(parse("1 day")?.checked_div(0.0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    /// Tests interval arithmetic on timestamps and times.
+    fn interval_add() -> Result<()> {
+        let ts = |s: &str| parse_timestamp(s);
+        let iv = |s: &str| s.parse::<Interval>();
+        assert_eq!(iv("1 month")?.add_to(ts("2024-01-31")?)?, ts("2024-02-29")?);
+        assert_eq!(iv("-1 month 1 day")?.add_to(ts("2024-03-31")?)?, ts("2024-03-01")?);
+        assert_eq!(iv("25:00")?.add_to(ts("2024-01-31 12:00")?)?, ts("2024-02-01 13:00")?);
+        assert!(iv("1000000 years")?.add_to(ts("2024-01-31")?).is_err());
+        assert_eq!(iv("1 day 02:00")?.add_to_time(parse_time("23:00")?), parse_time("01:00")?);
+        assert_eq!(iv("-02:00")?.add_to_time(parse_time("01:00")?), parse_time("23:00")?);
+        assert_eq!(
+            Interval::between(ts("2024-03-01 01:00")?, ts("2024-02-28")?)?.to_string(),
+            "2 days 01:00:00"
+        );
+        Ok(())
+    }
+
+    #[test]
+    /// Tests the extract() and date_trunc() functions.
+    fn extract_trunc() -> Result<()> {
+        let ts = Value::Timestamp(parse_timestamp("2024-03-31 13:45:01.5")?);
+        for (field, expect) in [
+            ("year", Value::Integer(2024)),
+            ("quarter", Value::Integer(1)),
+            ("month", Value::Integer(3)),
+            ("week", Value::Integer(13)),
+            ("day", Value::Integer(31)),
+            ("dow", Value::Integer(0)),
+            ("doy", Value::Integer(91)),
+            ("hour", Value::Integer(13)),
+            ("minute", Value::Integer(45)),
+            ("second", Value::Float(1.5)),
+            ("microseconds", Value::Integer(1_500_000)),
+            ("epoch", Value::Float(1711892701.5)),
+        ] {
+            assert_eq!(extract(field, ts.clone())?, expect, "{}", field);
+        }
+        let interval = Value::Interval("1 year 14 months 3 days 04:05:06".parse()?);
+        assert_eq!(extract("YEAR", interval.clone())?, Value::Integer(2));
+        assert_eq!(extract("month", interval.clone())?, Value::Integer(2));
+        assert_eq!(extract("hour", interval)?, Value::Integer(4));
+        assert!(extract("hour", Value::Time(parse_time("13:45")?)).is_ok());
+        assert!(extract("year", Value::Time(parse_time("13:45")?)).is_err());
+        assert!(extract("year", Value::Integer(1)).is_err());
+
+        for (field, expect) in [
+            ("second", "2024-03-31 13:45:01"),
+            ("minute", "2024-03-31 13:45:00"),
+            ("hour", "2024-03-31 13:00:00"),
+            ("day", "2024-03-31 00:00:00"),
+            ("week", "2024-03-25 00:00:00"),
+            ("month", "2024-03-01 00:00:00"),
+            ("quarter", "2024-01-01 00:00:00"),
+            ("year", "2024-01-01 00:00:00"),
+        ] {
+            assert_eq!(date_trunc(field, ts.clone())?.to_string(), expect, "{}", field);
+        }
+        assert!(date_trunc("fortnight", ts).is_err());
+        Ok(())
+    }
+}
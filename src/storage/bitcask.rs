@@ -1,97 +1,660 @@
-use super::{Engine, Status};
-use crate::error::Result;
+use super::encryption::{Key, KeyID, Keyring, OVERHEAD};
+use super::{Compression, Engine, Status, WriteBatch};
+use crate::error::{Error, Result};
 
 use fs4::FileExt;
-use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashSet};
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// The magic bytes at the start of a log segment file.
+const MAGIC: [u8; 4] = *b"BCSK";
+
+/// The magic bytes at the start of a hint file.
+const HINT_MAGIC: [u8; 4] = *b"BCHT";
+
+/// The log segment and hint file format version.
+const VERSION: u32 = 4;
+
+/// The length of the file header: magic bytes, version, and key ID.
+const HEADER_LEN: u64 = 16;
+
+/// The length of a log entry header: checksum, lengths checksum, key length
+/// and value length.
+const ENTRY_HEADER_LEN: u64 = 16;
+
+/// The value length of a batch header entry.
+const BATCH: i32 = -2;
+
+/// The length of a hint entry header: key length, value length, position, and
+/// decoded value length.
+const HINT_HEADER_LEN: usize = 20;
+
+/// The default size at which the active segment is closed and a new one
+/// is started.
+const MAX_SEGMENT_SIZE: u64 = 32 * 1024 * 1024;
 
 /// A very simple variant of BitCask, itself a very simple log-structured
 /// key-value engine used e.g. by the Riak database. It is not compatible with
 /// BitCask databases generated by other implementations. See:
 /// https://riak.com/assets/bitcask-intro.pdf
 ///
-/// BitCask writes key-value pairs to an append-only log file, and keeps a
-/// mapping of keys to file positions in memory. All live keys must fit in
-/// memory. Deletes write a tombstone value to the log file. To remove old
-/// garbage, logs can be compacted by writing new logs containing only live
-/// data, skipping replaced values and tombstones.
+/// BitCask writes key-value pairs to an append-only log, and keeps a mapping
+/// of keys to file positions in memory. All live keys must fit in memory.
+/// Deletes write a tombstone value to the log.
+///
+/// The log is split into segment files in the database directory, named by
+/// increasing segment ID. Writes are appended to the last, active segment.
+/// Once it exceeds the maximum segment size it is closed and becomes
+/// immutable, and a new active segment is started. Closed segments get a hint
+/// file containing the keys and positions of their entries but not the values,
+/// which is used to build the keydir on startup without reading the values.
 ///
-/// This implementation makes several significant simplifications over
-/// standard BitCask:
+/// To remove old garbage, immutable segments whose amount of garbage exceeds
+/// the compaction threshold are compacted by a background thread, which
+/// writes a new segment file containing only the live entries. Reads and
+/// writes continue while it is written, and are only paused to swap in the new
+/// file and update keydir positions.
 ///
-/// - Instead of writing multiple fixed-size log files, it uses a single
-///   append-only log file of arbitrary size. This increases the compaction
-///   volume, since the entire log file must be rewritten on every compaction,
-///   and can exceed the filesystem's file size limit, but RaDB databases are
-///   expected to be small.
+/// This implementation makes several simplifications over standard BitCask:
 ///
-/// - Compactions lock the database for reads and writes. This is ok since RaDB
-///   only compacts during node startup and files are expected to be small.
+/// - Segments are compacted one at a time, and are not merged with each other.
+///   Tombstones are only removed from the oldest segment, since older segments
+///   may contain values for the deleted keys.
 ///
-/// - Hint files are not used, the log itself is scanned when opened to
-///   build the keydir. Hint files only omit values, and RaDB values are
-///   expected to be small, so the hint files would be nearly as large as
-///   the compacted log files themselves.
+/// - Log entries don't contain timestamps.
 ///
-/// - Log entries don't contain timestamps or checksums.
+/// Log entries and hint files can be encrypted with a Keyring. Each log entry
+/// is encrypted separately, with authenticated encryption covering its key,
+/// value, and lengths. Segments and hint files record the ID of the key they
+/// were encrypted with in their header, and opening a segment encrypted with
+/// a key that isn't in the keyring is an error. Segments encrypted with a
+/// previous key (or unencrypted) are re-encrypted with the current key by
+/// compaction, regardless of their amount of garbage.
 ///
-/// The structure of a log entry is:
+/// Values are encoded with the configured compression (see Compression), which
+/// prefixes them with the compression ID and compresses them if that makes
+/// them smaller. Each value is decoded using its own compression ID, so the
+/// compression can be changed at any time.
 ///
+/// Log segment files start with a header containing the magic bytes "BCSK",
+/// the format version as big-endian u32, and the encryption key ID as
+/// big-endian u64 (0 if unencrypted). The structure of a log entry is:
+///
+/// - CRC32 checksum of the rest of the entry as big-endian u32.
+/// - CRC32 checksum of the key and value lengths as big-endian u32.
 /// - Key length as big-endian u32.
 /// - Value length as big-endian i32, or -1 for tombstones.
 /// - Key as raw bytes (max 2 GB).
-/// - Value as raw bytes (max 2 GB).
+/// - Encoded value as raw bytes (max 2 GB).
+/// - For encrypted entries, the authentication tag and nonce (see Key).
+///
+/// Encrypted entries contain the encrypted key and value in place of the key
+/// and value, since the ciphertext has the same length as the plaintext.
+///
+/// A write batch is written as a batch header entry, with the number of
+/// entries in the batch as a big-endian u32 key and a value length of -2,
+/// followed by the batch's entries.
+///
+/// Checksums are verified when a segment is scanned, and when values are
+/// read. The lengths have their own checksum, so that a corrupt length can't
+/// make an entry appear to extend beyond the end of the segment. An incomplete
+/// or corrupt entry at the end of the active segment is assumed to be a torn
+/// write and is truncated, but corruption elsewhere is an error. Similarly, a
+/// batch that is missing entries at the end of the active segment is
+/// truncated, so batches are applied either completely or not at all.
+/// Immutable segments are flushed to disk when closed, so they are never
+/// truncated: an incomplete entry in them is an error.
+///
+/// Hint files start with the magic bytes "BCHT", the format version, and the
+/// key ID of the segment, followed by an entry for each log entry (encrypted
+/// as a whole for encrypted segments) and a CRC32 checksum of the entire file.
+/// The structure of a hint entry is:
+///
+/// - Key length as big-endian u32.
+/// - Value length as big-endian i32, or -1 for tombstones.
+/// - Log entry position as big-endian u64.
+/// - Decoded value length as big-endian u32, or 0 for tombstones.
+/// - Key as raw bytes.
+///
+/// Missing, corrupt, or outdated hint files are ignored, and the segment is
+/// scanned instead.
 pub struct BitCask {
-    /// The active append-only log file.
-    log: Log,
-    /// Maps keys to a value position and length in the log file.
+    /// The database directory.
+    dir: PathBuf,
+    /// The database directory lock file, locked until the database is closed.
+    lock: std::fs::File,
+    /// The log segments by ID. The last segment is the active segment, the
+    /// others are immutable.
+    segments: BTreeMap<SegmentID, Segment>,
+    /// Maps keys to a segment, value position, value length, and decoded
+    /// value length.
     keydir: KeyDir,
+    /// The size at which the active segment is closed.
+    max_segment_size: u64,
+    /// The minimum garbage ratio and bytes at which a segment is compacted,
+    /// or None if compaction is disabled.
+    compact_threshold: Option<(f64, u64)>,
+    /// The compression used for new values.
+    compression: Compression,
+    /// The encryption keys, if encrypted.
+    keys: Option<Keyring>,
+    /// The background worker, which closes and compacts segments.
+    worker: Worker,
+}
+
+/// A log segment ID.
+type SegmentID = u64;
+
+/// Maps keys to a segment, value position, value length, and decoded value
+/// length.
+type KeyDir = BTreeMap<Vec<u8>, KeyDirEntry>;
+
+/// A keydir entry: segment, value position, value length, and decoded value
+/// length.
+type KeyDirEntry = (SegmentID, u64, u32, u32);
+
+/// A log segment.
+struct Segment {
+    /// The segment's log file.
+    log: Log,
+    /// The on-disk size of live data in the segment, including the header.
+    live_size: u64,
+    /// The on-disk size of tombstones in the segment.
+    tombstone_size: u64,
 }
 
-/// Maps keys to a value position and length in the log file.
-type KeyDir = std::collections::BTreeMap<Vec<u8>, (u64, u32)>;
+impl Segment {
+    /// Creates a new segment for the given log file, without any live data.
+    fn new(log: Log) -> Self {
+        Self { log, live_size: HEADER_LEN, tombstone_size: 0 }
+    }
+}
 
 impl BitCask {
-    /// Opens or creates a BitCask database in the given file.
+    /// Opens or creates a BitCask database in the given directory.
     pub fn new(path: PathBuf) -> Result<Self> {
+        Self::open(path, None)
+    }
+
+    /// Opens or creates an encrypted BitCask database in the given directory.
+    /// New data is encrypted with the keyring's current key, and existing
+    /// data must be encrypted with one of the keyring's keys.
+    pub fn new_encrypted(path: PathBuf, keys: Keyring) -> Result<Self> {
+        Self::open(path, Some(keys))
+    }
+
+    /// Opens or creates a BitCask database in the given directory, with
+    /// optional encryption keys. If the active segment isn't encrypted with
+    /// the current key, it is closed and a new one is started.
+    fn open(path: PathBuf, keys: Option<Keyring>) -> Result<Self> {
         log::info!("Opening database {}", path.display());
-        let mut log = Log::new(path.clone())?;
-        let keydir = log.build_keydir()?;
-        log::info!("Indexed {} live keys in {}", keydir.len(), path.display());
-        Ok(Self { log, keydir })
+        Self::import_legacy(&path, keys.as_ref())?;
+        std::fs::create_dir_all(&path)?;
+        let lock = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path.join("LOCK"))?;
+        lock.try_lock_exclusive()?;
+
+        // Find the segment files, and remove leftovers from interrupted
+        // compactions.
+        let mut ids = Vec::new();
+        for entry in std::fs::read_dir(&path)? {
+            let entry_path = entry?.path();
+            let Some(name) = entry_path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if name.ends_with(".new") {
+                std::fs::remove_file(&entry_path)?;
+            } else if let Some(id) = name.strip_suffix(".log").and_then(|id| id.parse().ok()) {
+                ids.push(id);
+            }
+        }
+        ids.sort_unstable();
+        let active_id = ids.last().copied().unwrap_or(1);
+
+        let mut s = Self {
+            dir: path,
+            lock,
+            segments: BTreeMap::new(),
+            keydir: KeyDir::new(),
+            max_segment_size: MAX_SEGMENT_SIZE,
+            compact_threshold: None,
+            compression: Compression::None,
+            keys,
+            worker: Worker::new()?,
+        };
+        for id in ids.into_iter().filter(|id| *id != active_id) {
+            s.load_segment(id, false)?;
+        }
+        s.load_segment(active_id, true)?;
+        if s.needs_reencryption(active_id) {
+            s.close_active()?;
+        }
+
+        log::info!(
+            "Indexed {} live keys in {} segments in {}",
+            s.keydir.len(),
+            s.segments.len(),
+            s.dir.display()
+        );
+        Ok(s)
     }
 
-    /// Opens a BitCask database, and automatically compacts it if the amount
-    /// of garbage exceeds the given ratio and byte size when opened.
+    /// Imports a legacy single-file database, written before the database
+    /// was split into log segments, as the first segment of a database
+    /// directory at the same path. The legacy file is first renamed to a
+    /// .legacy file, from which an interrupted import is retried. It is
+    /// retained after the import, and can be removed once it's no longer
+    /// needed. Legacy entries have no checksums or compression, and consist of
+    /// the key length as big-endian u32, the value length as big-endian i32
+    /// (-1 for tombstones), the key, and the value.
+    fn import_legacy(path: &Path, keys: Option<&Keyring>) -> Result<()> {
+        let sibling = |suffix: &str| {
+            let mut name = path.file_name().unwrap_or_default().to_os_string();
+            name.push(suffix);
+            path.with_file_name(name)
+        };
+        let legacy_path = sibling(".legacy");
+        let file = if path.is_file() {
+            let file = std::fs::File::open(path)?;
+            file.try_lock_exclusive()?;
+            std::fs::rename(path, &legacy_path)?;
+            file
+        } else if !path.exists() && legacy_path.is_file() {
+            let file = std::fs::File::open(&legacy_path)?;
+            file.try_lock_exclusive()?;
+            file
+        } else {
+            return Ok(());
+        };
+        log::info!("Importing legacy database file {}", legacy_path.display());
+
+        let import_path = sibling(".import");
+        if import_path.exists() {
+            std::fs::remove_dir_all(&import_path)?;
+        }
+        let mut log = Log::new(import_path.join(format!("{:010}.log", 1)), keys)?;
+        let mut len_buf = [0u8; 4];
+        let file_len = file.metadata()?.len();
+        let mut r = BufReader::new(file);
+        let mut pos = 0;
+        while pos + 8 <= file_len {
+            r.read_exact(&mut len_buf)?;
+            let key_len = u32::from_be_bytes(len_buf);
+            r.read_exact(&mut len_buf)?;
+            let value_len = i32::from_be_bytes(len_buf);
+            let end = pos + 8 + key_len as u64 + value_len.max(0) as u64;
+            if end > file_len {
+                break;
+            }
+            let mut key = vec![0; key_len as usize];
+            r.read_exact(&mut key)?;
+            let value = match value_len {
+                l if l >= 0 => {
+                    let mut value = vec![0; l as usize];
+                    r.read_exact(&mut value)?;
+                    Some(Compression::None.encode(&value))
+                }
+                _ => None, // -1 for tombstones
+            };
+            log.write_entry(&key, value.as_deref())?;
+            pos = end;
+        }
+        if pos < file_len {
+            log::error!(
+                "Found incomplete entry at offset {} in {}, discarding it",
+                pos,
+                legacy_path.display()
+            );
+        }
+        log.sync()?;
+        drop(log);
+        std::fs::rename(&import_path, path)?;
+        Ok(())
+    }
+
+    /// Opens a BitCask database with optional encryption keys, and compacts
+    /// segments in the background when their amount of garbage exceeds the
+    /// given ratio and byte size, or when they aren't encrypted with the
+    /// current key. If the active segment exceeds the thresholds when opened,
+    /// it is closed such that it can be compacted.
     pub fn new_compact(
         path: PathBuf,
         garbage_min_ratio: f64,
         garbage_min_bytes: u64,
+        keys: Option<Keyring>,
     ) -> Result<Self> {
-        let mut s = Self::new(path)?;
+        let mut s = Self::open(path, keys)?;
+        s.compact_threshold = Some((garbage_min_ratio, garbage_min_bytes));
 
-        let status = s.status()?;
-        let garbage_ratio = status.garbage_disk_size as f64 / status.total_disk_size as f64;
-        if status.garbage_disk_size > 0
-            && status.garbage_disk_size >= garbage_min_bytes
-            && garbage_ratio >= garbage_min_ratio
+        let (active_id, active) = s.active();
+        if active.log.size > HEADER_LEN && s.should_compact(active_id).is_some() {
+            s.close_active()?;
+        }
+        s.maybe_compact()?;
+        Ok(s)
+    }
+
+    /// Sets the size at which the active segment is closed and a new one is
+    /// started.
+    pub fn set_max_segment_size(&mut self, size: u64) {
+        self.max_segment_size = size;
+    }
+
+    /// Sets the compression used for new values. Existing values keep their
+    /// current compression.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// Returns the active segment and its ID.
+    fn active(&mut self) -> (SegmentID, &mut Segment) {
+        let (id, segment) = self.segments.iter_mut().next_back().expect("no active segment");
+        (*id, segment)
+    }
+
+    /// Returns the path of a segment's file with the given extension.
+    fn segment_path(&self, id: SegmentID, extension: &str) -> PathBuf {
+        self.dir.join(format!("{:010}.{}", id, extension))
+    }
+
+    /// Opens a segment and adds its entries to the keydir. Immutable segments
+    /// are indexed via their hint file if possible, otherwise the segment
+    /// file is scanned.
+    fn load_segment(&mut self, id: SegmentID, active: bool) -> Result<()> {
+        let mut log = Log::new(self.segment_path(id, "log"), self.keys.as_ref())?;
+        let hint_path = self.segment_path(id, "hint");
+        let hints = match active {
+            false => read_hints(&hint_path, log.size, log.key.as_ref())?,
+            true => None,
+        };
+        let has_hints = hints.is_some();
+
+        let entries = match hints {
+            Some(entries) => entries,
+            None => {
+                let mut entries = Vec::new();
+                let end = log.scan_entries(|pos, key, value| {
+                    entries.push((key.to_vec(), pos, value.map(value_lens).transpose()?));
+                    Ok(())
+                })?;
+                if end < log.size {
+                    if !active {
+                        return Err(Error::Internal(format!(
+                            "Incomplete or corrupt entry at offset {} in immutable segment {}",
+                            end,
+                            log.path.display()
+                        )));
+                    }
+                    log::error!(
+                        "Found incomplete or corrupt entry at offset {} in {}, truncating file",
+                        end,
+                        log.path.display()
+                    );
+                    log.truncate(end)?;
+                }
+                for (key, pos, value_lens) in &entries {
+                    log.hints.extend(encode_hint(key, *pos, *value_lens));
+                }
+                entries
+            }
+        };
+
+        self.segments.insert(id, Segment::new(log));
+        for (key, pos, value_lens) in entries {
+            self.index(id, key, pos, value_lens);
+        }
+
+        // Write missing hint files for immutable segments.
+        if !active && !has_hints {
+            let segment = self.segments.get_mut(&id).expect("no segment");
+            let (path, hints) = (segment.log.path.clone(), std::mem::take(&mut segment.log.hints));
+            let key = segment.log.key.clone();
+            self.worker.submit(Job::Close { id, path, hints, key })?;
+        }
+        Ok(())
+    }
+
+    /// Adds a log entry to the keydir, given its value length and decoded
+    /// value length, or removes the key for tombstones. Updates the live and
+    /// tombstone size of the affected segments.
+    fn index(&mut self, id: SegmentID, key: Vec<u8>, pos: u64, value_lens: Option<(u32, u32)>) {
+        let key_len = key.len() as u64;
+        let segment = self.segments.get_mut(&id).expect("no segment");
+        let entry_len = ENTRY_HEADER_LEN + key_len + segment.log.trailer_len();
+        let replaced = match value_lens {
+            Some((value_len, decoded_len)) => {
+                segment.live_size += entry_len + value_len as u64;
+                let value_pos = pos + ENTRY_HEADER_LEN + key_len;
+                self.keydir.insert(key, (id, value_pos, value_len, decoded_len))
+            }
+            None => {
+                segment.tombstone_size += entry_len;
+                self.keydir.remove(&key)
+            }
+        };
+        if let Some((old_id, _, old_value_len, _)) = replaced {
+            if let Some(segment) = self.segments.get_mut(&old_id) {
+                let entry_len = ENTRY_HEADER_LEN + key_len + segment.log.trailer_len();
+                segment.live_size -= entry_len + old_value_len as u64;
+            }
+        }
+    }
+
+    /// Reads and decodes the value of a keydir entry.
+    fn read_value(
+        segments: &mut BTreeMap<SegmentID, Segment>,
+        key: &[u8],
+        (id, value_pos, value_len, _): KeyDirEntry,
+    ) -> Result<Vec<u8>> {
+        let segment = segments
+            .get_mut(&id)
+            .ok_or_else(|| Error::Internal(format!("Segment {} not found", id)))?;
+        Compression::decode(segment.log.read_value(key, value_pos, value_len)?)
+    }
+
+    /// Performs background maintenance after a write: closes the active
+    /// segment if it is full, and installs completed background work.
+    fn maintain(&mut self) -> Result<()> {
+        let max_segment_size = self.max_segment_size;
+        if self.active().1.log.size >= max_segment_size {
+            self.close_active()?;
+            self.maybe_compact()?;
+        }
+        self.poll()
+    }
+
+    /// Closes the active segment and starts a new one. The closed segment is
+    /// flushed to disk before starting the new segment, such that immutable
+    /// segments never contain torn writes, and its hint file is written by the
+    /// background worker.
+    fn close_active(&mut self) -> Result<()> {
+        let (id, active) = self.active();
+        active.log.sync()?;
+        let path = active.log.path.clone();
+        let hints = std::mem::take(&mut active.log.hints);
+        let key = active.log.key.clone();
+        let log = Log::new(self.segment_path(id + 1, "log"), self.keys.as_ref())?;
+        self.segments.insert(id + 1, Segment::new(log));
+        self.worker.submit(Job::Close { id, path, hints, key })
+    }
+
+    /// Returns the amount of garbage in a segment that compaction would
+    /// remove. Tombstones are only removed from the oldest segment.
+    fn removable_garbage(&self, id: SegmentID) -> u64 {
+        let segment = &self.segments[&id];
+        let garbage = segment.log.size - segment.live_size;
+        match self.segments.keys().next() == Some(&id) {
+            true => garbage,
+            false => garbage - segment.tombstone_size,
+        }
+    }
+
+    /// Returns the garbage ratio of a segment if it should be compacted.
+    fn should_compact(&self, id: SegmentID) -> Option<f64> {
+        let (min_ratio, min_bytes) = self.compact_threshold?;
+        let garbage = self.removable_garbage(id);
+        let ratio = garbage as f64 / self.segments[&id].log.size as f64;
+        (garbage > 0 && garbage >= min_bytes && ratio >= min_ratio).then_some(ratio)
+    }
+
+    /// Returns the ID of the current encryption key, or 0 if unencrypted.
+    fn current_key_id(&self) -> KeyID {
+        self.keys.as_ref().map_or(0, |keys| keys.current().id())
+    }
+
+    /// Returns true if a segment isn't encrypted with the current key.
+    fn needs_reencryption(&self, id: SegmentID) -> bool {
+        self.segments[&id].log.key_id() != self.current_key_id()
+    }
+
+    /// Starts a background compaction of an immutable segment, if no
+    /// compaction is already running. Segments that aren't encrypted with the
+    /// current key are compacted first, oldest first, followed by the segment
+    /// with the most garbage if any exceeds the compaction threshold.
+    fn maybe_compact(&mut self) -> Result<()> {
+        if self.worker.compacting.is_some() || self.compact_threshold.is_none() {
+            return Ok(());
+        }
+        let active_id = *self.segments.keys().next_back().expect("no active segment");
+        if let Some(id) = self
+            .segments
+            .range(..active_id)
+            .map(|(id, _)| *id)
+            .find(|id| self.needs_reencryption(*id))
         {
             log::info!(
-                "Compacting {} to remove {:.0}% garbage ({} MB out of {} MB)",
-                s.log.path.display(),
-                garbage_ratio * 100.0,
-                status.garbage_disk_size / 1024 / 1024,
-                status.total_disk_size / 1024 / 1024
-            );
-            s.compact()?;
-            log::info!(
-                "Compacted {} to size {} MB",
-                s.log.path.display(),
-                (status.total_disk_size - status.garbage_disk_size) / 1024 / 1024
+                "Compacting {} to re-encrypt it with key {:016x}",
+                self.segments[&id].log.path.display(),
+                self.current_key_id()
             );
+            return self.start_compaction(id);
         }
+        let Some((id, ratio)) = self
+            .segments
+            .range(..active_id)
+            .filter_map(|(id, _)| Some((*id, self.should_compact(*id)?)))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        else {
+            return Ok(());
+        };
+
+        let segment = &self.segments[&id];
+        log::info!(
+            "Compacting {} to remove {:.0}% garbage ({} MB out of {} MB)",
+            segment.log.path.display(),
+            ratio * 100.0,
+            self.removable_garbage(id) / 1024 / 1024,
+            segment.log.size / 1024 / 1024
+        );
+        self.start_compaction(id)
+    }
 
-        Ok(s)
+    /// Starts a background compaction of an immutable segment.
+    fn start_compaction(&mut self, id: SegmentID) -> Result<()> {
+        let job = self.compact_job(id);
+        self.worker.submit(job)?;
+        self.worker.compacting = Some(id);
+        Ok(())
+    }
+
+    /// Creates a compaction job for an immutable segment, using the keydir
+    /// to determine which values are live.
+    fn compact_job(&self, id: SegmentID) -> Job {
+        let live = self
+            .keydir
+            .values()
+            .filter(|(segment_id, _, _, _)| *segment_id == id)
+            .map(|(_, value_pos, _, _)| *value_pos)
+            .collect();
+        let keep_tombstones = self.segments.keys().next() != Some(&id);
+        let path = self.segment_path(id, "log");
+        Job::Compact { id, path, live, keep_tombstones, keys: self.keys.clone() }
+    }
+
+    /// Installs background work results that have completed.
+    fn poll(&mut self) -> Result<()> {
+        while let Ok(done) = self.worker.rx.try_recv() {
+            self.complete(done)?;
+        }
+        Ok(())
+    }
+
+    /// Waits for all background work to complete.
+    fn wait(&mut self) -> Result<()> {
+        while self.worker.pending > 0 {
+            let done = self.worker.rx.recv()?;
+            self.complete(done)?;
+        }
+        Ok(())
+    }
+
+    /// Handles a completed background job. Failing to flush a closed segment
+    /// is an error, but a failed compaction only leaves the old segment
+    /// in place.
+    fn complete(&mut self, done: Done) -> Result<()> {
+        self.worker.pending -= 1;
+        match done {
+            Done::Closed(id, result) => {
+                result?;
+                log::debug!("Closed segment {}", id);
+                Ok(())
+            }
+            Done::Compacted(id, result) => {
+                self.worker.compacting = None;
+                match result {
+                    Ok(compacted) => self.install(id, compacted)?,
+                    Err(error) => log::error!("Failed to compact segment {}: {}", id, error),
+                }
+                self.maybe_compact()
+            }
+        }
+    }
+
+    /// Replaces an immutable segment with its compacted version, and points
+    /// keydir entries at the new value positions. Keys that were written or
+    /// deleted while compacting are left alone. Empty segments are removed.
+    fn install(&mut self, id: SegmentID, compacted: Compacted) -> Result<()> {
+        let path = self.segment_path(id, "log");
+        let hint_path = self.segment_path(id, "hint");
+
+        // Remove the old hint file before replacing the segment file, such
+        // that a crash can't leave behind a hint file for the wrong segment.
+        // A missing hint file just means the segment is scanned on startup.
+        self.segments.remove(&id);
+        if hint_path.exists() {
+            std::fs::remove_file(&hint_path)?;
+        }
+        std::fs::rename(self.segment_path(id, "log.new"), &path)?;
+        std::fs::rename(self.segment_path(id, "hint.new"), &hint_path)?;
+
+        let log = Log::new(path, self.keys.as_ref())?;
+        let mut live_size = HEADER_LEN;
+        for (key, old_pos, new_pos) in compacted.moves {
+            if let Some((segment_id, value_pos, value_len, _)) = self.keydir.get_mut(&key) {
+                if *segment_id == id && *value_pos == old_pos {
+                    *value_pos = new_pos;
+                    live_size +=
+                        ENTRY_HEADER_LEN + key.len() as u64 + *value_len as u64 + log.trailer_len();
+                }
+            }
+        }
+
+        if log.size == HEADER_LEN {
+            let log_path = log.path.clone();
+            drop(log);
+            std::fs::remove_file(&hint_path)?;
+            std::fs::remove_file(log_path)?;
+        } else {
+            log::info!("Compacted {} to size {} MB", log.path.display(), log.size / 1024 / 1024);
+            let tombstone_size = compacted.tombstone_size;
+            self.segments.insert(id, Segment { log, live_size, tombstone_size });
+        }
+        Ok(())
     }
 }
 
@@ -105,25 +668,29 @@ impl Engine for BitCask {
     type ScanIterator<'a> = ScanIterator<'a>;
 
     fn delete(&mut self, key: &[u8]) -> Result<()> {
-        self.log.write_entry(key, None)?;
-        self.keydir.remove(key);
-        Ok(())
+        let (id, active) = self.active();
+        let (pos, _) = active.log.write_entry(key, None)?;
+        self.index(id, key.to_vec(), pos, None);
+        self.maintain()
     }
 
     fn flush(&mut self) -> Result<()> {
-        Ok(self.log.file.sync_all()?)
+        for segment in self.segments.values_mut() {
+            segment.log.sync()?;
+        }
+        self.poll()
     }
 
     fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
-        if let Some((value_pos, value_len)) = self.keydir.get(key) {
-            Ok(Some(self.log.read_value(*value_pos, *value_len)?))
+        if let Some(entry) = self.keydir.get(key) {
+            Ok(Some(Self::read_value(&mut self.segments, key, *entry)?))
         } else {
             Ok(None)
         }
     }
 
     fn scan(&mut self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::ScanIterator<'_> {
-        ScanIterator { inner: self.keydir.range(range), log: &mut self.log }
+        ScanIterator { inner: self.keydir.range(range), segments: &mut self.segments }
     }
 
     fn scan_dyn(
@@ -134,20 +701,40 @@ impl Engine for BitCask {
     }
 
     fn set(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
-        let (pos, len) = self.log.write_entry(key, Some(&*value))?;
-        let value_len = value.len() as u32;
-        self.keydir.insert(key.to_vec(), (pos + len as u64 - value_len as u64, value_len));
-        Ok(())
+        let value = self.compression.encode(&value);
+        let (id, active) = self.active();
+        let (pos, _) = active.log.write_entry(key, Some(&value))?;
+        self.index(id, key.to_vec(), pos, Some(value_lens(&value)?));
+        self.maintain()
+    }
+
+    fn write_batch(&mut self, batch: WriteBatch) -> Result<()> {
+        let compression = self.compression;
+        let batch: Vec<_> =
+            batch.into_iter().map(|(k, v)| (k, v.map(|v| compression.encode(&v)))).collect();
+        let (id, active) = self.active();
+        let positions = active.log.write_batch(&batch)?;
+        for ((key, value), pos) in batch.into_iter().zip(positions) {
+            self.index(id, key, pos, value.as_deref().map(value_lens).transpose()?);
+        }
+        self.maintain()
     }
 
     fn status(&mut self) -> Result<Status> {
+        self.poll()?;
         let keys = self.keydir.len() as u64;
-        let size = self
-            .keydir
-            .iter()
-            .fold(0, |size, (key, (_, value_len))| size + key.len() as u64 + *value_len as u64);
-        let total_disk_size = self.log.file.metadata()?.len();
-        let live_disk_size = size + 8 * keys; // account for length prefixes
+        let size = self.keydir.iter().fold(0, |size, (key, (_, _, _, decoded_len))| {
+            size + key.len() as u64 + *decoded_len as u64
+        });
+        let (values_size, decoded_size) = self.keydir.values().fold(
+            (0, 0),
+            |(values, decoded), (_, _, value_len, decoded_len)| {
+                let (value_len, decoded_len) = (*value_len as u64, *decoded_len as u64);
+                (values + Compression::stored_len(value_len, decoded_len), decoded + decoded_len)
+            },
+        );
+        let total_disk_size = self.segments.values().map(|s| s.log.size).sum();
+        let live_disk_size = self.segments.values().map(|s| s.live_size).sum();
         let garbage_disk_size = total_disk_size - live_disk_size;
         Ok(Status {
             name: self.to_string(),
@@ -156,19 +743,23 @@ impl Engine for BitCask {
             total_disk_size,
             live_disk_size,
             garbage_disk_size,
+            compression_ratio: match values_size {
+                0 => 1.0,
+                _ => decoded_size as f64 / values_size as f64,
+            },
         })
     }
 }
 
 pub struct ScanIterator<'a> {
-    inner: std::collections::btree_map::Range<'a, Vec<u8>, (u64, u32)>,
-    log: &'a mut Log,
+    inner: std::collections::btree_map::Range<'a, Vec<u8>, KeyDirEntry>,
+    segments: &'a mut BTreeMap<SegmentID, Segment>,
 }
 
 impl<'a> ScanIterator<'a> {
-    fn map(&mut self, item: (&Vec<u8>, &(u64, u32))) -> <Self as Iterator>::Item {
-        let (key, (value_pos, value_len)) = item;
-        Ok((key.clone(), self.log.read_value(*value_pos, *value_len)?))
+    fn map(&mut self, item: (&Vec<u8>, &KeyDirEntry)) -> <Self as Iterator>::Item {
+        let (key, entry) = item;
+        Ok((key.clone(), BitCask::read_value(self.segments, key, *entry)?))
     }
 }
 
@@ -187,63 +778,344 @@ impl<'a> DoubleEndedIterator for ScanIterator<'a> {
 }
 
 impl BitCask {
-    /// Compacts the current log file by writing out a new log file containing
-    /// only live keys and replacing the current file with it.
+    /// Compacts the entire database in the foreground: waits for background
+    /// work, closes the active segment, and compacts all immutable segments
+    /// containing garbage or not encrypted with the current key, oldest first.
     pub fn compact(&mut self) -> Result<()> {
-        let mut tmp_path = self.log.path.clone();
-        tmp_path.set_extension("new");
-        let (mut new_log, new_keydir) = self.write_log(tmp_path)?;
-
-        std::fs::rename(&new_log.path, &self.log.path)?;
-        new_log.path.clone_from(&self.log.path);
-        self.log = new_log;
-        self.keydir = new_keydir;
-        Ok(())
-    }
-
-    /// Writes out a new log file with the live entries of the current log file
-    /// and returns it along with its keydir. Entries are written in key order.
-    fn write_log(&mut self, path: PathBuf) -> Result<(Log, KeyDir)> {
-        let mut new_keydir = KeyDir::new();
-        let mut new_log = Log::new(path)?;
-        new_log.file.set_len(0)?; // truncate file if it exists
-        for (key, (value_pos, value_len)) in self.keydir.iter() {
-            let value = self.log.read_value(*value_pos, *value_len)?;
-            let (pos, len) = new_log.write_entry(key, Some(&value))?;
-            new_keydir.insert(key.clone(), (pos + len as u64 - *value_len as u64, *value_len));
+        self.wait()?;
+        if self.active().1.log.size > HEADER_LEN {
+            self.close_active()?;
+            self.wait()?;
+        }
+        let active_id = *self.segments.keys().next_back().expect("no active segment");
+        let ids: Vec<SegmentID> = self.segments.range(..active_id).map(|(id, _)| *id).collect();
+        for id in ids {
+            if self.removable_garbage(id) > 0 || self.needs_reencryption(id) {
+                let compacted = self.compact_job(id).run_compact()?;
+                self.install(id, compacted)?;
+            }
         }
-        Ok((new_log, new_keydir))
+        Ok(())
     }
 }
 
-/// Attempt to flush the file when the database is closed.
+/// Flush the files and shut down the background worker when the database is
+/// closed. Unfinished compactions are discarded.
 impl Drop for BitCask {
     fn drop(&mut self) {
         if let Err(error) = self.flush() {
             log::error!("failed to flush file: {}", error)
         }
+        self.worker.shutdown();
+        if let Err(error) = self.lock.unlock() {
+            log::error!("failed to unlock database: {}", error)
+        }
     }
 }
 
-/// A BitCask append-only log file, containing a sequence of key/value
-/// entries encoded as follows;
+/// A background worker thread, which runs jobs in submission order.
+struct Worker {
+    /// Sends jobs to the worker thread.
+    tx: Option<crossbeam::channel::Sender<Job>>,
+    /// Receives completed jobs from the worker thread.
+    rx: crossbeam::channel::Receiver<Done>,
+    /// The worker thread.
+    thread: Option<std::thread::JoinHandle<()>>,
+    /// The number of submitted jobs that have not been received as done.
+    pending: usize,
+    /// The segment being compacted, if any.
+    compacting: Option<SegmentID>,
+}
+
+/// A background job.
+enum Job {
+    /// Flushes a closed segment to disk and writes its hint file, encrypted
+    /// with the segment's key.
+    Close { id: SegmentID, path: PathBuf, hints: Vec<u8>, key: Option<Key> },
+    /// Writes a compacted version of an immutable segment to a temporary file,
+    /// containing the values at the given live positions, and tombstones if
+    /// keep_tombstones is true. The new file is encrypted with the current key.
+    Compact {
+        id: SegmentID,
+        path: PathBuf,
+        live: HashSet<u64>,
+        keep_tombstones: bool,
+        keys: Option<Keyring>,
+    },
+}
+
+/// A completed background job.
+enum Done {
+    /// A segment was closed.
+    Closed(SegmentID, Result<()>),
+    /// A compacted segment was written.
+    Compacted(SegmentID, Result<Compacted>),
+}
+
+/// A compacted segment, written to a temporary file.
+struct Compacted {
+    /// The moved values, as (key, old position, new position).
+    moves: Vec<(Vec<u8>, u64, u64)>,
+    /// The on-disk size of the retained tombstones.
+    tombstone_size: u64,
+}
+
+impl Worker {
+    /// Starts a new worker thread.
+    fn new() -> Result<Self> {
+        let (tx, job_rx) = crossbeam::channel::unbounded::<Job>();
+        let (done_tx, rx) = crossbeam::channel::unbounded();
+        let thread = std::thread::Builder::new().name("bitcask".to_string()).spawn(move || {
+            while let Ok(job) = job_rx.recv() {
+                if done_tx.send(job.run()).is_err() {
+                    break;
+                }
+            }
+        })?;
+        Ok(Self { tx: Some(tx), rx, thread: Some(thread), pending: 0, compacting: None })
+    }
+
+    /// Submits a job to the worker.
+    fn submit(&mut self, job: Job) -> Result<()> {
+        self.tx.as_ref().ok_or_else(|| Error::Internal("worker shut down".into()))?.send(job)?;
+        self.pending += 1;
+        Ok(())
+    }
+
+    /// Shuts down the worker, waiting for submitted jobs to complete.
+    fn shutdown(&mut self) {
+        self.tx = None;
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("bitcask worker thread panicked")
+            }
+        }
+    }
+}
+
+impl Job {
+    /// Runs the job.
+    fn run(self) -> Done {
+        match self {
+            Job::Close { id, path, hints, key } => {
+                Done::Closed(id, Self::run_close(&path, &hints, key.as_ref()))
+            }
+            Job::Compact { id, .. } => Done::Compacted(id, self.run_compact()),
+        }
+    }
+
+    /// Writes the hint file of a closed segment.
+    fn run_close(path: &Path, hints: &[u8], key: Option<&Key>) -> Result<()> {
+        write_hints(&path.with_extension("hint"), hints, key)
+    }
+
+    /// Writes a compacted segment and hint file to temporary files.
+    fn run_compact(self) -> Result<Compacted> {
+        let Job::Compact { path, live, keep_tombstones, keys, .. } = self else {
+            return Err(Error::Internal("not a compaction job".into()));
+        };
+        let new_path = path.with_extension("log.new");
+        if new_path.exists() {
+            std::fs::remove_file(&new_path)?;
+        }
+        let mut log = Log::new(path.clone(), keys.as_ref())?;
+        let mut new_log = Log::new(new_path, keys.as_ref())?;
+        let mut moves = Vec::new();
+        let mut tombstone_size = 0;
+        let end = log.scan_entries(|pos, key, value| {
+            match value {
+                Some(value) => {
+                    let value_pos = pos + ENTRY_HEADER_LEN + key.len() as u64;
+                    if live.contains(&value_pos) {
+                        let (new_pos, _) = new_log.write_entry(key, Some(value))?;
+                        moves.push((key.to_vec(), value_pos, new_pos + value_pos - pos));
+                    }
+                }
+                None if keep_tombstones => {
+                    let (_, len) = new_log.write_entry(key, None)?;
+                    tombstone_size += len as u64;
+                }
+                None => {}
+            }
+            Ok(())
+        })?;
+        if end < log.size {
+            return Err(Error::Internal(format!(
+                "Incomplete or corrupt entry at offset {} in {}",
+                end,
+                path.display()
+            )));
+        }
+        new_log.file.sync_all()?;
+        write_hints(&path.with_extension("hint.new"), &new_log.hints, new_log.key.as_ref())?;
+        Ok(Compacted { moves, tombstone_size })
+    }
+}
+
+/// Encodes a checksummed log entry into a buffer, with the given value length:
+/// the value's length, -1 for tombstones, or -2 for batch headers. The key and
+/// value are encrypted if an encryption key is given.
+fn encode_entry(
+    buf: &mut Vec<u8>,
+    key: &[u8],
+    value_len: i32,
+    value: &[u8],
+    encryption_key: Option<&Key>,
+) {
+    let start = buf.len();
+    let mut lengths = [0u8; 8];
+    lengths[..4].copy_from_slice(&(key.len() as u32).to_be_bytes());
+    lengths[4..].copy_from_slice(&value_len.to_be_bytes());
+    buf.extend_from_slice(&[0; 4]); // checksum, filled in below
+    buf.extend_from_slice(&crc32fast::hash(&lengths).to_be_bytes());
+    buf.extend_from_slice(&lengths);
+    match encryption_key {
+        Some(encryption_key) => {
+            let aad = buf[start + 4..].to_vec();
+            let body = encryption_key.encrypt(&[key, value].concat(), &aad);
+            buf.extend_from_slice(&body);
+        }
+        None => {
+            buf.extend_from_slice(key);
+            buf.extend_from_slice(value);
+        }
+    }
+    let checksum = crc32fast::hash(&buf[start + 4..]);
+    buf[start..start + 4].copy_from_slice(&checksum.to_be_bytes());
+}
+
+/// Returns the length and decoded length of an encoded value.
+fn value_lens(value: &[u8]) -> Result<(u32, u32)> {
+    Ok((value.len() as u32, Compression::decoded_len(value)? as u32))
+}
+
+/// Encodes a hint entry for a log entry at the given position, with the value
+/// length and decoded value length, or None for tombstones.
+fn encode_hint(key: &[u8], pos: u64, value_lens: Option<(u32, u32)>) -> Vec<u8> {
+    let (value_len, decoded_len) = value_lens.map_or((-1, 0), |(l, d)| (l as i32, d));
+    let mut hint = Vec::with_capacity(HINT_HEADER_LEN + key.len());
+    hint.extend_from_slice(&(key.len() as u32).to_be_bytes());
+    hint.extend_from_slice(&value_len.to_be_bytes());
+    hint.extend_from_slice(&pos.to_be_bytes());
+    hint.extend_from_slice(&decoded_len.to_be_bytes());
+    hint.extend_from_slice(key);
+    hint
+}
+
+/// Writes a hint file with the given encoded hint entries, encrypted with the
+/// segment's key if any, and flushes it to disk.
+fn write_hints(path: &Path, hints: &[u8], key: Option<&Key>) -> Result<()> {
+    let mut buf = Vec::with_capacity(HEADER_LEN as usize + hints.len() + OVERHEAD + 4);
+    buf.extend_from_slice(&HINT_MAGIC);
+    buf.extend_from_slice(&VERSION.to_be_bytes());
+    buf.extend_from_slice(&key.map_or(0, |k| k.id()).to_be_bytes());
+    match key {
+        Some(key) => buf.extend_from_slice(&key.encrypt(hints, &buf.clone())),
+        None => buf.extend_from_slice(hints),
+    }
+    let checksum = crc32fast::hash(&buf);
+    buf.extend_from_slice(&checksum.to_be_bytes());
+
+    let mut file = std::fs::File::create(path)?;
+    file.write_all(&buf)?;
+    Ok(file.sync_all()?)
+}
+
+/// A decoded hint entry: the key, position, and value length and decoded value
+/// length (None for tombstones) of a log entry.
+type Hint = (Vec<u8>, u64, Option<(u32, u32)>);
+
+/// Reads a hint file, returning the hint entries for each log entry in the
+/// segment, decrypting them with the segment's key if any. Returns None if the
+/// hint file is missing, corrupt, or does not match the segment file.
+fn read_hints(path: &Path, log_size: u64, key: Option<&Key>) -> Result<Option<Vec<Hint>>> {
+    let buf = match std::fs::read(path) {
+        Ok(buf) => buf,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    let parse = || -> Option<Vec<Hint>> {
+        let (body, checksum) = buf.split_at(buf.len().checked_sub(4)?);
+        if crc32fast::hash(body) != u32::from_be_bytes(checksum.try_into().ok()?) {
+            return None;
+        }
+        let (header, body) = split_at(body, HEADER_LEN as usize)?;
+        if header[..4] != HINT_MAGIC
+            || header[4..8] != VERSION.to_be_bytes()
+            || header[8..] != key.map_or(0, |k| k.id()).to_be_bytes()
+        {
+            return None;
+        }
+        let body = match key {
+            Some(key) => key.decrypt(body, header).ok()?,
+            None => body.to_vec(),
+        };
+        let mut body = body.as_slice();
+        let trailer_len = key.map_or(0, |_| OVERHEAD as u64);
+
+        let mut entries = Vec::new();
+        let mut end = HEADER_LEN;
+        while !body.is_empty() {
+            let (header, rest) = split_at(body, HINT_HEADER_LEN)?;
+            let key_len = u32::from_be_bytes(header[0..4].try_into().ok()?) as usize;
+            let value_len = i32::from_be_bytes(header[4..8].try_into().ok()?);
+            let pos = u64::from_be_bytes(header[8..16].try_into().ok()?);
+            let decoded_len = u32::from_be_bytes(header[16..20].try_into().ok()?);
+            let value_lens = (value_len >= 0).then_some((value_len as u32, decoded_len));
+            let (key, rest) = split_at(rest, key_len)?;
+            end = pos + ENTRY_HEADER_LEN + key_len as u64 + value_len.max(0) as u64 + trailer_len;
+            entries.push((key.to_vec(), pos, value_lens));
+            body = rest;
+        }
+        (end == log_size).then_some(entries)
+    };
+
+    /// Splits a slice at the given index, or returns None if it's too short.
+    fn split_at(buf: &[u8], mid: usize) -> Option<(&[u8], &[u8])> {
+        (mid <= buf.len()).then(|| buf.split_at(mid))
+    }
+
+    let entries = parse();
+    if entries.is_none() {
+        log::warn!("Ignoring invalid hint file {}", path.display());
+    }
+    Ok(entries)
+}
+
+/// A BitCask append-only log segment file, containing a header with the magic
+/// bytes and format version, followed by a sequence of key/value entries
+/// encoded as follows:
 ///
+/// - CRC32 checksum of the rest of the entry as big-endian u32.
+/// - CRC32 checksum of the key and value lengths as big-endian u32.
 /// - Key length as big-endian u32.
 /// - Value length as big-endian i32, or -1 for tombstones.
 /// - Key as raw bytes (max 2 GB).
-/// - Value as raw bytes (max 2 GB).
+/// - Encoded value as raw bytes (max 2 GB).
+/// - For encrypted entries, the authentication tag and nonce.
 struct Log {
     /// Path to the log file.
     path: PathBuf,
     /// The opened file containing the log.
     file: std::fs::File,
+    /// The size of the log file.
+    size: u64,
+    /// Encoded hint entries for entries written to the log, used to write a
+    /// hint file when the segment is closed.
+    hints: Vec<u8>,
+    /// Whether the log has been written to since it was last synced.
+    dirty: bool,
+    /// The encryption key of the log, if encrypted.
+    key: Option<Key>,
 }
 
 impl Log {
-    /// Opens a log file, or creates one if it does not exist. Takes out an
-    /// exclusive lock on the file until it is closed, or errors if the lock is
-    /// already held.
-    fn new(path: PathBuf) -> Result<Self> {
+    /// Opens a log file, or creates one if it does not exist. New files are
+    /// encrypted with the current key of the given keyring, if any. Errors if
+    /// the file header is invalid, or the file is encrypted with a key that
+    /// isn't in the keyring.
+    fn new(path: PathBuf, keys: Option<&Keyring>) -> Result<Self> {
         if let Some(dir) = path.parent() {
             std::fs::create_dir_all(dir)?
         }
@@ -253,114 +1125,332 @@ impl Log {
             .create(true)
             .truncate(false)
             .open(&path)?;
-        file.try_lock_exclusive()?;
-        Ok(Self { path, file })
+        let mut log = Self { path, file, size: 0, hints: Vec::new(), dirty: false, key: None };
+        log.init_header(keys)?;
+        log.size = log.file.metadata()?.len();
+        Ok(log)
     }
 
-    /// Builds a keydir by scanning the log file. If an incomplete entry is
-    /// encountered, it is assumed to be caused by an incomplete write operation
-    /// and the remainder of the file is truncated.
-    fn build_keydir(&mut self) -> Result<KeyDir> {
-        let mut len_buf = [0u8; 4];
-        let mut keydir = KeyDir::new();
+    /// Returns the file header for the given key ID.
+    fn header(key_id: KeyID) -> Vec<u8> {
+        MAGIC.iter().copied().chain(VERSION.to_be_bytes()).chain(key_id.to_be_bytes()).collect()
+    }
+
+    /// Returns the ID of the log's encryption key, or 0 if unencrypted.
+    fn key_id(&self) -> KeyID {
+        self.key.as_ref().map_or(0, |key| key.id())
+    }
+
+    /// Returns the length of the encryption trailer of each entry.
+    fn trailer_len(&self) -> u64 {
+        self.key.as_ref().map_or(0, |_| OVERHEAD as u64)
+    }
+
+    /// Writes the file header to a new (or partially written) file, or checks
+    /// the header of an existing file and looks up its encryption key.
+    fn init_header(&mut self, keys: Option<&Keyring>) -> Result<()> {
+        let mut buf = Vec::with_capacity(HEADER_LEN as usize);
+        self.file.seek(SeekFrom::Start(0))?;
+        (&mut self.file).take(HEADER_LEN).read_to_end(&mut buf)?;
+
+        // A short file with a partial header is the result of an incomplete
+        // write when creating the file, so write the header again.
+        let key = keys.map(|keys| keys.current());
+        let header = Self::header(key.map_or(0, |key| key.id()));
+        let prefix_len = buf.len().min(MAGIC.len() + 4);
+        if (buf.len() as u64) < HEADER_LEN && header[..prefix_len] == buf[..prefix_len] {
+            self.file.set_len(0)?;
+            self.file.seek(SeekFrom::Start(0))?;
+            self.file.write_all(&header)?;
+            self.key = key.cloned();
+            return Ok(());
+        }
+        if buf.len() < MAGIC.len() || buf[..MAGIC.len()] != MAGIC {
+            return Err(Error::Internal(format!(
+                "Invalid header in {}, not a BitCask file",
+                self.path.display()
+            )));
+        }
+        let version = u32::from_be_bytes(buf[4..8.min(buf.len())].try_into().unwrap_or_default());
+        if buf.len() as u64 != HEADER_LEN || version != VERSION {
+            return Err(Error::Internal(format!(
+                "Unsupported BitCask version {} in {}, expected {}",
+                version,
+                self.path.display(),
+                VERSION
+            )));
+        }
+        self.key = match (u64::from_be_bytes(buf[8..].try_into()?), keys) {
+            (0, _) => None,
+            (id, Some(keys)) => Some(keys.get_or_err(id, &self.path)?.clone()),
+            (id, None) => {
+                return Err(Error::Config(format!(
+                    "{} is encrypted with key {:016x}, but no encryption key is configured",
+                    self.path.display(),
+                    id
+                )))
+            }
+        };
+        Ok(())
+    }
+
+    /// Scans the log entries in order, calling the given closure with the
+    /// position, key, and value (None for tombstones) of each entry, and
+    /// verifying entry checksums. Returns the end position of the last valid
+    /// entry: if the last entry in the file is incomplete or corrupt, it is
+    /// assumed to be caused by an incomplete write and the scan stops before
+    /// it. Corrupt entries elsewhere, including entries with corrupt lengths,
+    /// return an error. The entries of a batch
+    /// are only passed to the closure once the entire batch has been read,
+    /// and an incomplete batch at the end of the file is treated as an
+    /// incomplete write.
+    fn scan_entries(
+        &mut self,
+        mut f: impl FnMut(u64, &[u8], Option<&[u8]>) -> Result<()>,
+    ) -> Result<u64> {
+        let mut header = [0u8; ENTRY_HEADER_LEN as usize];
+        let trailer_len = self.trailer_len();
         let file_len = self.file.metadata()?.len();
         let mut r = BufReader::new(&mut self.file);
-        let mut pos = r.seek(SeekFrom::Start(0))?;
+        let mut pos = r.seek(SeekFrom::Start(HEADER_LEN))?;
+        // The entries read so far of the current batch, along with the batch
+        // position and the number of remaining entries.
+        let mut batch: Vec<(u64, Vec<u8>, Option<Vec<u8>>)> = Vec::new();
+        let mut batch_pos = 0;
+        let mut batch_remaining = 0;
 
         while pos < file_len {
-            // Read the next entry from the file, returning the key, value
-            // position, and value length or None for tombstones.
-            let result = || -> std::result::Result<(Vec<u8>, u64, Option<u32>), std::io::Error> {
-                r.read_exact(&mut len_buf)?;
-                let key_len = u32::from_be_bytes(len_buf);
-                r.read_exact(&mut len_buf)?;
-                let value_len_or_tombstone = match i32::from_be_bytes(len_buf) {
-                    l if l >= 0 => Some(l as u32),
-                    _ => None, // -1 for tombstones
-                };
-                let value_pos = pos + 4 + 4 + key_len as u64;
-
-                let mut key = vec![0; key_len as usize];
-                r.read_exact(&mut key)?;
-
-                if let Some(value_len) = value_len_or_tombstone {
-                    if value_pos + value_len as u64 > file_len {
-                        return Err(std::io::Error::new(
-                            std::io::ErrorKind::UnexpectedEof,
-                            "value extends beyond end of file",
-                        ));
-                    }
-                    r.seek_relative(value_len as i64)?; // avoids discarding buffer
+            // Read the next entry from the file, returning the key length,
+            // value length (-1 for tombstones, -2 for batch headers), the key
+            // and value, and whether the checksum matched.
+            let result = || -> std::result::Result<_, std::io::Error> {
+                r.read_exact(&mut header)?;
+                let checksum = u32::from_be_bytes(header[0..4].try_into().unwrap());
+                let lengths_checksum = u32::from_be_bytes(header[4..8].try_into().unwrap());
+                if crc32fast::hash(&header[8..]) != lengths_checksum {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "lengths checksum mismatch",
+                    ));
+                }
+                let key_len = u32::from_be_bytes(header[8..12].try_into().unwrap());
+                let value_len = i32::from_be_bytes(header[12..16].try_into().unwrap());
+                let end =
+                    pos + ENTRY_HEADER_LEN + key_len as u64 + value_len.max(0) as u64 + trailer_len;
+                if end > file_len {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "entry extends beyond end of file",
+                    ));
                 }
 
-                Ok((key, value_pos, value_len_or_tombstone))
+                let mut body = vec![0; (end - pos - ENTRY_HEADER_LEN) as usize];
+                r.read_exact(&mut body)?;
+                let mut hasher = crc32fast::Hasher::new();
+                hasher.update(&header[4..]);
+                hasher.update(&body);
+                let valid = hasher.finalize() == checksum;
+                Ok((key_len as usize, value_len, body, end, valid))
             }();
 
+            // Decrypt valid entries. The checksum has already detected torn
+            // writes, so a decryption failure means the entry was modified.
+            let result = match (result, &self.key) {
+                (Ok((key_len, value_len, body, end, true)), Some(key)) => {
+                    let body = key.decrypt(&body, &header[4..]).map_err(|_| {
+                        Error::Internal(format!(
+                            "Failed to decrypt entry at offset {} in {}",
+                            pos,
+                            self.path.display()
+                        ))
+                    })?;
+                    Ok((key_len, value_len, body, end, true))
+                }
+                (result, _) => result,
+            };
+
             match result {
-                // Populate the keydir with the entry, or remove it on tombstones.
-                Ok((key, value_pos, Some(value_len))) => {
-                    keydir.insert(key, (value_pos, value_len));
-                    pos = value_pos + value_len as u64;
+                Ok((key_len, BATCH, body, end, true)) => {
+                    if batch_remaining > 0 {
+                        return Err(Error::Internal(format!(
+                            "Nested batch at offset {} in {}",
+                            pos,
+                            self.path.display()
+                        )));
+                    }
+                    batch_remaining = u32::from_be_bytes(body[..key_len].try_into()?);
+                    batch_pos = pos;
+                    pos = end;
                 }
-                Ok((key, value_pos, None)) => {
-                    keydir.remove(&key);
-                    pos = value_pos;
+                Ok((key_len, value_len, mut body, end, true)) if batch_remaining > 0 => {
+                    let value = body.split_off(key_len);
+                    batch.push((pos, body, (value_len >= 0).then_some(value)));
+                    batch_remaining -= 1;
+                    if batch_remaining == 0 {
+                        for (pos, key, value) in batch.drain(..) {
+                            f(pos, &key, value.as_deref())?;
+                        }
+                    }
+                    pos = end;
                 }
-                // If an incomplete entry was found at the end of the file, assume an
-                // incomplete write and truncate the file.
-                Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
-                    log::error!("Found incomplete entry at offset {}, truncating file", pos);
-                    self.file.set_len(pos)?;
-                    break;
+                Ok((key_len, value_len, body, end, true)) => {
+                    let (key, value) = body.split_at(key_len);
+                    f(pos, key, (value_len >= 0).then_some(value))?;
+                    pos = end;
+                }
+                // If the last entry in the file is incomplete or corrupt,
+                // assume an incomplete write. The lengths are checksummed, so
+                // an entry that extends beyond the end of the file is
+                // incomplete rather than corrupt.
+                Ok((_, _, _, end, false)) if end == file_len => break,
+                Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Ok((_, _, _, _, false)) => {
+                    return Err(Error::Internal(format!(
+                        "Checksum mismatch for entry at offset {} in {}",
+                        pos,
+                        self.path.display()
+                    )))
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::InvalidData => {
+                    return Err(Error::Internal(format!(
+                        "Checksum mismatch for entry at offset {} in {}",
+                        pos,
+                        self.path.display()
+                    )))
                 }
                 Err(err) => return Err(err.into()),
             }
         }
 
-        Ok(keydir)
+        if batch_remaining > 0 {
+            return Ok(batch_pos);
+        }
+        Ok(pos)
+    }
+
+    /// Truncates the log file to the given size.
+    fn truncate(&mut self, size: u64) -> Result<()> {
+        self.file.set_len(size)?;
+        self.size = size;
+        Ok(())
+    }
+
+    /// Flushes the log file to disk, if it has been written to.
+    fn sync(&mut self) -> Result<()> {
+        if self.dirty {
+            self.file.sync_all()?;
+            self.dirty = false;
+        }
+        Ok(())
     }
 
-    /// Reads a value from the log file.
-    fn read_value(&mut self, value_pos: u64, value_len: u32) -> Result<Vec<u8>> {
-        let mut value = vec![0; value_len as usize];
-        self.file.seek(SeekFrom::Start(value_pos))?;
-        self.file.read_exact(&mut value)?;
-        Ok(value)
+    /// Reads an encoded value from the log file, verifying the entry checksum
+    /// and decrypting it if necessary.
+    fn read_value(&mut self, key: &[u8], value_pos: u64, value_len: u32) -> Result<Vec<u8>> {
+        let pos = value_pos - key.len() as u64 - ENTRY_HEADER_LEN;
+        let mut entry = vec![0; (value_pos + value_len as u64 + self.trailer_len() - pos) as usize];
+        self.file.seek(SeekFrom::Start(pos))?;
+        self.file.read_exact(&mut entry)?;
+
+        let checksum = u32::from_be_bytes(entry[0..4].try_into().unwrap());
+        if crc32fast::hash(&entry[4..]) != checksum {
+            return Err(Error::Internal(format!(
+                "Checksum mismatch for entry at offset {} in {}",
+                pos,
+                self.path.display()
+            )));
+        }
+        if let Some(encryption_key) = &self.key {
+            let (header, body) = entry.split_at(ENTRY_HEADER_LEN as usize);
+            let mut body = encryption_key.decrypt(body, &header[4..]).map_err(|_| {
+                Error::Internal(format!(
+                    "Failed to decrypt entry at offset {} in {}",
+                    pos,
+                    self.path.display()
+                ))
+            })?;
+            return Ok(body.split_off(key.len()));
+        }
+        Ok(entry.split_off((ENTRY_HEADER_LEN as usize) + key.len()))
     }
 
-    /// Appends a key/value entry to the log file, using a None value for
-    /// tombstones. It returns the position and length of the entry.
+    /// Appends a key/value entry to the log file, with an encoded value or None
+    /// for tombstones. It returns the position and length of the entry.
     fn write_entry(&mut self, key: &[u8], value: Option<&[u8]>) -> Result<(u64, u32)> {
-        let key_len = key.len() as u32;
-        let value_len = value.map_or(0, |v| v.len() as u32);
-        let value_len_or_tombstone = value.map_or(-1, |v| v.len() as i32);
-        let len = 4 + 4 + key_len + value_len;
+        let value_lens = value.map(value_lens).transpose()?;
+        let mut entry = Vec::new();
+        let value_len = value.map_or(-1, |v| v.len() as i32);
+        encode_entry(&mut entry, key, value_len, value.unwrap_or(&[]), self.key.as_ref());
+        let len = entry.len() as u32;
 
         let pos = self.file.seek(SeekFrom::End(0))?;
-        let mut w = BufWriter::with_capacity(len as usize, &mut self.file);
-        w.write_all(&key_len.to_be_bytes())?;
-        w.write_all(&value_len_or_tombstone.to_be_bytes())?;
-        w.write_all(key)?;
-        if let Some(value) = value {
-            w.write_all(value)?;
-        }
-        w.flush()?;
+        self.file.write_all(&entry)?;
+        self.size = pos + len as u64;
+        self.dirty = true;
+        self.hints.extend(encode_hint(key, pos, value_lens));
 
         Ok((pos, len))
     }
 
+    /// Appends a batch of key/value entries to the log file, with encoded values
+    /// or None for tombstones. Multiple entries are preceded by a batch header entry,
+    /// and written with a single write. Returns the position of each entry.
+    fn write_batch(&mut self, batch: &[(Vec<u8>, Option<Vec<u8>>)]) -> Result<Vec<u64>> {
+        match batch {
+            [] => return Ok(Vec::new()),
+            [(key, value)] => return Ok(vec![self.write_entry(key, value.as_deref())?.0]),
+            _ => {}
+        }
+        let value_lens = batch
+            .iter()
+            .map(|(_, value)| value.as_deref().map(value_lens).transpose())
+            .collect::<Result<Vec<_>>>()?;
+        let mut buf = Vec::new();
+        let count = (batch.len() as u32).to_be_bytes();
+        encode_entry(&mut buf, &count, BATCH, &[], self.key.as_ref());
+        let mut offsets = Vec::with_capacity(batch.len());
+        for (key, value) in batch {
+            offsets.push(buf.len() as u64);
+            let value_len = value.as_ref().map_or(-1, |v| v.len() as i32);
+            let value = value.as_deref().unwrap_or(&[]);
+            encode_entry(&mut buf, key, value_len, value, self.key.as_ref());
+        }
+
+        let pos = self.file.seek(SeekFrom::End(0))?;
+        self.file.write_all(&buf)?;
+        self.size = pos + buf.len() as u64;
+        self.dirty = true;
+        let positions: Vec<u64> = offsets.into_iter().map(|offset| pos + offset).collect();
+        for (((key, _), value_lens), pos) in batch.iter().zip(value_lens).zip(&positions) {
+            self.hints.extend(encode_hint(key, *pos, value_lens));
+        }
+        Ok(positions)
+    }
+
     #[cfg(test)]
     /// Prints the entire log file to the given writer in human-readable form.
     fn print<W: Write>(&mut self, w: &mut W) -> Result<()> {
         let mut len_buf = [0u8; 4];
+        let trailer_len = self.trailer_len();
         let file_len = self.file.metadata()?.len();
         let mut r = BufReader::new(&mut self.file);
-        let mut pos = r.seek(SeekFrom::Start(0))?;
-        let mut idx = 0;
+        r.seek(SeekFrom::Start(0))?;
+
+        let mut header = [0u8; HEADER_LEN as usize];
+        r.read_exact(&mut header)?;
+        writeln!(w, "header = {:x?}\n", header)?;
 
+        let mut pos = HEADER_LEN;
+        let mut idx = 0;
         while pos < file_len {
             writeln!(w, "entry = {}, offset {}", idx, pos)?;
 
+            r.read_exact(&mut len_buf)?;
+            writeln!(w, "crc   = {:x?}", len_buf)?;
+
+            r.read_exact(&mut len_buf)?;
+            writeln!(w, "lcrc  = {:x?}", len_buf)?;
+
             r.read_exact(&mut len_buf)?;
             let key_len = u32::from_be_bytes(len_buf);
             writeln!(w, "klen  = {} {:x?}", key_len, len_buf)?;
@@ -381,7 +1471,9 @@ impl Log {
             let mut value = vec![0; value_len as usize];
             r.read_exact(&mut value)?;
             write!(w, "value = ")?;
-            if value_len_or_tombstone < 0 {
+            if value_len_or_tombstone == BATCH {
+                write!(w, "batch ")?;
+            } else if value_len_or_tombstone < 0 {
                 write!(w, "tombstone ")?;
             } else if let Ok(str) = std::str::from_utf8(&value) {
                 if str.chars().all(|c| !c.is_control()) {
@@ -390,7 +1482,10 @@ impl Log {
             }
             write!(w, "{:x?}\n\n", value)?;
 
-            pos += 4 + 4 + key_len as u64 + value_len as u64;
+            // Skip the encryption trailer, if any. Encrypted keys and values
+            // are printed as ciphertext.
+            r.seek_relative(trailer_len as i64)?;
+            pos += ENTRY_HEADER_LEN + key_len as u64 + value_len as u64 + trailer_len;
             idx += 1;
         }
         Ok(())
@@ -464,7 +1559,7 @@ mod tests {
         setup_log(&mut s)?;
 
         let mut mint = goldenfile::Mint::new(GOLDEN_DIR);
-        s.log.print(&mut mint.new_goldenfile("log")?)?;
+        s.active().1.log.print(&mut mint.new_goldenfile("log")?)?;
         Ok(())
     }
 
@@ -498,14 +1593,15 @@ mod tests {
 
         // Dump the initial log file.
         let mut mint = goldenfile::Mint::new(GOLDEN_DIR);
-        s.log.print(&mut mint.new_goldenfile("compact-before")?)?;
+        s.active().1.log.print(&mut mint.new_goldenfile("compact-before")?)?;
         let expect = s.scan(..).collect::<Result<Vec<_>>>()?;
 
-        // Compact the log file and assert the new log file contents.
+        // Compact the log file and assert the new log file contents. The
+        // active segment is closed, and a new empty one is started.
         s.compact()?;
-        assert_eq!(path, s.log.path);
+        assert_eq!(s.segments.keys().copied().collect::<Vec<_>>(), vec![1, 2]);
         assert_eq!(expect, s.scan(..).collect::<Result<Vec<_>>>()?,);
-        s.log.print(&mut mint.new_goldenfile("compact-after")?)?;
+        s.segments.get_mut(&1).unwrap().log.print(&mut mint.new_goldenfile("compact-after")?)?;
 
         // Reopen the log file and assert that the contents are the same.
         drop(s);
@@ -515,6 +1611,20 @@ mod tests {
         Ok(())
     }
 
+    /// Copies the files in a database directory to a different directory,
+    /// replacing any existing files.
+    fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+        if to.exists() {
+            std::fs::remove_dir_all(to)?;
+        }
+        std::fs::create_dir_all(to)?;
+        for entry in std::fs::read_dir(from)? {
+            let entry = entry?;
+            std::fs::copy(entry.path(), to.join(entry.file_name()))?;
+        }
+        Ok(())
+    }
+
     #[test]
     /// Tests that new_compact() will automatically compact the file when appropriate.
     fn new_compact() -> Result<()> {
@@ -523,7 +1633,7 @@ mod tests {
         let path = dir.path().join("orig");
         let compactpath = dir.path().join("compact");
 
-        let mut s = BitCask::new_compact(path.clone(), 0.2, 0)?;
+        let mut s = BitCask::new_compact(path.clone(), 0.2, 0, None)?;
         setup_log(&mut s)?;
         let status = s.status()?;
         let garbage_ratio = status.garbage_disk_size as f64 / status.total_disk_size as f64;
@@ -545,12 +1655,15 @@ mod tests {
             (0.0, garbage_size + 1, false),
         ];
         for (min_ratio, min_size, expect_compact) in cases.into_iter() {
-            std::fs::copy(&path, &compactpath)?;
-            let mut s = BitCask::new_compact(compactpath.clone(), min_ratio, min_size)?;
+            copy_dir(&path, &compactpath)?;
+            let mut s = BitCask::new_compact(compactpath.clone(), min_ratio, min_size, None)?;
+            s.wait()?;
             let new_status = s.status()?;
-            assert_eq!(new_status.live_disk_size, status.live_disk_size);
             if expect_compact {
-                assert_eq!(new_status.total_disk_size, status.live_disk_size);
+                // The compacted segment is retained, along with a new empty
+                // active segment.
+                assert_eq!(new_status.live_disk_size, status.live_disk_size + HEADER_LEN);
+                assert_eq!(new_status.total_disk_size, new_status.live_disk_size);
                 assert_eq!(new_status.garbage_disk_size, 0);
             } else {
                 assert_eq!(new_status, status);
@@ -560,6 +1673,53 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    /// Tests that values are compressed when enabled, that compressed and
+    /// uncompressed values can be mixed, and that they are read back after
+    /// reopening and compacting.
+    fn compression() -> Result<()> {
+        let path = tempdir::TempDir::new("radb")?.path().join("radb");
+        let mut s = BitCask::new(path.clone())?;
+        let value = b"foo bar ".repeat(100);
+        s.set(b"a", value.clone())?;
+        assert_eq!(s.status()?.compression_ratio, 1.0);
+
+        s.set_compression(Compression::Lz);
+        s.set(b"b", value.clone())?;
+        s.set(b"c", vec![0x01, 0x02, 0x03])?;
+        let mut batch = WriteBatch::new();
+        batch.set(b"d", value.clone());
+        batch.delete(b"a");
+        s.write_batch(batch)?;
+
+        let expect = vec![
+            (b"b".to_vec(), value.clone()),
+            (b"c".to_vec(), vec![0x01, 0x02, 0x03]),
+            (b"d".to_vec(), value.clone()),
+        ];
+        assert_eq!(s.scan(..).collect::<Result<Vec<_>>>()?, expect);
+        let status = s.status()?;
+        assert_eq!(status.size, 3 + 2 * value.len() as u64 + 3);
+        assert!(status.compression_ratio > 10.0, "ratio {}", status.compression_ratio);
+
+        // Reopen, which scans the active segment, then compact and reopen,
+        // which reads the compacted segment's hint file.
+        drop(s);
+        let mut s = BitCask::new(path.clone())?;
+        assert_eq!(s.scan(..).collect::<Result<Vec<_>>>()?, expect);
+        assert_eq!(s.status()?, status);
+
+        s.compact()?;
+        drop(s);
+        let mut s = BitCask::new(path)?;
+        assert_eq!(s.scan(..).collect::<Result<Vec<_>>>()?, expect);
+        let compacted = s.status()?;
+        assert_eq!(compacted.size, status.size);
+        assert_eq!(compacted.compression_ratio, status.compression_ratio);
+        assert_eq!(compacted.garbage_disk_size, 0);
+        Ok(())
+    }
+
     #[test]
     /// Tests that exclusive locks are taken out on log files, released when the
     /// database is closed, and that an error is returned if a lock is already
@@ -581,22 +1741,26 @@ mod tests {
     fn recovery() -> Result<()> {
         // Create an initial log file with a few entries.
         let dir = tempdir::TempDir::new("radb")?;
-        let path = dir.path().join("complete");
-        let truncpath = dir.path().join("truncated");
+        let path = dir.path().join("complete").join("0000000001.log");
+        let truncdir = dir.path().join("truncated");
+        let truncpath = truncdir.join("0000000001.log");
+        std::fs::create_dir_all(&truncdir)?;
 
-        let mut log = Log::new(path.clone())?;
+        let mut log = Log::new(path.clone(), None)?;
         let mut ends = vec![];
 
-        let (pos, len) = log.write_entry("deleted".as_bytes(), Some(&[1, 2, 3]))?;
+        let (pos, len) =
+            log.write_entry("deleted".as_bytes(), Some(&Compression::None.encode(&[1, 2, 3])))?;
         ends.push(pos + len as u64);
 
         let (pos, len) = log.write_entry("deleted".as_bytes(), None)?;
         ends.push(pos + len as u64);
 
-        let (pos, len) = log.write_entry(&[], Some(&[]))?;
+        let (pos, len) = log.write_entry(&[], Some(&Compression::None.encode(&[])))?;
         ends.push(pos + len as u64);
 
-        let (pos, len) = log.write_entry("key".as_bytes(), Some(&[1, 2, 3, 4, 5]))?;
+        let (pos, len) =
+            log.write_entry("key".as_bytes(), Some(&Compression::None.encode(&[1, 2, 3, 4, 5])))?;
         ends.push(pos + len as u64);
 
         drop(log);
@@ -624,18 +1788,462 @@ mod tests {
                 expect.push((b"key".to_vec(), vec![1, 2, 3, 4, 5]))
             }
 
-            let mut s = BitCask::new(truncpath.clone())?;
+            let mut s = BitCask::new(truncdir.clone())?;
             assert_eq!(expect, s.scan(..).collect::<Result<Vec<_>>>()?);
         }
 
         Ok(())
     }
 
+    #[test]
+    /// Tests that a write batch is recovered either completely or not at all
+    /// when the log is truncated.
+    fn batch_recovery() -> Result<()> {
+        let dir = tempdir::TempDir::new("radb")?;
+        let path = dir.path().join("complete");
+        let truncdir = dir.path().join("truncated");
+        let truncpath = truncdir.join("0000000001.log");
+        std::fs::create_dir_all(&truncdir)?;
+
+        let mut s = BitCask::new(path.clone())?;
+        s.set(b"a", vec![1])?;
+        let start = s.active().1.log.size;
+        let mut batch = WriteBatch::new();
+        batch.set(b"b", vec![2]);
+        batch.delete(b"a");
+        batch.set(b"c", vec![3]);
+        s.write_batch(batch)?;
+        let end = s.active().1.log.size;
+        s.set(b"d", vec![4])?;
+        drop(s);
+
+        for pos in start..=end {
+            std::fs::copy(path.join("0000000001.log"), &truncpath)?;
+            let f = std::fs::OpenOptions::new().write(true).open(&truncpath)?;
+            f.set_len(pos)?;
+            drop(f);
+
+            let expect = match pos {
+                pos if pos < end => vec![(b"a".to_vec(), vec![1])],
+                _ => vec![(b"b".to_vec(), vec![2]), (b"c".to_vec(), vec![3])],
+            };
+            let mut s = BitCask::new(truncdir.clone())?;
+            assert_eq!(expect, s.scan(..).collect::<Result<Vec<_>>>()?);
+            assert_eq!(s.active().1.log.size, if pos < end { start } else { end });
+        }
+
+        // The batch is also recovered from a closed segment's hint file, and
+        // retained by compaction.
+        let mut s = BitCask::new(path.clone())?;
+        s.close_active()?;
+        s.compact()?;
+        drop(s);
+        let mut s = BitCask::new(path)?;
+        assert_eq!(
+            s.scan(..).collect::<Result<Vec<_>>>()?,
+            vec![(b"b".to_vec(), vec![2]), (b"c".to_vec(), vec![3]), (b"d".to_vec(), vec![4])]
+        );
+        Ok(())
+    }
+
+    /// Writes a database with a single log segment containing a few entries,
+    /// returning the database path, the segment path, and the position of
+    /// each entry.
+    fn setup_corrupt(dir: &tempdir::TempDir) -> Result<(PathBuf, PathBuf, Vec<u64>)> {
+        let path = dir.path().join("radb");
+        let log_path = path.join("0000000001.log");
+        let mut log = Log::new(log_path.clone(), None)?;
+        let mut positions = vec![];
+        for (key, value) in [("a", Some(&[1, 2, 3][..])), ("b", Some(&[4, 5][..])), ("c", None)] {
+            let value = value.map(|v| Compression::None.encode(v));
+            let (pos, _) = log.write_entry(key.as_bytes(), value.as_deref())?;
+            positions.push(pos);
+        }
+        Ok((path, log_path, positions))
+    }
+
+    /// Flips all bits of the byte at the given file position.
+    fn corrupt_byte(path: &PathBuf, pos: u64) -> Result<()> {
+        let mut f = std::fs::OpenOptions::new().read(true).write(true).open(path)?;
+        let mut byte = [0u8; 1];
+        f.seek(SeekFrom::Start(pos))?;
+        f.read_exact(&mut byte)?;
+        f.seek(SeekFrom::Start(pos))?;
+        f.write_all(&[!byte[0]])?;
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that a corrupt entry at the end of the log file is assumed to be
+    /// a torn write and discarded.
+    fn corrupt_tail() -> Result<()> {
+        let dir = tempdir::TempDir::new("radb")?;
+        let (path, log_path, positions) = setup_corrupt(&dir)?;
+        corrupt_byte(&log_path, positions[2] + ENTRY_HEADER_LEN)?;
+
+        let mut s = BitCask::new(path)?;
+        assert_eq!(
+            s.scan(..).collect::<Result<Vec<_>>>()?,
+            vec![(b"a".to_vec(), vec![1, 2, 3]), (b"b".to_vec(), vec![4, 5])]
+        );
+        assert_eq!(std::fs::metadata(&log_path)?.len(), positions[2]);
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that corruption in the middle of the log file is an error, both
+    /// in the checksums, lengths, key, and value of an entry.
+    fn corrupt_middle() -> Result<()> {
+        for offset in [0, 4, 8, 11, 12, 15, ENTRY_HEADER_LEN, ENTRY_HEADER_LEN + 1] {
+            let dir = tempdir::TempDir::new("radb")?;
+            let (path, log_path, positions) = setup_corrupt(&dir)?;
+            corrupt_byte(&log_path, positions[1] + offset)?;
+
+            match BitCask::new(path) {
+                Err(Error::Internal(msg)) => assert!(msg.starts_with(&format!(
+                    "Checksum mismatch for entry at offset {}",
+                    positions[1]
+                ))),
+                Err(err) => panic!("unexpected error {:?}", err),
+                Ok(_) => panic!("expected corruption error at offset {}", offset),
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that an incomplete entry at the end of an immutable segment is an
+    /// error, and the segment isn't truncated.
+    fn corrupt_closed() -> Result<()> {
+        let dir = tempdir::TempDir::new("radb")?;
+        let (path, log_path, _) = setup_corrupt(&dir)?;
+        Log::new(path.join("0000000002.log"), None)?;
+        let size = std::fs::metadata(&log_path)?.len();
+        std::fs::OpenOptions::new().write(true).open(&log_path)?.set_len(size - 1)?;
+
+        match BitCask::new(path) {
+            Err(Error::Internal(msg)) => {
+                assert!(msg.starts_with("Incomplete or corrupt entry"), "{}", msg)
+            }
+            Err(err) => panic!("unexpected error {:?}", err),
+            Ok(_) => panic!("expected corruption error"),
+        }
+        assert_eq!(std::fs::metadata(&log_path)?.len(), size - 1);
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that corruption is detected when reading a value.
+    fn corrupt_read() -> Result<()> {
+        let dir = tempdir::TempDir::new("radb")?;
+        let (path, log_path, positions) = setup_corrupt(&dir)?;
+        let mut s = BitCask::new(path)?;
+        corrupt_byte(&log_path, positions[0] + ENTRY_HEADER_LEN + 1)?;
+
+        assert!(matches!(s.get(b"a"), Err(Error::Internal(_))));
+        assert_eq!(s.get(b"b")?, Some(vec![4, 5]));
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that a legacy single-file database is imported as a segment, and
+    /// that an interrupted import is retried from the renamed legacy file.
+    fn legacy() -> Result<()> {
+        let mut legacy = Vec::new();
+        for (key, value) in [("a", Some(&[1][..])), ("b", Some(&[2, 3][..])), ("a", None)] {
+            legacy.extend_from_slice(&(key.len() as u32).to_be_bytes());
+            legacy.extend_from_slice(&value.map_or(-1, |v| v.len() as i32).to_be_bytes());
+            legacy.extend_from_slice(key.as_bytes());
+            legacy.extend_from_slice(value.unwrap_or_default());
+        }
+        legacy.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 9]); // incomplete entry
+
+        let dir = tempdir::TempDir::new("radb")?;
+        let path = dir.path().join("radb");
+        std::fs::write(&path, &legacy)?;
+        let mut s = BitCask::new(path.clone())?;
+        assert_eq!(s.scan(..).collect::<Result<Vec<_>>>()?, vec![(b"b".to_vec(), vec![2, 3])]);
+        assert!(path.is_dir());
+        assert_eq!(std::fs::read(dir.path().join("radb.legacy"))?, legacy);
+        drop(s);
+
+        // An import interrupted after renaming the legacy file is retried.
+        std::fs::remove_dir_all(&path)?;
+        std::fs::create_dir_all(dir.path().join("radb.import"))?;
+        let mut s = BitCask::new(path)?;
+        assert_eq!(s.scan(..).collect::<Result<Vec<_>>>()?, vec![(b"b".to_vec(), vec![2, 3])]);
+        assert!(!dir.path().join("radb.import").exists());
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that an invalid or unsupported file header is an error.
+    fn corrupt_header() -> Result<()> {
+        for (pos, expect) in [(0, "Invalid header"), (7, "Unsupported BitCask version")] {
+            let dir = tempdir::TempDir::new("radb")?;
+            let (path, log_path, _) = setup_corrupt(&dir)?;
+            corrupt_byte(&log_path, pos)?;
+
+            match BitCask::new(path) {
+                Err(Error::Internal(msg)) => assert!(msg.starts_with(expect), "{}", msg),
+                Err(err) => panic!("unexpected error {:?}", err),
+                Ok(_) => panic!("expected header error"),
+            }
+        }
+
+        // Files that aren't a BitCask log at all are rejected too.
+        let dir = tempdir::TempDir::new("radb")?;
+        let path = dir.path().join("radb");
+        std::fs::create_dir_all(&path)?;
+        std::fs::write(path.join("0000000001.log"), b"foo")?;
+        assert!(matches!(BitCask::new(path), Err(Error::Internal(_))));
+        Ok(())
+    }
+
+    /// Applies a deterministic pseudo-random sequence of writes to the engine
+    /// and a model, with some large values to fill up segments.
+    fn write_random(s: &mut BitCask, model: &mut BTreeMap<Vec<u8>, Vec<u8>>, n: u64) -> Result<()> {
+        let mut rand: u64 = 1;
+        for i in 0..n {
+            rand = rand.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let key = format!("key{}", (rand >> 33) % 20).into_bytes();
+            if (rand >> 40) & 3 == 0 {
+                s.delete(&key)?;
+                model.remove(&key);
+            } else {
+                let value = vec![i as u8; (rand >> 50) as usize % 64];
+                s.set(&key, value.clone())?;
+                model.insert(key, value);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that the log is split into segments with hint files, and that
+    /// the database can be reopened from them, also when hint files are
+    /// missing or corrupt.
+    fn segments() -> Result<()> {
+        let dir = tempdir::TempDir::new("radb")?;
+        let path = dir.path().join("radb");
+        let mut s = BitCask::new(path.clone())?;
+        s.set_max_segment_size(512);
+        let mut model = BTreeMap::new();
+        write_random(&mut s, &mut model, 200)?;
+        s.wait()?;
+
+        // All segments except the active one are capped and have hint files.
+        let ids: Vec<_> = s.segments.keys().copied().collect();
+        assert!(ids.len() > 3, "expected multiple segments, got {:?}", ids);
+        for id in &ids[..ids.len() - 1] {
+            assert!(s.segments[id].log.size < 512 + 100);
+            assert!(s.segment_path(*id, "hint").exists());
+        }
+        assert!(!s.segment_path(*ids.last().unwrap(), "hint").exists());
+        let status = s.status()?;
+        drop(s);
+
+        // Reopening with hint files yields the same data and status.
+        let mut s = BitCask::new(path.clone())?;
+        assert_eq!(
+            s.scan(..).collect::<Result<Vec<_>>>()?,
+            model.clone().into_iter().collect::<Vec<_>>()
+        );
+        assert_eq!(s.status()?, status);
+        drop(s);
+
+        // Corrupt and remove hint files, which should be ignored and
+        // rewritten when reopening.
+        std::fs::write(path.join(format!("{:010}.hint", ids[0])), b"foo")?;
+        std::fs::remove_file(path.join(format!("{:010}.hint", ids[1])))?;
+        let mut s = BitCask::new(path.clone())?;
+        assert_eq!(s.scan(..).collect::<Result<Vec<_>>>()?, model.into_iter().collect::<Vec<_>>());
+        assert_eq!(s.status()?, status);
+        s.wait()?;
+        assert!(read_hints(&s.segment_path(ids[0], "hint"), s.segments[&ids[0]].log.size, None)?
+            .is_some());
+        assert!(read_hints(&s.segment_path(ids[1], "hint"), s.segments[&ids[1]].log.size, None)?
+            .is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that segments are compacted in the background while writes
+    /// continue, and that compacted segments retain the right data when
+    /// reopened, including tombstones for values in older segments.
+    fn compact_background() -> Result<()> {
+        let dir = tempdir::TempDir::new("radb")?;
+        let path = dir.path().join("radb");
+        let mut s = BitCask::new_compact(path.clone(), 0.2, 0, None)?;
+        s.set_max_segment_size(512);
+        let mut model = BTreeMap::new();
+        write_random(&mut s, &mut model, 1000)?;
+        let expect: Vec<_> = model.into_iter().collect();
+        assert_eq!(s.scan(..).collect::<Result<Vec<_>>>()?, expect);
+
+        // Once background compaction completes, no segments should exceed
+        // the garbage threshold, and the data should be unchanged.
+        s.wait()?;
+        let ids: Vec<_> = s.segments.keys().copied().collect();
+        for id in &ids[..ids.len() - 1] {
+            assert_eq!(s.should_compact(*id), None, "segment {} not compacted", id);
+        }
+        let status = s.status()?;
+        assert!(status.garbage_disk_size < status.total_disk_size / 2, "{:?}", status);
+        assert_eq!(s.scan(..).collect::<Result<Vec<_>>>()?, expect);
+        drop(s);
+
+        // Reopening yields the same data.
+        let mut s = BitCask::new(path)?;
+        assert_eq!(s.scan(..).collect::<Result<Vec<_>>>()?, expect);
+        assert_eq!(s.status()?, status);
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that encrypted databases don't contain plaintext keys or values
+    /// in log or hint files, and that they can only be opened with the right
+    /// key.
+    fn encryption() -> Result<()> {
+        let dir = tempdir::TempDir::new("radb")?;
+        let path = dir.path().join("radb");
+        let keys = Keyring::new(Key::generate(), Vec::new());
+        let mut s = BitCask::new_encrypted(path.clone(), keys.clone())?;
+        s.set_max_segment_size(256);
+        let mut model = BTreeMap::new();
+        for i in 0..20 {
+            let (key, value) = (format!("secret key {}", i), format!("secret value {}", i));
+            s.set(key.as_bytes(), value.as_bytes().to_vec())?;
+            model.insert(key.into_bytes(), value.into_bytes());
+        }
+        s.delete(b"secret key 0")?;
+        model.remove(b"secret key 0".as_slice());
+        let mut batch = WriteBatch::new();
+        batch.set(b"secret key 1", b"secret batch".to_vec());
+        batch.delete(b"secret key 2");
+        s.write_batch(batch)?;
+        model.insert(b"secret key 1".to_vec(), b"secret batch".to_vec());
+        model.remove(b"secret key 2".as_slice());
+
+        let expect: Vec<_> = model.into_iter().collect();
+        assert_eq!(s.scan(..).collect::<Result<Vec<_>>>()?, expect);
+        s.wait()?;
+        assert!(s.segments.len() > 2);
+        drop(s);
+
+        // No files contain plaintext.
+        for entry in std::fs::read_dir(&path)? {
+            let file = entry?.path();
+            let data = std::fs::read(&file)?;
+            assert!(!data.windows(6).any(|w| w == b"secret"), "plaintext in {}", file.display());
+        }
+
+        // The database can be reopened with the key, both via hint files and
+        // by scanning segments.
+        let mut s = BitCask::new_encrypted(path.clone(), keys.clone())?;
+        assert_eq!(s.scan(..).collect::<Result<Vec<_>>>()?, expect);
+        drop(s);
+        for entry in std::fs::read_dir(&path)? {
+            let file = entry?.path();
+            if file.extension() == Some("hint".as_ref()) {
+                std::fs::remove_file(file)?;
+            }
+        }
+        let mut s = BitCask::new_encrypted(path.clone(), keys)?;
+        assert_eq!(s.scan(..).collect::<Result<Vec<_>>>()?, expect);
+        drop(s);
+
+        // Opening with the wrong key or no key errors.
+        match BitCask::new_encrypted(path.clone(), Keyring::new(Key::generate(), Vec::new())) {
+            Err(Error::Config(msg)) => assert!(msg.starts_with("Wrong encryption key"), "{}", msg),
+            Err(err) => panic!("unexpected error {:?}", err),
+            Ok(_) => panic!("expected wrong key error"),
+        }
+        match BitCask::new(path) {
+            Err(Error::Config(msg)) => assert!(msg.contains("no encryption key"), "{}", msg),
+            Err(err) => panic!("unexpected error {:?}", err),
+            Ok(_) => panic!("expected missing key error"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that modified encrypted entries fail authentication, even if
+    /// their checksum is valid.
+    fn encryption_tamper() -> Result<()> {
+        let dir = tempdir::TempDir::new("radb")?;
+        let path = dir.path().join("radb");
+        let log_path = path.join("0000000001.log");
+        let keys = Keyring::new(Key::generate(), Vec::new());
+        let mut log = Log::new(log_path.clone(), Some(&keys))?;
+        let (pos, len) = log.write_entry(b"a", Some(&Compression::None.encode(&[1, 2, 3])))?;
+        log.write_entry(b"b", Some(&Compression::None.encode(&[4, 5])))?;
+        drop(log);
+
+        // Flip a ciphertext bit and fix up the checksum.
+        let mut data = std::fs::read(&log_path)?;
+        let entry = &mut data[pos as usize..(pos + len as u64) as usize];
+        entry[ENTRY_HEADER_LEN as usize + 1] ^= 0x01;
+        let checksum = crc32fast::hash(&entry[4..]);
+        entry[..4].copy_from_slice(&checksum.to_be_bytes());
+        std::fs::write(&log_path, data)?;
+
+        match BitCask::new_encrypted(path, keys) {
+            Err(Error::Internal(msg)) => {
+                assert!(msg.starts_with("Failed to decrypt entry at offset 16"), "{}", msg)
+            }
+            Err(err) => panic!("unexpected error {:?}", err),
+            Ok(_) => panic!("expected decryption error"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that compaction encrypts unencrypted segments and re-encrypts
+    /// segments encrypted with a previous key, after which the previous key
+    /// is no longer needed.
+    fn key_rotation() -> Result<()> {
+        let dir = tempdir::TempDir::new("radb")?;
+        let path = dir.path().join("radb");
+        let mut s = BitCask::new(path.clone())?;
+        s.set_max_segment_size(512);
+        let mut model = BTreeMap::new();
+        write_random(&mut s, &mut model, 200)?;
+        let expect: Vec<_> = model.into_iter().collect();
+        drop(s);
+
+        let first = Key::generate();
+        let second = Key::generate();
+        for keys in [
+            Keyring::new(first.clone(), Vec::new()),
+            Keyring::new(second.clone(), vec![first.clone()]),
+        ] {
+            // Disable garbage-based compaction, to only re-encrypt.
+            let mut s = BitCask::new_compact(path.clone(), 1.0, u64::MAX, Some(keys.clone()))?;
+            s.wait()?;
+            for segment in s.segments.values() {
+                assert_eq!(segment.log.key_id(), keys.current().id());
+            }
+            assert_eq!(s.scan(..).collect::<Result<Vec<_>>>()?, expect);
+            drop(s);
+
+            let current = Keyring::new(keys.current().clone(), Vec::new());
+            let mut s = BitCask::new_encrypted(path.clone(), current)?;
+            assert_eq!(s.scan(..).collect::<Result<Vec<_>>>()?, expect);
+        }
+        Ok(())
+    }
+
     #[test]
     /// Tests status(), both for a log file with known garbage, and
     /// after compacting it when the live size must equal the file size.
     fn status_full() -> Result<()> {
-        let mut s = setup()?;
+        // NB: Don't use setup(), because compaction writes new files in the
+        // directory, which is removed when the tempdir falls out of scope.
+        let dir = tempdir::TempDir::new("radb")?;
+        let mut s = BitCask::new(dir.path().join("radb"))?;
         setup_log(&mut s)?;
 
         // Before compaction.
@@ -645,9 +2253,10 @@ mod tests {
                 name: "bitcask".to_string(),
                 keys: 5,
                 size: 8,
-                total_disk_size: 114,
-                live_disk_size: 48,
-                garbage_disk_size: 66
+                total_disk_size: 234,
+                live_disk_size: 109,
+                garbage_disk_size: 125,
+                compression_ratio: 1.0,
             }
         );
 
@@ -659,9 +2268,10 @@ mod tests {
                 name: "bitcask".to_string(),
                 keys: 5,
                 size: 8,
-                total_disk_size: 48,
-                live_disk_size: 48,
+                total_disk_size: 125,
+                live_disk_size: 125,
                 garbage_disk_size: 0,
+                compression_ratio: 1.0,
             }
         );
 
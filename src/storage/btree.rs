@@ -0,0 +1,1421 @@
+use super::{Engine, Status, WriteBatch};
+use crate::error::{Error, Result};
+
+use fs4::FileExt;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::{Bound, RangeBounds};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// The magic bytes at the start of a meta page.
+const MAGIC: [u8; 4] = *b"BTRE";
+
+/// The file format version.
+const VERSION: u32 = 1;
+
+/// The page size.
+const PAGE_SIZE: usize = 4096;
+
+/// The number of meta pages at the start of the file.
+const META_PAGES: u64 = 2;
+
+/// The length of a node page header: checksum, node kind, and entry count.
+const NODE_HEADER_LEN: usize = 9;
+
+/// The maximum length of a key or value stored inline in a node. Larger keys
+/// and values are stored in overflow pages. This ensures that a node holds at
+/// least four entries, so a split node always fits in two pages.
+const MAX_INLINE: usize = 480;
+
+/// The default buffer pool size, in pages.
+const DEFAULT_CACHE_SIZE: usize = 1024;
+
+/// Node kinds.
+const LEAF: u8 = 1;
+const INTERNAL: u8 = 2;
+
+/// A page ID, i.e. the page's position in the file in units of PAGE_SIZE.
+type PageID = u64;
+
+/// A B+tree key/value storage engine, storing fixed-size pages in a single
+/// file with a buffer pool of cached pages. Unlike BitCask, keys don't have
+/// to fit in memory and values are read in place, making it suitable for
+/// read-heavy workloads with large values. See:
+/// https://en.wikipedia.org/wiki/B%2B_tree
+///
+/// Leaf nodes contain sorted key/value pairs, and internal nodes contain
+/// separator keys and child page IDs, where the keys in child i are greater
+/// than or equal to separator i-1 and less than separator i. Keys and values
+/// longer than MAX_INLINE bytes are stored in contiguous overflow pages and
+/// referenced by their page ID, length, and CRC32 checksum.
+///
+/// Crash safety uses copy-on-write (shadow paging), as in LMDB: pages that
+/// are reachable from the last committed tree are never modified. Instead,
+/// a write copies the path from the leaf to the root into new pages, and
+/// frees the old ones once the new tree is committed. A flush commits the
+/// current tree by writing out all dirty pages, fsyncing, and then writing a
+/// meta page with the new root and fsyncing again. There are two meta pages
+/// which are written alternately, and the valid one with the highest
+/// transaction ID is used on startup. A crash at any point will thus recover
+/// the last committed tree. Pages are only copied once per transaction, so
+/// subsequent writes to the same pages before a flush modify them in place.
+///
+/// The buffer pool caches a fixed number of decoded pages, evicting the least
+/// recently used. Dirty pages can be evicted before the commit, since they
+/// are never reachable from the committed tree. Overflow pages bypass the
+/// buffer pool.
+///
+/// This implementation makes several simplifications over standard B+trees:
+///
+/// - Nodes are only removed when they become empty, and are never merged
+///   with or rebalanced against their siblings.
+/// - Leaves are not linked to their siblings, since the links would have to
+///   be copied on write. Scans instead keep the path to the current leaf.
+/// - The free list is not persisted, but rebuilt on startup by walking the
+///   tree and collecting unreachable pages.
+/// - Multi-page overflow runs are always appended at the end of the file,
+///   while freed pages are only reused for nodes and single-page runs.
+///
+/// The file starts with two meta pages, each containing the magic bytes
+/// "BTRE", the format version as big-endian u32, then the transaction ID,
+/// root page ID, page count, key count, and total key/value size as
+/// big-endian u64, followed by a CRC32 checksum of the preceding bytes.
+///
+/// Node pages contain a CRC32 checksum of the rest of the page, the node kind
+/// (1 for leaves, 2 for internal nodes), and the entry count as big-endian
+/// u32. Leaves then contain the key and value of each entry, while internal
+/// nodes contain the first child page ID followed by each separator key and
+/// the following child page ID. Keys and values are encoded as 0, the length
+/// as big-endian u32 and the bytes if inline, or 1, the page ID and length as
+/// big-endian u64 and the CRC32 checksum if stored in overflow pages.
+pub struct BTree {
+    /// The data file path.
+    path: PathBuf,
+    /// The database directory lock file, locked until the database is closed.
+    lock: std::fs::File,
+    /// The data file.
+    file: std::fs::File,
+    /// The buffer pool.
+    pool: BufferPool,
+    /// The last committed meta page.
+    committed: Meta,
+    /// The meta page of the current, uncommitted transaction.
+    current: Meta,
+    /// Pages that can be reused.
+    free: BTreeSet<PageID>,
+    /// Pages of the committed tree that were freed in the current
+    /// transaction. They can be reused once it commits.
+    pending: Vec<PageID>,
+    /// Pages allocated in the current transaction, which can be modified in
+    /// place.
+    allocated: HashSet<PageID>,
+    /// The number of pages in use, including meta pages.
+    live_pages: u64,
+}
+
+/// A meta page, describing a committed tree.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Meta {
+    /// The transaction ID, incremented on every commit.
+    txn: u64,
+    /// The root node page ID.
+    root: PageID,
+    /// The number of pages in the file.
+    page_count: u64,
+    /// The number of live keys.
+    keys: u64,
+    /// The total size of live keys and values.
+    size: u64,
+}
+
+impl Meta {
+    /// The encoded length, excluding the checksum.
+    const LEN: usize = 48;
+
+    /// Encodes the meta page.
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(PAGE_SIZE);
+        buf.extend(MAGIC);
+        buf.extend(VERSION.to_be_bytes());
+        for n in [self.txn, self.root, self.page_count, self.keys, self.size] {
+            buf.extend(n.to_be_bytes());
+        }
+        buf.extend(crc32fast::hash(&buf).to_be_bytes());
+        buf.resize(PAGE_SIZE, 0);
+        buf
+    }
+
+    /// Decodes a meta page, returning None if it is invalid, e.g. because
+    /// of a torn write.
+    fn decode(buf: &[u8]) -> Result<Option<Self>> {
+        if buf.len() < Self::LEN + 4 || buf[..4] != MAGIC {
+            return Ok(None);
+        }
+        let checksum = u32::from_be_bytes(buf[Self::LEN..Self::LEN + 4].try_into()?);
+        if checksum != crc32fast::hash(&buf[..Self::LEN]) {
+            return Ok(None);
+        }
+        let version = u32::from_be_bytes(buf[4..8].try_into()?);
+        if version != VERSION {
+            return Err(Error::Internal(format!("Unsupported B+tree version {}", version)));
+        }
+        let n = |i: usize| -> Result<u64> {
+            Ok(u64::from_be_bytes(buf[8 + i * 8..16 + i * 8].try_into()?))
+        };
+        Ok(Some(Self { txn: n(0)?, root: n(1)?, page_count: n(2)?, keys: n(3)?, size: n(4)? }))
+    }
+}
+
+impl BTree {
+    /// Opens or creates a B+tree database in the given directory, using the
+    /// default buffer pool size.
+    pub fn new(path: PathBuf) -> Result<Self> {
+        Self::new_with_cache_size(path, DEFAULT_CACHE_SIZE)
+    }
+
+    /// Opens or creates a B+tree database in the given directory, with a
+    /// buffer pool of the given number of pages.
+    pub fn new_with_cache_size(path: PathBuf, cache_size: usize) -> Result<Self> {
+        log::info!("Opening database {}", path.display());
+        std::fs::create_dir_all(&path)?;
+        let lock = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path.join("LOCK"))?;
+        lock.try_lock_exclusive()?;
+
+        let path = path.join("data");
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        // Use the valid meta page with the highest transaction ID. If there
+        // is none and the file is no larger than an empty tree, it is either
+        // new or its initialization was interrupted, so initialize it.
+        let mut metas = Vec::new();
+        let len = file.metadata()?.len();
+        for slot in 0..META_PAGES {
+            if len >= (slot + 1) * PAGE_SIZE as u64 {
+                let mut buf = vec![0; PAGE_SIZE];
+                file.seek(SeekFrom::Start(slot * PAGE_SIZE as u64))?;
+                file.read_exact(&mut buf)?;
+                metas.extend(Meta::decode(&buf)?);
+            }
+        }
+        let committed = match metas.into_iter().max_by_key(|m| m.txn) {
+            Some(meta) => meta,
+            None if len <= (META_PAGES + 1) * PAGE_SIZE as u64 => {
+                let meta = Meta { txn: 1, root: META_PAGES, page_count: 3, keys: 0, size: 0 };
+                file.set_len(0)?;
+                file.seek(SeekFrom::Start(META_PAGES * PAGE_SIZE as u64))?;
+                file.write_all(&Node::Leaf(Vec::new()).encode())?;
+                file.sync_all()?;
+                file.seek(SeekFrom::Start(meta.txn % META_PAGES * PAGE_SIZE as u64))?;
+                file.write_all(&meta.encode())?;
+                file.sync_all()?;
+                meta
+            }
+            None => {
+                return Err(Error::Internal(format!("No valid meta page in {}", path.display())))
+            }
+        };
+
+        // Discard any pages written after the last commit.
+        file.set_len(committed.page_count * PAGE_SIZE as u64)?;
+
+        let mut s = Self {
+            path,
+            lock,
+            file,
+            pool: BufferPool::new(cache_size),
+            committed,
+            current: Meta { txn: committed.txn + 1, ..committed },
+            free: BTreeSet::new(),
+            pending: Vec::new(),
+            allocated: HashSet::new(),
+            live_pages: 0,
+        };
+
+        // Walk the tree to find the free pages.
+        let mut live = vec![false; committed.page_count as usize];
+        live[..META_PAGES as usize].fill(true);
+        let mut mark = |page: PageID, count: u64| -> Result<()> {
+            for id in page..page + count {
+                match live.get_mut(id as usize) {
+                    Some(live) if !*live => *live = true,
+                    _ => return Err(Error::Internal(format!("Invalid page reference {}", id))),
+                }
+            }
+            Ok(())
+        };
+        let mut stack = vec![committed.root];
+        while let Some(id) = stack.pop() {
+            mark(id, 1)?;
+            let node = s.read_node(id)?;
+            let blobs: Box<dyn Iterator<Item = &Blob>> = match &*node {
+                Node::Leaf(entries) => Box::new(entries.iter().flat_map(|(k, v)| [k, v])),
+                Node::Internal(keys, children) => {
+                    stack.extend(children);
+                    Box::new(keys.iter())
+                }
+            };
+            for blob in blobs {
+                if let Blob::Overflow { page, len, .. } = blob {
+                    mark(*page, Blob::page_count(*len))?;
+                }
+            }
+        }
+        s.free = (META_PAGES..committed.page_count).filter(|id| !live[*id as usize]).collect();
+        s.live_pages = committed.page_count - s.free.len() as u64;
+
+        log::info!(
+            "Indexed {} live keys in {} pages ({} free) in {}",
+            s.current.keys,
+            s.current.page_count,
+            s.free.len(),
+            s.path.display()
+        );
+        Ok(s)
+    }
+
+    /// Commits the current transaction, if it has any changes.
+    fn commit(&mut self) -> Result<()> {
+        if self.allocated.is_empty() && self.pending.is_empty() {
+            return Ok(());
+        }
+        for (id, node) in self.pool.take_dirty() {
+            self.write_page(id, &node.encode())?;
+        }
+        self.file.set_len(self.current.page_count * PAGE_SIZE as u64)?;
+        self.file.sync_all()?;
+        self.write_page(self.current.txn % META_PAGES, &self.current.encode())?;
+        self.file.sync_all()?;
+
+        self.committed = self.current;
+        self.current.txn += 1;
+        self.free.extend(self.pending.drain(..));
+        self.allocated.clear();
+        Ok(())
+    }
+
+    /// Writes a page to the file.
+    fn write_page(&mut self, id: PageID, buf: &[u8]) -> Result<()> {
+        self.file.seek(SeekFrom::Start(id * PAGE_SIZE as u64))?;
+        self.file.write_all(buf)?;
+        Ok(())
+    }
+
+    /// Allocates a page, reusing a free page if possible.
+    fn allocate(&mut self) -> PageID {
+        let id = self.free.pop_first().unwrap_or_else(|| {
+            self.current.page_count += 1;
+            self.current.page_count - 1
+        });
+        self.allocated.insert(id);
+        self.live_pages += 1;
+        id
+    }
+
+    /// Allocates a run of contiguous pages, returning the first page ID.
+    fn allocate_run(&mut self, count: u64) -> PageID {
+        if count == 1 {
+            return self.allocate();
+        }
+        let id = self.current.page_count;
+        self.current.page_count += count;
+        self.allocated.extend(id..id + count);
+        self.live_pages += count;
+        id
+    }
+
+    /// Frees a page. Pages allocated in the current transaction can be
+    /// reused immediately, otherwise once the transaction commits.
+    fn free_page(&mut self, id: PageID) {
+        self.pool.remove(id);
+        self.live_pages -= 1;
+        if self.allocated.remove(&id) {
+            self.free.insert(id);
+        } else {
+            self.pending.push(id);
+        }
+    }
+
+    /// Reads a node, via the buffer pool.
+    fn read_node(&mut self, id: PageID) -> Result<Arc<Node>> {
+        if let Some(node) = self.pool.get(id) {
+            return Ok(node);
+        }
+        let mut buf = vec![0; PAGE_SIZE];
+        self.file.seek(SeekFrom::Start(id * PAGE_SIZE as u64))?;
+        self.file.read_exact(&mut buf)?;
+        let node = Arc::new(Node::decode(&buf).ok_or_else(|| {
+            Error::Internal(format!("Checksum mismatch for page {} in {}", id, self.path.display()))
+        })?);
+        self.cache(id, node.clone(), false)?;
+        Ok(node)
+    }
+
+    /// Writes a node to the given page, or to a new page if the page belongs
+    /// to the committed tree. Returns the node's page ID.
+    fn write_node(&mut self, id: PageID, node: Node) -> Result<PageID> {
+        let id = match self.allocated.contains(&id) {
+            true => id,
+            false => {
+                self.free_page(id);
+                self.allocate()
+            }
+        };
+        self.cache(id, Arc::new(node), true)?;
+        Ok(id)
+    }
+
+    /// Writes a node to a new page, returning its page ID.
+    fn write_new_node(&mut self, node: Node) -> Result<PageID> {
+        let id = self.allocate();
+        self.cache(id, Arc::new(node), true)?;
+        Ok(id)
+    }
+
+    /// Inserts a node into the buffer pool, writing out any evicted dirty
+    /// page. This is safe, since dirty pages are never part of the committed
+    /// tree.
+    fn cache(&mut self, id: PageID, node: Arc<Node>, dirty: bool) -> Result<()> {
+        if let Some((id, node)) = self.pool.insert(id, node, dirty) {
+            self.write_page(id, &node.encode())?;
+        }
+        Ok(())
+    }
+
+    /// Creates a blob for a key or value, writing it to overflow pages if
+    /// it is too large to store inline.
+    fn new_blob(&mut self, bytes: &[u8]) -> Result<Blob> {
+        if bytes.len() <= MAX_INLINE {
+            return Ok(Blob::Inline(bytes.to_vec()));
+        }
+        let len = bytes.len() as u64;
+        let page = self.allocate_run(Blob::page_count(len));
+        self.file.seek(SeekFrom::Start(page * PAGE_SIZE as u64))?;
+        self.file.write_all(bytes)?;
+        let padding = (PAGE_SIZE - bytes.len() % PAGE_SIZE) % PAGE_SIZE;
+        self.file.write_all(&vec![0; padding])?;
+        Ok(Blob::Overflow { page, len, checksum: crc32fast::hash(bytes) })
+    }
+
+    /// Copies a blob, such that it has its own overflow pages.
+    fn copy_blob(&mut self, blob: &Blob) -> Result<Blob> {
+        match blob {
+            Blob::Inline(bytes) => Ok(Blob::Inline(bytes.clone())),
+            Blob::Overflow { .. } => self.new_blob(&self.read_blob(blob)?),
+        }
+    }
+
+    /// Frees a blob's overflow pages, if any.
+    fn free_blob(&mut self, blob: &Blob) {
+        if let Blob::Overflow { page, len, .. } = blob {
+            for id in *page..*page + Blob::page_count(*len) {
+                self.free_page(id)
+            }
+        }
+    }
+
+    /// Reads a blob's bytes.
+    fn read_blob(&self, blob: &Blob) -> Result<Vec<u8>> {
+        match blob {
+            Blob::Inline(bytes) => Ok(bytes.clone()),
+            Blob::Overflow { page, len, checksum } => {
+                let mut buf = vec![0; *len as usize];
+                let mut file = &self.file;
+                file.seek(SeekFrom::Start(page * PAGE_SIZE as u64))?;
+                file.read_exact(&mut buf)?;
+                if crc32fast::hash(&buf) != *checksum {
+                    return Err(Error::Internal(format!(
+                        "Checksum mismatch for overflow page {} in {}",
+                        page,
+                        self.path.display()
+                    )));
+                }
+                Ok(buf)
+            }
+        }
+    }
+
+    /// Compares a blob with a key.
+    fn compare(&self, blob: &Blob, key: &[u8]) -> Result<Ordering> {
+        match blob {
+            Blob::Inline(bytes) => Ok(bytes.as_slice().cmp(key)),
+            Blob::Overflow { .. } => Ok(self.read_blob(blob)?.as_slice().cmp(key)),
+        }
+    }
+
+    /// Binary searches sorted blobs for a key, with the same semantics as
+    /// slice::binary_search.
+    fn search<'b>(
+        &self,
+        len: usize,
+        blob: impl Fn(usize) -> &'b Blob,
+        key: &[u8],
+    ) -> Result<std::result::Result<usize, usize>> {
+        let (mut low, mut high) = (0, len);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.compare(blob(mid), key)? {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return Ok(Ok(mid)),
+            }
+        }
+        Ok(Err(low))
+    }
+
+    /// Returns the index of the child that may contain the given key.
+    fn child_index(&self, keys: &[Blob], key: &[u8]) -> Result<usize> {
+        Ok(match self.search(keys.len(), |i| &keys[i], key)? {
+            Ok(i) => i + 1,
+            Err(i) => i,
+        })
+    }
+
+    /// Inserts a key/value pair into the subtree at the given page.
+    fn insert(&mut self, id: PageID, key: &[u8], value: Blob) -> Result<Insert> {
+        let mut node = Node::clone(&*self.read_node(id)?);
+        let mut replaced = None;
+        match &mut node {
+            Node::Leaf(entries) => match self.search(entries.len(), |i| &entries[i].0, key)? {
+                Ok(i) => {
+                    let old = std::mem::replace(&mut entries[i].1, value);
+                    replaced = Some(old.len());
+                    self.free_blob(&old);
+                }
+                Err(i) => entries.insert(i, (self.new_blob(key)?, value)),
+            },
+            Node::Internal(keys, children) => {
+                let i = self.child_index(keys, key)?;
+                let insert = self.insert(children[i], key, value)?;
+                children[i] = insert.page;
+                if let Some((key, page)) = insert.split {
+                    keys.insert(i, key);
+                    children.insert(i + 1, page);
+                }
+                replaced = insert.replaced;
+            }
+        }
+        let (page, split) = self.write_split(id, node)?;
+        Ok(Insert { page, split, replaced })
+    }
+
+    /// Writes a node, splitting it in two if it doesn't fit in a page.
+    /// Returns the node's page ID, and the separator key and page ID of the
+    /// new right node if split.
+    fn write_split(&mut self, id: PageID, node: Node) -> Result<(PageID, Option<(Blob, PageID)>)> {
+        if node.encoded_len() <= PAGE_SIZE {
+            return Ok((self.write_node(id, node)?, None));
+        }
+        // Find the split point, at half of the encoded size.
+        let sizes: Vec<usize> = match &node {
+            Node::Leaf(entries) => {
+                entries.iter().map(|(k, v)| k.encoded_len() + v.encoded_len()).collect()
+            }
+            Node::Internal(keys, _) => keys.iter().map(|k| k.encoded_len() + 8).collect(),
+        };
+        let half = sizes.iter().sum::<usize>() / 2;
+        let mut at = 0;
+        let mut size = 0;
+        while size < half {
+            size += sizes[at];
+            at += 1;
+        }
+        let (left, key, right) = match node {
+            Node::Leaf(mut entries) => {
+                let right = entries.split_off(at.clamp(1, entries.len() - 1));
+                let key = self.copy_blob(&right[0].0)?;
+                (Node::Leaf(entries), key, Node::Leaf(right))
+            }
+            Node::Internal(mut keys, mut children) => {
+                let at = at.clamp(1, keys.len() - 2);
+                let right_keys = keys.split_off(at + 1);
+                let right_children = children.split_off(at + 1);
+                let key = keys.pop().expect("no separator key");
+                (Node::Internal(keys, children), key, Node::Internal(right_keys, right_children))
+            }
+        };
+        let left = self.write_node(id, left)?;
+        let right = self.write_new_node(right)?;
+        Ok((left, Some((key, right))))
+    }
+
+    /// Removes a key from the subtree at the given page. Returns None if the
+    /// key wasn't found, otherwise the subtree's new page ID (None if it is
+    /// now empty) and the removed key/value size.
+    fn remove(&mut self, id: PageID, key: &[u8]) -> Result<Option<(Option<PageID>, u64)>> {
+        let mut node = Node::clone(&*self.read_node(id)?);
+        let size = match &mut node {
+            Node::Leaf(entries) => {
+                let Ok(i) = self.search(entries.len(), |i| &entries[i].0, key)? else {
+                    return Ok(None);
+                };
+                let (key, value) = entries.remove(i);
+                self.free_blob(&key);
+                self.free_blob(&value);
+                key.len() + value.len()
+            }
+            Node::Internal(keys, children) => {
+                let i = self.child_index(keys, key)?;
+                let Some((child, size)) = self.remove(children[i], key)? else {
+                    return Ok(None);
+                };
+                match child {
+                    Some(child) => children[i] = child,
+                    None => {
+                        children.remove(i);
+                        if !keys.is_empty() {
+                            let key = keys.remove(i.saturating_sub(1));
+                            self.free_blob(&key);
+                        }
+                    }
+                }
+                size
+            }
+        };
+        if node.is_empty() {
+            self.free_page(id);
+            return Ok(Some((None, size)));
+        }
+        Ok(Some((Some(self.write_node(id, node)?), size)))
+    }
+}
+
+impl std::fmt::Display for BTree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "btree")
+    }
+}
+
+impl Engine for BTree {
+    type ScanIterator<'a> = ScanIterator<'a>;
+
+    fn delete(&mut self, key: &[u8]) -> Result<()> {
+        let Some((page, size)) = self.remove(self.current.root, key)? else {
+            return Ok(());
+        };
+        self.current.keys -= 1;
+        self.current.size -= size;
+
+        // Replace an empty root with an empty leaf, and remove internal root
+        // nodes with a single child.
+        let mut root = match page {
+            Some(page) => page,
+            None => self.write_new_node(Node::Leaf(Vec::new()))?,
+        };
+        loop {
+            match &*self.read_node(root)? {
+                Node::Internal(_, children) if children.len() == 1 => {
+                    self.free_page(root);
+                    root = children[0];
+                }
+                _ => break,
+            }
+        }
+        self.current.root = root;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.commit()
+    }
+
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let mut id = self.current.root;
+        loop {
+            let node = self.read_node(id)?;
+            match &*node {
+                Node::Internal(keys, children) => id = children[self.child_index(keys, key)?],
+                Node::Leaf(entries) => {
+                    return match self.search(entries.len(), |i| &entries[i].0, key)? {
+                        Ok(i) => Ok(Some(self.read_blob(&entries[i].1)?)),
+                        Err(_) => Ok(None),
+                    }
+                }
+            }
+        }
+    }
+
+    fn scan(&mut self, range: impl RangeBounds<Vec<u8>>) -> Self::ScanIterator<'_> {
+        ScanIterator {
+            tree: self,
+            range: (range.start_bound().cloned(), range.end_bound().cloned()),
+            front: Cursor::default(),
+            back: Cursor::default(),
+            front_key: None,
+            back_key: None,
+        }
+    }
+
+    fn scan_dyn(
+        &mut self,
+        range: (Bound<Vec<u8>>, Bound<Vec<u8>>),
+    ) -> Box<dyn super::ScanIterator + '_> {
+        Box::new(self.scan(range))
+    }
+
+    fn set(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        let value_len = value.len() as u64;
+        let value = self.new_blob(&value)?;
+        let insert = self.insert(self.current.root, key, value)?;
+        self.current.root = match insert.split {
+            Some((key, page)) => {
+                self.write_new_node(Node::Internal(vec![key], vec![insert.page, page]))?
+            }
+            None => insert.page,
+        };
+        match insert.replaced {
+            Some(old_len) => self.current.size = self.current.size - old_len + value_len,
+            None => {
+                self.current.keys += 1;
+                self.current.size += key.len() as u64 + value_len;
+            }
+        }
+        Ok(())
+    }
+
+    fn status(&mut self) -> Result<Status> {
+        let total_disk_size = self.current.page_count * PAGE_SIZE as u64;
+        let live_disk_size = self.live_pages * PAGE_SIZE as u64;
+        Ok(Status {
+            name: self.to_string(),
+            keys: self.current.keys,
+            size: self.current.size,
+            total_disk_size,
+            live_disk_size,
+            garbage_disk_size: total_disk_size - live_disk_size,
+            compression_ratio: 1.0,
+        })
+    }
+
+    /// Batches are atomic, since they're committed along with the rest of the
+    /// current transaction.
+    fn write_batch(&mut self, batch: WriteBatch) -> Result<()> {
+        for (key, value) in batch {
+            match value {
+                Some(value) => self.set(&key, value)?,
+                None => self.delete(&key)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Commit the current transaction and unlock the database when it is closed.
+impl Drop for BTree {
+    fn drop(&mut self) {
+        if let Err(error) = self.commit() {
+            log::error!("failed to flush file: {}", error)
+        }
+        if let Err(error) = self.lock.unlock() {
+            log::error!("failed to unlock database: {}", error)
+        }
+    }
+}
+
+/// The result of inserting into a subtree.
+struct Insert {
+    /// The subtree's new page ID.
+    page: PageID,
+    /// The separator key and page ID of a new right sibling, if split.
+    split: Option<(Blob, PageID)>,
+    /// The length of the replaced value, if any.
+    replaced: Option<u64>,
+}
+
+/// A key or value, stored inline or in overflow pages.
+#[derive(Clone, Debug, PartialEq)]
+enum Blob {
+    Inline(Vec<u8>),
+    Overflow { page: PageID, len: u64, checksum: u32 },
+}
+
+impl Blob {
+    /// Returns the number of overflow pages needed for the given length.
+    fn page_count(len: u64) -> u64 {
+        len.div_ceil(PAGE_SIZE as u64)
+    }
+
+    /// Returns the length of the key or value.
+    fn len(&self) -> u64 {
+        match self {
+            Self::Inline(bytes) => bytes.len() as u64,
+            Self::Overflow { len, .. } => *len,
+        }
+    }
+
+    /// Returns the encoded length.
+    fn encoded_len(&self) -> usize {
+        match self {
+            Self::Inline(bytes) => 5 + bytes.len(),
+            Self::Overflow { .. } => 21,
+        }
+    }
+
+    /// Encodes the blob into a buffer.
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Self::Inline(bytes) => {
+                buf.push(0);
+                buf.extend((bytes.len() as u32).to_be_bytes());
+                buf.extend(bytes);
+            }
+            Self::Overflow { page, len, checksum } => {
+                buf.push(1);
+                buf.extend(page.to_be_bytes());
+                buf.extend(len.to_be_bytes());
+                buf.extend(checksum.to_be_bytes());
+            }
+        }
+    }
+
+    /// Decodes a blob from a buffer, advancing it.
+    fn decode(buf: &mut &[u8]) -> Option<Self> {
+        match take(buf, 1)?[0] {
+            0 => {
+                let len = u32::from_be_bytes(take(buf, 4)?.try_into().ok()?);
+                Some(Self::Inline(take(buf, len as usize)?.to_vec()))
+            }
+            1 => Some(Self::Overflow {
+                page: u64::from_be_bytes(take(buf, 8)?.try_into().ok()?),
+                len: u64::from_be_bytes(take(buf, 8)?.try_into().ok()?),
+                checksum: u32::from_be_bytes(take(buf, 4)?.try_into().ok()?),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Takes the given number of bytes from the front of a buffer.
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if buf.len() < len {
+        return None;
+    }
+    let (head, tail) = buf.split_at(len);
+    *buf = tail;
+    Some(head)
+}
+
+/// A B+tree node.
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    /// A leaf node, with sorted key/value pairs.
+    Leaf(Vec<(Blob, Blob)>),
+    /// An internal node, with separator keys and one more child page ID.
+    Internal(Vec<Blob>, Vec<PageID>),
+}
+
+impl Node {
+    /// Returns true if the node has no entries or children.
+    fn is_empty(&self) -> bool {
+        match self {
+            Self::Leaf(entries) => entries.is_empty(),
+            Self::Internal(_, children) => children.is_empty(),
+        }
+    }
+
+    /// Returns the encoded length.
+    fn encoded_len(&self) -> usize {
+        NODE_HEADER_LEN
+            + match self {
+                Self::Leaf(entries) => {
+                    entries.iter().map(|(k, v)| k.encoded_len() + v.encoded_len()).sum()
+                }
+                Self::Internal(keys, children) => {
+                    keys.iter().map(|k| k.encoded_len()).sum::<usize>() + 8 * children.len()
+                }
+            }
+    }
+
+    /// Encodes the node as a page.
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(PAGE_SIZE);
+        buf.extend([0; 4]);
+        match self {
+            Self::Leaf(entries) => {
+                buf.push(LEAF);
+                buf.extend((entries.len() as u32).to_be_bytes());
+                for (key, value) in entries {
+                    key.encode(&mut buf);
+                    value.encode(&mut buf);
+                }
+            }
+            Self::Internal(keys, children) => {
+                buf.push(INTERNAL);
+                buf.extend((keys.len() as u32).to_be_bytes());
+                buf.extend(children[0].to_be_bytes());
+                for (key, child) in keys.iter().zip(&children[1..]) {
+                    key.encode(&mut buf);
+                    buf.extend(child.to_be_bytes());
+                }
+            }
+        }
+        buf.resize(PAGE_SIZE, 0);
+        let checksum = crc32fast::hash(&buf[4..]);
+        buf[..4].copy_from_slice(&checksum.to_be_bytes());
+        buf
+    }
+
+    /// Decodes a node page, returning None if it is invalid.
+    fn decode(page: &[u8]) -> Option<Self> {
+        let mut buf = page;
+        let checksum = u32::from_be_bytes(take(&mut buf, 4)?.try_into().ok()?);
+        if checksum != crc32fast::hash(buf) {
+            return None;
+        }
+        let kind = take(&mut buf, 1)?[0];
+        let count = u32::from_be_bytes(take(&mut buf, 4)?.try_into().ok()?) as usize;
+        let child = |buf: &mut &[u8]| Some(u64::from_be_bytes(take(buf, 8)?.try_into().ok()?));
+        match kind {
+            LEAF => {
+                let mut entries = Vec::with_capacity(count);
+                for _ in 0..count {
+                    entries.push((Blob::decode(&mut buf)?, Blob::decode(&mut buf)?));
+                }
+                Some(Self::Leaf(entries))
+            }
+            INTERNAL => {
+                let mut keys = Vec::with_capacity(count);
+                let mut children = vec![child(&mut buf)?];
+                for _ in 0..count {
+                    keys.push(Blob::decode(&mut buf)?);
+                    children.push(child(&mut buf)?);
+                }
+                Some(Self::Internal(keys, children))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A buffer pool, caching a fixed number of decoded pages and evicting the
+/// least recently used.
+struct BufferPool {
+    /// The maximum number of cached pages.
+    capacity: usize,
+    /// Cached pages, with a dirty flag and last use.
+    frames: HashMap<PageID, Frame>,
+    /// Page IDs by last use.
+    lru: BTreeMap<u64, PageID>,
+    /// The use counter.
+    clock: u64,
+}
+
+/// A buffer pool frame.
+struct Frame {
+    node: Arc<Node>,
+    dirty: bool,
+    used: u64,
+}
+
+impl BufferPool {
+    /// Creates a new buffer pool with the given capacity.
+    fn new(capacity: usize) -> Self {
+        Self { capacity: capacity.max(1), frames: HashMap::new(), lru: BTreeMap::new(), clock: 0 }
+    }
+
+    /// Marks a frame as used, returning it.
+    fn touch(&mut self, id: PageID) -> Option<&mut Frame> {
+        let frame = self.frames.get_mut(&id)?;
+        self.lru.remove(&frame.used);
+        self.clock += 1;
+        frame.used = self.clock;
+        self.lru.insert(self.clock, id);
+        Some(frame)
+    }
+
+    /// Fetches a cached page.
+    fn get(&mut self, id: PageID) -> Option<Arc<Node>> {
+        self.touch(id).map(|frame| frame.node.clone())
+    }
+
+    /// Caches a page. If the pool is full, the least recently used page is
+    /// evicted, and returned if it was dirty.
+    fn insert(&mut self, id: PageID, node: Arc<Node>, dirty: bool) -> Option<(PageID, Arc<Node>)> {
+        if let Some(frame) = self.touch(id) {
+            frame.node = node;
+            frame.dirty |= dirty;
+            return None;
+        }
+        let mut evicted = None;
+        if self.frames.len() >= self.capacity {
+            if let Some((_, id)) = self.lru.pop_first() {
+                let frame = self.frames.remove(&id).expect("missing frame");
+                if frame.dirty {
+                    evicted = Some((id, frame.node));
+                }
+            }
+        }
+        self.clock += 1;
+        self.frames.insert(id, Frame { node, dirty, used: self.clock });
+        self.lru.insert(self.clock, id);
+        evicted
+    }
+
+    /// Removes a page from the pool.
+    fn remove(&mut self, id: PageID) {
+        if let Some(frame) = self.frames.remove(&id) {
+            self.lru.remove(&frame.used);
+        }
+    }
+
+    /// Returns all dirty pages, marking them as clean.
+    fn take_dirty(&mut self) -> Vec<(PageID, Arc<Node>)> {
+        let mut dirty: Vec<_> = self
+            .frames
+            .iter_mut()
+            .filter(|(_, frame)| frame.dirty)
+            .map(|(id, frame)| {
+                frame.dirty = false;
+                (*id, frame.node.clone())
+            })
+            .collect();
+        dirty.sort_by_key(|(id, _)| *id);
+        dirty
+    }
+}
+
+/// A scan cursor, iterating over the leaves in one direction.
+#[derive(Default)]
+struct Cursor {
+    /// The path from the root to the current leaf, as internal node page IDs
+    /// and child indexes.
+    path: Vec<(PageID, usize)>,
+    /// Buffered in-range entries from the current leaf, in iteration order.
+    entries: VecDeque<(Vec<u8>, Blob)>,
+    /// True if the cursor has been positioned at the first leaf.
+    started: bool,
+    /// True if there are no more leaves in range.
+    done: bool,
+}
+
+pub struct ScanIterator<'a> {
+    /// The B+tree.
+    tree: &'a mut BTree,
+    /// The scan range.
+    range: (Bound<Vec<u8>>, Bound<Vec<u8>>),
+    /// The front and back cursors.
+    front: Cursor,
+    back: Cursor,
+    /// The last keys returned from the front and back, to detect when the
+    /// cursors cross.
+    front_key: Option<Vec<u8>>,
+    back_key: Option<Vec<u8>>,
+}
+
+impl<'a> ScanIterator<'a> {
+    /// Moves a cursor to the next leaf in the given direction, and buffers
+    /// its entries in the scan range.
+    fn advance(&mut self, reverse: bool) -> Result<()> {
+        let tree = &mut *self.tree;
+        let range = &self.range;
+        let cursor = if reverse { &mut self.back } else { &mut self.front };
+
+        // On the first call, descend from the root towards the start (or end)
+        // of the range. Otherwise, find the closest ancestor with a next (or
+        // previous) child, and descend to its first (or last) leaf.
+        let mut id = match cursor.started {
+            false => {
+                cursor.started = true;
+                tree.current.root
+            }
+            true => loop {
+                let Some((parent, i)) = cursor.path.pop() else {
+                    cursor.done = true;
+                    return Ok(());
+                };
+                let Node::Internal(_, children) = &*tree.read_node(parent)? else {
+                    return Err(Error::Internal(format!(
+                        "Page {} is not an internal node",
+                        parent
+                    )));
+                };
+                let next = match reverse {
+                    false => Some(i + 1).filter(|i| *i < children.len()),
+                    true => i.checked_sub(1),
+                };
+                if let Some(next) = next {
+                    cursor.path.push((parent, next));
+                    break children[next];
+                }
+            },
+        };
+        let seek = match (cursor.path.is_empty(), reverse) {
+            (true, false) => range.0.as_ref(),
+            (true, true) => range.1.as_ref(),
+            (false, _) => Bound::Unbounded,
+        };
+        let node = loop {
+            let node = tree.read_node(id)?;
+            let Node::Internal(keys, children) = &*node else {
+                break node;
+            };
+            let i = match seek {
+                Bound::Included(key) | Bound::Excluded(key) => tree.child_index(keys, key)?,
+                Bound::Unbounded if reverse => children.len() - 1,
+                Bound::Unbounded => 0,
+            };
+            cursor.path.push((id, i));
+            id = children[i];
+        };
+
+        // Buffer the leaf entries in range, and stop at the end of the range.
+        let Node::Leaf(entries) = &*node else { unreachable!() };
+        let mut iter: Box<dyn Iterator<Item = &(Blob, Blob)>> = match reverse {
+            false => Box::new(entries.iter()),
+            true => Box::new(entries.iter().rev()),
+        };
+        iter.try_for_each(|(key, value)| -> Result<()> {
+            let key = tree.read_blob(key)?;
+            let (before, after) = match reverse {
+                false => (&range.0, &range.1),
+                true => (&range.1, &range.0),
+            };
+            let is_before = match before {
+                Bound::Included(b) => {
+                    if reverse {
+                        key > *b
+                    } else {
+                        key < *b
+                    }
+                }
+                Bound::Excluded(b) => {
+                    if reverse {
+                        key >= *b
+                    } else {
+                        key <= *b
+                    }
+                }
+                Bound::Unbounded => false,
+            };
+            let is_after = match after {
+                Bound::Included(a) => {
+                    if reverse {
+                        key < *a
+                    } else {
+                        key > *a
+                    }
+                }
+                Bound::Excluded(a) => {
+                    if reverse {
+                        key <= *a
+                    } else {
+                        key >= *a
+                    }
+                }
+                Bound::Unbounded => false,
+            };
+            if is_after {
+                cursor.done = true;
+            } else if !is_before && !cursor.done {
+                cursor.entries.push_back((key, value.clone()));
+            }
+            Ok(())
+        })
+    }
+
+    /// Returns the next entry from the front or back.
+    fn try_next(&mut self, reverse: bool) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        loop {
+            let (cursor, other_key) = match reverse {
+                false => (&mut self.front, &self.back_key),
+                true => (&mut self.back, &self.front_key),
+            };
+            if let Some((key, value)) = cursor.entries.pop_front() {
+                let crossed = match other_key {
+                    Some(other) if reverse => key <= *other,
+                    Some(other) => key >= *other,
+                    None => false,
+                };
+                if crossed {
+                    cursor.entries.clear();
+                    cursor.done = true;
+                    return Ok(None);
+                }
+                let value = self.tree.read_blob(&value)?;
+                match reverse {
+                    false => self.front_key = Some(key.clone()),
+                    true => self.back_key = Some(key.clone()),
+                }
+                return Ok(Some((key, value)));
+            }
+            if cursor.done {
+                return Ok(None);
+            }
+            self.advance(reverse)?;
+        }
+    }
+}
+
+impl<'a> Iterator for ScanIterator<'a> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.try_next(false).transpose()
+    }
+}
+
+impl<'a> DoubleEndedIterator for ScanIterator<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.try_next(true).transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a new BTree engine for testing, with a small buffer pool to
+    /// exercise evictions.
+    fn setup() -> Result<BTree> {
+        BTree::new_with_cache_size(tempdir::TempDir::new("radb")?.path().join("radb"), 8)
+    }
+
+    super::super::engine::tests::test_engine!(setup()?);
+
+    /// Applies a deterministic pseudo-random sequence of writes to the engine
+    /// and a model, with some values stored in overflow pages.
+    fn write_random(s: &mut BTree, model: &mut BTreeMap<Vec<u8>, Vec<u8>>, n: u64) -> Result<()> {
+        let mut rand: u64 = 1;
+        for i in 0..n {
+            rand = rand.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let key = format!("key{}", (rand >> 33) % 1000).into_bytes();
+            if (rand >> 40) & 3 == 0 {
+                s.delete(&key)?;
+                model.remove(&key);
+            } else {
+                let len = match (rand >> 50) % 16 {
+                    0 => 10000,
+                    _ => (rand >> 54) as usize % 100,
+                };
+                let value = vec![i as u8; len];
+                s.set(&key, value.clone())?;
+                model.insert(key, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Asserts that the engine contains the model data, via scans and gets,
+    /// and that the status matches.
+    #[track_caller]
+    fn assert_model(s: &mut BTree, model: &BTreeMap<Vec<u8>, Vec<u8>>) -> Result<()> {
+        let expect: Vec<_> = model.clone().into_iter().collect();
+        assert_eq!(s.scan(..).collect::<Result<Vec<_>>>()?, expect);
+        let mut reversed = expect.clone();
+        reversed.reverse();
+        assert_eq!(s.scan(..).rev().collect::<Result<Vec<_>>>()?, reversed);
+        for (key, value) in model {
+            assert_eq!(s.get(key)?.as_ref(), Some(value));
+        }
+        let status = s.status()?;
+        assert_eq!(status.keys, model.len() as u64);
+        assert_eq!(status.size, model.iter().map(|(k, v)| (k.len() + v.len()) as u64).sum::<u64>());
+        Ok(())
+    }
+
+    /// Simulates a crash, dropping the engine without committing.
+    fn crash(mut s: BTree) {
+        s.pool = BufferPool::new(1);
+        s.allocated.clear();
+        s.pending.clear();
+    }
+
+    #[test]
+    /// Tests that the data can be read back after reopening, with the same
+    /// live and garbage pages.
+    fn reopen() -> Result<()> {
+        let dir = tempdir::TempDir::new("radb")?;
+        let path = dir.path().join("radb");
+        let mut s = BTree::new_with_cache_size(path.clone(), 8)?;
+        let mut model = BTreeMap::new();
+        write_random(&mut s, &mut model, 5000)?;
+        assert_model(&mut s, &model)?;
+        s.flush()?;
+        let status = s.status()?;
+        assert!(status.garbage_disk_size > 0);
+        drop(s);
+
+        let mut s = BTree::new_with_cache_size(path, 8)?;
+        assert_model(&mut s, &model)?;
+        assert_eq!(s.status()?, status);
+        Ok(())
+    }
+
+    #[test]
+    /// Tests bounded and interleaved scans across many leaves.
+    fn scan_ranges() -> Result<()> {
+        let mut s = setup()?;
+        let mut model = BTreeMap::new();
+        write_random(&mut s, &mut model, 3000)?;
+        assert!(matches!(&*s.read_node(s.current.root)?, Node::Internal(..)));
+
+        let keys: Vec<Vec<u8>> = [b"key".to_vec(), b"key1".to_vec(), b"key5".to_vec()]
+            .into_iter()
+            .chain(model.keys().step_by(97).cloned())
+            .collect();
+        for start in &keys {
+            for end in &keys {
+                let ranges = [
+                    (Bound::Included(start.clone()), Bound::Excluded(end.clone())),
+                    (Bound::Excluded(start.clone()), Bound::Included(end.clone())),
+                    (Bound::Included(start.clone()), Bound::Unbounded),
+                    (Bound::Unbounded, Bound::Excluded(end.clone())),
+                ];
+                for range in ranges {
+                    if start > end {
+                        continue;
+                    }
+                    let expect: Vec<_> =
+                        model.range(range.clone()).map(|(k, v)| (k.clone(), v.clone())).collect();
+                    assert_eq!(s.scan(range.clone()).collect::<Result<Vec<_>>>()?, expect);
+
+                    // Alternate between the front and back.
+                    let mut iter = s.scan(range);
+                    let (mut front, mut back) = (Vec::new(), Vec::new());
+                    while let Some(item) = iter.next() {
+                        front.push(item?);
+                        let Some(item) = iter.next_back() else { break };
+                        back.push(item?);
+                    }
+                    assert!(iter.next().is_none() && iter.next_back().is_none());
+                    back.reverse();
+                    front.extend(back);
+                    assert_eq!(front, expect);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that a crash recovers the last committed tree, even when dirty
+    /// pages have been evicted from the buffer pool.
+    fn crash_recovery() -> Result<()> {
+        let dir = tempdir::TempDir::new("radb")?;
+        let path = dir.path().join("radb");
+        let mut s = BTree::new_with_cache_size(path.clone(), 4)?;
+        let mut model = BTreeMap::new();
+        write_random(&mut s, &mut model, 2000)?;
+        s.flush()?;
+
+        let mut uncommitted = model.clone();
+        write_random(&mut s, &mut uncommitted, 2000)?;
+        s.delete(b"key1")?;
+        crash(s);
+
+        let mut s = BTree::new_with_cache_size(path, 4)?;
+        assert_model(&mut s, &model)?;
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that a corrupt meta page, e.g. due to a torn write, falls back
+    /// to the previous commit.
+    fn corrupt_meta() -> Result<()> {
+        let dir = tempdir::TempDir::new("radb")?;
+        let path = dir.path().join("radb");
+        let mut s = BTree::new(path.clone())?;
+        s.set(b"a", vec![1])?;
+        s.flush()?;
+        s.set(b"b", vec![2])?;
+        s.flush()?;
+        let slot = s.committed.txn % META_PAGES;
+        drop(s);
+
+        let mut f = std::fs::OpenOptions::new().write(true).open(path.join("data"))?;
+        f.seek(SeekFrom::Start(slot * PAGE_SIZE as u64 + 10))?;
+        f.write_all(&[0xff])?;
+        drop(f);
+
+        let mut s = BTree::new(path)?;
+        assert_eq!(s.scan(..).collect::<Result<Vec<_>>>()?, vec![(b"a".to_vec(), vec![1])]);
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that corruption in overflow pages is detected when read, and in
+    /// node pages when opened.
+    fn corrupt_page() -> Result<()> {
+        let dir = tempdir::TempDir::new("radb")?;
+        let path = dir.path().join("radb");
+        let mut s = BTree::new(path.clone())?;
+        s.set(b"a", vec![1; 100])?;
+        s.set(b"b", vec![2; 10000])?;
+        s.flush()?;
+        let root = s.committed.root;
+        let Node::Leaf(entries) = &*s.read_node(root)? else { panic!("root is not a leaf") };
+        let Blob::Overflow { page: overflow, .. } = entries[1].1 else { panic!("not overflow") };
+        drop(s);
+
+        let corrupt = |page: PageID| -> Result<()> {
+            let mut f = std::fs::OpenOptions::new().write(true).open(path.join("data"))?;
+            f.seek(SeekFrom::Start(page * PAGE_SIZE as u64 + 100))?;
+            f.write_all(&[0xff])?;
+            Ok(())
+        };
+
+        corrupt(overflow)?;
+        let mut s = BTree::new(path.clone())?;
+        assert_eq!(s.get(b"a")?, Some(vec![1; 100]));
+        assert!(matches!(s.get(b"b"), Err(Error::Internal(_))));
+        assert!(matches!(s.scan(..).collect::<Result<Vec<_>>>(), Err(Error::Internal(_))));
+        drop(s);
+
+        corrupt(root)?;
+        assert!(matches!(BTree::new(path), Err(Error::Internal(_))));
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that freed pages are reported as garbage and reused.
+    fn free_pages() -> Result<()> {
+        let mut s = setup()?;
+        for i in 0..1000_u64 {
+            s.set(&i.to_be_bytes(), vec![1; 100])?;
+        }
+        s.flush()?;
+        let status = s.status()?;
+        for i in 0..1000_u64 {
+            s.delete(&i.to_be_bytes())?;
+        }
+        s.flush()?;
+
+        // Only the meta pages and the empty root leaf remain live. The new
+        // root leaf may need a new page, since the freed pages can't be
+        // reused until the commit.
+        let empty = s.status()?;
+        assert!(empty.total_disk_size <= status.total_disk_size + PAGE_SIZE as u64);
+        assert_eq!(empty.live_disk_size, 3 * PAGE_SIZE as u64);
+        assert_eq!(empty.garbage_disk_size, empty.total_disk_size - 3 * PAGE_SIZE as u64);
+
+        for i in 0..1000_u64 {
+            s.set(&i.to_be_bytes(), vec![1; 100])?;
+        }
+        s.flush()?;
+        assert_eq!(s.status()?.total_disk_size, empty.total_disk_size);
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that exclusive locks are taken out on the database directory.
+    fn lock() -> Result<()> {
+        let dir = tempdir::TempDir::new("radb")?;
+        let path = dir.path().join("radb");
+        let s = BTree::new(path.clone())?;
+        assert!(BTree::new(path.clone()).is_err());
+        drop(s);
+        assert!(BTree::new(path).is_ok());
+        Ok(())
+    }
+}
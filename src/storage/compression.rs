@@ -0,0 +1,359 @@
+use crate::error::{Error, Result};
+
+/// A compression codec, which compresses and decompresses byte strings.
+pub trait Codec: Send + Sync {
+    /// Compresses the input.
+    fn compress(&self, input: &[u8]) -> Vec<u8>;
+
+    /// Decompresses the input, given the uncompressed length. Errors if the
+    /// input is invalid or does not decompress to the given length.
+    fn decompress(&self, input: &[u8], len: usize) -> Result<Vec<u8>>;
+}
+
+/// The compression used by a storage engine. Values (or blocks) are encoded
+/// with a compression ID byte, so each one can be decoded regardless of the
+/// engine's current compression setting. To add a codec, implement Codec
+/// and add a variant with a new ID.
+///
+/// Encoded data consists of:
+///
+/// - The compression ID as u8: 0 for none, 1 for LZ.
+/// - For compressed data, the uncompressed length as big-endian u32.
+/// - The (compressed) data.
+///
+/// Data is only stored compressed if it makes it smaller.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// No compression.
+    #[default]
+    None,
+    /// LZ compression, see Lz.
+    Lz,
+}
+
+impl Compression {
+    /// Returns the compression with the given ID.
+    fn from_id(id: u8) -> Result<Self> {
+        match id {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Lz),
+            id => Err(Error::Internal(format!("Unknown compression ID {}", id))),
+        }
+    }
+
+    /// Returns the compression's ID.
+    fn id(self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Lz => 1,
+        }
+    }
+
+    /// Returns the compression's codec, if any.
+    fn codec(self) -> Option<&'static dyn Codec> {
+        match self {
+            Self::None => None,
+            Self::Lz => Some(&Lz),
+        }
+    }
+
+    /// Encodes data for storage, compressing it if that makes it smaller.
+    pub fn encode(self, data: &[u8]) -> Vec<u8> {
+        if let Some(codec) = self.codec() {
+            let compressed = codec.compress(data);
+            if compressed.len() + 4 < data.len() {
+                let mut buf = Vec::with_capacity(5 + compressed.len());
+                buf.push(self.id());
+                buf.extend_from_slice(&(data.len() as u32).to_be_bytes());
+                buf.extend_from_slice(&compressed);
+                return buf;
+            }
+        }
+        let mut buf = Vec::with_capacity(1 + data.len());
+        buf.push(Self::None.id());
+        buf.extend_from_slice(data);
+        buf
+    }
+
+    /// Decodes encoded data, decompressing it if necessary.
+    pub fn decode(mut data: Vec<u8>) -> Result<Vec<u8>> {
+        let (compression, len) = Self::decode_header(&data)?;
+        match compression.codec() {
+            None => {
+                data.remove(0);
+                Ok(data)
+            }
+            Some(codec) => codec.decompress(&data[5..], len),
+        }
+    }
+
+    /// Returns the stored length of encoded data with the given encoded and
+    /// decoded length, for use in compression ratios. Data is only compressed
+    /// if that makes it smaller, so encoded data that is longer than its
+    /// decoded length is uncompressed, and its header is not counted. The
+    /// ratio of uncompressed data is thus 1.0.
+    pub fn stored_len(encoded_len: u64, decoded_len: u64) -> u64 {
+        encoded_len.min(decoded_len)
+    }
+
+    /// Returns the decoded length of encoded data, without decoding it.
+    pub fn decoded_len(data: &[u8]) -> Result<usize> {
+        Ok(Self::decode_header(data)?.1)
+    }
+
+    /// Decodes the header of encoded data, returning the compression and the
+    /// decoded length.
+    fn decode_header(data: &[u8]) -> Result<(Self, usize)> {
+        let Some(id) = data.first() else {
+            return Err(Error::Internal("Missing compression ID".into()));
+        };
+        let compression = Self::from_id(*id)?;
+        if compression == Self::None {
+            return Ok((compression, data.len() - 1));
+        }
+        let len =
+            data.get(1..5).ok_or_else(|| Error::Internal("Missing uncompressed length".into()))?;
+        Ok((compression, u32::from_be_bytes(len.try_into()?) as usize))
+    }
+}
+
+impl std::fmt::Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Lz => write!(f, "lz"),
+        }
+    }
+}
+
+impl std::str::FromStr for Compression {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "none" | "" => Ok(Self::None),
+            "lz" => Ok(Self::Lz),
+            s => Err(Error::Config(format!("Unknown compression codec {}", s))),
+        }
+    }
+}
+
+/// A simple LZ77 codec, using a block format similar to LZ4's (but not
+/// compatible with it). It is fast and compresses repeated byte strings, such
+/// as repeated column values in rows, but is not a good general-purpose
+/// compressor.
+///
+/// The compressed data is a sequence of literal runs and back-references to
+/// earlier data (matches), each encoded as:
+///
+/// - A token byte, with the literal length in the high 4 bits and the match
+///   length minus 4 in the low 4 bits. A length of 15 is followed by
+///   additional length bytes, which are added to it until a byte below 255.
+/// - The literal bytes.
+/// - The match offset, i.e. the distance back from the current output
+///   position, as little-endian u16.
+///
+/// The last sequence contains only literals, and ends the data.
+pub struct Lz;
+
+impl Lz {
+    /// The minimum match length.
+    const MIN_MATCH: usize = 4;
+
+    /// The maximum match offset.
+    const MAX_OFFSET: usize = u16::MAX as usize;
+
+    /// The number of hash table bits.
+    const HASH_BITS: u32 = 12;
+
+    /// Writes a sequence of literals followed by an optional match offset and
+    /// length.
+    fn write_sequence(output: &mut Vec<u8>, literals: &[u8], m: Option<(usize, usize)>) {
+        let match_len = m.map_or(0, |(_, len)| len - Self::MIN_MATCH);
+        output.push(((literals.len().min(15) << 4) | match_len.min(15)) as u8);
+        if literals.len() >= 15 {
+            Self::write_length(output, literals.len() - 15);
+        }
+        output.extend_from_slice(literals);
+        if let Some((offset, _)) = m {
+            output.extend_from_slice(&(offset as u16).to_le_bytes());
+            if match_len >= 15 {
+                Self::write_length(output, match_len - 15);
+            }
+        }
+    }
+
+    /// Writes additional length bytes.
+    fn write_length(output: &mut Vec<u8>, mut len: usize) {
+        while len >= 255 {
+            output.push(255);
+            len -= 255;
+        }
+        output.push(len as u8);
+    }
+
+    /// Reads additional length bytes, if the token length is 15.
+    fn read_length(input: &[u8], pos: &mut usize, mut len: usize) -> Option<usize> {
+        if len < 15 {
+            return Some(len);
+        }
+        loop {
+            let byte = *input.get(*pos)?;
+            *pos += 1;
+            len = len.checked_add(byte as usize)?;
+            if byte < 255 {
+                return Some(len);
+            }
+        }
+    }
+}
+
+impl Codec for Lz {
+    fn compress(&self, input: &[u8]) -> Vec<u8> {
+        let mut output = Vec::with_capacity(input.len() / 2);
+        // Maps hashes of 4-byte sequences to their last position plus 1.
+        let mut table = vec![0usize; 1 << Self::HASH_BITS];
+        let mut literal_start = 0;
+        let mut pos = 0;
+        while pos + Self::MIN_MATCH <= input.len() {
+            let seq = &input[pos..pos + Self::MIN_MATCH];
+            let hash = u32::from_le_bytes(seq.try_into().expect("invalid sequence length"))
+                .wrapping_mul(2654435761)
+                >> (32 - Self::HASH_BITS);
+            let candidate = std::mem::replace(&mut table[hash as usize], pos + 1);
+            if candidate == 0
+                || pos - (candidate - 1) > Self::MAX_OFFSET
+                || input[candidate - 1..candidate - 1 + Self::MIN_MATCH] != *seq
+            {
+                pos += 1;
+                continue;
+            }
+            let start = candidate - 1;
+            let mut len = Self::MIN_MATCH;
+            while pos + len < input.len() && input[start + len] == input[pos + len] {
+                len += 1;
+            }
+            Self::write_sequence(&mut output, &input[literal_start..pos], Some((pos - start, len)));
+            pos += len;
+            literal_start = pos;
+        }
+        Self::write_sequence(&mut output, &input[literal_start..], None);
+        output
+    }
+
+    fn decompress(&self, input: &[u8], len: usize) -> Result<Vec<u8>> {
+        let decompress = || -> Option<Vec<u8>> {
+            let mut output = Vec::with_capacity(len);
+            let mut pos = 0;
+            loop {
+                let token = *input.get(pos)?;
+                pos += 1;
+                let literal_len = Self::read_length(input, &mut pos, (token >> 4) as usize)?;
+                output.extend_from_slice(input.get(pos..pos.checked_add(literal_len)?)?);
+                pos += literal_len;
+                if pos == input.len() {
+                    break;
+                }
+
+                let offset = u16::from_le_bytes(input.get(pos..pos + 2)?.try_into().ok()?) as usize;
+                pos += 2;
+                let match_len = Self::read_length(input, &mut pos, (token & 0xf) as usize)?
+                    .checked_add(Self::MIN_MATCH)?;
+                if offset == 0 || offset > output.len() || output.len() + match_len > len {
+                    return None;
+                }
+                // The match may overlap the output it's copying, so copy it
+                // byte by byte.
+                let start = output.len() - offset;
+                for i in start..start + match_len {
+                    output.push(output[i]);
+                }
+            }
+            (output.len() == len).then_some(output)
+        };
+        decompress().ok_or_else(|| Error::Internal("Invalid compressed data".into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Tests that data roundtrips through encode/decode with each compression.
+    fn roundtrip() -> Result<()> {
+        let mut random = vec![0u8; 1000];
+        rand::Rng::fill(&mut rand::thread_rng(), random.as_mut_slice());
+        let cases: Vec<Vec<u8>> = vec![
+            vec![],
+            vec![0x01],
+            b"abcd".to_vec(),
+            b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".to_vec(),
+            b"foo bar foo bar foo bar baz".repeat(100),
+            (0..=255).cycle().take(10_000).collect(),
+            vec![0; 100_000],
+            random,
+        ];
+        for compression in [Compression::None, Compression::Lz] {
+            for data in &cases {
+                let encoded = compression.encode(data);
+                assert_eq!(Compression::decoded_len(&encoded)?, data.len());
+                assert_eq!(&Compression::decode(encoded)?, data);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that the stored length excludes the header of uncompressed data,
+    /// but includes the header of compressed data.
+    fn stored_len() -> Result<()> {
+        for (compression, data, expect) in [
+            (Compression::None, b"foo bar ".repeat(100), 800),
+            (Compression::Lz, b"abc".to_vec(), 3),
+            (Compression::Lz, vec![], 0),
+        ] {
+            let encoded = compression.encode(&data);
+            let decoded_len = Compression::decoded_len(&encoded)? as u64;
+            assert_eq!(Compression::stored_len(encoded.len() as u64, decoded_len), expect);
+        }
+        let encoded = Compression::Lz.encode(&b"foo bar ".repeat(100));
+        assert_eq!(Compression::stored_len(encoded.len() as u64, 800), encoded.len() as u64);
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that compression is only used when it makes data smaller.
+    fn encode() -> Result<()> {
+        assert_eq!(Compression::None.encode(b"abc"), b"\x00abc");
+        assert_eq!(Compression::Lz.encode(b"abc"), b"\x00abc");
+
+        let data = b"foo bar ".repeat(100);
+        assert_eq!(Compression::None.encode(&data).len(), data.len() + 1);
+        let encoded = Compression::Lz.encode(&data);
+        assert_eq!(encoded[0], 1);
+        assert!(encoded.len() < 30, "compressed to {} bytes", encoded.len());
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that invalid encoded data errors.
+    fn decode_invalid() {
+        let mut encoded = Compression::Lz.encode(&b"foo bar ".repeat(100));
+        assert!(Compression::decode(vec![]).is_err());
+        assert!(Compression::decode(vec![0xff, 0x01]).is_err());
+        assert!(Compression::decode(encoded[..encoded.len() - 1].to_vec()).is_err());
+        encoded[4] += 1; // uncompressed length
+        assert!(Compression::decode(encoded).is_err());
+    }
+
+    #[test]
+    /// Tests parsing compression names.
+    fn from_str() -> Result<()> {
+        assert_eq!("none".parse::<Compression>()?, Compression::None);
+        assert_eq!("lz".parse::<Compression>()?, Compression::Lz);
+        assert_eq!(Compression::Lz.to_string().parse::<Compression>()?, Compression::Lz);
+        assert!(matches!("zip".parse::<Compression>(), Err(Error::Config(_))));
+        Ok(())
+    }
+}
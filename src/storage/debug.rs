@@ -1,8 +1,9 @@
 //! Storage debug helpers, primarily formatting of raw engine data.
 
 use std::collections::HashSet;
+use std::ops::{Bound, RangeBounds};
 
-use super::engine::{self, ScanIterator, Status};
+use super::engine::{self, ScanIterator, Status, WriteBatch};
 use super::mvcc::{self, TransactionState};
 use crate::encoding::bincode;
 use crate::error::Result;
@@ -24,13 +25,32 @@ pub fn format_raw(v: &[u8]) -> String {
 /// Formats a transaction state.
 pub fn format_txn(state: &TransactionState) -> String {
     format!(
-        "v{} {} active={}",
+        "v{} {}{}{} active={}",
         state.version,
         if state.read_only { "read-only" } else { "read-write" },
+        if state.serializable { " serializable" } else { "" },
+        if state.savepoints > 0 { format!(" savepoints={}", state.savepoints) } else { "".into() },
         format_hashset(&state.active)
     )
 }
 
+/// Formats a key range, e.g. ["a".."c") or ("a"..
+pub fn format_range(range: &impl RangeBounds<Vec<u8>>) -> String {
+    format!(
+        "{}..{}",
+        match range.start_bound() {
+            Bound::Excluded(k) => format!("({}", format_raw(k)),
+            Bound::Included(k) => format!("[{}", format_raw(k)),
+            Bound::Unbounded => "".to_string(),
+        },
+        match range.end_bound() {
+            Bound::Excluded(k) => format!("{})", format_raw(k)),
+            Bound::Included(k) => format!("{}]", format_raw(k)),
+            Bound::Unbounded => "".to_string(),
+        },
+    )
+}
+
 /// Formats a HashSet with sorted elements.
 pub fn format_hashset<T: Copy + Ord + std::fmt::Display>(set: &HashSet<T>) -> String {
     let mut elements: Vec<T> = set.iter().copied().collect();
@@ -54,7 +74,7 @@ pub fn format_key_value(key: &[u8], value: &Option<Vec<u8>>) -> (String, Option<
         fkey = format!("{:?}", key);
 
         match key {
-            mvcc::Key::NextVersion => {
+            mvcc::Key::NextVersion | mvcc::Key::LowWater => {
                 if let Some(ref v) = value {
                     if let Ok(v) = bincode::deserialize::<u64>(v) {
                         fvalue = Some(format!("{}", v))
@@ -85,6 +105,55 @@ pub fn format_key_value(key: &[u8], value: &Option<Vec<u8>>) -> (String, Option<
             mvcc::Key::Unversioned(userkey) => {
                 fkey = format!("Unversioned({})", format_raw(&userkey));
             }
+            mvcc::Key::TxnRead(version, range) => {
+                if let Ok(range) = bincode::deserialize::<(Bound<Vec<u8>>, Bound<Vec<u8>>)>(&range)
+                {
+                    fkey = format!("TxnRead({}, {})", version, format_range(&range));
+                }
+            }
+            mvcc::Key::TxnSavepoint(version, savepoint, userkey) => {
+                fkey =
+                    format!("TxnSavepoint({}, {}, {})", version, savepoint, format_raw(&userkey));
+                // The previous value is an Option of the raw version value,
+                // which is itself an Option (None for tombstones).
+                if let Some(ref v) = value {
+                    match bincode::deserialize::<Option<Vec<u8>>>(v) {
+                        Ok(Some(previous)) => match bincode::deserialize(&previous) {
+                            Ok(Some(v)) => fvalue = Some(format!("Some({})", format_raw(v))),
+                            Ok(None) => fvalue = Some(String::from("Some(None)")),
+                            Err(_) => {}
+                        },
+                        Ok(None) => fvalue = Some(String::from("None")),
+                        Err(_) => {}
+                    }
+                }
+            }
+            mvcc::Key::Lock(userkey, version) => {
+                fkey = format!("Lock({}, {})", format_raw(&userkey), version);
+                if let Some(ref v) = value {
+                    if let Ok(mode) = bincode::deserialize::<mvcc::LockMode>(v) {
+                        fvalue = Some(format!("{:?}", mode));
+                    }
+                }
+            }
+            mvcc::Key::TxnLock(version, userkey) => {
+                fkey = format!("TxnLock({}, {})", version, format_raw(&userkey))
+            }
+            mvcc::Key::TxnWait(_) => {
+                if let Some(ref v) = value {
+                    if let Ok(waiting) = bincode::deserialize::<HashSet<u64>>(v) {
+                        fvalue = Some(format_hashset(&waiting));
+                    }
+                }
+            }
+            mvcc::Key::TxnCommitted(_) => {
+                if let Some(ref v) = value {
+                    if let Ok(conflicts) = bincode::deserialize::<mvcc::Conflicts>(v) {
+                        fvalue =
+                            Some(format!("rw_in={} rw_out={}", conflicts.rw_in, conflicts.rw_out));
+                    }
+                }
+            }
         }
     }
 
@@ -119,7 +188,10 @@ impl<E: engine::Engine> Engine<E> {
 }
 
 impl<E: engine::Engine> engine::Engine for Engine<E> {
-    type ScanIterator<'a> = E::ScanIterator<'a> where E: 'a;
+    type ScanIterator<'a>
+        = E::ScanIterator<'a>
+    where
+        E: 'a;
 
     fn flush(&mut self) -> Result<()> {
         self.inner.flush()
@@ -155,4 +227,10 @@ impl<E: engine::Engine> engine::Engine for Engine<E> {
     fn status(&mut self) -> Result<Status> {
         self.inner.status()
     }
+
+    fn write_batch(&mut self, batch: WriteBatch) -> Result<()> {
+        self.inner.write_batch(batch.clone())?;
+        self.write_log.extend(batch);
+        Ok(())
+    }
 }
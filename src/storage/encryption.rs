@@ -0,0 +1,225 @@
+use crate::error::{Error, Result};
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::RngCore as _;
+use std::path::Path;
+
+/// The length of an encryption key.
+pub const KEY_LEN: usize = 32;
+
+/// The length of an encryption nonce.
+const NONCE_LEN: usize = 24;
+
+/// The length of an authentication tag.
+const TAG_LEN: usize = 16;
+
+/// The number of bytes that encryption adds to the plaintext: the
+/// authentication tag and nonce.
+pub const OVERHEAD: usize = TAG_LEN + NONCE_LEN;
+
+/// An encryption key ID, derived from the key. 0 is used for unencrypted data.
+pub type KeyID = u64;
+
+/// An encryption key, which encrypts and authenticates data using
+/// XChaCha20-Poly1305 with random nonces.
+///
+/// Encrypted data consists of the ciphertext, which has the same length as
+/// the plaintext, followed by the authentication tag and the nonce. Keeping
+/// the ciphertext first allows callers to locate parts of the plaintext
+/// within it.
+#[derive(Clone)]
+pub struct Key {
+    /// The key ID.
+    id: KeyID,
+    /// The cipher.
+    cipher: XChaCha20Poly1305,
+}
+
+impl Key {
+    /// Creates a key from raw key bytes.
+    pub fn new(key: [u8; KEY_LEN]) -> Self {
+        let cipher = XChaCha20Poly1305::new(&key.into());
+        // The key ID is derived from the authentication tag of an empty
+        // message with a fixed nonce, which doesn't reveal the key.
+        let tag = cipher
+            .encrypt(&XNonce::default(), Payload { msg: &[], aad: b"radb key id" })
+            .expect("encryption failed");
+        let id = u64::from_be_bytes(tag[..8].try_into().expect("invalid tag length"));
+        Self { id, cipher }
+    }
+
+    /// Generates a new random key.
+    pub fn generate() -> Self {
+        let mut key = [0; KEY_LEN];
+        rand::thread_rng().fill_bytes(&mut key);
+        Self::new(key)
+    }
+
+    /// Parses a hex-encoded key.
+    pub fn from_hex(hex: &str) -> Result<Self> {
+        let mut key = [0; KEY_LEN];
+        hex::decode_to_slice(hex.trim(), &mut key)
+            .map_err(|err| Error::Config(format!("Invalid encryption key: {}", err)))?;
+        Ok(Self::new(key))
+    }
+
+    /// Returns the key ID.
+    pub fn id(&self) -> KeyID {
+        self.id
+    }
+
+    /// Encrypts data, authenticating it along with the given associated data.
+    pub fn encrypt(&self, data: &[u8], aad: &[u8]) -> Vec<u8> {
+        let mut nonce = XNonce::default();
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let mut buf =
+            self.cipher.encrypt(&nonce, Payload { msg: data, aad }).expect("encryption failed");
+        buf.extend_from_slice(&nonce);
+        buf
+    }
+
+    /// Decrypts and authenticates data encrypted by encrypt(), with the same
+    /// associated data. Errors if the data was not encrypted with this key or
+    /// has been modified.
+    pub fn decrypt(&self, data: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        let (msg, nonce) = data
+            .len()
+            .checked_sub(NONCE_LEN)
+            .filter(|len| *len >= TAG_LEN)
+            .map(|len| data.split_at(len))
+            .ok_or_else(|| Error::Internal("Encrypted data is too short".into()))?;
+        self.cipher
+            .decrypt(XNonce::from_slice(nonce), Payload { msg, aad })
+            .map_err(|_| Error::Internal("Decryption failed, data is corrupt".into()))
+    }
+}
+
+impl std::fmt::Debug for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Key({:016x})", self.id)
+    }
+}
+
+/// A set of encryption keys: the current key, used to encrypt new data, and
+/// any previous keys, which can still decrypt existing data. To rotate keys,
+/// add a new current key and keep the previous keys until all data has been
+/// re-encrypted with the new key.
+#[derive(Clone, Debug)]
+pub struct Keyring {
+    /// The keys, with the current key first.
+    keys: Vec<Key>,
+}
+
+impl Keyring {
+    /// Creates a keyring from the current key and any previous keys.
+    pub fn new(current: Key, previous: Vec<Key>) -> Self {
+        Self { keys: std::iter::once(current).chain(previous).collect() }
+    }
+
+    /// Loads a keyring from a key file, containing one hex-encoded 256-bit
+    /// key per line: first the current key, then any previous keys. Empty
+    /// lines and lines starting with # are ignored.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path).map_err(|err| {
+            Error::Config(format!("Can't read key file {}: {}", path.display(), err))
+        })?;
+        let mut keys = content
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(Key::from_hex)
+            .collect::<Result<Vec<_>>>()?
+            .into_iter();
+        let current = keys
+            .next()
+            .ok_or_else(|| Error::Config(format!("No keys in key file {}", path.display())))?;
+        Ok(Self::new(current, keys.collect()))
+    }
+
+    /// Returns the current key.
+    pub fn current(&self) -> &Key {
+        &self.keys[0]
+    }
+
+    /// Looks up a key by ID.
+    pub fn get(&self, id: KeyID) -> Option<&Key> {
+        self.keys.iter().find(|key| key.id == id)
+    }
+
+    /// Looks up a key by ID, returning an error if it isn't in the keyring.
+    /// The path is used in the error message.
+    pub fn get_or_err(&self, id: KeyID, path: &Path) -> Result<&Key> {
+        self.get(id).ok_or_else(|| {
+            Error::Config(format!(
+                "Wrong encryption key: {} is encrypted with key {:016x}, but the key file only \
+                 contains keys {}",
+                path.display(),
+                id,
+                self.keys.iter().map(|k| format!("{:016x}", k.id)).collect::<Vec<_>>().join(", ")
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Tests encryption roundtrips, and that decryption fails with the wrong
+    /// key, associated data, or modified data.
+    fn encrypt_decrypt() -> Result<()> {
+        let key = Key::generate();
+        for data in [&b""[..], b"foo", &[7; 1000]] {
+            let encrypted = key.encrypt(data, b"aad");
+            assert_eq!(encrypted.len(), data.len() + OVERHEAD);
+            assert_eq!(key.decrypt(&encrypted, b"aad")?, data);
+            assert_ne!(key.encrypt(data, b"aad"), encrypted, "nonce reused");
+
+            assert!(Key::generate().decrypt(&encrypted, b"aad").is_err());
+            assert!(key.decrypt(&encrypted, b"other").is_err());
+            for i in 0..encrypted.len() {
+                let mut corrupt = encrypted.clone();
+                corrupt[i] ^= 0x01;
+                assert!(key.decrypt(&corrupt, b"aad").is_err());
+            }
+        }
+        assert!(key.decrypt(&[0; OVERHEAD - 1], b"").is_err());
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that key IDs are deterministic and differ between keys.
+    fn key_id() -> Result<()> {
+        let hex = "00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff";
+        assert_eq!(Key::from_hex(hex)?.id(), Key::from_hex(hex)?.id());
+        assert_ne!(Key::from_hex(hex)?.id(), Key::generate().id());
+        assert_ne!(Key::from_hex(hex)?.id(), 0);
+        assert!(matches!(Key::from_hex("0011"), Err(Error::Config(_))));
+        assert!(matches!(Key::from_hex(&hex.replace('0', "x")), Err(Error::Config(_))));
+        Ok(())
+    }
+
+    #[test]
+    /// Tests loading a keyring from a key file.
+    fn keyring_load() -> Result<()> {
+        let dir = tempdir::TempDir::new("radb")?;
+        let path = dir.path().join("key");
+        let (current, previous) = (Key::generate(), Key::generate());
+        let hex1 = "00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff";
+        let hex2 = "ffeeddccbbaa99887766554433221100ffeeddccbbaa99887766554433221100";
+        std::fs::write(&path, format!("# current key\n{}\n\n{}\n", hex1, hex2))?;
+
+        let keyring = Keyring::load(&path)?;
+        assert_eq!(keyring.current().id(), Key::from_hex(hex1)?.id());
+        assert!(keyring.get(Key::from_hex(hex2)?.id()).is_some());
+        assert!(keyring.get(current.id()).is_none());
+        assert!(matches!(keyring.get_or_err(previous.id(), &path), Err(Error::Config(_))));
+
+        std::fs::write(&path, "# no keys\n")?;
+        assert!(matches!(Keyring::load(&path), Err(Error::Config(_))));
+        assert!(matches!(Keyring::load(&dir.path().join("missing")), Err(Error::Config(_))));
+        Ok(())
+    }
+}
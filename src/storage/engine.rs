@@ -56,6 +56,54 @@ pub trait Engine: std::fmt::Display + Send + Sync {
 
     /// Returns engine status.
     fn status(&mut self) -> Result<Status>;
+
+    /// Atomically applies a batch of writes, in order. If the process crashes,
+    /// either all or none of the writes are applied. Like other writes, they
+    /// are only guaranteed durable after calling flush().
+    fn write_batch(&mut self, batch: WriteBatch) -> Result<()>;
+}
+
+/// A batch of writes, applied atomically via Engine::write_batch().
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WriteBatch {
+    /// The writes as key/value pairs, with None values for deletes.
+    writes: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl WriteBatch {
+    /// Creates a new, empty write batch.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a delete of a key to the batch.
+    pub fn delete(&mut self, key: &[u8]) {
+        self.writes.push((key.to_vec(), None))
+    }
+
+    /// Returns true if the batch is empty.
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    /// Returns the number of writes in the batch.
+    pub fn len(&self) -> usize {
+        self.writes.len()
+    }
+
+    /// Adds a write of a key/value pair to the batch.
+    pub fn set(&mut self, key: &[u8], value: Vec<u8>) {
+        self.writes.push((key.to_vec(), Some(value)))
+    }
+}
+
+impl IntoIterator for WriteBatch {
+    type Item = (Vec<u8>, Option<Vec<u8>>);
+    type IntoIter = std::vec::IntoIter<Self::Item>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.writes.into_iter()
+    }
 }
 
 /// A scan iterator, with a blanket implementation (in lieu of trait aliases).
@@ -78,6 +126,9 @@ pub struct Status {
     pub live_disk_size: u64,
     /// The on-disk size of garbage data.
     pub garbage_disk_size: u64,
+    /// The compression ratio of stored data, i.e. its uncompressed size
+    /// divided by its compressed size. 1.0 if uncompressed.
+    pub compression_ratio: f64,
 }
 
 #[cfg(test)]
@@ -335,6 +386,38 @@ pub(crate) mod tests {
                 Ok(())
             }
 
+            #[test]
+            /// Tests that write batches are applied in order.
+            fn write_batch() -> Result<()> {
+                let mut s = $setup;
+                s.set(b"a", vec![1])?;
+                s.set(b"b", vec![2])?;
+
+                // An empty batch does nothing.
+                s.write_batch(crate::storage::WriteBatch::new())?;
+                assert_scan(s.scan(..), vec![(b"a", vec![1]), (b"b", vec![2])])?;
+
+                // Later writes in the batch replace earlier ones.
+                let mut batch = crate::storage::WriteBatch::new();
+                batch.set(b"c", vec![3]);
+                batch.delete(b"a");
+                batch.set(b"b", vec![0]);
+                batch.delete(b"x");
+                batch.set(b"b", vec![2, 2]);
+                batch.set(b"d", vec![4]);
+                batch.delete(b"d");
+                assert_eq!(batch.len(), 7);
+                s.write_batch(batch)?;
+
+                assert_scan(s.scan(..), vec![(b"b", vec![2, 2]), (b"c", vec![3])])?;
+                assert_eq!(s.get(b"a")?, None);
+                assert_eq!(s.get(b"d")?, None);
+                let status = s.status()?;
+                assert_eq!(status.keys, 2);
+                assert_eq!(status.size, 5);
+                Ok(())
+            }
+
             #[test]
             /// Runs random operations both on a Engine and a known-good
             /// BTreeMap, comparing the results of each operation as well as the
@@ -0,0 +1,1600 @@
+use super::{Compression, Engine, Status, WriteBatch};
+use crate::encoding::bincode;
+use crate::error::{Error, Result};
+
+use fs4::FileExt;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::{Bound, RangeBounds};
+use std::path::{Path, PathBuf};
+
+/// The magic bytes at the start of a write-ahead log file.
+const WAL_MAGIC: [u8; 4] = *b"LSMW";
+
+/// The magic bytes at the start and end of an SSTable file.
+const TABLE_MAGIC: [u8; 4] = *b"LSMT";
+
+/// The file format version.
+const VERSION: u32 = 3;
+
+/// The length of the file header: magic bytes and version.
+const HEADER_LEN: u64 = 8;
+
+/// The length of an SSTable footer: index offset, index length, and magic.
+const FOOTER_LEN: u64 = 20;
+
+/// The length of an entry header: key length and value length.
+const ENTRY_HEADER_LEN: u64 = 8;
+
+/// The value length of a WAL entry containing a write batch.
+const BATCH: i32 = -2;
+
+/// A key/value entry, with a None value for tombstones.
+type Entry = (Vec<u8>, Option<Vec<u8>>);
+
+/// The memtable, mapping keys to values or None for tombstones.
+type Memtable = BTreeMap<Vec<u8>, Option<Vec<u8>>>;
+
+/// A log-structured merge-tree (LSM-tree) key/value storage engine. Unlike
+/// BitCask, it does not keep all keys in memory, so the dataset can be much
+/// larger than RAM. See: https://www.cs.umb.edu/~poneil/lsmtree.pdf
+///
+/// Writes are appended to a write-ahead log (WAL) and inserted into an
+/// in-memory memtable. Deletes insert a tombstone. When the memtable exceeds
+/// the memtable size, it is written out as a sorted string table (SSTable)
+/// in level 0, and the WAL is cleared.
+///
+/// SSTables are organized into levels, using leveled compaction. Tables in
+/// level 0 may overlap, while tables in the following levels are sorted and
+/// non-overlapping. When level 0 exceeds a number of tables, they are merged
+/// into level 1 along with the overlapping level 1 tables. When a following
+/// level exceeds its size limit (the level size multiplied by 10 for each
+/// level), one of its tables is merged into the next level, round-robin.
+/// Tombstones are removed when merging into the bottommost level.
+///
+/// Reads check the memtable, then level 0 from newest to oldest, then each
+/// following level. Scans merge the memtable and all overlapping tables.
+///
+/// The set of tables in each level is recorded in a manifest file, which is
+/// replaced atomically. Table files not in the manifest are removed on
+/// startup, as they are leftovers from interrupted flushes or compactions.
+///
+/// This implementation makes several simplifications over standard LSM-trees:
+///
+/// - Flushes and compactions run synchronously during writes.
+/// - There are no bloom filters or block cache, so point reads can read a
+///   block from each level.
+/// - Table block indexes are kept in memory.
+///
+/// The WAL starts with the magic bytes "LSMW" and the format version as
+/// big-endian u32, followed by entries with the same format as BitCask log
+/// entries: a CRC32 checksum, the key length, the value length or -1 for
+/// tombstones, the key, and the value. A write batch is written as a single
+/// entry with a value length of -2, whose key contains the batch's encoded
+/// entries, so that it is replayed either completely or not at all. An
+/// incomplete or corrupt entry at the end of the WAL is assumed to be a torn
+/// write and is truncated, but corruption elsewhere is an error.
+///
+/// SSTables start with the magic bytes "LSMT" and the format version,
+/// followed by data blocks, the block index, and a footer. Data blocks
+/// contain entries of key length as big-endian u32, value length as
+/// big-endian i32 or -1 for tombstones, key, and value. Blocks are encoded
+/// with the configured compression (see Compression), and followed by a CRC32
+/// checksum of the encoded block. The block index contains the length and
+/// bytes of the table's first key, the number of live (non-tombstone) entries
+/// and their key and value size as big-endian u64, followed by the last key
+/// length and bytes, offset, length, and decoded length of each data block
+/// (lengths and offsets as big-endian u32 and u64 respectively), followed by
+/// a CRC32 checksum. The footer contains the index offset and length as
+/// big-endian u64, and the magic bytes.
+pub struct Lsm {
+    /// The database directory.
+    dir: PathBuf,
+    /// The database directory lock file, locked until the database is closed.
+    lock: std::fs::File,
+    /// Engine options.
+    opts: Options,
+    /// The write-ahead log, containing the memtable writes.
+    wal: Wal,
+    /// The memtable.
+    memtable: Memtable,
+    /// The approximate size of the memtable.
+    memtable_size: u64,
+    /// The SSTables in each level. Level 0 is ordered from oldest to newest,
+    /// the following levels are ordered by key.
+    levels: Vec<Vec<Table>>,
+    /// The next SSTable ID.
+    next_id: u64,
+    /// The last key compacted in each level, for round-robin compaction.
+    cursors: Vec<Option<Vec<u8>>>,
+}
+
+/// LSM-tree engine options.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    /// The memtable size at which it is flushed to level 0.
+    pub memtable_size: u64,
+    /// The target size of SSTable data blocks.
+    pub block_size: u64,
+    /// The target size of SSTables written by compactions.
+    pub table_size: u64,
+    /// The number of level 0 tables at which they are compacted into level 1.
+    pub l0_tables: usize,
+    /// The maximum size of level 1. Each following level is 10 times larger.
+    pub level_size: u64,
+    /// The compression used for SSTable data blocks.
+    pub compression: Compression,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            memtable_size: 4 * 1024 * 1024,
+            block_size: 4 * 1024,
+            table_size: 2 * 1024 * 1024,
+            l0_tables: 4,
+            level_size: 10 * 1024 * 1024,
+            compression: Compression::None,
+        }
+    }
+}
+
+/// The manifest, listing the SSTable IDs in each level.
+#[derive(Default, Serialize, Deserialize)]
+struct Manifest {
+    next_id: u64,
+    levels: Vec<Vec<u64>>,
+}
+
+impl Lsm {
+    /// Opens or creates an LSM-tree database in the given directory, using
+    /// default options.
+    pub fn new(path: PathBuf) -> Result<Self> {
+        Self::new_with_options(path, Options::default())
+    }
+
+    /// Opens or creates an LSM-tree database in the given directory.
+    pub fn new_with_options(path: PathBuf, opts: Options) -> Result<Self> {
+        log::info!("Opening database {}", path.display());
+        std::fs::create_dir_all(&path)?;
+        let lock = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path.join("LOCK"))?;
+        lock.try_lock_exclusive()?;
+
+        // Open the tables in the manifest.
+        let manifest = match std::fs::read(path.join("MANIFEST")) {
+            Ok(buf) => Self::decode_manifest(&buf)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Manifest::default(),
+            Err(err) => return Err(err.into()),
+        };
+        let mut levels = Vec::new();
+        for ids in &manifest.levels {
+            let tables = ids
+                .iter()
+                .map(|id| Table::open(*id, Self::table_path(&path, *id)))
+                .collect::<Result<Vec<_>>>()?;
+            levels.push(tables);
+        }
+        if levels.is_empty() {
+            levels.push(Vec::new());
+        }
+
+        // Remove table files that aren't in the manifest, left behind by
+        // interrupted flushes and compactions.
+        for entry in std::fs::read_dir(&path)? {
+            let entry_path = entry?.path();
+            let Some(name) = entry_path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            let orphan = match name.strip_suffix(".sst").and_then(|id| id.parse::<u64>().ok()) {
+                Some(id) => !manifest.levels.iter().any(|ids| ids.contains(&id)),
+                None => name.ends_with(".new"),
+            };
+            if orphan {
+                std::fs::remove_file(&entry_path)?;
+            }
+        }
+
+        // Replay the WAL into the memtable.
+        let mut memtable = Memtable::new();
+        let mut memtable_size = 0;
+        let wal = Wal::open(path.join("wal"), |key, value| {
+            memtable_size += Self::entry_size(&key, value.as_deref());
+            memtable.insert(key, value);
+        })?;
+
+        let cursors = vec![None; levels.len()];
+        let s = Self {
+            dir: path,
+            lock,
+            opts,
+            wal,
+            memtable,
+            memtable_size,
+            levels,
+            next_id: manifest.next_id.max(1),
+            cursors,
+        };
+        log::info!(
+            "Opened {} tables in {} levels and {} memtable entries in {}",
+            s.levels.iter().map(|l| l.len()).sum::<usize>(),
+            s.levels.len(),
+            s.memtable.len(),
+            s.dir.display()
+        );
+        Ok(s)
+    }
+
+    /// Returns the path of an SSTable file.
+    fn table_path(dir: &Path, id: u64) -> PathBuf {
+        dir.join(format!("{:010}.sst", id))
+    }
+
+    /// Returns the approximate size of an entry.
+    fn entry_size(key: &[u8], value: Option<&[u8]>) -> u64 {
+        ENTRY_HEADER_LEN + key.len() as u64 + value.map_or(0, |v| v.len() as u64)
+    }
+
+    /// Returns the size limit of a level, for levels 1 and above.
+    fn level_limit(&self, level: usize) -> u64 {
+        self.opts.level_size.saturating_mul(10u64.saturating_pow(level as u32 - 1))
+    }
+
+    /// Decodes a manifest, verifying its checksum.
+    fn decode_manifest(buf: &[u8]) -> Result<Manifest> {
+        let Some(split) = buf.len().checked_sub(4) else {
+            return Err(Error::Internal("Invalid LSM manifest".into()));
+        };
+        let (body, checksum) = buf.split_at(split);
+        if crc32fast::hash(body) != u32::from_be_bytes(checksum.try_into()?) {
+            return Err(Error::Internal("LSM manifest checksum mismatch".into()));
+        }
+        bincode::deserialize(body)
+    }
+
+    /// Atomically writes the manifest, by writing a new file and renaming it.
+    /// The directory is synced after the rename, so that the new manifest is
+    /// durable before e.g. the WAL is cleared.
+    fn write_manifest(&self) -> Result<()> {
+        let manifest = Manifest {
+            next_id: self.next_id,
+            levels: self.levels.iter().map(|l| l.iter().map(|t| t.id).collect()).collect(),
+        };
+        let mut buf = bincode::serialize(&manifest)?;
+        buf.extend_from_slice(&crc32fast::hash(&buf).to_be_bytes());
+
+        let path = self.dir.join("MANIFEST.new");
+        let mut file = std::fs::File::create(&path)?;
+        file.write_all(&buf)?;
+        file.sync_all()?;
+        std::fs::rename(&path, self.dir.join("MANIFEST"))?;
+        sync_dir(&self.dir)
+    }
+
+    /// Writes a key/value entry, with None for tombstones.
+    fn write(&mut self, key: &[u8], value: Option<Vec<u8>>) -> Result<()> {
+        self.wal.append(key, value.as_deref())?;
+        self.memtable_size += Self::entry_size(key, value.as_deref());
+        self.memtable.insert(key.to_vec(), value);
+        self.maybe_flush_memtable()
+    }
+
+    /// Flushes the memtable and compacts levels if the memtable is full.
+    fn maybe_flush_memtable(&mut self) -> Result<()> {
+        if self.memtable_size >= self.opts.memtable_size {
+            self.flush_memtable()?;
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the memtable to a new level 0 table, and clears the WAL. The WAL
+    /// is only cleared once the table and manifest are durable, otherwise a
+    /// crash could restore the old manifest and discard the table as an orphan
+    /// after the WAL entries are gone.
+    fn flush_memtable(&mut self) -> Result<()> {
+        if self.memtable.is_empty() {
+            return Ok(());
+        }
+        let mut builder = self.new_table()?;
+        for (key, value) in &self.memtable {
+            builder.add(key, value.as_deref())?;
+        }
+        let table = builder.finish()?;
+        log::debug!("Flushed memtable to {}", table.path.display());
+        self.levels[0].push(table);
+        self.write_manifest()?;
+
+        self.memtable.clear();
+        self.memtable_size = 0;
+        self.wal.clear()
+    }
+
+    /// Starts writing a new table.
+    fn new_table(&mut self) -> Result<TableBuilder> {
+        let id = self.next_id;
+        self.next_id += 1;
+        TableBuilder::new(id, Self::table_path(&self.dir, id), &self.opts)
+    }
+
+    /// Compacts levels until they are within their limits.
+    fn compact(&mut self) -> Result<()> {
+        loop {
+            if self.levels[0].len() >= self.opts.l0_tables.max(1) {
+                self.compact_level(0)?;
+                continue;
+            }
+            let Some(level) = (1..self.levels.len()).find(|l| {
+                self.levels[*l].iter().map(|t| t.size).sum::<u64>() > self.level_limit(*l)
+            }) else {
+                return Ok(());
+            };
+            self.compact_level(level)?;
+        }
+    }
+
+    /// Merges tables from a level into the next level: all tables for level
+    /// 0, or the next table after the level's cursor for the following
+    /// levels. Overlapping tables in the next level are merged too.
+    fn compact_level(&mut self, level: usize) -> Result<()> {
+        if self.levels.len() == level + 1 {
+            self.levels.push(Vec::new());
+            self.cursors.push(None);
+        }
+
+        // Pick the input tables, ordered from newest to oldest.
+        let mut inputs: Vec<usize> = match level {
+            0 => (0..self.levels[0].len()).rev().collect(),
+            _ => {
+                let tables = &self.levels[level];
+                let next = match &self.cursors[level] {
+                    Some(cursor) => tables.iter().position(|t| t.first_key() > cursor.as_slice()),
+                    None => None,
+                };
+                vec![next.unwrap_or(0)]
+            }
+        };
+        let first =
+            inputs.iter().map(|i| self.levels[level][*i].first_key()).min().unwrap_or_default();
+        let last =
+            inputs.iter().map(|i| self.levels[level][*i].last_key()).max().unwrap_or_default();
+        let (first, last) = (first.to_vec(), last.to_vec());
+        let overlapping: Vec<usize> = (0..self.levels[level + 1].len())
+            .filter(|i| {
+                let table = &self.levels[level + 1][*i];
+                table.last_key() >= first.as_slice() && table.first_key() <= last.as_slice()
+            })
+            .collect();
+        let bottommost = self.levels[level + 2..].iter().all(|l| l.is_empty());
+        log::debug!(
+            "Compacting {} tables in level {} with {} tables in level {}",
+            inputs.len(),
+            level,
+            overlapping.len(),
+            level + 1
+        );
+
+        // Merge the tables into new tables in the next level, splitting them
+        // at the table size.
+        let mut outputs = Vec::new();
+        let mut builder: Option<TableBuilder> = None;
+        let mut sources: Vec<Box<dyn DoubleEndedIterator<Item = Result<Entry>> + '_>> = Vec::new();
+        for i in &inputs {
+            sources
+                .push(Box::new(self.levels[level][*i].range((Bound::Unbounded, Bound::Unbounded))));
+        }
+        for i in &overlapping {
+            sources.push(Box::new(
+                self.levels[level + 1][*i].range((Bound::Unbounded, Bound::Unbounded)),
+            ));
+        }
+        let mut next_id = self.next_id;
+        for entry in MergeIterator::new(sources, bottommost) {
+            let (key, value) = entry?;
+            if builder.is_none() {
+                let path = Self::table_path(&self.dir, next_id);
+                builder = Some(TableBuilder::new(next_id, path, &self.opts)?);
+                next_id += 1;
+            }
+            let b = builder.as_mut().expect("no builder");
+            b.add(&key, value.as_deref())?;
+            if b.size() >= self.opts.table_size {
+                outputs.push(builder.take().expect("no builder").finish()?);
+            }
+        }
+        if let Some(b) = builder {
+            outputs.push(b.finish()?);
+        }
+        self.next_id = next_id;
+
+        // Replace the tables, write the manifest, and remove the old files.
+        inputs.sort_unstable();
+        let mut removed = Vec::new();
+        for i in inputs.into_iter().rev() {
+            removed.push(self.levels[level].remove(i));
+        }
+        for i in overlapping.into_iter().rev() {
+            removed.push(self.levels[level + 1].remove(i));
+        }
+        let next_level = &mut self.levels[level + 1];
+        let pos = next_level.partition_point(|t| t.first_key() < first.as_slice());
+        next_level.splice(pos..pos, outputs);
+        self.cursors[level] = Some(last);
+        self.write_manifest()?;
+        for table in removed {
+            let path = table.path.clone();
+            drop(table);
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Lsm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "lsm")
+    }
+}
+
+impl Engine for Lsm {
+    type ScanIterator<'a> = ScanIterator<'a>;
+
+    fn delete(&mut self, key: &[u8]) -> Result<()> {
+        self.write(key, None)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.wal.sync()
+    }
+
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        if let Some(value) = self.memtable.get(key) {
+            return Ok(value.clone());
+        }
+        for table in self.levels[0].iter().rev() {
+            if let Some(value) = table.get(key)? {
+                return Ok(value);
+            }
+        }
+        for tables in &self.levels[1..] {
+            let i = tables.partition_point(|t| t.last_key() < key);
+            if let Some(table) = tables.get(i) {
+                if let Some(value) = table.get(key)? {
+                    return Ok(value);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn scan(&mut self, range: impl RangeBounds<Vec<u8>>) -> Self::ScanIterator<'_> {
+        let range = (range.start_bound().cloned(), range.end_bound().cloned());
+        let mut sources: Vec<Box<dyn DoubleEndedIterator<Item = Result<Entry>> + '_>> = Vec::new();
+        sources.push(Box::new(
+            self.memtable.range(range.clone()).map(|(k, v)| Ok((k.clone(), v.clone()))),
+        ));
+        for table in self.levels[0].iter().rev() {
+            sources.push(Box::new(table.range(range.clone())));
+        }
+        for tables in &self.levels[1..] {
+            let range = range.clone();
+            let tables = tables.iter().filter(|t| t.overlaps(&range)).collect::<Vec<_>>();
+            sources.push(Box::new(tables.into_iter().flat_map(move |t| t.range(range.clone()))));
+        }
+        ScanIterator { inner: MergeIterator::new(sources, true) }
+    }
+
+    fn scan_dyn(
+        &mut self,
+        range: (Bound<Vec<u8>>, Bound<Vec<u8>>),
+    ) -> Box<dyn super::ScanIterator + '_> {
+        Box::new(self.scan(range))
+    }
+
+    fn set(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        self.write(key, Some(value))
+    }
+
+    /// The key count and size are estimated from the memtable and the table
+    /// indexes, to avoid scanning the entire dataset. Keys that have been
+    /// overwritten or deleted are counted once per live version, until
+    /// compaction merges the versions.
+    fn status(&mut self) -> Result<Status> {
+        let memtable = self.memtable.iter().filter_map(|(k, v)| Some((k, v.as_ref()?)));
+        let (mut keys, mut size) = memtable.fold((0, 0), |(keys, size), (key, value)| {
+            (keys + 1, size + key.len() as u64 + value.len() as u64)
+        });
+        for table in self.levels.iter().flatten() {
+            keys += table.index.keys;
+            size += table.index.size;
+        }
+        let total_disk_size =
+            self.wal.size + self.levels.iter().flatten().map(|t| t.size).sum::<u64>();
+        // The live size is estimated from the encoded size of the live entries.
+        let live_disk_size = (size + ENTRY_HEADER_LEN * keys).min(total_disk_size);
+        let blocks = self.levels.iter().flatten().flat_map(|t| &t.index.blocks);
+        let (blocks_size, decoded_size) = blocks.fold((0, 0), |(size, decoded), b| {
+            (size + Compression::stored_len(b.len, b.decoded_len), decoded + b.decoded_len)
+        });
+        Ok(Status {
+            name: self.to_string(),
+            keys,
+            size,
+            total_disk_size,
+            live_disk_size,
+            garbage_disk_size: total_disk_size - live_disk_size,
+            compression_ratio: match blocks_size {
+                0 => 1.0,
+                _ => decoded_size as f64 / blocks_size as f64,
+            },
+        })
+    }
+
+    fn write_batch(&mut self, batch: WriteBatch) -> Result<()> {
+        let batch: Vec<Entry> = batch.into_iter().collect();
+        if batch.is_empty() {
+            return Ok(());
+        }
+        self.wal.append_batch(&batch)?;
+        for (key, value) in batch {
+            self.memtable_size += Self::entry_size(&key, value.as_deref());
+            self.memtable.insert(key, value);
+        }
+        self.maybe_flush_memtable()
+    }
+}
+
+/// Flush the WAL and unlock the database when it is closed.
+impl Drop for Lsm {
+    fn drop(&mut self) {
+        if let Err(error) = self.flush() {
+            log::error!("failed to flush file: {}", error)
+        }
+        if let Err(error) = self.lock.unlock() {
+            log::error!("failed to unlock database: {}", error)
+        }
+    }
+}
+
+pub struct ScanIterator<'a> {
+    inner: MergeIterator<'a>,
+}
+
+impl<'a> Iterator for ScanIterator<'a> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|r| r.map(|(k, v)| (k, v.unwrap_or_default())))
+    }
+}
+
+impl<'a> DoubleEndedIterator for ScanIterator<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|r| r.map(|(k, v)| (k, v.unwrap_or_default())))
+    }
+}
+
+/// Merges ordered entry iterators, where earlier sources take precedence over
+/// later sources for the same key. Optionally skips tombstones.
+struct MergeIterator<'a> {
+    /// The sources, in order of precedence.
+    sources: Vec<MergeSource<'a>>,
+    /// Whether to skip tombstones.
+    skip_tombstones: bool,
+    /// The last key returned from the front, if any.
+    front_key: Option<Vec<u8>>,
+    /// The last key returned from the back, if any.
+    back_key: Option<Vec<u8>>,
+}
+
+/// A merge iterator source, with peeked entries from either end.
+struct MergeSource<'a> {
+    iter: Box<dyn DoubleEndedIterator<Item = Result<Entry>> + 'a>,
+    front: Option<Entry>,
+    back: Option<Entry>,
+}
+
+impl<'a> MergeSource<'a> {
+    /// Peeks the next entry from the front. If the iterator is exhausted, the
+    /// entry peeked from the back is used.
+    fn peek_front(&mut self) -> Result<Option<&Entry>> {
+        if self.front.is_none() {
+            self.front = match self.iter.next().transpose()? {
+                Some(entry) => Some(entry),
+                None => self.back.take(),
+            };
+        }
+        Ok(self.front.as_ref())
+    }
+
+    /// Peeks the next entry from the back. If the iterator is exhausted, the
+    /// entry peeked from the front is used.
+    fn peek_back(&mut self) -> Result<Option<&Entry>> {
+        if self.back.is_none() {
+            self.back = match self.iter.next_back().transpose()? {
+                Some(entry) => Some(entry),
+                None => self.front.take(),
+            };
+        }
+        Ok(self.back.as_ref())
+    }
+}
+
+impl<'a> MergeIterator<'a> {
+    fn new(
+        sources: Vec<Box<dyn DoubleEndedIterator<Item = Result<Entry>> + 'a>>,
+        skip_tombstones: bool,
+    ) -> Self {
+        let sources =
+            sources.into_iter().map(|iter| MergeSource { iter, front: None, back: None }).collect();
+        Self { sources, skip_tombstones, front_key: None, back_key: None }
+    }
+
+    /// Returns the next entry from the front, including tombstones.
+    fn try_next(&mut self) -> Result<Option<Entry>> {
+        for source in &mut self.sources {
+            source.peek_front()?;
+        }
+        let mut best: Option<&Entry> = None;
+        let mut best_index = 0;
+        for (i, source) in self.sources.iter().enumerate() {
+            let Some(entry) = &source.front else { continue };
+            if best.is_some_and(|b| entry.0 >= b.0) {
+                continue;
+            }
+            best = Some(entry);
+            best_index = i;
+        }
+        let best = best.map(|_| best_index);
+        let Some(best) = best else { return Ok(None) };
+        let entry = self.sources[best].front.take().expect("no entry");
+        if self.back_key.as_ref().is_some_and(|k| &entry.0 >= k) {
+            return Ok(None);
+        }
+        for source in &mut self.sources {
+            if source.front.as_ref().is_some_and(|(k, _)| k == &entry.0) {
+                source.front = None;
+            }
+        }
+        self.front_key = Some(entry.0.clone());
+        Ok(Some(entry))
+    }
+
+    /// Returns the next entry from the back, including tombstones.
+    fn try_next_back(&mut self) -> Result<Option<Entry>> {
+        for source in &mut self.sources {
+            source.peek_back()?;
+        }
+        let mut best: Option<&Entry> = None;
+        let mut best_index = 0;
+        for (i, source) in self.sources.iter().enumerate() {
+            let Some(entry) = &source.back else { continue };
+            if best.is_some_and(|b| entry.0 <= b.0) {
+                continue;
+            }
+            best = Some(entry);
+            best_index = i;
+        }
+        let best = best.map(|_| best_index);
+        let Some(best) = best else { return Ok(None) };
+        let entry = self.sources[best].back.take().expect("no entry");
+        if self.front_key.as_ref().is_some_and(|k| &entry.0 <= k) {
+            return Ok(None);
+        }
+        for source in &mut self.sources {
+            if source.back.as_ref().is_some_and(|(k, _)| k == &entry.0) {
+                source.back = None;
+            }
+        }
+        self.back_key = Some(entry.0.clone());
+        Ok(Some(entry))
+    }
+}
+
+impl<'a> Iterator for MergeIterator<'a> {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.try_next().transpose()? {
+                Ok((_, None)) if self.skip_tombstones => continue,
+                result => return Some(result),
+            }
+        }
+    }
+}
+
+impl<'a> DoubleEndedIterator for MergeIterator<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.try_next_back().transpose()? {
+                Ok((_, None)) if self.skip_tombstones => continue,
+                result => return Some(result),
+            }
+        }
+    }
+}
+
+/// Writes a file header.
+fn write_header(w: &mut impl Write, magic: [u8; 4]) -> Result<()> {
+    w.write_all(&magic)?;
+    w.write_all(&VERSION.to_be_bytes())?;
+    Ok(())
+}
+
+/// Checks a file header.
+fn check_header(r: &mut impl Read, magic: [u8; 4], path: &Path) -> Result<()> {
+    let mut header = [0u8; HEADER_LEN as usize];
+    r.read_exact(&mut header)?;
+    if header[..4] != magic {
+        return Err(Error::Internal(format!("Invalid header in {}", path.display())));
+    }
+    let version = u32::from_be_bytes(header[4..].try_into()?);
+    if version != VERSION {
+        return Err(Error::Internal(format!(
+            "Unsupported LSM version {} in {}, expected {}",
+            version,
+            path.display(),
+            VERSION
+        )));
+    }
+    Ok(())
+}
+
+/// Encodes an entry into a buffer: key length, value length or -1 for
+/// tombstones, key, and value.
+fn encode_entry(buf: &mut Vec<u8>, key: &[u8], value: Option<&[u8]>) {
+    buf.extend_from_slice(&(key.len() as u32).to_be_bytes());
+    buf.extend_from_slice(&value.map_or(-1, |v| v.len() as i32).to_be_bytes());
+    buf.extend_from_slice(key);
+    if let Some(value) = value {
+        buf.extend_from_slice(value);
+    }
+}
+
+/// Decodes an entry from a buffer, returning it along with the rest of the
+/// buffer. Returns None if the buffer is too short.
+fn decode_entry(buf: &[u8]) -> Option<(Entry, &[u8])> {
+    let key_len = u32::from_be_bytes(buf.get(0..4)?.try_into().ok()?) as usize;
+    let value_len = i32::from_be_bytes(buf.get(4..8)?.try_into().ok()?);
+    let buf = &buf[8..];
+    let key = buf.get(..key_len)?.to_vec();
+    let buf = &buf[key_len..];
+    if value_len < 0 {
+        return Some(((key, None), buf));
+    }
+    let value = buf.get(..value_len as usize)?.to_vec();
+    Some(((key, Some(value)), &buf[value_len as usize..]))
+}
+
+/// A write-ahead log, containing a header and checksummed entries: a CRC32
+/// checksum of the rest of the entry as big-endian u32, followed by an
+/// encoded entry.
+struct Wal {
+    /// The opened WAL file.
+    file: std::fs::File,
+    /// The size of the WAL file.
+    size: u64,
+}
+
+impl Wal {
+    /// Opens a WAL file, or creates one if it does not exist, and replays
+    /// its entries via the given closure.
+    fn open(path: PathBuf, mut f: impl FnMut(Vec<u8>, Option<Vec<u8>>)) -> Result<Self> {
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        let file_len = file.metadata()?.len();
+        if file_len < HEADER_LEN {
+            // A short file is the result of an incomplete write when creating
+            // the file, so write the header again.
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            write_header(&mut file, WAL_MAGIC)?;
+            return Ok(Self { file, size: HEADER_LEN });
+        }
+
+        let mut r = BufReader::new(&mut file);
+        r.seek(SeekFrom::Start(0))?;
+        check_header(&mut r, WAL_MAGIC, &path)?;
+        let mut pos = HEADER_LEN;
+        let mut len_buf = [0u8; 4];
+        while pos < file_len {
+            // Read the next entry, or None if it extends beyond the end of
+            // the file.
+            let mut read = || -> Result<Option<(u32, Vec<u8>)>> {
+                r.read_exact(&mut len_buf)?;
+                let checksum = u32::from_be_bytes(len_buf);
+                let mut header = [0u8; ENTRY_HEADER_LEN as usize];
+                r.read_exact(&mut header)?;
+                let key_len = u32::from_be_bytes(header[0..4].try_into()?) as u64;
+                let value_len = i32::from_be_bytes(header[4..8].try_into()?).max(0) as u64;
+                if pos + 4 + ENTRY_HEADER_LEN + key_len + value_len > file_len {
+                    return Ok(None);
+                }
+                let mut entry = header.to_vec();
+                entry.resize((ENTRY_HEADER_LEN + key_len + value_len) as usize, 0);
+                r.read_exact(&mut entry[ENTRY_HEADER_LEN as usize..])?;
+                Ok(Some((checksum, entry)))
+            };
+            let entry = match pos + 4 + ENTRY_HEADER_LEN > file_len {
+                true => None,
+                false => read()?,
+            };
+            let end = pos + 4 + entry.as_ref().map_or(0, |(_, e)| e.len() as u64);
+            match entry {
+                Some((checksum, entry)) if crc32fast::hash(&entry) == checksum => {
+                    let ((key, value), _) = decode_entry(&entry).expect("invalid entry");
+                    if entry[4..8] == BATCH.to_be_bytes() {
+                        let mut batch = key.as_slice();
+                        while !batch.is_empty() {
+                            let ((key, value), rest) = decode_entry(batch).ok_or_else(|| {
+                                Error::Internal(format!("Invalid batch at offset {}", pos))
+                            })?;
+                            f(key, value);
+                            batch = rest;
+                        }
+                    } else {
+                        f(key, value);
+                    }
+                    pos = end;
+                }
+                // If the last entry is incomplete or corrupt, assume an
+                // incomplete write and truncate the file.
+                None => break,
+                Some(_) if end == file_len => break,
+                Some(_) => {
+                    return Err(Error::Internal(format!(
+                        "Checksum mismatch for entry at offset {} in {}",
+                        pos,
+                        path.display()
+                    )))
+                }
+            }
+        }
+        if pos < file_len {
+            log::error!("Found incomplete or corrupt entry at offset {}, truncating WAL", pos);
+            file.set_len(pos)?;
+        }
+        Ok(Self { file, size: pos })
+    }
+
+    /// Appends an entry to the WAL.
+    fn append(&mut self, key: &[u8], value: Option<&[u8]>) -> Result<()> {
+        let mut entry = vec![0; 4]; // checksum, filled in below
+        encode_entry(&mut entry, key, value);
+        self.write(entry)
+    }
+
+    /// Appends a write batch to the WAL, as a single entry with a value length
+    /// of BATCH and the encoded batch entries as the key.
+    fn append_batch(&mut self, batch: &[Entry]) -> Result<()> {
+        let mut entries = Vec::new();
+        for (key, value) in batch {
+            encode_entry(&mut entries, key, value.as_deref());
+        }
+        let mut entry = vec![0; 4]; // checksum, filled in below
+        entry.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+        entry.extend_from_slice(&BATCH.to_be_bytes());
+        entry.extend_from_slice(&entries);
+        self.write(entry)
+    }
+
+    /// Fills in the checksum of an encoded entry and appends it to the WAL.
+    fn write(&mut self, mut entry: Vec<u8>) -> Result<()> {
+        let checksum = crc32fast::hash(&entry[4..]);
+        entry[0..4].copy_from_slice(&checksum.to_be_bytes());
+        self.file.seek(SeekFrom::Start(self.size))?;
+        self.file.write_all(&entry)?;
+        self.size += entry.len() as u64;
+        Ok(())
+    }
+
+    /// Removes all entries from the WAL, once they have been written to a
+    /// table.
+    fn clear(&mut self) -> Result<()> {
+        self.file.set_len(HEADER_LEN)?;
+        self.file.sync_all()?;
+        self.size = HEADER_LEN;
+        Ok(())
+    }
+
+    /// Flushes the WAL to disk.
+    fn sync(&mut self) -> Result<()> {
+        Ok(self.file.sync_all()?)
+    }
+}
+
+/// An SSTable block index.
+struct Index {
+    /// The first key in the table.
+    first_key: Vec<u8>,
+    /// The number of live (non-tombstone) entries in the table.
+    keys: u64,
+    /// The logical size of the table's live keys and values.
+    size: u64,
+    /// The table's data blocks, in key order.
+    blocks: Vec<BlockHandle>,
+}
+
+/// The location of an SSTable data block, excluding its checksum.
+struct BlockHandle {
+    /// The last key in the block.
+    last_key: Vec<u8>,
+    /// The block offset in the file.
+    offset: u64,
+    /// The encoded block length.
+    len: u64,
+    /// The decoded block length.
+    decoded_len: u64,
+}
+
+impl Index {
+    /// Encodes the index.
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&(self.first_key.len() as u32).to_be_bytes());
+        buf.extend_from_slice(&self.first_key);
+        buf.extend_from_slice(&self.keys.to_be_bytes());
+        buf.extend_from_slice(&self.size.to_be_bytes());
+        for block in &self.blocks {
+            buf.extend_from_slice(&(block.last_key.len() as u32).to_be_bytes());
+            buf.extend_from_slice(&block.last_key);
+            buf.extend_from_slice(&block.offset.to_be_bytes());
+            buf.extend_from_slice(&block.len.to_be_bytes());
+            buf.extend_from_slice(&block.decoded_len.to_be_bytes());
+        }
+        buf
+    }
+
+    /// Decodes an index, returning None if it is invalid.
+    fn decode(mut buf: &[u8]) -> Option<Self> {
+        fn take<'a>(buf: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+            let bytes = buf.get(..len)?;
+            *buf = &buf[len..];
+            Some(bytes)
+        }
+        fn take_key(buf: &mut &[u8]) -> Option<Vec<u8>> {
+            let len = u32::from_be_bytes(take(buf, 4)?.try_into().ok()?) as usize;
+            Some(take(buf, len)?.to_vec())
+        }
+
+        let first_key = take_key(&mut buf)?;
+        let keys = u64::from_be_bytes(take(&mut buf, 8)?.try_into().ok()?);
+        let size = u64::from_be_bytes(take(&mut buf, 8)?.try_into().ok()?);
+        let mut blocks = Vec::new();
+        while !buf.is_empty() {
+            let last_key = take_key(&mut buf)?;
+            let offset = u64::from_be_bytes(take(&mut buf, 8)?.try_into().ok()?);
+            let len = u64::from_be_bytes(take(&mut buf, 8)?.try_into().ok()?);
+            let decoded_len = u64::from_be_bytes(take(&mut buf, 8)?.try_into().ok()?);
+            blocks.push(BlockHandle { last_key, offset, len, decoded_len });
+        }
+        Some(Self { first_key, keys, size, blocks })
+    }
+}
+
+/// An immutable sorted string table (SSTable). Tables are never empty.
+struct Table {
+    /// The table ID.
+    id: u64,
+    /// Path to the table file.
+    path: PathBuf,
+    /// The opened table file.
+    file: std::fs::File,
+    /// The table file size.
+    size: u64,
+    /// The block index.
+    index: Index,
+}
+
+impl Table {
+    /// Opens a table file, reading its index.
+    fn open(id: u64, path: PathBuf) -> Result<Self> {
+        let mut file = std::fs::File::open(&path)?;
+        let size = file.metadata()?.len();
+        if size < HEADER_LEN + FOOTER_LEN {
+            return Err(Error::Internal(format!("Table {} is too short", path.display())));
+        }
+        check_header(&mut file, TABLE_MAGIC, &path)?;
+
+        let mut footer = [0u8; FOOTER_LEN as usize];
+        file.seek(SeekFrom::Start(size - FOOTER_LEN))?;
+        file.read_exact(&mut footer)?;
+        if footer[16..] != TABLE_MAGIC {
+            return Err(Error::Internal(format!("Invalid footer in {}", path.display())));
+        }
+        let index_offset = u64::from_be_bytes(footer[0..8].try_into()?);
+        let index_len = u64::from_be_bytes(footer[8..16].try_into()?);
+        if index_offset + index_len + 4 + FOOTER_LEN != size {
+            return Err(Error::Internal(format!("Invalid footer in {}", path.display())));
+        }
+        let buf = Self::read_checksummed(&file, &path, index_offset, index_len)?;
+        let index = Index::decode(&buf)
+            .ok_or_else(|| Error::Internal(format!("Invalid index in {}", path.display())))?;
+        if index.blocks.is_empty() {
+            return Err(Error::Internal(format!("Table {} is empty", path.display())));
+        }
+        Ok(Self { id, path, file, size, index })
+    }
+
+    /// Reads a section of the file followed by a CRC32 checksum, and verifies
+    /// the checksum.
+    fn read_checksummed(
+        file: &std::fs::File,
+        path: &Path,
+        offset: u64,
+        len: u64,
+    ) -> Result<Vec<u8>> {
+        let mut file = file;
+        let mut buf = vec![0; len as usize + 4];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut buf)?;
+        let checksum = buf.split_off(len as usize);
+        if crc32fast::hash(&buf) != u32::from_be_bytes(checksum.as_slice().try_into()?) {
+            return Err(Error::Internal(format!(
+                "Checksum mismatch for block at offset {} in {}",
+                offset,
+                path.display()
+            )));
+        }
+        Ok(buf)
+    }
+
+    /// Returns the first key in the table.
+    fn first_key(&self) -> &[u8] {
+        &self.index.first_key
+    }
+
+    /// Returns the last key in the table.
+    fn last_key(&self) -> &[u8] {
+        &self.index.blocks.last().expect("empty table").last_key
+    }
+
+    /// Returns true if the table may contain keys in the given range.
+    fn overlaps(&self, range: &(Bound<Vec<u8>>, Bound<Vec<u8>>)) -> bool {
+        let after_start = match &range.0 {
+            Bound::Included(start) => self.last_key() >= start.as_slice(),
+            Bound::Excluded(start) => self.last_key() > start.as_slice(),
+            Bound::Unbounded => true,
+        };
+        let before_end = match &range.1 {
+            Bound::Included(end) => self.first_key() <= end.as_slice(),
+            Bound::Excluded(end) => self.first_key() < end.as_slice(),
+            Bound::Unbounded => true,
+        };
+        after_start && before_end
+    }
+
+    /// Reads and decodes a data block.
+    fn read_block(&self, i: usize) -> Result<Vec<Entry>> {
+        let handle = &self.index.blocks[i];
+        let buf = Self::read_checksummed(&self.file, &self.path, handle.offset, handle.len)?;
+        let buf = Compression::decode(buf)?;
+        let mut entries = Vec::new();
+        let mut rest = buf.as_slice();
+        while !rest.is_empty() {
+            let Some((entry, next)) = decode_entry(rest) else {
+                return Err(Error::Internal(format!(
+                    "Invalid block at offset {} in {}",
+                    handle.offset,
+                    self.path.display()
+                )));
+            };
+            entries.push(entry);
+            rest = next;
+        }
+        Ok(entries)
+    }
+
+    /// Looks up a key, returning Some(None) for tombstones.
+    fn get(&self, key: &[u8]) -> Result<Option<Option<Vec<u8>>>> {
+        if key < self.first_key() {
+            return Ok(None);
+        }
+        let i = self.index.blocks.partition_point(|b| b.last_key.as_slice() < key);
+        if i == self.index.blocks.len() {
+            return Ok(None);
+        }
+        let entries = self.read_block(i)?;
+        Ok(entries
+            .binary_search_by(|(k, _)| k.as_slice().cmp(key))
+            .ok()
+            .map(|j| entries[j].1.clone()))
+    }
+
+    /// Iterates over the entries in a key range, including tombstones.
+    fn range(&self, range: (Bound<Vec<u8>>, Bound<Vec<u8>>)) -> TableIterator<'_> {
+        let blocks = &self.index.blocks;
+        let start = match &range.0 {
+            Bound::Included(k) | Bound::Excluded(k) => {
+                blocks.partition_point(|b| b.last_key.as_slice() < k.as_slice())
+            }
+            Bound::Unbounded => 0,
+        };
+        let end = match &range.1 {
+            Bound::Included(k) | Bound::Excluded(k) => {
+                (blocks.partition_point(|b| b.last_key.as_slice() < k.as_slice()) + 1)
+                    .min(blocks.len())
+            }
+            Bound::Unbounded => blocks.len(),
+        };
+        TableIterator {
+            table: self,
+            range,
+            blocks: (start..end.max(start)).collect(),
+            front: VecDeque::new(),
+            back: VecDeque::new(),
+        }
+    }
+}
+
+/// Iterates over the entries of a table in a key range. Blocks are read
+/// lazily from either end.
+struct TableIterator<'a> {
+    table: &'a Table,
+    range: (Bound<Vec<u8>>, Bound<Vec<u8>>),
+    /// Blocks that haven't been read yet.
+    blocks: VecDeque<usize>,
+    /// Entries from the block read from the front.
+    front: VecDeque<Entry>,
+    /// Entries from the block read from the back.
+    back: VecDeque<Entry>,
+}
+
+impl<'a> TableIterator<'a> {
+    /// Reads a block and returns the entries in the range.
+    fn read(&self, i: usize) -> Result<VecDeque<Entry>> {
+        let mut entries = self.table.read_block(i)?;
+        entries.retain(|(key, _)| self.range.contains(key));
+        Ok(entries.into())
+    }
+}
+
+impl<'a> Iterator for TableIterator<'a> {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.front.is_empty() {
+            let Some(i) = self.blocks.pop_front() else {
+                return self.back.pop_front().map(Ok);
+            };
+            match self.read(i) {
+                Ok(entries) => self.front = entries,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        self.front.pop_front().map(Ok)
+    }
+}
+
+impl<'a> DoubleEndedIterator for TableIterator<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        while self.back.is_empty() {
+            let Some(i) = self.blocks.pop_back() else {
+                return self.front.pop_back().map(Ok);
+            };
+            match self.read(i) {
+                Ok(entries) => self.back = entries,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        self.back.pop_back().map(Ok)
+    }
+}
+
+/// Writes a new table. Entries must be added in key order.
+struct TableBuilder {
+    id: u64,
+    path: PathBuf,
+    file: BufWriter<std::fs::File>,
+    /// The current file offset.
+    offset: u64,
+    /// The target block size.
+    block_size: u64,
+    /// The block compression.
+    compression: Compression,
+    /// The current data block.
+    block: Vec<u8>,
+    /// The last key added.
+    last_key: Vec<u8>,
+    /// The block index.
+    index: Index,
+}
+
+impl TableBuilder {
+    /// Creates a new table file.
+    fn new(id: u64, path: PathBuf, opts: &Options) -> Result<Self> {
+        let mut file = BufWriter::new(std::fs::File::create(&path)?);
+        write_header(&mut file, TABLE_MAGIC)?;
+        Ok(Self {
+            id,
+            path,
+            file,
+            offset: HEADER_LEN,
+            block_size: opts.block_size,
+            compression: opts.compression,
+            block: Vec::new(),
+            last_key: Vec::new(),
+            index: Index { first_key: Vec::new(), keys: 0, size: 0, blocks: Vec::new() },
+        })
+    }
+
+    /// Adds an entry to the table.
+    fn add(&mut self, key: &[u8], value: Option<&[u8]>) -> Result<()> {
+        if self.index.blocks.is_empty() && self.block.is_empty() {
+            self.index.first_key = key.to_vec();
+        }
+        encode_entry(&mut self.block, key, value);
+        if let Some(value) = value {
+            self.index.keys += 1;
+            self.index.size += key.len() as u64 + value.len() as u64;
+        }
+        self.last_key = key.to_vec();
+        if self.block.len() as u64 >= self.block_size {
+            self.finish_block()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the approximate size of the table.
+    fn size(&self) -> u64 {
+        self.offset + self.block.len() as u64
+    }
+
+    /// Writes out the current data block.
+    fn finish_block(&mut self) -> Result<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+        let block = self.compression.encode(&self.block);
+        let len = block.len() as u64;
+        self.file.write_all(&block)?;
+        self.file.write_all(&crc32fast::hash(&block).to_be_bytes())?;
+        self.index.blocks.push(BlockHandle {
+            last_key: std::mem::take(&mut self.last_key),
+            offset: self.offset,
+            len,
+            decoded_len: self.block.len() as u64,
+        });
+        self.offset += len + 4;
+        self.block.clear();
+        Ok(())
+    }
+
+    /// Writes out the index and footer, flushes the file and its directory
+    /// entry to disk, and opens the table.
+    fn finish(mut self) -> Result<Table> {
+        self.finish_block()?;
+        let index = self.index.encode();
+        self.file.write_all(&index)?;
+        self.file.write_all(&crc32fast::hash(&index).to_be_bytes())?;
+        self.file.write_all(&self.offset.to_be_bytes())?;
+        self.file.write_all(&(index.len() as u64).to_be_bytes())?;
+        self.file.write_all(&TABLE_MAGIC)?;
+        self.file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        if let Some(dir) = self.path.parent() {
+            sync_dir(dir)?;
+        }
+        Table::open(self.id, self.path)
+    }
+}
+
+/// Syncs a directory to disk, making file creations and renames in it durable.
+fn sync_dir(dir: &Path) -> Result<()> {
+    Ok(std::fs::File::open(dir)?.sync_all()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Small options, to exercise flushes and compactions.
+    fn small_options() -> Options {
+        Options {
+            memtable_size: 256,
+            block_size: 64,
+            table_size: 512,
+            l0_tables: 2,
+            level_size: 1024,
+            compression: Compression::None,
+        }
+    }
+
+    /// An Lsm engine in a temporary directory, which is removed when dropped.
+    /// Tables are written to the directory during use, so it must outlive
+    /// the engine.
+    struct TestLsm {
+        lsm: Lsm,
+        _dir: tempdir::TempDir,
+    }
+
+    impl std::ops::Deref for TestLsm {
+        type Target = Lsm;
+        fn deref(&self) -> &Lsm {
+            &self.lsm
+        }
+    }
+
+    impl std::ops::DerefMut for TestLsm {
+        fn deref_mut(&mut self) -> &mut Lsm {
+            &mut self.lsm
+        }
+    }
+
+    /// Creates a new Lsm engine for testing, using small options.
+    fn setup() -> Result<TestLsm> {
+        let dir = tempdir::TempDir::new("radb")?;
+        let lsm = Lsm::new_with_options(dir.path().join("radb"), small_options())?;
+        Ok(TestLsm { lsm, _dir: dir })
+    }
+
+    super::super::engine::tests::test_engine!(setup()?);
+
+    /// Applies a deterministic pseudo-random sequence of writes to the engine
+    /// and a model.
+    fn write_random(s: &mut Lsm, model: &mut BTreeMap<Vec<u8>, Vec<u8>>, n: u64) -> Result<()> {
+        let mut rand: u64 = 1;
+        for i in 0..n {
+            rand = rand.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let key = format!("key{}", (rand >> 33) % 200).into_bytes();
+            if (rand >> 40) & 3 == 0 {
+                s.delete(&key)?;
+                model.remove(&key);
+            } else {
+                let value = vec![i as u8; (rand >> 50) as usize % 32];
+                s.set(&key, value.clone())?;
+                model.insert(key, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Asserts that the engine contains the model data, via scans and gets.
+    #[track_caller]
+    fn assert_model(s: &mut Lsm, model: &BTreeMap<Vec<u8>, Vec<u8>>) -> Result<()> {
+        let expect: Vec<_> = model.clone().into_iter().collect();
+        assert_eq!(s.scan(..).collect::<Result<Vec<_>>>()?, expect);
+        let mut reversed = expect.clone();
+        reversed.reverse();
+        assert_eq!(s.scan(..).rev().collect::<Result<Vec<_>>>()?, reversed);
+        for (key, value) in model {
+            assert_eq!(s.get(key)?.as_ref(), Some(value));
+        }
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that memtables are flushed and levels compacted, keeping tables
+    /// in levels 1 and above sorted and non-overlapping, and that the data
+    /// can be read back after reopening.
+    fn compaction() -> Result<()> {
+        let dir = tempdir::TempDir::new("radb")?;
+        let path = dir.path().join("radb");
+        let mut s = Lsm::new_with_options(path.clone(), small_options())?;
+        let mut model = BTreeMap::new();
+        write_random(&mut s, &mut model, 5000)?;
+        assert_model(&mut s, &model)?;
+
+        assert!(s.levels.len() > 2, "expected multiple levels, got {}", s.levels.len());
+        assert!(s.levels[0].len() < s.opts.l0_tables);
+        for (level, tables) in s.levels.iter().enumerate().skip(1) {
+            for pair in tables.windows(2) {
+                assert!(pair[0].last_key() < pair[1].first_key(), "overlap in level {}", level);
+            }
+            if level < s.levels.len() - 1 {
+                assert!(tables.iter().map(|t| t.size).sum::<u64>() <= s.level_limit(level));
+            }
+        }
+
+        // Only files in the manifest should remain on disk.
+        let tables = s.levels.iter().flatten().count();
+        let files = std::fs::read_dir(&path)?
+            .filter(|e| e.as_ref().is_ok_and(|e| e.path().extension() == Some("sst".as_ref())))
+            .count();
+        assert_eq!(files, tables);
+
+        drop(s);
+        let mut s = Lsm::new_with_options(path, small_options())?;
+        assert_model(&mut s, &model)?;
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that the status key count and size are estimated from the
+    /// memtable and table indexes: exact for distinct keys, and an upper
+    /// bound when keys are overwritten or deleted.
+    fn status_estimate() -> Result<()> {
+        let mut s = setup()?;
+        for i in 0..100u8 {
+            s.set(&[i], vec![i; 3])?;
+        }
+        assert!(s.levels.iter().flatten().count() > 1);
+        let status = s.status()?;
+        assert_eq!(status.keys, 100);
+        assert_eq!(status.size, 400);
+
+        let mut s = setup()?;
+        let mut model = BTreeMap::new();
+        write_random(&mut s, &mut model, 1000)?;
+        let size = model.iter().map(|(k, v)| k.len() as u64 + v.len() as u64).sum::<u64>();
+        let status = s.status()?;
+        assert!(status.keys >= model.len() as u64);
+        assert!(status.size >= size);
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that unflushed writes are recovered from the WAL, and that an
+    /// incomplete write at the end of the WAL is discarded.
+    fn wal_recovery() -> Result<()> {
+        let dir = tempdir::TempDir::new("radb")?;
+        let path = dir.path().join("radb");
+        let truncpath = dir.path().join("truncated");
+
+        let mut s = Lsm::new(path.clone())?;
+        let mut ends = vec![];
+        s.set(b"deleted", vec![1, 2, 3])?;
+        ends.push(s.wal.size);
+        s.delete(b"deleted")?;
+        ends.push(s.wal.size);
+        s.set(b"", vec![])?;
+        ends.push(s.wal.size);
+        s.set(b"key", vec![1, 2, 3, 4, 5])?;
+        ends.push(s.wal.size);
+        drop(s);
+
+        // Truncate the WAL at each byte, and assert that we always retain a
+        // prefix of entries.
+        let size = std::fs::metadata(path.join("wal"))?.len();
+        for pos in 0..=size {
+            std::fs::create_dir_all(&truncpath)?;
+            std::fs::copy(path.join("wal"), truncpath.join("wal"))?;
+            let f = std::fs::OpenOptions::new().write(true).open(truncpath.join("wal"))?;
+            f.set_len(pos)?;
+            drop(f);
+
+            let mut expect = vec![];
+            if pos >= ends[0] {
+                expect.push((b"deleted".to_vec(), vec![1, 2, 3]))
+            }
+            if pos >= ends[1] {
+                expect.pop(); // "deleted" key removed
+            }
+            if pos >= ends[2] {
+                expect.push((b"".to_vec(), vec![]))
+            }
+            if pos >= ends[3] {
+                expect.push((b"key".to_vec(), vec![1, 2, 3, 4, 5]))
+            }
+
+            let mut s = Lsm::new(truncpath.clone())?;
+            assert_eq!(expect, s.scan(..).collect::<Result<Vec<_>>>()?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that a write batch is replayed from the WAL either completely or
+    /// not at all.
+    fn wal_batch_recovery() -> Result<()> {
+        let dir = tempdir::TempDir::new("radb")?;
+        let path = dir.path().join("radb");
+        let truncpath = dir.path().join("truncated");
+
+        let mut s = Lsm::new(path.clone())?;
+        s.set(b"a", vec![1])?;
+        let start = s.wal.size;
+        let mut batch = WriteBatch::new();
+        batch.set(b"b", vec![2]);
+        batch.delete(b"a");
+        batch.set(b"c", vec![3]);
+        s.write_batch(batch)?;
+        let end = s.wal.size;
+        drop(s);
+
+        for pos in start..=end {
+            std::fs::create_dir_all(&truncpath)?;
+            std::fs::copy(path.join("wal"), truncpath.join("wal"))?;
+            let f = std::fs::OpenOptions::new().write(true).open(truncpath.join("wal"))?;
+            f.set_len(pos)?;
+            drop(f);
+
+            let expect = match pos {
+                pos if pos < end => vec![(b"a".to_vec(), vec![1])],
+                _ => vec![(b"b".to_vec(), vec![2]), (b"c".to_vec(), vec![3])],
+            };
+            let mut s = Lsm::new(truncpath.clone())?;
+            assert_eq!(expect, s.scan(..).collect::<Result<Vec<_>>>()?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that table blocks are compressed when enabled, and that they can
+    /// be read back after reopening with a different compression setting.
+    fn compression() -> Result<()> {
+        let dir = tempdir::TempDir::new("radb")?;
+        let path = dir.path().join("radb");
+        let opts = Options { block_size: 512, compression: Compression::Lz, ..small_options() };
+        let mut s = Lsm::new_with_options(path.clone(), opts)?;
+        let mut model = BTreeMap::new();
+        write_random(&mut s, &mut model, 1000)?;
+        assert_model(&mut s, &model)?;
+        let status = s.status()?;
+        assert!(status.compression_ratio > 1.5, "ratio {}", status.compression_ratio);
+
+        // Reopening without compression can read the compressed tables, and
+        // new tables are uncompressed.
+        drop(s);
+        let mut s = Lsm::new_with_options(path, small_options())?;
+        assert_model(&mut s, &model)?;
+        assert_eq!(s.status()?.compression_ratio, status.compression_ratio);
+        write_random(&mut s, &mut model, 5000)?;
+        assert_model(&mut s, &model)?;
+        assert!(s.status()?.compression_ratio < status.compression_ratio);
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that corruption in a table block is detected when read.
+    fn corrupt_table() -> Result<()> {
+        let mut s = setup()?;
+        let mut model = BTreeMap::new();
+        write_random(&mut s, &mut model, 200)?;
+
+        let table = s.levels.iter().flatten().next().expect("no tables");
+        let handle = &table.index.blocks[0];
+        let key = handle.last_key.clone();
+        let mut f = std::fs::OpenOptions::new().read(true).write(true).open(&table.path)?;
+        f.seek(SeekFrom::Start(handle.offset))?;
+        f.write_all(&[0xff])?;
+        drop(f);
+
+        assert!(matches!(s.scan(..).collect::<Result<Vec<_>>>(), Err(Error::Internal(_))));
+        if !s.memtable.contains_key(&key) {
+            assert!(matches!(s.get(&key), Err(Error::Internal(_))));
+        }
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that files left behind by interrupted flushes and compactions
+    /// are removed on startup.
+    fn orphans() -> Result<()> {
+        let dir = tempdir::TempDir::new("radb")?;
+        let path = dir.path().join("radb");
+        let mut s = Lsm::new_with_options(path.clone(), small_options())?;
+        let mut model = BTreeMap::new();
+        write_random(&mut s, &mut model, 200)?;
+        drop(s);
+
+        std::fs::write(Lsm::table_path(&path, 999999), b"foo")?;
+        std::fs::write(path.join("MANIFEST.new"), b"foo")?;
+        let mut s = Lsm::new_with_options(path.clone(), small_options())?;
+        assert!(!Lsm::table_path(&path, 999999).exists());
+        assert!(!path.join("MANIFEST.new").exists());
+        assert_model(&mut s, &model)?;
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that exclusive locks are taken out on the database directory.
+    fn lock() -> Result<()> {
+        let dir = tempdir::TempDir::new("radb")?;
+        let path = dir.path().join("radb");
+        let s = Lsm::new(path.clone())?;
+        assert!(Lsm::new(path.clone()).is_err());
+        drop(s);
+        assert!(Lsm::new(path).is_ok());
+        Ok(())
+    }
+}
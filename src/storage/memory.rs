@@ -1,4 +1,4 @@
-use super::{Engine, Status};
+use super::{Engine, Status, WriteBatch};
 use crate::error::Result;
 
 /// An in-memory key/value storage engine using the Rust standard library B-tree
@@ -60,8 +60,19 @@ impl Engine for Memory {
             total_disk_size: 0,
             live_disk_size: 0,
             garbage_disk_size: 0,
+            compression_ratio: 1.0,
         })
     }
+
+    fn write_batch(&mut self, batch: WriteBatch) -> Result<()> {
+        for (key, value) in batch {
+            match value {
+                Some(value) => self.data.insert(key, value),
+                None => self.data.remove(&key),
+            };
+        }
+        Ok(())
+    }
 }
 
 pub struct ScanIterator<'a> {
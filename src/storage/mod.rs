@@ -1,11 +1,19 @@
 mod bitcask;
+mod btree;
+mod compression;
 pub mod debug;
+mod encryption;
 pub mod engine;
+mod lsm;
 mod memory;
 pub mod mvcc;
 
 pub use bitcask::BitCask;
+pub use btree::BTree;
+pub use compression::{Codec, Compression};
 #[cfg(test)]
 pub use debug::Engine as Debug;
-pub use engine::{Engine, ScanIterator, Status};
+pub use encryption::{Key, Keyring};
+pub use engine::{Engine, ScanIterator, Status, WriteBatch};
+pub use lsm::{Lsm, Options as LsmOptions};
 pub use memory::Memory;
@@ -88,6 +88,101 @@
 //! not see any of t2's writes, because it's still in its local snapshot of the
 //! active set at the time it began.
 //!
+//! SAVEPOINTS
+//! ==========
+//!
+//! A read-write transaction can create savepoints, and later roll back to a
+//! savepoint to undo all writes made after it, without rolling back the
+//! entire transaction. Savepoints are numbered from 0 in the order they're
+//! created, and the transaction state keeps track of how many exist.
+//!
+//! When a transaction writes a key while it has a savepoint, it records its
+//! own previous value of the key as Key::TxnSavepoint(version, savepoint, key)
+//! for the latest savepoint, unless already recorded. The value is None if the
+//! transaction hadn't written the key before, in which case rolling back
+//! removes the version and its Key::TxnWrite record entirely. Rolling back to a
+//! savepoint restores these values for it and all later savepoints, and
+//! removes the later savepoints. Releasing a savepoint removes it and all
+//! later savepoints but keeps their writes, moving their undo records to the
+//! previous savepoint if any.
+//!
+//! LOCKING
+//! =======
+//!
+//! Write conflicts are normally detected when writing, and the later writer
+//! immediately fails with a serialization error. Under heavy contention, e.g.
+//! for a hot counter row, this can cause lots of retries. Instead, read-write
+//! transactions can lock keys before reading and writing them, waiting for
+//! conflicting transactions to finish. Locks are either shared, which can be
+//! held by several transactions, or exclusive, which conflict with all other
+//! locks. They're held until the transaction commits or rolls back.
+//!
+//! A lock is recorded as Key::Lock(key, version) with its LockMode, and as
+//! Key::TxnLock(version, key) so that the transaction can release it. An
+//! uncommitted write by another active transaction is considered an exclusive
+//! lock. Conversely, writing a key that's locked by another transaction fails
+//! with a serialization error, like other write conflicts.
+//!
+//! Waiting for a lock is done by the caller, since the MVCC engine can't block
+//! (e.g. it may be applying a Raft log). Transaction::lock() returns false if
+//! the lock is held by other transactions, and if asked to wait, records the
+//! transactions it's waiting for as Key::TxnWait(version). The caller can then
+//! retry whenever a transaction commits or rolls back, until the lock is
+//! acquired, or give up after a timeout. Before
+//! waiting, lock() checks whether this would create a cycle of waiting
+//! transactions, i.e. a deadlock, and if so returns a serialization error.
+//! The transaction should then roll back, releasing its locks.
+//!
+//! A locking read should see the latest committed version, like in other
+//! databases: if the key was modified by a transaction that was still active
+//! when this transaction began, and has since committed, acquiring the lock
+//! records that version in the Key::TxnLock value. get() then returns it, and
+//! the transaction may write on top of it, while the rest of its snapshot is
+//! unchanged. This isn't possible if the version is newer than the
+//! transaction's own version, since versions order writes to a key, or for
+//! serializable transactions, whose reads must come from their snapshot to
+//! detect conflicts. In these cases locking fails with a serialization error.
+//!
+//! SERIALIZABILITY
+//! ===============
+//!
+//! Snapshot isolation is not fully serializable, since it allows write skew:
+//! two concurrent transactions may each read a key that the other one writes,
+//! and both commit. For example, t1 reads a and b then writes a, while t2 reads
+//! a and b then writes b. Neither sees the other's write, so there's no serial
+//! order in which they could both have read what they did.
+//!
+//! Read-write transactions can optionally run at the serializable isolation
+//! level, using serializable snapshot isolation (SSI). These record the key
+//! ranges they read as Key::TxnRead(version, range). Conflicts between
+//! concurrent transactions are rw-antidependencies, where one transaction reads
+//! a key and a concurrent transaction writes a new version of it, such that the
+//! reader must be ordered before the writer. A cycle of these can't be
+//! serialized, and every such cycle contains a "dangerous structure": a pivot
+//! transaction with both an incoming and an outgoing rw-antidependency, i.e.
+//! tin → pivot → tout.
+//!
+//! When a serializable transaction commits, it finds its outgoing conflicts by
+//! scanning its read ranges for versions it can't see, and its incoming
+//! conflicts by checking its writes against the read ranges of concurrent
+//! serializable transactions that have already committed. It aborts with a
+//! serialization error if it is the pivot of a dangerous structure, or if it
+//! would complete one where a committed transaction is the pivot. To detect
+//! the latter, committed transactions record whether they have incoming or
+//! outgoing conflicts as Key::TxnCommitted(version), along with their read
+//! ranges, until they are visible to all active transactions and thus can't
+//! conflict with any others.
+//!
+//! In the write skew example, t1 commits first with an outgoing conflict to t2,
+//! which wrote b. When t2 commits, it has an outgoing conflict to t1, which
+//! wrote a, and an incoming conflict from t1, which read b, so t2 aborts.
+//!
+//! This may abort transactions that could in fact have been serialized, since
+//! a dangerous structure is not necessarily part of a cycle. Conflicts are only
+//! tracked between serializable transactions, so snapshot isolation
+//! transactions may still see or cause anomalies. Read-only transactions always
+//! use snapshot isolation.
+//!
 //! READ-ONLY AND TIME TRAVEL QUERIES
 //! =================================
 //!
@@ -133,19 +228,51 @@
 //! GARBAGE COLLECTION
 //! ==================
 //!
-//! Normally, old versions would be garbage collected regularly, when they are
-//! no longer needed by active transactions or time-travel queries. However,
-//! RaDB does not implement garbage collection, instead keeping all history
-//! forever, both out of laziness and also because it allows unlimited time
-//! travel queries (it's a feature, not a bug!).
+//! Old versions are garbage collected when they are no longer needed by active
+//! transactions or time-travel queries, via MVCC::gc(). This removes versions
+//! below a low-water mark, stored as Key::LowWater. For each key, only the
+//! latest version below the low-water mark is retained (unless it is a
+//! tombstone), since this is the only one visible to transactions at or
+//! above it.
+//!
+//! The low-water mark is given by the caller, typically based on a time-travel
+//! retention period, but is lowered to the oldest version that is visible to
+//! an active transaction, i.e. the oldest active transaction or an entry in
+//! one of their active set snapshots. Similarly, it is lowered such that a
+//! time-travel query at the low-water mark can restore its active set.
+//!
+//! Time-travel queries below the low-water mark, or with an active set
+//! snapshot containing versions below it, return an error. Read-only
+//! transactions that began before a garbage collection and which have since
+//! fallen below the low-water mark also return an error when resumed, but
+//! within a single read (e.g. a scan) the caller must ensure the retention
+//! period is long enough to not remove versions that they may see.
+//!
+//! In the following example, garbage collecting at version 5 with no active
+//! transactions would remove a1 as well as d1 and its tombstone, retaining
+//! a4, b3, and c1. Time-travel queries at versions 1-4 would then error.
+//!
+//! Time
+//! 5
+//! 4  a4
+//! 3      b3      x
+//! 2
+//! 1  a1      c1  d1
+//!    a   b   c   d   Keys
+//!
+//! Versions are logical timestamps, so the caller must map wall-clock retention
+//! periods to versions. When replicated through Raft, garbage collection must
+//! also be deterministic, such that all replicas remove the same versions. The
+//! Raft SQL engine does this by recording checkpoints of the version sequence
+//! at the proposer's time, see sql::engine::Raft.
 
-use super::engine::Engine;
+use super::engine::{Engine, WriteBatch};
 use crate::encoding::{bincode, keycode};
 use crate::error::{Error, Result};
 
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::ops::{Bound, RangeBounds};
 use std::sync::{Arc, Mutex, MutexGuard};
 
@@ -189,6 +316,52 @@ pub enum Key<'a> {
         #[serde(borrow)]
         Cow<'a, [u8]>,
     ),
+    /// The garbage collection low-water mark. Versions below it have been
+    /// garbage collected, except the latest version of each key, and can't be
+    /// read by time-travel queries.
+    LowWater,
+    /// Key ranges read by a serializable transaction (identified by its
+    /// version), for conflict detection. The range is a Bincode-encoded
+    /// KeyRange.
+    TxnRead(
+        Version,
+        #[serde(with = "serde_bytes")]
+        #[serde(borrow)]
+        Cow<'a, [u8]>,
+    ),
+    /// Committed serializable transactions that may still conflict with
+    /// active transactions, with their Conflicts.
+    TxnCommitted(Version),
+    /// Undo records for a savepoint of an active transaction: its previous
+    /// value of a key written after the savepoint, as a Bincode-encoded Option
+    /// of the raw Key::Version value. None if it hadn't written the key.
+    TxnSavepoint(
+        Version,
+        u64,
+        #[serde(with = "serde_bytes")]
+        #[serde(borrow)]
+        Cow<'a, [u8]>,
+    ),
+    /// A lock on a key held by an active transaction, with its LockMode.
+    Lock(
+        #[serde(with = "serde_bytes")]
+        #[serde(borrow)]
+        Cow<'a, [u8]>,
+        Version,
+    ),
+    /// Keeps track of all keys locked by an active transaction, so that it
+    /// can release them. The value is the latest committed version of the key
+    /// when it was locked, if it's invisible to the transaction, and otherwise
+    /// empty.
+    TxnLock(
+        Version,
+        #[serde(with = "serde_bytes")]
+        #[serde(borrow)]
+        Cow<'a, [u8]>,
+    ),
+    /// The set of transactions that an active transaction is waiting for to
+    /// release a lock, used for deadlock detection.
+    TxnWait(Version),
 }
 
 impl<'a> Key<'a> {
@@ -215,6 +388,16 @@ enum KeyPrefix<'a> {
         Cow<'a, [u8]>,
     ),
     Unversioned,
+    LowWater,
+    TxnRead(Version),
+    TxnCommitted,
+    TxnSavepoint(Version, u64),
+    Lock(
+        #[serde(with = "serde_bytes")]
+        #[serde(borrow)]
+        Cow<'a, [u8]>,
+    ),
+    TxnLock(Version),
 }
 
 impl<'a> KeyPrefix<'a> {
@@ -223,6 +406,44 @@ impl<'a> KeyPrefix<'a> {
     }
 }
 
+/// A lock mode. See the module documentation for details.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LockMode {
+    /// A shared lock, which can be held by several transactions at once.
+    Shared,
+    /// An exclusive lock, which can only be held by a single transaction.
+    Exclusive,
+}
+
+impl LockMode {
+    /// Returns true if the lock modes conflict.
+    fn conflicts(self, other: Self) -> bool {
+        self == Self::Exclusive || other == Self::Exclusive
+    }
+}
+
+impl std::fmt::Display for LockMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Shared => write!(f, "share"),
+            Self::Exclusive => write!(f, "update"),
+        }
+    }
+}
+
+/// A range of keys read by a serializable transaction.
+type KeyRange = (Bound<Vec<u8>>, Bound<Vec<u8>>);
+
+/// The rw-antidependencies of a committed serializable transaction, used to
+/// detect dangerous structures. See the module documentation for details.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Conflicts {
+    /// A concurrent transaction read a key that this transaction wrote.
+    pub rw_in: bool,
+    /// This transaction read a key that a concurrent transaction wrote.
+    pub rw_out: bool,
+}
+
 /// An MVCC-based transactional key-value engine. It wraps an underlying storage
 /// engine that's used for raw key/value storage.
 ///
@@ -250,7 +471,12 @@ impl<E: Engine> MVCC<E> {
 
     /// Begins a new read-write transaction.
     pub fn begin(&self) -> Result<Transaction<E>> {
-        Transaction::begin(self.engine.clone())
+        Transaction::begin(self.engine.clone(), false)
+    }
+
+    /// Begins a new serializable read-write transaction.
+    pub fn begin_serializable(&self) -> Result<Transaction<E>> {
+        Transaction::begin(self.engine.clone(), true)
     }
 
     /// Begins a new read-only transaction at the latest version.
@@ -278,6 +504,168 @@ impl<E: Engine> MVCC<E> {
         self.engine.lock()?.set(&Key::Unversioned(key.into()).encode()?, value)
     }
 
+    /// Exports all raw key/value pairs in the storage engine, including all
+    /// versions and transaction metadata. Used for Raft snapshots.
+    pub fn export(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.engine.lock()?.scan(..).collect()
+    }
+
+    /// Replaces the entire contents of the storage engine with the given raw
+    /// key/value pairs, as returned by export(), and sets the given unversioned
+    /// keys. This is done atomically in a single write batch, such that a crash
+    /// can't leave a mix of old and new contents, and flushed to disk.
+    pub fn import(
+        &self,
+        data: Vec<(Vec<u8>, Vec<u8>)>,
+        unversioned: Vec<(&[u8], Vec<u8>)>,
+    ) -> Result<()> {
+        let mut engine = self.engine.lock()?;
+        let mut batch = WriteBatch::new();
+        for item in engine.scan(..) {
+            batch.delete(&item?.0);
+        }
+        for (key, value) in data {
+            batch.set(&key, value);
+        }
+        for (key, value) in unversioned {
+            batch.set(&Key::Unversioned(key.into()).encode()?, value);
+        }
+        engine.write_batch(batch)?;
+        engine.flush()
+    }
+
+    /// Flushes the storage engine to disk.
+    pub fn flush(&self) -> Result<()> {
+        self.engine.lock()?.flush()
+    }
+
+    /// Garbage collects versions below the given version, which becomes the
+    /// new low-water mark. It is lowered as necessary to retain versions that
+    /// are visible to active transactions, and it never moves backwards. See
+    /// the module documentation for details. Returns the number of removed
+    /// versions.
+    ///
+    /// This is deterministic: given the same engine state and version, it will
+    /// always remove the same versions.
+    pub fn gc(&self, version: Version) -> Result<u64> {
+        let mut session = self.engine.lock()?;
+        let next_version = match session.get(&Key::NextVersion.encode()?)? {
+            Some(ref v) => bincode::deserialize(v)?,
+            None => 1,
+        };
+
+        // Lower the low-water mark to the oldest version visible to any active
+        // transaction, and to any version in its own active set snapshot (and
+        // so on), such that time-travel queries at the low-water mark work.
+        let mut low_water = version.min(next_version);
+        for active in Transaction::scan_active(&mut session)? {
+            low_water = low_water.min(active);
+            let snapshot = Transaction::get_active_snapshot(&mut session, active)?;
+            low_water = low_water.min(snapshot.into_iter().min().unwrap_or(Version::MAX));
+        }
+        loop {
+            let snapshot = Transaction::get_active_snapshot(&mut session, low_water)?;
+            match snapshot.into_iter().min() {
+                Some(min) if min < low_water => low_water = min,
+                _ => break,
+            }
+        }
+        if low_water <= Transaction::get_low_water(&mut session)? {
+            return Ok(0);
+        }
+
+        // Remove versions below the low-water mark, except the latest version
+        // of each key unless it's a tombstone. Versions are ordered by key and
+        // then version, so we keep track of the previous version below the
+        // low-water mark and decide whether to remove it once we see the next.
+        let mut batch = WriteBatch::new();
+        let mut removed = 0;
+        let mut prev: Option<(Vec<u8>, Vec<u8>, bool)> = None; // key, engine key, tombstone
+        let from = Key::Version(vec![].into(), 0).encode()?;
+        let to = KeyPrefix::Unversioned.encode()?;
+        let mut scan = session.scan(from..to);
+        while let Some((raw_key, value)) = scan.next().transpose()? {
+            let (key, version) = match Key::decode(&raw_key)? {
+                Key::Version(key, version) => (key, version),
+                key => return Err(Error::Internal(format!("Expected Key::Version got {:?}", key))),
+            };
+            if let Some((prev_key, prev_raw_key, tombstone)) = prev.take() {
+                if tombstone || (prev_key == *key && version < low_water) {
+                    batch.delete(&prev_raw_key);
+                    removed += 1;
+                }
+            }
+            if version < low_water {
+                let tombstone = bincode::deserialize::<Option<Vec<u8>>>(&value)?.is_none();
+                prev = Some((key.into_owned(), raw_key, tombstone));
+            }
+        }
+        if let Some((_, prev_raw_key, true)) = prev {
+            batch.delete(&prev_raw_key);
+            removed += 1;
+        }
+        drop(scan);
+
+        // Remove active set snapshots below the low-water mark, since
+        // time-travel queries can no longer use them.
+        let mut scan = session.scan_prefix(&KeyPrefix::TxnActiveSnapshot.encode()?);
+        while let Some((key, _)) = scan.next().transpose()? {
+            match Key::decode(&key)? {
+                Key::TxnActiveSnapshot(version) if version < low_water => batch.delete(&key),
+                Key::TxnActiveSnapshot(_) => break,
+                key => {
+                    return Err(Error::Internal(format!(
+                        "Expected TxnActiveSnapshot got {:?}",
+                        key
+                    )))
+                }
+            }
+        }
+        drop(scan);
+
+        // Remove conflict records of committed serializable transactions below
+        // the low-water mark, since they're visible to all active transactions
+        // and can no longer conflict with them.
+        let mut committed = Vec::new();
+        let mut scan = session.scan_prefix(&KeyPrefix::TxnCommitted.encode()?);
+        while let Some((key, _)) = scan.next().transpose()? {
+            match Key::decode(&key)? {
+                Key::TxnCommitted(version) if version < low_water => committed.push(version),
+                Key::TxnCommitted(_) => break,
+                key => return Err(Error::Internal(format!("Expected TxnCommitted got {:?}", key))),
+            }
+        }
+        drop(scan);
+        for version in committed {
+            Transaction::delete_reads(&mut session, version, &mut batch)?;
+            batch.delete(&Key::TxnCommitted(version).encode()?);
+        }
+
+        batch.set(&Key::LowWater.encode()?, bincode::serialize(&low_water)?);
+        session.write_batch(batch)?;
+        Ok(removed)
+    }
+
+    /// Returns the versions of all active read-write transactions.
+    pub fn active(&self) -> Result<HashSet<Version>> {
+        Transaction::scan_active(&mut self.engine.lock()?)
+    }
+
+    /// Rolls back the active read-write transaction at the given version,
+    /// without its transaction state. This is used to clean up transactions
+    /// that were abandoned by their clients.
+    pub fn rollback(&self, version: Version) -> Result<()> {
+        let active = Transaction::get_active_snapshot(&mut self.engine.lock()?, version)?;
+        let state = TransactionState {
+            version,
+            read_only: false,
+            serializable: false,
+            savepoints: 0,
+            active,
+        };
+        Transaction::resume(self.engine.clone(), state)?.rollback()
+    }
+
     /// Returns the status of the MVCC and storage engines.
     pub fn status(&self) -> Result<Status> {
         let mut engine = self.engine.lock()?;
@@ -286,7 +674,8 @@ impl<E: Engine> MVCC<E> {
             None => 0,
         };
         let active_txns = engine.scan_prefix(&KeyPrefix::TxnActive.encode()?).count() as u64;
-        Ok(Status { versions, active_txns, storage: engine.status()? })
+        let low_water = Transaction::get_low_water(&mut engine)?;
+        Ok(Status { versions, active_txns, low_water, storage: engine.status()? })
     }
 }
 
@@ -297,6 +686,9 @@ pub struct Status {
     pub versions: u64,
     /// Number of currently active transactions.
     pub active_txns: u64,
+    /// The garbage collection low-water mark. Older versions have been
+    /// garbage collected, and can't be read by time-travel queries.
+    pub low_water: u64,
     /// The storage engine.
     pub storage: super::engine::Status,
 }
@@ -329,6 +721,13 @@ pub struct TransactionState {
     pub version: Version,
     /// If true, the transaction is read only.
     pub read_only: bool,
+    /// If true, the transaction is serializable: it records its reads, and
+    /// aborts on commit if it may violate serializability. Otherwise, it uses
+    /// snapshot isolation. Read-only transactions are never serializable.
+    pub serializable: bool,
+    /// The number of savepoints. Writes record undo information for the
+    /// latest savepoint.
+    pub savepoints: u64,
     /// The set of concurrent active (uncommitted) transactions, as of the start
     /// of this transaction. Their writes should be invisible to this
     /// transaction even if they're writing at a lower version, since they're
@@ -364,7 +763,7 @@ impl<E: Engine> Transaction<E> {
     /// Begins a new transaction in read-write mode. This will allocate a new
     /// version that the transaction can write at, add it to the active set, and
     /// record its active snapshot for time-travel queries.
-    fn begin(engine: Arc<Mutex<E>>) -> Result<Self> {
+    fn begin(engine: Arc<Mutex<E>>, serializable: bool) -> Result<Self> {
         let mut session = engine.lock()?;
 
         // Allocate a new version to write at.
@@ -383,7 +782,10 @@ impl<E: Engine> Transaction<E> {
         session.set(&Key::TxnActive(version).encode()?, vec![])?;
         drop(session);
 
-        Ok(Self { engine, st: TransactionState { version, read_only: false, active } })
+        Ok(Self {
+            engine,
+            st: TransactionState { version, read_only: false, serializable, savepoints: 0, active },
+        })
     }
 
     /// Begins a new read-only transaction. If version is given it will see the
@@ -402,34 +804,86 @@ impl<E: Engine> Transaction<E> {
         // If requested, create the transaction as of a past version, restoring
         // the active snapshot as of the beginning of that version. Otherwise,
         // use the latest version and get the current, real-time snapshot.
-        let mut active = HashSet::new();
-        if let Some(as_of) = as_of {
+        let active = if let Some(as_of) = as_of {
             if as_of >= version {
                 return Err(Error::Value(format!("Version {} does not exist", as_of)));
             }
             version = as_of;
-            if let Some(value) = session.get(&Key::TxnActiveSnapshot(version).encode()?)? {
-                active = bincode::deserialize(&value)?;
-            }
+            let active = Self::get_active_snapshot(&mut session, version)?;
+            Self::check_gc(&mut session, version, &active)?;
+            active
         } else {
-            active = Self::scan_active(&mut session)?;
-        }
+            Self::scan_active(&mut session)?
+        };
 
         drop(session);
 
-        Ok(Self { engine, st: TransactionState { version, read_only: true, active } })
+        Ok(Self {
+            engine,
+            st: TransactionState {
+                version,
+                read_only: true,
+                serializable: false,
+                savepoints: 0,
+                active,
+            },
+        })
     }
 
     /// Resumes a transaction from the given state.
     fn resume(engine: Arc<Mutex<E>>, s: TransactionState) -> Result<Self> {
         // For read-write transactions, verify that the transaction is still
-        // active before making further writes.
+        // active before making further writes. For read-only transactions,
+        // verify that the versions they can see haven't been garbage collected
+        // since they began.
+        // The transaction may have been rolled back by MVCC::rollback(), e.g.
+        // if it was abandoned, so this is a user-facing error.
         if !s.read_only && engine.lock()?.get(&Key::TxnActive(s.version).encode()?)?.is_none() {
-            return Err(Error::Internal(format!("No active transaction at version {}", s.version)));
+            return Err(Error::Value(format!("No active transaction at version {}", s.version)));
+        }
+        if s.read_only {
+            Self::check_gc(&mut engine.lock()?, s.version, &s.active)?;
         }
         Ok(Self { engine, st: s })
     }
 
+    /// Fetches the active set snapshot at the given version.
+    fn get_active_snapshot(
+        session: &mut MutexGuard<E>,
+        version: Version,
+    ) -> Result<HashSet<Version>> {
+        match session.get(&Key::TxnActiveSnapshot(version).encode()?)? {
+            Some(value) => bincode::deserialize(&value),
+            None => Ok(HashSet::new()),
+        }
+    }
+
+    /// Fetches the garbage collection low-water mark.
+    fn get_low_water(session: &mut MutexGuard<E>) -> Result<Version> {
+        match session.get(&Key::LowWater.encode()?)? {
+            Some(ref v) => bincode::deserialize(v),
+            None => Ok(0),
+        }
+    }
+
+    /// Checks that a read-only transaction at the given version and active
+    /// set can see a consistent snapshot, i.e. that none of the versions it
+    /// can see have been garbage collected.
+    fn check_gc(
+        session: &mut MutexGuard<E>,
+        version: Version,
+        active: &HashSet<Version>,
+    ) -> Result<()> {
+        let low_water = Self::get_low_water(session)?;
+        if version < low_water || active.iter().any(|v| *v < low_water) {
+            return Err(Error::Value(format!(
+                "Version {} has been garbage collected, the oldest available version is {}",
+                version, low_water
+            )));
+        }
+        Ok(())
+    }
+
     /// Fetches the set of currently active transactions.
     fn scan_active(session: &mut MutexGuard<E>) -> Result<HashSet<Version>> {
         let mut active = HashSet::new();
@@ -462,19 +916,29 @@ impl<E: Engine> Transaction<E> {
     /// Commits the transaction, by removing it from the active set. This will
     /// immediately make its writes visible to subsequent transactions. Also
     /// removes its TxnWrite records, which are no longer needed.
+    ///
+    /// Serializable transactions first check for serialization conflicts, and
+    /// if found, roll back and return a serialization error.
     pub fn commit(self) -> Result<()> {
         if self.st.read_only {
             return Ok(());
         }
         let mut session = self.engine.lock()?;
-        let remove = session
-            .scan_prefix(&KeyPrefix::TxnWrite(self.st.version).encode()?)
-            .map(|r| r.map(|(k, _)| k))
-            .collect::<Result<Vec<_>>>()?;
-        for key in remove {
-            session.delete(&key)?
+        let mut batch = WriteBatch::new();
+        if self.st.serializable && !self.check_serializable(&mut session, &mut batch)? {
+            drop(session);
+            self.rollback()?;
+            return Err(Error::Serialization);
         }
-        session.delete(&Key::TxnActive(self.st.version).encode()?)
+        let mut scan = session.scan_prefix(&KeyPrefix::TxnWrite(self.st.version).encode()?);
+        while let Some((key, _)) = scan.next().transpose()? {
+            batch.delete(&key);
+        }
+        drop(scan);
+        self.delete_savepoints(&mut session, &mut batch)?;
+        self.release_locks(&mut session, &mut batch)?;
+        batch.delete(&Key::TxnActive(self.st.version).encode()?);
+        session.write_batch(batch)
     }
 
     /// Rolls back the transaction, by undoing all written versions and removing
@@ -485,22 +949,410 @@ impl<E: Engine> Transaction<E> {
             return Ok(());
         }
         let mut session = self.engine.lock()?;
-        let mut rollback = Vec::new();
+        let mut batch = WriteBatch::new();
         let mut scan = session.scan_prefix(&KeyPrefix::TxnWrite(self.st.version).encode()?);
         while let Some((key, _)) = scan.next().transpose()? {
             match Key::decode(&key)? {
                 Key::TxnWrite(_, key) => {
-                    rollback.push(Key::Version(key, self.st.version).encode()?) // the version
+                    batch.delete(&Key::Version(key, self.st.version).encode()?) // the version
                 }
                 key => return Err(Error::Internal(format!("Expected TxnWrite, got {:?}", key))),
             };
-            rollback.push(key); // the TxnWrite record
+            batch.delete(&key); // the TxnWrite record
+        }
+        drop(scan);
+        Self::delete_reads(&mut session, self.st.version, &mut batch)?;
+        self.delete_savepoints(&mut session, &mut batch)?;
+        self.release_locks(&mut session, &mut batch)?;
+        batch.delete(&Key::TxnActive(self.st.version).encode()?); // remove from active set
+        session.write_batch(batch)
+    }
+
+    /// Creates a savepoint, returning its ID. Writes made after it can be
+    /// undone with rollback_to().
+    pub fn savepoint(&mut self) -> u64 {
+        self.st.savepoints += 1;
+        self.st.savepoints - 1
+    }
+
+    /// Rolls back to a savepoint, undoing all writes made after it was
+    /// created. Later savepoints are removed, but the savepoint itself is kept
+    /// and can be rolled back to again.
+    pub fn rollback_to(&mut self, savepoint: u64) -> Result<()> {
+        self.check_savepoint(savepoint)?;
+        if !self.st.read_only {
+            let mut session = self.engine.lock()?;
+            let mut batch = WriteBatch::new();
+            // Undo later savepoints first, such that earlier values win.
+            for id in (savepoint..self.st.savepoints).rev() {
+                let prefix = KeyPrefix::TxnSavepoint(self.st.version, id).encode()?;
+                let mut scan = session.scan_prefix(&prefix);
+                while let Some((key, value)) = scan.next().transpose()? {
+                    let userkey = match Key::decode(&key)? {
+                        Key::TxnSavepoint(_, _, userkey) => userkey,
+                        key => {
+                            return Err(Error::Internal(format!(
+                                "Expected TxnSavepoint, got {:?}",
+                                key
+                            )))
+                        }
+                    };
+                    let version = Key::Version(userkey.clone(), self.st.version).encode()?;
+                    match bincode::deserialize::<Option<Vec<u8>>>(&value)? {
+                        Some(value) => batch.set(&version, value),
+                        None => {
+                            batch.delete(&version);
+                            batch.delete(&Key::TxnWrite(self.st.version, userkey).encode()?);
+                        }
+                    }
+                    batch.delete(&key); // the TxnSavepoint record
+                }
+            }
+            session.write_batch(batch)?;
+        }
+        self.st.savepoints = savepoint + 1;
+        Ok(())
+    }
+
+    /// Releases a savepoint, removing it and all later savepoints while
+    /// keeping their writes. These can still be undone by rolling back to an
+    /// earlier savepoint.
+    pub fn release(&mut self, savepoint: u64) -> Result<()> {
+        self.check_savepoint(savepoint)?;
+        if !self.st.read_only {
+            let mut session = self.engine.lock()?;
+            let mut batch = WriteBatch::new();
+            // Collect the earliest undo record of each key, and move them to
+            // the previous savepoint unless it already has one.
+            let mut undo = BTreeMap::new();
+            for id in savepoint..self.st.savepoints {
+                let prefix = KeyPrefix::TxnSavepoint(self.st.version, id).encode()?;
+                let mut scan = session.scan_prefix(&prefix);
+                while let Some((key, value)) = scan.next().transpose()? {
+                    match Key::decode(&key)? {
+                        Key::TxnSavepoint(_, _, userkey) => {
+                            undo.entry(userkey.into_owned()).or_insert(value);
+                        }
+                        key => {
+                            return Err(Error::Internal(format!(
+                                "Expected TxnSavepoint, got {:?}",
+                                key
+                            )))
+                        }
+                    }
+                    batch.delete(&key);
+                }
+            }
+            if let Some(previous) = savepoint.checked_sub(1) {
+                for (userkey, value) in undo {
+                    let key =
+                        Key::TxnSavepoint(self.st.version, previous, userkey.into()).encode()?;
+                    if session.get(&key)?.is_none() {
+                        batch.set(&key, value);
+                    }
+                }
+            }
+            session.write_batch(batch)?;
+        }
+        self.st.savepoints = savepoint;
+        Ok(())
+    }
+
+    /// Checks that a savepoint exists.
+    fn check_savepoint(&self, savepoint: u64) -> Result<()> {
+        if savepoint >= self.st.savepoints {
+            return Err(Error::Value(format!("Savepoint {} does not exist", savepoint)));
+        }
+        Ok(())
+    }
+
+    /// Deletes the undo records of all savepoints. These are scanned by
+    /// version rather than savepoint, since a transaction rolled back via
+    /// MVCC::rollback() doesn't know its savepoints.
+    fn delete_savepoints(&self, session: &mut MutexGuard<E>, batch: &mut WriteBatch) -> Result<()> {
+        let from = KeyPrefix::TxnSavepoint(self.st.version, 0).encode()?;
+        let to = KeyPrefix::TxnSavepoint(self.st.version + 1, 0).encode()?;
+        let mut scan = session.scan(from..to);
+        while let Some((key, _)) = scan.next().transpose()? {
+            batch.delete(&key);
+        }
+        Ok(())
+    }
+
+    /// Attempts to lock a key in the given mode, returning false if the lock
+    /// is held by other transactions in a conflicting mode, or if the key has
+    /// an uncommitted write by another transaction. If wait is true and the
+    /// lock isn't acquired, records the transactions we're waiting for, and
+    /// returns a serialization error if waiting would deadlock. See the module
+    /// documentation for details.
+    pub fn lock(&self, key: &[u8], mode: LockMode, wait: bool) -> Result<bool> {
+        if self.st.read_only {
+            return Err(Error::ReadOnly);
+        }
+        let mut session = self.engine.lock()?;
+
+        // Find the transactions holding conflicting locks, and our own lock.
+        let mut blockers = HashSet::new();
+        let mut held = None;
+        let mut scan = session.scan_prefix(&KeyPrefix::Lock(key.into()).encode()?);
+        while let Some((k, v)) = scan.next().transpose()? {
+            match Key::decode(&k)? {
+                Key::Lock(_, version) if version == self.st.version => {
+                    held = Some(bincode::deserialize::<LockMode>(&v)?)
+                }
+                Key::Lock(_, version) => {
+                    if mode.conflicts(bincode::deserialize(&v)?) {
+                        blockers.insert(version);
+                    }
+                }
+                key => return Err(Error::Internal(format!("Expected Lock, got {:?}", key))),
+            }
+        }
+        drop(scan);
+
+        // If the latest version is invisible to us, it's either an uncommitted
+        // write that we must wait for, or a committed write that we'll read and
+        // write on top of once locked. We can't do so if it's newer than us, or
+        // if we're serializable.
+        let mut latest = None;
+        let from = Key::Version(key.into(), 0).encode()?;
+        let to = Key::Version(key.into(), u64::MAX).encode()?;
+        if let Some((k, _)) = session.scan(from..=to).last().transpose()? {
+            match Key::decode(&k)? {
+                Key::Version(_, version) if !self.st.is_visible(version) => {
+                    if session.get(&Key::TxnActive(version).encode()?)?.is_some() {
+                        blockers.insert(version);
+                    } else if version > self.st.version || self.st.serializable {
+                        return Err(Error::Serialization);
+                    } else {
+                        latest = Some(version);
+                    }
+                }
+                Key::Version(..) => {}
+                key => return Err(Error::Internal(format!("Expected Key::Version got {:?}", key))),
+            }
+        }
+
+        // If the lock is held by others and we're waiting for it, record the
+        // transactions we're waiting for, unless this would deadlock.
+        // Otherwise, clear any previous wait record.
+        let wait_key = Key::TxnWait(self.st.version).encode()?;
+        if !blockers.is_empty() && wait {
+            if Self::waits_for(&mut session, &blockers, self.st.version)? {
+                if session.get(&wait_key)?.is_some() {
+                    session.delete(&wait_key)?;
+                }
+                return Err(Error::Serialization);
+            }
+            session.set(&wait_key, bincode::serialize(&blockers)?)?;
+            return Ok(false);
+        }
+        if session.get(&wait_key)?.is_some() {
+            session.delete(&wait_key)?;
+        }
+        if !blockers.is_empty() {
+            return Ok(false);
+        }
+
+        // Acquire the lock, unless we already hold it in a sufficient mode.
+        if held != Some(LockMode::Exclusive) && held != Some(mode) {
+            let latest = match latest {
+                Some(version) => bincode::serialize(&version)?,
+                None => vec![],
+            };
+            session.set(&Key::TxnLock(self.st.version, key.into()).encode()?, latest)?;
+            session.set(
+                &Key::Lock(key.into(), self.st.version).encode()?,
+                bincode::serialize(&mode)?,
+            )?;
+        }
+        Ok(true)
+    }
+
+    /// Checks whether any of the given transactions are waiting for the given
+    /// version, either directly or transitively.
+    fn waits_for(
+        session: &mut MutexGuard<E>,
+        waiters: &HashSet<Version>,
+        version: Version,
+    ) -> Result<bool> {
+        let mut visited = HashSet::new();
+        let mut queue: Vec<Version> = waiters.iter().copied().collect();
+        while let Some(waiter) = queue.pop() {
+            if waiter == version {
+                return Ok(true);
+            }
+            if !visited.insert(waiter) {
+                continue;
+            }
+            if let Some(v) = session.get(&Key::TxnWait(waiter).encode()?)? {
+                queue.extend(bincode::deserialize::<HashSet<Version>>(&v)?);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Returns the latest committed version of a key that was invisible to us
+    /// when we locked it, if any. See the module documentation for details.
+    fn locked_version(&self, session: &mut MutexGuard<E>, key: &[u8]) -> Result<Option<Version>> {
+        if self.st.read_only {
+            return Ok(None);
+        }
+        match session.get(&Key::TxnLock(self.st.version, key.into()).encode()?)? {
+            Some(v) if !v.is_empty() => Ok(Some(bincode::deserialize(&v)?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Releases all locks held by the transaction, and removes its wait record.
+    fn release_locks(&self, session: &mut MutexGuard<E>, batch: &mut WriteBatch) -> Result<()> {
+        let mut scan = session.scan_prefix(&KeyPrefix::TxnLock(self.st.version).encode()?);
+        while let Some((key, _)) = scan.next().transpose()? {
+            match Key::decode(&key)? {
+                Key::TxnLock(_, k) => batch.delete(&Key::Lock(k, self.st.version).encode()?),
+                key => return Err(Error::Internal(format!("Expected TxnLock, got {:?}", key))),
+            }
+            batch.delete(&key);
         }
         drop(scan);
-        for key in rollback.into_iter() {
-            session.delete(&key)?;
+        let wait_key = Key::TxnWait(self.st.version).encode()?;
+        if session.get(&wait_key)?.is_some() {
+            batch.delete(&wait_key);
         }
-        session.delete(&Key::TxnActive(self.st.version).encode()?) // remove from active set
+        Ok(())
+    }
+
+    /// Checks whether a serializable transaction can commit, i.e. that it
+    /// doesn't form a dangerous structure of rw-antidependencies with
+    /// concurrent serializable transactions. If it can, adds writes to the
+    /// batch that record the new conflicts, and remove conflict records that
+    /// are no longer needed. See the module documentation for details.
+    fn check_serializable(
+        &self,
+        session: &mut MutexGuard<E>,
+        batch: &mut WriteBatch,
+    ) -> Result<bool> {
+        let version = self.st.version;
+
+        // Find outgoing conflicts: concurrent transactions that wrote keys we
+        // read, i.e. versions in our read ranges that we can't see.
+        let mut rw_out = HashSet::new();
+        for range in Self::scan_reads(session, version)? {
+            let mut scan = session.scan(Self::version_range(&range)?);
+            while let Some((key, _)) = scan.next().transpose()? {
+                match Key::decode(&key)? {
+                    Key::Version(_, v) if !self.st.is_visible(v) => rw_out.insert(v),
+                    Key::Version(..) => false,
+                    key => {
+                        return Err(Error::Internal(format!("Expected Key::Version got {:?}", key)))
+                    }
+                };
+            }
+        }
+
+        // Find incoming conflicts: concurrent committed serializable
+        // transactions that read keys we wrote. Committed transactions are
+        // concurrent if we can't see them.
+        let mut writes = Vec::new();
+        let mut scan = session.scan_prefix(&KeyPrefix::TxnWrite(version).encode()?);
+        while let Some((key, _)) = scan.next().transpose()? {
+            match Key::decode(&key)? {
+                Key::TxnWrite(_, key) => writes.push(key.into_owned()),
+                key => return Err(Error::Internal(format!("Expected TxnWrite, got {:?}", key))),
+            };
+        }
+        drop(scan);
+
+        let mut committed = BTreeMap::new();
+        let mut scan = session.scan_prefix(&KeyPrefix::TxnCommitted.encode()?);
+        while let Some((key, value)) = scan.next().transpose()? {
+            match Key::decode(&key)? {
+                Key::TxnCommitted(v) => {
+                    committed.insert(v, bincode::deserialize::<Conflicts>(&value)?)
+                }
+                key => {
+                    return Err(Error::Internal(format!("Expected TxnCommitted, got {:?}", key)))
+                }
+            };
+        }
+        drop(scan);
+
+        let mut rw_in = HashSet::new();
+        for v in committed.keys().copied().filter(|v| !self.st.is_visible(*v)) {
+            let reads = Self::scan_reads(session, v)?;
+            if writes.iter().any(|key| reads.iter().any(|range| range.contains(key))) {
+                rw_in.insert(v);
+            }
+        }
+
+        // Abort if we're the pivot of a dangerous structure, or if we complete
+        // one where a committed transaction is the pivot.
+        if !rw_in.is_empty() && !rw_out.is_empty()
+            || rw_out.iter().any(|v| committed.get(v).is_some_and(|c| c.rw_out))
+            || rw_in.iter().any(|v| committed.get(v).is_some_and(|c| c.rw_in))
+        {
+            return Ok(false);
+        }
+
+        // Record the conflicts. Committed transactions (including ourself)
+        // can only conflict with concurrent transactions, so once they're
+        // visible to all active transactions their records can be removed.
+        let mut horizon = Version::MAX;
+        for active in Self::scan_active(session)? {
+            if active != version {
+                let snapshot = Self::get_active_snapshot(session, active)?;
+                horizon = horizon.min(active).min(snapshot.into_iter().min().unwrap_or(active));
+            }
+        }
+        let conflicts = Conflicts { rw_in: !rw_in.is_empty(), rw_out: !rw_out.is_empty() };
+        committed.insert(version, conflicts);
+        for (v, mut conflicts) in committed {
+            if v < horizon {
+                Self::delete_reads(session, v, batch)?;
+                batch.delete(&Key::TxnCommitted(v).encode()?);
+                continue;
+            }
+            conflicts.rw_in |= rw_out.contains(&v);
+            conflicts.rw_out |= rw_in.contains(&v);
+            batch.set(&Key::TxnCommitted(v).encode()?, bincode::serialize(&conflicts)?);
+        }
+        Ok(true)
+    }
+
+    /// Fetches the key ranges read by a serializable transaction.
+    fn scan_reads(session: &mut MutexGuard<E>, version: Version) -> Result<Vec<KeyRange>> {
+        let mut reads = Vec::new();
+        let mut scan = session.scan_prefix(&KeyPrefix::TxnRead(version).encode()?);
+        while let Some((key, _)) = scan.next().transpose()? {
+            match Key::decode(&key)? {
+                Key::TxnRead(_, range) => reads.push(bincode::deserialize(&range)?),
+                key => return Err(Error::Internal(format!("Expected TxnRead, got {:?}", key))),
+            };
+        }
+        Ok(reads)
+    }
+
+    /// Deletes the read records of a serializable transaction.
+    fn delete_reads(
+        session: &mut MutexGuard<E>,
+        version: Version,
+        batch: &mut WriteBatch,
+    ) -> Result<()> {
+        let mut scan = session.scan_prefix(&KeyPrefix::TxnRead(version).encode()?);
+        while let Some((key, _)) = scan.next().transpose()? {
+            batch.delete(&key);
+        }
+        Ok(())
+    }
+
+    /// Records a read of the given key range, if the transaction is
+    /// serializable.
+    fn record_read(&self, session: &mut MutexGuard<E>, range: KeyRange) -> Result<()> {
+        if !self.st.serializable {
+            return Ok(());
+        }
+        let range = bincode::serialize(&range)?;
+        session.set(&Key::TxnRead(self.st.version, range.into()).encode()?, vec![])
     }
 
     /// Deletes a key.
@@ -516,7 +1368,7 @@ impl<E: Engine> Transaction<E> {
     /// Writes a new version for a key at the transaction's version. None writes
     /// a deletion tombstone. If a write conflict is found (either a newer or
     /// uncommitted version), a serialization error is returned.  Replacing our
-    /// own uncommitted write is fine.
+    /// own uncommitted write, or a committed version we've locked, is fine.
     fn write_version(&self, key: &[u8], value: Option<Vec<u8>>) -> Result<()> {
         if self.st.read_only {
             return Err(Error::ReadOnly);
@@ -533,10 +1385,12 @@ impl<E: Engine> Transaction<E> {
         )
         .encode()?;
         let to = Key::Version(key.into(), u64::MAX).encode()?;
-        if let Some((key, _)) = session.scan(from..=to).last().transpose()? {
-            match Key::decode(&key)? {
+        if let Some((k, _)) = session.scan(from..=to).last().transpose()? {
+            match Key::decode(&k)? {
                 Key::Version(_, version) => {
-                    if !self.st.is_visible(version) {
+                    if !self.st.is_visible(version)
+                        && self.locked_version(&mut session, key)? != Some(version)
+                    {
                         return Err(Error::Serialization);
                     }
                 }
@@ -544,6 +1398,29 @@ impl<E: Engine> Transaction<E> {
             }
         }
 
+        // Check for locks held by other transactions.
+        let mut scan = session.scan_prefix(&KeyPrefix::Lock(key.into()).encode()?);
+        while let Some((k, _)) = scan.next().transpose()? {
+            match Key::decode(&k)? {
+                Key::Lock(_, version) if version != self.st.version => {
+                    return Err(Error::Serialization)
+                }
+                Key::Lock(..) => {}
+                key => return Err(Error::Internal(format!("Expected Lock, got {:?}", key))),
+            }
+        }
+        drop(scan);
+
+        // If there's a savepoint, record our previous value of the key unless
+        // already recorded, so it can be restored if rolled back.
+        if let Some(savepoint) = self.st.savepoints.checked_sub(1) {
+            let undo = Key::TxnSavepoint(self.st.version, savepoint, key.into()).encode()?;
+            if session.get(&undo)?.is_none() {
+                let previous = session.get(&Key::Version(key.into(), self.st.version).encode()?)?;
+                session.set(&undo, bincode::serialize(&previous)?)?;
+            }
+        }
+
         // Write the new version and its write record.
         //
         // NB: TxnWrite contains the provided user key, not the encoded engine
@@ -556,13 +1433,18 @@ impl<E: Engine> Transaction<E> {
     /// Fetches a key's value, or None if it does not exist.
     pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
         let mut session = self.engine.lock()?;
+        self.record_read(
+            &mut session,
+            (Bound::Included(key.to_vec()), Bound::Included(key.to_vec())),
+        )?;
+        let locked = self.locked_version(&mut session, key)?;
         let from = Key::Version(key.into(), 0).encode()?;
         let to = Key::Version(key.into(), self.st.version).encode()?;
         let mut scan = session.scan(from..=to).rev();
         while let Some((key, value)) = scan.next().transpose()? {
             match Key::decode(&key)? {
                 Key::Version(_, version) => {
-                    if self.st.is_visible(version) {
+                    if self.st.is_visible(version) || Some(version) == locked {
                         return bincode::deserialize(&value);
                     }
                 }
@@ -575,6 +1457,15 @@ impl<E: Engine> Transaction<E> {
     /// Returns an iterator over the latest visible key/value pairs at the
     /// transaction's version.
     pub fn scan<R: RangeBounds<Vec<u8>>>(&self, range: R) -> Result<Scan<E>> {
+        let (start, end) = Self::version_range(&range)?;
+        let mut session = self.engine.lock()?;
+        self.record_read(&mut session, (range.start_bound().cloned(), range.end_bound().cloned()))?;
+        Ok(Scan::new(session, self.state(), start, end))
+    }
+
+    /// Converts a key range into an engine key range spanning all versions
+    /// of the keys.
+    fn version_range(range: &impl RangeBounds<Vec<u8>>) -> Result<KeyRange> {
         let start = match range.start_bound() {
             Bound::Excluded(k) => Bound::Excluded(Key::Version(k.into(), u64::MAX).encode()?),
             Bound::Included(k) => Bound::Included(Key::Version(k.into(), 0).encode()?),
@@ -585,7 +1476,7 @@ impl<E: Engine> Transaction<E> {
             Bound::Included(k) => Bound::Included(Key::Version(k.into(), u64::MAX).encode()?),
             Bound::Unbounded => Bound::Excluded(KeyPrefix::Unversioned.encode()?),
         };
-        Ok(Scan::new(self.engine.lock()?, self.state(), start, end))
+        Ok((start, end))
     }
 
     /// Scans keys under a given prefix.
@@ -593,9 +1484,18 @@ impl<E: Engine> Transaction<E> {
         // Normally, KeyPrefix::Version will only match all versions of the
         // exact given key. We want all keys maching the prefix, so we chop off
         // the KeyCode byte slice terminator 0x0000 at the end.
+        let mut session = self.engine.lock()?;
+        let end = match prefix.iter().rposition(|b| *b != 0xff) {
+            Some(i) => Bound::Excluded(
+                prefix.iter().take(i).copied().chain(std::iter::once(prefix[i] + 1)).collect(),
+            ),
+            None => Bound::Unbounded,
+        };
+        self.record_read(&mut session, (Bound::Included(prefix.to_vec()), end))?;
+
         let mut prefix = KeyPrefix::Version(prefix.into()).encode()?;
         prefix.truncate(prefix.len() - 2);
-        Ok(Scan::new_prefix(self.engine.lock()?, self.state(), prefix))
+        Ok(Scan::new_prefix(session, self.state(), prefix))
     }
 }
 
@@ -848,6 +1748,10 @@ pub mod tests {
             self.new_txn("begin", self.mvcc.begin())
         }
 
+        fn begin_serializable(&mut self) -> Result<ScheduleTransaction> {
+            self.new_txn("begin serializable", self.mvcc.begin_serializable())
+        }
+
         fn begin_read_only(&mut self) -> Result<ScheduleTransaction> {
             self.new_txn("begin read-only", self.mvcc.begin_read_only())
         }
@@ -920,6 +1824,32 @@ pub mod tests {
             Ok(())
         }
 
+        fn gc(&self, version: Version) -> Result<u64> {
+            let mut f = self.file.lock()?;
+            let result = self.mvcc.gc(version);
+            write!(f, "T_: gc {}", version)?;
+            match &result {
+                Ok(removed) => writeln!(f, " → {} removed", removed)?,
+                Err(err) => writeln!(f, " → Error::{:?}", err)?,
+            }
+            Schedule::print_log(&mut f, &mut self.mvcc.engine.lock()?)?;
+            writeln!(f)?;
+            result
+        }
+
+        fn rollback(&self, version: Version) -> Result<()> {
+            let mut f = self.file.lock()?;
+            let result = self.mvcc.rollback(version);
+            write!(f, "T_: rollback {}", version)?;
+            match &result {
+                Ok(()) => writeln!(f)?,
+                Err(err) => writeln!(f, " → Error::{:?}", err)?,
+            }
+            Schedule::print_log(&mut f, &mut self.mvcc.engine.lock()?)?;
+            writeln!(f)?;
+            result
+        }
+
         fn get_unversioned(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
             let value = self.mvcc.get_unversioned(key)?;
             write!(
@@ -995,6 +1925,44 @@ pub mod tests {
             result
         }
 
+        fn lock(&self, key: &[u8], mode: LockMode, wait: bool) -> Result<bool> {
+            let result = self.txn.lock(key, mode, wait);
+            let mut f = self.file.lock()?;
+            write!(
+                f,
+                "T{}: lock {} {}{}",
+                self.id,
+                debug::format_raw(key),
+                mode,
+                if wait { "" } else { " nowait" }
+            )?;
+            match &result {
+                Ok(acquired) => writeln!(f, " → {}", acquired)?,
+                Err(err) => writeln!(f, " → Error::{:?}", err)?,
+            }
+            Schedule::print_log(&mut f, &mut self.txn.engine.lock()?)?;
+            writeln!(f)?;
+            result
+        }
+
+        fn savepoint(&mut self) -> Result<u64> {
+            let savepoint = self.txn.savepoint();
+            self.print_mutation(&format!("savepoint → {}", savepoint), &Ok(()))?;
+            Ok(savepoint)
+        }
+
+        fn rollback_to(&mut self, savepoint: u64) -> Result<()> {
+            let result = self.txn.rollback_to(savepoint);
+            self.print_mutation(&format!("rollback to {}", savepoint), &result)?;
+            result
+        }
+
+        fn release(&mut self, savepoint: u64) -> Result<()> {
+            let result = self.txn.release(savepoint);
+            self.print_mutation(&format!("release {}", savepoint), &result)?;
+            result
+        }
+
         fn delete(&self, key: &[u8]) -> Result<()> {
             let result = self.txn.delete(key);
             self.print_mutation(&format!("del {}", debug::format_raw(key)), &result)?;
@@ -1027,19 +1995,7 @@ pub mod tests {
         }
 
         fn scan<R: RangeBounds<Vec<u8>>>(&self, range: R) -> Result<Scan<Debug<Memory>>> {
-            let name = format!(
-                "scan {}..{}",
-                match range.start_bound() {
-                    Bound::Excluded(k) => format!("({}", debug::format_raw(k)),
-                    Bound::Included(k) => format!("[{}", debug::format_raw(k)),
-                    Bound::Unbounded => "".to_string(),
-                },
-                match range.end_bound() {
-                    Bound::Excluded(k) => format!("{})", debug::format_raw(k)),
-                    Bound::Included(k) => format!("{}]", debug::format_raw(k)),
-                    Bound::Unbounded => "".to_string(),
-                },
-            );
+            let name = format!("scan {}", debug::format_range(&range));
             let mut scan = self.txn.scan(range)?;
             self.print_scan(&name, scan.to_vec()?)?;
             Ok(scan)
@@ -1130,6 +2086,7 @@ pub mod tests {
                 Key::Version(b"foo".as_slice().into(), 1),
             ),
             (KeyPrefix::Unversioned, Key::Unversioned(b"foo".as_slice().into())),
+            (KeyPrefix::LowWater, Key::LowWater),
         ];
 
         for (prefix, key) in cases {
@@ -1148,19 +2105,37 @@ pub mod tests {
         let t1 = mvcc.begin()?;
         assert_eq!(
             t1.state(),
-            TransactionState { version: 1, read_only: false, active: HashSet::new() }
+            TransactionState {
+                version: 1,
+                read_only: false,
+                serializable: false,
+                savepoints: 0,
+                active: HashSet::new()
+            }
         );
 
         let t2 = mvcc.begin()?;
         assert_eq!(
             t2.state(),
-            TransactionState { version: 2, read_only: false, active: HashSet::from([1]) }
+            TransactionState {
+                version: 2,
+                read_only: false,
+                serializable: false,
+                savepoints: 0,
+                active: HashSet::from([1])
+            }
         );
 
         let t3 = mvcc.begin()?;
         assert_eq!(
             t3.state(),
-            TransactionState { version: 3, read_only: false, active: HashSet::from([1, 2]) }
+            TransactionState {
+                version: 3,
+                read_only: false,
+                serializable: false,
+                savepoints: 0,
+                active: HashSet::from([1, 2])
+            }
         );
 
         t2.commit()?; // commit to remove from active set
@@ -1168,7 +2143,13 @@ pub mod tests {
         let t4 = mvcc.begin()?;
         assert_eq!(
             t4.state(),
-            TransactionState { version: 4, read_only: false, active: HashSet::from([1, 3]) }
+            TransactionState {
+                version: 4,
+                read_only: false,
+                serializable: false,
+                savepoints: 0,
+                active: HashSet::from([1, 3])
+            }
         );
 
         Ok(())
@@ -1185,7 +2166,13 @@ pub mod tests {
         let t1 = mvcc.begin_read_only()?;
         assert_eq!(
             t1.state(),
-            TransactionState { version: 1, read_only: true, active: HashSet::new() }
+            TransactionState {
+                version: 1,
+                read_only: true,
+                serializable: false,
+                savepoints: 0,
+                active: HashSet::new()
+            }
         );
         assert_eq!(t1.set(b"foo", vec![1]), Err(Error::ReadOnly));
         assert_eq!(t1.delete(b"foo"), Err(Error::ReadOnly));
@@ -1196,13 +2183,25 @@ pub mod tests {
         let t2 = mvcc.begin()?;
         assert_eq!(
             t2.state(),
-            TransactionState { version: 1, read_only: false, active: HashSet::new() }
+            TransactionState {
+                version: 1,
+                read_only: false,
+                serializable: false,
+                savepoints: 0,
+                active: HashSet::new()
+            }
         );
 
         let t3 = mvcc.begin_read_only()?;
         assert_eq!(
             t3.state(),
-            TransactionState { version: 2, read_only: true, active: HashSet::from([1]) }
+            TransactionState {
+                version: 2,
+                read_only: true,
+                serializable: false,
+                savepoints: 0,
+                active: HashSet::from([1])
+            }
         );
 
         Ok(())
@@ -1230,7 +2229,13 @@ pub mod tests {
         let t4 = mvcc.begin_as_of(3)?;
         assert_eq!(
             t4.state(),
-            TransactionState { version: 3, read_only: true, active: HashSet::from([1]) }
+            TransactionState {
+                version: 3,
+                read_only: true,
+                serializable: false,
+                savepoints: 0,
+                active: HashSet::from([1])
+            }
         );
         assert_scan!(t4.scan(..)? => {b"key" => [2]});
 
@@ -1261,7 +2266,13 @@ pub mod tests {
         let t7 = mvcc.begin_as_of(4)?;
         assert_eq!(
             t7.state(),
-            TransactionState { version: 4, read_only: true, active: HashSet::new() }
+            TransactionState {
+                version: 4,
+                read_only: true,
+                serializable: false,
+                savepoints: 0,
+                active: HashSet::new()
+            }
         );
         assert_scan!(t7.scan(..)? => {b"key" => [3], b"other" => [1]});
 
@@ -1278,6 +2289,73 @@ pub mod tests {
         Ok(())
     }
 
+    #[test]
+    /// GC should remove versions below the low-water mark, except the latest
+    /// live version of each key, while retaining versions visible to active
+    /// transactions. Time-travel queries below it should error.
+    fn gc() -> Result<()> {
+        let mut mvcc = Schedule::new("gc")?;
+        mvcc.setup(vec![
+            (b"a", 1, Some(&[1])),
+            (b"b", 1, Some(&[1])),
+            (b"c", 1, Some(&[1])),
+            (b"a", 2, Some(&[2])),
+            (b"b", 2, None),
+            (b"a", 3, Some(&[3])),
+            (b"d", 3, Some(&[3])),
+        ])?;
+
+        // Start an active transaction at version 4, and commit a newer one.
+        let t1 = mvcc.begin()?;
+        t1.set(b"e", vec![4])?;
+        let t2 = mvcc.begin()?;
+        t2.set(b"a", vec![5])?;
+        t2.commit()?;
+
+        // GC is limited by t1, removing a@1, a@2, b@1, and the b@2 tombstone.
+        // t1 should still see the same data.
+        assert_eq!(mvcc.gc(9)?, 4);
+        assert_scan!(t1.scan(..)? => {b"a" => [3], b"c" => [1], b"d" => [3], b"e" => [4]});
+
+        // Time-travel queries below the low-water mark error, but not at it.
+        assert_eq!(
+            mvcc.begin_as_of(3).err(),
+            Some(Error::Value(
+                "Version 3 has been garbage collected, the oldest available version is 4".into()
+            ))
+        );
+        let t3 = mvcc.begin_as_of(4)?;
+        assert_scan!(t3.scan(..)? => {b"a" => [3], b"c" => [1], b"d" => [3]});
+
+        // The low-water mark never moves backwards.
+        assert_eq!(mvcc.gc(2)?, 0);
+
+        // Delete d and commit t1, then GC up to the latest version. This
+        // removes a@3, d@3, and the d@6 tombstone.
+        let t4 = mvcc.begin()?;
+        t4.delete(b"d")?;
+        t4.commit()?;
+        t1.commit()?;
+        assert_eq!(mvcc.gc(9)?, 3);
+
+        // The existing time-travel transaction can no longer be resumed.
+        assert!(mvcc.resume(t3.state()).is_err());
+        let t5 = mvcc.begin_read_only()?;
+        assert_scan!(t5.scan(..)? => {b"a" => [5], b"c" => [1], b"e" => [4]});
+
+        // GC is also limited by the active set snapshots of active
+        // transactions: t7 can't see t6, even though it has committed.
+        let t6 = mvcc.begin()?;
+        let t7 = mvcc.begin()?;
+        t6.set(b"a", vec![7])?;
+        t6.commit()?;
+        assert_eq!(mvcc.gc(9)?, 0);
+        assert_scan!(t7.scan(..)? => {b"a" => [5], b"c" => [1], b"e" => [4]});
+        t7.commit()?;
+
+        Ok(())
+    }
+
     #[test]
     /// Resume should resume a transaction with the same state.
     fn resume() -> Result<()> {
@@ -1307,7 +2385,13 @@ pub mod tests {
         let state = t3.state().clone();
         assert_eq!(
             state,
-            TransactionState { version: 3, read_only: false, active: HashSet::from([2]) }
+            TransactionState {
+                version: 3,
+                read_only: false,
+                serializable: false,
+                savepoints: 0,
+                active: HashSet::from([2])
+            }
         );
         drop(t3);
 
@@ -1342,7 +2426,7 @@ pub mod tests {
         // Resuming an inactive transaction should error.
         assert_eq!(
             mvcc.resume(state).err(),
-            Some(Error::Internal("No active transaction at version 3".into()))
+            Some(Error::Value("No active transaction at version 3".into()))
         );
 
         // It should also be possible to start a snapshot transaction in t3
@@ -1350,7 +2434,13 @@ pub mod tests {
         let t8 = mvcc.begin_as_of(3)?;
         assert_eq!(
             t8.state(),
-            TransactionState { version: 3, read_only: true, active: HashSet::from([2]) }
+            TransactionState {
+                version: 3,
+                read_only: true,
+                serializable: false,
+                savepoints: 0,
+                active: HashSet::from([2])
+            }
         );
 
         assert_scan!(t8.scan(..)? => {
@@ -1739,6 +2829,175 @@ pub mod tests {
         Ok(())
     }
 
+    #[test]
+    /// Tests that an abandoned transaction can be rolled back by version,
+    /// removing its writes, savepoints, and locks.
+    fn rollback_version() -> Result<()> {
+        let mut mvcc = Schedule::new("rollback_version")?;
+        mvcc.setup(vec![(b"a", 1, Some(&[0])), (b"b", 1, Some(&[0]))])?;
+
+        let mut t1 = mvcc.begin()?;
+        let t2 = mvcc.begin()?;
+        t1.set(b"a", vec![1])?;
+        t1.savepoint()?;
+        t1.savepoint()?;
+        t1.delete(b"a")?;
+        t1.lock(b"b", LockMode::Exclusive, false)?;
+        assert_eq!(mvcc.mvcc.active()?, HashSet::from([t1.state().version, t2.state().version]));
+
+        mvcc.rollback(t1.state().version)?;
+        assert_eq!(mvcc.mvcc.active()?, HashSet::from([t2.state().version]));
+        assert!(matches!(mvcc.rollback(t1.state().version), Err(Error::Value(_))));
+
+        // t2 can now write the keys, and doesn't see t1's writes.
+        t2.set(b"a", vec![2])?;
+        t2.set(b"b", vec![2])?;
+        t2.commit()?;
+
+        let t3 = mvcc.begin_read_only()?;
+        assert_scan!(t3.scan(..)? => {
+            b"a" => [2],
+            b"b" => [2],
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that rolling back to a savepoint undoes writes made after it,
+    /// restoring the transaction's own earlier writes, and that releasing a
+    /// savepoint keeps its writes undoable by earlier savepoints.
+    fn savepoint() -> Result<()> {
+        let mut mvcc = Schedule::new("savepoint")?;
+        mvcc.setup(vec![(b"a", 1, Some(&[0])), (b"b", 1, Some(&[0])), (b"c", 1, Some(&[0]))])?;
+
+        let mut t1 = mvcc.begin()?;
+        let t2 = mvcc.begin()?;
+        t1.set(b"a", vec![1])?;
+
+        // Writes after a savepoint are undone by rolling back to it, both new
+        // writes and overwrites of our own writes.
+        let s0 = t1.savepoint()?;
+        t1.set(b"a", vec![2])?;
+        t1.delete(b"b")?;
+        t1.set(b"d", vec![2])?;
+        assert_eq!(t2.set(b"d", vec![9]), Err(Error::Serialization));
+        t1.rollback_to(s0)?;
+        assert_scan!(t1.scan(..)? => {
+            b"a" => [1],
+            b"b" => [0],
+            b"c" => [0],
+        });
+
+        // The rolled back write to d is gone, so t2 can write it.
+        t2.set(b"d", vec![9])?;
+        t2.rollback()?;
+
+        // The savepoint is kept. Nested savepoints can be rolled back to
+        // individually, and releasing a savepoint keeps its writes but they
+        // can still be rolled back by an earlier savepoint.
+        let s1 = t1.savepoint()?;
+        t1.set(b"a", vec![3])?;
+        let s2 = t1.savepoint()?;
+        t1.set(b"a", vec![4])?;
+        t1.set(b"c", vec![4])?;
+        t1.rollback_to(s2)?;
+        assert_eq!(t1.get(b"a")?, Some(vec![3]));
+        assert_eq!(t1.get(b"c")?, Some(vec![0]));
+
+        t1.set(b"b", vec![5])?;
+        t1.release(s1)?;
+        assert_eq!(t1.rollback_to(s2), Err(Error::Value("Savepoint 2 does not exist".into())));
+        assert_scan!(t1.scan(..)? => {
+            b"a" => [3],
+            b"b" => [5],
+            b"c" => [0],
+        });
+        t1.rollback_to(s0)?;
+        assert_scan!(t1.scan(..)? => {
+            b"a" => [1],
+            b"b" => [0],
+            b"c" => [0],
+        });
+
+        // Committing keeps the remaining writes, and removes undo records.
+        t1.set(b"c", vec![6])?;
+        t1.commit()?;
+
+        let t3 = mvcc.begin_read_only()?;
+        assert_scan!(t3.scan(..)? => {
+            b"a" => [1],
+            b"b" => [0],
+            b"c" => [6],
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tests locking: conflicting locks and uncommitted writes block lockers,
+    /// locks block writers, locks are released on commit and rollback, and
+    /// deadlocks are detected.
+    fn lock() -> Result<()> {
+        let mut mvcc = Schedule::new("lock")?;
+        mvcc.setup(vec![(b"a", 1, Some(&[0])), (b"b", 1, Some(&[0])), (b"c", 1, Some(&[0]))])?;
+
+        let t1 = mvcc.begin()?;
+        let t2 = mvcc.begin()?;
+        let t3 = mvcc.begin()?;
+
+        // An exclusive lock blocks other locks, and t2 records that it's
+        // waiting for t1. Shared locks don't conflict with each other.
+        assert!(t1.lock(b"a", LockMode::Exclusive, true)?);
+        assert!(!t2.lock(b"a", LockMode::Shared, true)?);
+        assert!(t3.lock(b"b", LockMode::Shared, true)?);
+        assert!(t1.lock(b"b", LockMode::Shared, true)?);
+
+        // Locks block writes by other transactions, but not by the holder.
+        assert_eq!(t2.set(b"b", vec![2]), Err(Error::Serialization));
+        t1.set(b"a", vec![1])?;
+
+        // t3 waits for t1 to release a. If t1 waits for t3 to release b, it
+        // would deadlock, so t1 gets a serialization error.
+        assert!(!t3.lock(b"a", LockMode::Exclusive, true)?);
+        assert_eq!(t1.lock(b"b", LockMode::Exclusive, true), Err(Error::Serialization));
+
+        // Rolling back t1 releases its locks and write, allowing t2 to lock a.
+        // t3 gives up waiting for a, clearing its wait record.
+        t1.rollback()?;
+        assert!(t2.lock(b"a", LockMode::Shared, true)?);
+        assert!(!t3.lock(b"a", LockMode::Exclusive, false)?);
+        t2.commit()?;
+        assert!(t3.lock(b"a", LockMode::Exclusive, true)?);
+
+        // An uncommitted write blocks lockers. Once committed, a transaction
+        // that couldn't see it reads it when locked and can write on top of
+        // it, but other keys still come from its snapshot. A transaction
+        // older than the write gets a serialization error.
+        let t4 = mvcc.begin()?;
+        t3.set(b"a", vec![3])?;
+        t3.set(b"c", vec![3])?;
+        let t5 = mvcc.begin()?;
+        assert!(!t4.lock(b"c", LockMode::Shared, false)?);
+        t3.commit()?;
+        assert_eq!(t5.get(b"a")?, Some(vec![0]));
+        assert!(t5.lock(b"a", LockMode::Exclusive, true)?);
+        assert_eq!(t5.get(b"a")?, Some(vec![3]));
+        assert_eq!(t5.get(b"c")?, Some(vec![0]));
+        t5.set(b"a", vec![5])?;
+        assert_eq!(t5.get(b"a")?, Some(vec![5]));
+        t5.commit()?;
+        assert_eq!(t4.lock(b"a", LockMode::Shared, true), Err(Error::Serialization));
+        t4.rollback()?;
+        assert_eq!(mvcc.begin_read_only()?.get(b"a")?, Some(vec![5]));
+
+        // Read-only transactions can't take locks.
+        let t6 = mvcc.begin_read_only()?;
+        assert_eq!(t6.lock(b"a", LockMode::Shared, true), Err(Error::ReadOnly));
+
+        Ok(())
+    }
+
     #[test]
     // A dirty write is when t2 overwrites an uncommitted value written by t1.
     // Snapshot isolation prevents this.
@@ -1859,8 +3118,8 @@ pub mod tests {
     #[test]
     // Write skew is when t1 reads a and writes it to b while t2 reads b and
     // writes it to a. Snapshot isolation DOES NOT prevent this, which is
-    // expected, so we assert the current behavior. Fixing this requires
-    // implementing serializable snapshot isolation.
+    // expected, so we assert the current behavior. Serializable transactions
+    // prevent it, see serializable_write_skew.
     fn anomaly_write_skew() -> Result<()> {
         let mut mvcc = Schedule::new("anomaly_write_skew")?;
         mvcc.setup(vec![(b"a", 1, Some(&[1])), (b"b", 1, Some(&[2]))])?;
@@ -1880,6 +3139,88 @@ pub mod tests {
         Ok(())
     }
 
+    #[test]
+    // Serializable transactions should prevent write skew, by aborting t2 at
+    // commit time since it's the pivot of t1 → t2 → t1.
+    fn serializable_write_skew() -> Result<()> {
+        let mut mvcc = Schedule::new("serializable_write_skew")?;
+        mvcc.setup(vec![(b"a", 1, Some(&[1])), (b"b", 1, Some(&[2]))])?;
+
+        let t1 = mvcc.begin_serializable()?;
+        let t2 = mvcc.begin_serializable()?;
+
+        assert_eq!(t1.get(b"a")?, Some(vec![1]));
+        assert_eq!(t2.get(b"b")?, Some(vec![2]));
+
+        t1.set(b"b", vec![1])?;
+        t2.set(b"a", vec![2])?;
+
+        t1.commit()?;
+        assert_eq!(t2.commit(), Err(Error::Serialization));
+
+        // t2's writes were rolled back.
+        let t3 = mvcc.begin_read_only()?;
+        assert_scan!(t3.scan(..)? => {b"a" => [1], b"b" => [1]});
+
+        Ok(())
+    }
+
+    #[test]
+    /// Serializable transactions should allow conflicts that don't form
+    /// dangerous structures, detect dangerous structures with a committed
+    /// pivot, and detect conflicts with range scans (i.e. phantoms).
+    fn serializable() -> Result<()> {
+        let mut mvcc = Schedule::new("serializable")?;
+        mvcc.setup(vec![(b"a", 1, Some(&[1])), (b"b", 1, Some(&[1])), (b"c", 1, Some(&[1]))])?;
+
+        // t1 reads a, which t2 writes and commits. t1 → t2 is fine, and
+        // serializes as t1, t2.
+        let t1 = mvcc.begin_serializable()?;
+        let t2 = mvcc.begin_serializable()?;
+        assert_eq!(t1.get(b"a")?, Some(vec![1]));
+        t2.set(b"a", vec![2])?;
+        t2.commit()?;
+        t1.set(b"b", vec![2])?;
+        t1.commit()?;
+
+        // t3 → t4 → t5, where t5 commits first, then the pivot t4. When t3
+        // commits, it completes the dangerous structure and aborts.
+        let t3 = mvcc.begin_serializable()?;
+        let t4 = mvcc.begin_serializable()?;
+        let t5 = mvcc.begin_serializable()?;
+        t5.set(b"c", vec![5])?;
+        t5.commit()?;
+        assert_eq!(t4.get(b"c")?, Some(vec![1]));
+        t4.set(b"b", vec![4])?;
+        t4.commit()?;
+        assert_eq!(t3.get(b"b")?, Some(vec![2]));
+        t3.set(b"d", vec![3])?;
+        assert_eq!(t3.commit(), Err(Error::Serialization));
+
+        // Range scans conflict with writes of new keys in the range. t6 and t7
+        // both count the keys and insert a new one, which isn't serializable.
+        let t6 = mvcc.begin_serializable()?;
+        let t7 = mvcc.begin_serializable()?;
+        assert_eq!(t6.scan_prefix(b"")?.to_vec()?.len(), 3);
+        assert_eq!(t7.scan(..)?.to_vec()?.len(), 3);
+        t6.set(b"x", vec![6])?;
+        t7.set(b"y", vec![7])?;
+        t6.commit()?;
+        assert_eq!(t7.commit(), Err(Error::Serialization));
+
+        // Snapshot isolation transactions aren't affected.
+        let t8 = mvcc.begin()?;
+        let t9 = mvcc.begin()?;
+        assert_eq!(t8.scan(..)?.to_vec()?.len(), 4);
+        assert_eq!(t9.scan(..)?.to_vec()?.len(), 4);
+        t8.set(b"x", vec![8])?;
+        t9.set(b"y", vec![9])?;
+        t8.commit()?;
+        t9.commit()?;
+
+        Ok(())
+    }
+
     #[test]
     /// Tests unversioned key/value pairs, via set/get_unversioned().
     fn unversioned() -> Result<()> {
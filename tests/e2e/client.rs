@@ -32,6 +32,7 @@ fn get_table() -> Result<()> {
                     default: None,
                     unique: true,
                     index: false,
+                    index_path: None,
                     references: None,
                 },
                 schema::Column {
@@ -42,6 +43,7 @@ fn get_table() -> Result<()> {
                     default: None,
                     unique: false,
                     index: false,
+                    index_path: None,
                     references: None,
                 },
                 schema::Column {
@@ -52,6 +54,7 @@ fn get_table() -> Result<()> {
                     default: None,
                     unique: false,
                     index: false,
+                    index_path: None,
                     references: Some("studios".into()),
                 },
                 schema::Column {
@@ -62,6 +65,7 @@ fn get_table() -> Result<()> {
                     default: None,
                     unique: false,
                     index: false,
+                    index_path: None,
                     references: Some("genres".into()),
                 },
                 schema::Column {
@@ -72,6 +76,7 @@ fn get_table() -> Result<()> {
                     default: None,
                     unique: false,
                     index: false,
+                    index_path: None,
                     references: None,
                 },
                 schema::Column {
@@ -82,6 +87,7 @@ fn get_table() -> Result<()> {
                     default: Some(Value::Null),
                     unique: false,
                     index: false,
+                    index_path: None,
                     references: None,
                 },
                 schema::Column {
@@ -92,6 +98,7 @@ fn get_table() -> Result<()> {
                     default: Some(Value::Null),
                     unique: false,
                     index: false,
+                    index_path: None,
                     references: None,
                 },
             ]
@@ -124,27 +131,32 @@ fn status() -> Result<()> {
                 leader: 1,
                 term: 1,
                 last_index: [(1, 27)].into(),
+                learners: [].into(),
                 commit_index: 27,
                 apply_index: 27,
+                snapshot_index: 0,
                 storage: storage::engine::Status {
                     name: "bitcask".to_string(),
-                    keys: 29,
-                    size: 1322,
-                    total_disk_size: 1829,
-                    live_disk_size: 1554,
-                    garbage_disk_size: 275
+                    keys: 30,
+                    size: 1428,
+                    total_disk_size: 2454,
+                    live_disk_size: 1954,
+                    garbage_disk_size: 500,
+                    compression_ratio: 1.0,
                 },
             },
             mvcc: mvcc::Status {
                 versions: 1,
                 active_txns: 0,
+                low_water: 0,
                 storage: engine::Status {
                     name: "bitcask".to_string(),
-                    keys: 26,
-                    size: 1630,
-                    total_disk_size: 4556,
-                    live_disk_size: 1838,
-                    garbage_disk_size: 2718
+                    keys: 27,
+                    size: 1662,
+                    total_disk_size: 5588,
+                    live_disk_size: 2137,
+                    garbage_disk_size: 3451,
+                    compression_ratio: 1.0,
                 },
             }
         },
@@ -152,6 +164,116 @@ fn status() -> Result<()> {
     Ok(())
 }
 
+#[test]
+#[serial]
+// Nodes can be removed from and added back to the cluster.
+fn change_membership() -> Result<()> {
+    let tc = TestCluster::run_with(3, dataset::TEST_TABLE)?;
+    let leader = tc.connect_any()?.status()?.raft.leader;
+    // Removed nodes can't reach the cluster, so connect to the leader.
+    let mut c = tc.connect(leader)?;
+    let node = (1..=3).find(|id| *id != leader).unwrap();
+    assert_eq!(
+        c.remove_node(leader),
+        Err(Error::Value(format!("Can't remove the leader {}", leader)))
+    );
+    assert_eq!(
+        c.add_node(node, "localhost:1"),
+        Err(Error::Value(format!("Node {} is already a member", node)))
+    );
+
+    c.remove_node(node)?;
+    assert_eq!(c.remove_node(node), Err(Error::Value(format!("Node {} is not a member", node))));
+    let mut nodes: Vec<_> = c.status()?.raft.last_index.into_keys().collect();
+    nodes.sort();
+    assert_eq!(nodes, (1..=3).filter(|id| *id != node).collect::<Vec<_>>());
+    c.execute("INSERT INTO test VALUES (1, 'a')")?;
+
+    c.add_node(node, &format!("localhost:{}", 19700 + node as u16))?;
+    let mut nodes: Vec<_> = c.status()?.raft.last_index.into_keys().collect();
+    nodes.sort();
+    assert_eq!(nodes, vec![1, 2, 3]);
+    assert_row(
+        c.execute("SELECT * FROM test WHERE id = 1")?,
+        vec![Value::Integer(1), Value::String("a".into())],
+    );
+    Ok(())
+}
+
+#[test]
+#[serial]
+// Leadership can be transferred to another node.
+fn transfer_leadership() -> Result<()> {
+    let tc = TestCluster::run_with(3, dataset::TEST_TABLE)?;
+    let mut c = tc.connect_any()?;
+
+    let leader = c.status()?.raft.leader;
+    let target = (1..=3).find(|id| *id != leader).unwrap();
+    assert_eq!(c.transfer_leadership(4), Err(Error::Value("Node 4 is not a voter".into())));
+    c.transfer_leadership(target)?;
+
+    // Wait for the target to win the election.
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    while c.status().map(|s| s.raft.leader) != Ok(target) {
+        assert!(std::time::Instant::now() < deadline, "leadership transfer timed out");
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    c.with_retry(|c| c.execute("INSERT INTO test VALUES (1, 'a')"))?;
+    assert_row(
+        c.execute("SELECT * FROM test WHERE id = 1")?,
+        vec![Value::Integer(1), Value::String("a".into())],
+    );
+    Ok(())
+}
+
+#[test]
+#[serial]
+// Historical reads are served by followers that have applied the version,
+// even without a leader. Current reads can use bounded-staleness follower
+// reads.
+fn follower_reads() -> Result<()> {
+    let mut tc = TestCluster::run_with(3, dataset::TEST_TABLE)?;
+    let leader = tc.connect_any()?.status()?.raft.leader;
+    let follower = (1..=3).find(|id| *id != leader).unwrap();
+    let other = (1..=3).find(|id| *id != leader && *id != follower).unwrap();
+
+    let mut c = tc.connect(leader)?;
+    c.execute("INSERT INTO test VALUES (1, 'a')")?;
+    let version = match c.execute("BEGIN READ ONLY")? {
+        ResultSet::Begin { version, .. } => version,
+        result => panic!("unexpected result {:?}", result),
+    };
+    c.execute("COMMIT")?;
+    c.execute("INSERT INTO test VALUES (2, 'b')")?;
+
+    // Bounded-staleness reads are served by the follower.
+    let mut f = tc.connect(follower)?;
+    f.set_max_staleness(Some(std::time::Duration::from_secs(10)))?;
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    while f.execute("SELECT * FROM test WHERE id = 2")?.into_rows()?.count() == 0 {
+        assert!(std::time::Instant::now() < deadline, "follower did not catch up");
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    f.set_max_staleness(None)?;
+
+    // Once the rest of the cluster is stopped, historical reads are still
+    // served by the follower, but current reads aren't.
+    tc.stop(leader)?;
+    tc.stop(other)?;
+    f.execute(&format!("BEGIN READ ONLY AS OF SYSTEM TIME {}", version))?;
+    assert_rows(
+        f.execute("SELECT * FROM test")?,
+        vec![vec![Value::Integer(1), Value::String("a".into())]],
+    );
+    f.execute("COMMIT")?;
+    assert_eq!(f.execute("SELECT * FROM test"), Err(Error::Abort));
+    assert_eq!(
+        f.execute(&format!("BEGIN READ ONLY AS OF SYSTEM TIME {}", version + 100)),
+        Err(Error::Abort)
+    );
+    Ok(())
+}
+
 #[test]
 #[serial]
 fn execute() -> Result<()> {
@@ -316,6 +438,51 @@ fn execute_txn() -> Result<()> {
     Ok(())
 }
 
+#[test]
+#[serial]
+fn execute_txn_savepoint() -> Result<()> {
+    let tc = TestCluster::run_with(5, dataset::MOVIES)?;
+    let mut c = tc.connect_any()?;
+
+    // Savepoints require a transaction.
+    assert_eq!(c.execute("SAVEPOINT a"), Err(Error::Value("Not in a transaction".into())));
+
+    // Rolling back to a savepoint should undo later changes, but keep earlier ones.
+    c.execute("BEGIN")?;
+    c.execute("INSERT INTO genres VALUES (4, 'Drama')")?;
+    assert_eq!(c.execute("SAVEPOINT a")?, ResultSet::Savepoint { name: "a".into() });
+    c.execute("INSERT INTO genres VALUES (5, 'Horror')")?;
+    c.execute("UPDATE genres SET name = 'Sci-Fi' WHERE id = 1")?;
+    assert_eq!(c.execute("SAVEPOINT b")?, ResultSet::Savepoint { name: "b".into() });
+    c.execute("DELETE FROM genres WHERE id = 4")?;
+    assert_eq!(c.execute("ROLLBACK TO b")?, ResultSet::RollbackTo { name: "b".into() });
+    assert_row(
+        c.execute("SELECT * FROM genres WHERE id = 4")?,
+        vec![Value::Integer(4), Value::String("Drama".into())],
+    );
+    assert_eq!(c.execute("ROLLBACK TO SAVEPOINT a")?, ResultSet::RollbackTo { name: "a".into() });
+    assert_eq!(c.execute("ROLLBACK TO b"), Err(Error::Value("Savepoint b does not exist".into())));
+
+    // The savepoint remains after rolling back to it, and can be released.
+    c.execute("INSERT INTO genres VALUES (6, 'Western')")?;
+    assert_eq!(c.execute("RELEASE SAVEPOINT a")?, ResultSet::Release { name: "a".into() });
+    assert_eq!(c.execute("RELEASE a"), Err(Error::Value("Savepoint a does not exist".into())));
+    assert_eq!(c.execute("COMMIT")?, ResultSet::Commit { version: 2 });
+
+    assert_rows(
+        c.execute("SELECT * FROM genres")?,
+        vec![
+            vec![Value::Integer(1), Value::String("Science Fiction".into())],
+            vec![Value::Integer(2), Value::String("Action".into())],
+            vec![Value::Integer(3), Value::String("Comedy".into())],
+            vec![Value::Integer(4), Value::String("Drama".into())],
+            vec![Value::Integer(6), Value::String("Western".into())],
+        ],
+    );
+
+    Ok(())
+}
+
 #[test]
 #[serial]
 fn execute_txn_concurrent() -> Result<()> {
@@ -3,6 +3,7 @@ use super::{assert_row, assert_rows, dataset, TestCluster};
 use radb::error::{Error, Result};
 use radb::sql::types::Value;
 use serial_test::serial;
+use std::time::{Duration, Instant};
 
 #[test]
 #[serial]
@@ -153,4 +154,230 @@ fn anomaly_phantom_read() -> Result<()> {
     Ok(())
 }
 
-// FIXME We should test write skew, but we need to implement serializable snapshot isolation first.
+#[test]
+#[serial]
+// Write skew is when a reads 1 and writes it to 2, while b reads 2 and writes it to 1.
+// Snapshot isolation, the default, allows this.
+fn anomaly_write_skew() -> Result<()> {
+    let tc = TestCluster::run_with(5, dataset::TEST_TABLE)?;
+    let mut a = tc.connect_any()?;
+    let mut b = tc.connect_any()?;
+    let mut c = tc.connect_any()?;
+
+    c.execute("INSERT INTO test VALUES (1, 'a'), (2, 'b')")?;
+
+    a.execute("BEGIN")?;
+    b.execute("BEGIN")?;
+
+    assert_row(
+        a.execute("SELECT * FROM test WHERE id = 1")?,
+        vec![Value::Integer(1), Value::String("a".into())],
+    );
+    assert_row(
+        b.execute("SELECT * FROM test WHERE id = 2")?,
+        vec![Value::Integer(2), Value::String("b".into())],
+    );
+    a.execute("UPDATE test SET value = 'a' WHERE id = 2")?;
+    b.execute("UPDATE test SET value = 'b' WHERE id = 1")?;
+    a.execute("COMMIT")?;
+    b.execute("COMMIT")?;
+
+    assert_rows(
+        c.execute("SELECT * FROM test")?,
+        vec![
+            vec![Value::Integer(1), Value::String("b".into())],
+            vec![Value::Integer(2), Value::String("a".into())],
+        ],
+    );
+
+    Ok(())
+}
+
+#[test]
+#[serial]
+// Serializable transactions prevent write skew, by aborting the last committer.
+fn serializable_write_skew() -> Result<()> {
+    let tc = TestCluster::run_with(5, dataset::TEST_TABLE)?;
+    let mut a = tc.connect_any()?;
+    let mut b = tc.connect_any()?;
+    let mut c = tc.connect_any()?;
+
+    c.execute("INSERT INTO test VALUES (1, 'a'), (2, 'b')")?;
+
+    a.execute("BEGIN TRANSACTION ISOLATION LEVEL SERIALIZABLE")?;
+    b.execute("BEGIN TRANSACTION ISOLATION LEVEL SERIALIZABLE")?;
+
+    assert_row(
+        a.execute("SELECT * FROM test WHERE id = 1")?,
+        vec![Value::Integer(1), Value::String("a".into())],
+    );
+    assert_row(
+        b.execute("SELECT * FROM test WHERE id = 2")?,
+        vec![Value::Integer(2), Value::String("b".into())],
+    );
+    a.execute("UPDATE test SET value = 'a' WHERE id = 2")?;
+    b.execute("UPDATE test SET value = 'b' WHERE id = 1")?;
+    a.execute("COMMIT")?;
+    assert_eq!(b.execute("COMMIT"), Err(Error::Serialization));
+
+    assert_rows(
+        c.execute("SELECT * FROM test")?,
+        vec![
+            vec![Value::Integer(1), Value::String("a".into())],
+            vec![Value::Integer(2), Value::String("a".into())],
+        ],
+    );
+
+    // The aborted transaction is gone, and b can retry.
+    b.execute("BEGIN TRANSACTION ISOLATION LEVEL SERIALIZABLE")?;
+    b.execute("UPDATE test SET value = 'b' WHERE id = 1")?;
+    b.execute("COMMIT")?;
+
+    Ok(())
+}
+
+#[test]
+#[serial]
+// Serializable transactions also detect conflicts with predicate reads: a and b each
+// count the matching rows and insert a new one based on the count.
+fn serializable_phantom() -> Result<()> {
+    let tc = TestCluster::run_with(5, dataset::TEST_TABLE)?;
+    let mut a = tc.connect_any()?;
+    let mut b = tc.connect_any()?;
+    let mut c = tc.connect_any()?;
+
+    c.execute("INSERT INTO test VALUES (1, 'x')")?;
+
+    a.execute("BEGIN TRANSACTION ISOLATION LEVEL SERIALIZABLE")?;
+    b.execute("BEGIN TRANSACTION ISOLATION LEVEL SERIALIZABLE")?;
+
+    assert_row(a.execute("SELECT COUNT(*) FROM test WHERE value = 'x'")?, vec![Value::Integer(1)]);
+    assert_row(b.execute("SELECT COUNT(*) FROM test WHERE value = 'x'")?, vec![Value::Integer(1)]);
+    a.execute("INSERT INTO test VALUES (2, 'x')")?;
+    b.execute("INSERT INTO test VALUES (3, 'x')")?;
+    a.execute("COMMIT")?;
+    assert_eq!(b.execute("COMMIT"), Err(Error::Serialization));
+
+    assert_row(c.execute("SELECT COUNT(*) FROM test")?, vec![Value::Integer(2)]);
+
+    Ok(())
+}
+
+#[test]
+#[serial]
+// SELECT FOR UPDATE prevents lost updates: b waits for a's row lock, and once a commits its update
+// b reads and updates the latest version rather than overwriting it. If a commits without writing,
+// b gets the lock.
+fn select_for_update() -> Result<()> {
+    let tc = TestCluster::run_with(5, dataset::TEST_TABLE)?;
+    let mut a = tc.connect_any()?;
+    let mut b = tc.connect_any()?;
+
+    a.execute("INSERT INTO test VALUES (1, 'a'), (2, 'b')")?;
+
+    a.execute("BEGIN")?;
+    b.execute("BEGIN")?;
+    assert_row(
+        a.execute("SELECT * FROM test WHERE id = 1 FOR UPDATE")?,
+        vec![Value::Integer(1), Value::String("a".into())],
+    );
+    let waiter = std::thread::spawn(move || {
+        let result = b.execute("SELECT * FROM test WHERE id = 1 FOR UPDATE");
+        (b, result)
+    });
+    std::thread::sleep(Duration::from_millis(200));
+    a.execute("UPDATE test SET value = 'x' WHERE id = 1")?;
+    a.execute("COMMIT")?;
+    let (mut b, result) = waiter.join().expect("waiter panicked");
+    assert_row(result?, vec![Value::Integer(1), Value::String("x".into())]);
+    b.execute("UPDATE test SET value = 'y' WHERE id = 1")?;
+    b.execute("COMMIT")?;
+    assert_row(
+        a.execute("SELECT * FROM test WHERE id = 1")?,
+        vec![Value::Integer(1), Value::String("y".into())],
+    );
+
+    a.execute("BEGIN")?;
+    b.execute("BEGIN")?;
+    a.execute("SELECT * FROM test WHERE id = 2 FOR UPDATE")?;
+    let waiter = std::thread::spawn(move || {
+        let result = b.execute("SELECT * FROM test WHERE id = 2 FOR UPDATE");
+        (b, result)
+    });
+    std::thread::sleep(Duration::from_millis(200));
+    a.execute("COMMIT")?;
+    let (mut b, result) = waiter.join().expect("waiter panicked");
+    assert_row(result?, vec![Value::Integer(2), Value::String("b".into())]);
+    b.execute("UPDATE test SET value = 'y' WHERE id = 2")?;
+    b.execute("COMMIT")?;
+
+    Ok(())
+}
+
+#[test]
+#[serial]
+// Shared row locks are compatible with each other but not with writes or exclusive locks, and
+// NOWAIT fails immediately instead of waiting for a conflicting lock.
+fn select_for_share_nowait() -> Result<()> {
+    let tc = TestCluster::run_with(5, dataset::TEST_TABLE)?;
+    let mut a = tc.connect_any()?;
+    let mut b = tc.connect_any()?;
+
+    a.execute("INSERT INTO test VALUES (1, 'a')")?;
+
+    a.execute("BEGIN")?;
+    b.execute("BEGIN")?;
+    a.execute("SELECT * FROM test FOR SHARE")?;
+    assert_row(
+        b.execute("SELECT * FROM test FOR SHARE NOWAIT")?,
+        vec![Value::Integer(1), Value::String("a".into())],
+    );
+    assert_eq!(b.execute("SELECT * FROM test FOR UPDATE NOWAIT"), Err(Error::Serialization));
+    assert_eq!(b.execute("UPDATE test SET value = 'b' WHERE id = 1"), Err(Error::Serialization));
+    a.execute("COMMIT")?;
+    b.execute("SELECT * FROM test FOR UPDATE NOWAIT")?;
+    b.execute("UPDATE test SET value = 'b' WHERE id = 1")?;
+    b.execute("COMMIT")?;
+
+    // Uncommitted writes count as exclusive locks.
+    a.execute("BEGIN")?;
+    b.execute("BEGIN")?;
+    a.execute("UPDATE test SET value = 'a' WHERE id = 1")?;
+    assert_eq!(b.execute("SELECT * FROM test FOR SHARE NOWAIT"), Err(Error::Serialization));
+    a.execute("ROLLBACK")?;
+    b.execute("ROLLBACK")?;
+
+    Ok(())
+}
+
+#[test]
+#[serial]
+// A deadlock between two transactions waiting for each other's row locks is detected, and the
+// transaction that would complete the cycle fails with a serialization error.
+fn select_for_update_deadlock() -> Result<()> {
+    let tc = TestCluster::run_with(5, dataset::TEST_TABLE)?;
+    let mut a = tc.connect_any()?;
+    let mut b = tc.connect_any()?;
+
+    a.execute("INSERT INTO test VALUES (1, 'a'), (2, 'b')")?;
+
+    a.execute("BEGIN")?;
+    b.execute("BEGIN")?;
+    a.execute("SELECT * FROM test WHERE id = 1 FOR UPDATE")?;
+    b.execute("SELECT * FROM test WHERE id = 2 FOR UPDATE")?;
+    let waiter = std::thread::spawn(move || {
+        let result = a.execute("SELECT * FROM test WHERE id = 2 FOR UPDATE");
+        (a, result)
+    });
+    std::thread::sleep(Duration::from_millis(200));
+    let start = Instant::now();
+    assert_eq!(b.execute("SELECT * FROM test WHERE id = 1 FOR UPDATE"), Err(Error::Serialization));
+    assert!(start.elapsed() < Duration::from_secs(1), "deadlock not detected");
+    b.execute("ROLLBACK")?;
+
+    let (mut a, result) = waiter.join().expect("waiter panicked");
+    assert_row(result?, vec![Value::Integer(2), Value::String("b".into())]);
+    a.execute("COMMIT")?;
+
+    Ok(())
+}
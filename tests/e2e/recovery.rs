@@ -3,6 +3,7 @@ use super::{assert_row, dataset, TestCluster};
 use radb::error::{Error, Result};
 use radb::sql::types::Value;
 use serial_test::serial;
+use std::time::{Duration, Instant};
 
 #[test]
 #[serial]
@@ -48,3 +49,53 @@ fn client_commit_error() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+#[serial]
+// A transaction whose node crashes should be rolled back by the leader once it stops receiving
+// heartbeats for it, while idle transactions on live nodes are kept alive.
+fn node_crash_txn_expiry() -> Result<()> {
+    let mut tc = TestCluster::run_with(3, dataset::TEST_TABLE)?;
+    let mut a = tc.connect(1)?;
+    let mut b = tc.connect(2)?;
+    let mut c = tc.connect(3)?;
+
+    a.execute("BEGIN")?;
+    a.execute("INSERT INTO test VALUES (1, 'a')")?;
+    c.execute("BEGIN")?;
+    c.execute("INSERT INTO test VALUES (2, 'c')")?;
+    let versions: Vec<u64> = b.list_transactions()?.into_iter().map(|t| t.version).collect();
+    assert_eq!(versions.len(), 2);
+
+    // Once node 1 crashes, its transaction blocks writers until it expires.
+    tc.stop(1)?;
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut blocked = false;
+    loop {
+        match b.execute("INSERT INTO test VALUES (1, 'b')") {
+            Ok(_) => break,
+            // If node 1 was the leader, writes abort until a new leader is elected.
+            Err(Error::Abort) => {}
+            Err(Error::Serialization) => blocked = true,
+            Err(err) => return Err(err),
+        }
+        assert!(Instant::now() < deadline, "transaction not expired");
+        std::thread::sleep(Duration::from_millis(200));
+    }
+    assert!(blocked, "write was not blocked by the crashed transaction");
+    let txns = b.list_transactions()?;
+    assert_eq!(txns.len(), 1);
+    assert_eq!(txns[0].version, versions[1]);
+    assert!(txns[0].age >= Duration::from_secs(3));
+    assert!(txns[0].idle < Duration::from_secs(3));
+
+    // c's idle transaction was kept alive by heartbeats, and can commit.
+    c.execute("COMMIT")?;
+    assert_row(
+        b.execute("SELECT * FROM test WHERE id = 2")?,
+        vec![Value::Integer(2), Value::String("c".into())],
+    );
+    assert!(b.list_transactions()?.is_empty());
+
+    Ok(())
+}
@@ -90,6 +90,8 @@ impl TestCluster {
             cfg.push_str(&format!("  '{}': {},\n", peer, self.node_address_raft(peer)))
         }
         cfg.push_str("}\n");
+        // Expire abandoned transactions quickly, so tests don't have to wait.
+        cfg.push_str("txn_timeout: 3\n");
         cfg
     }
 
@@ -151,6 +153,16 @@ impl TestCluster {
         Ok(())
     }
 
+    /// Stops the given cluster node, by killing its process.
+    pub fn stop(&mut self, id: NodeID) -> Result<()> {
+        self.assert_id(id);
+        if let Some(mut child) = self.children.remove(&id) {
+            child.kill()?;
+            child.wait()?;
+        }
+        Ok(())
+    }
+
     /// Connects to the given cluster node.
     pub fn connect(&self, id: NodeID) -> Result<Client> {
         self.assert_id(id);
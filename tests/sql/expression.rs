@@ -1,7 +1,7 @@
 //! Evaluates SQL expressions and compares with expectations.
 use radb::error::{Error, Result};
 use radb::sql::engine::Engine;
-use radb::sql::types::Value;
+use radb::sql::types::{decimal, temporal, Interval, Value};
 
 fn eval_expr(expr: &str) -> Result<Value> {
     let engine = super::setup(Vec::new())?;
@@ -30,6 +30,34 @@ macro_rules! test_expr {
 
 use Value::*;
 
+fn date(s: &str) -> Value {
+    Date(temporal::parse_date(s).unwrap())
+}
+
+fn time(s: &str) -> Value {
+    Time(temporal::parse_time(s).unwrap())
+}
+
+fn ts(s: &str) -> Value {
+    Timestamp(temporal::parse_timestamp(s).unwrap())
+}
+
+fn tstz(s: &str) -> Value {
+    TimestampTz(temporal::parse_timestamptz(s).unwrap())
+}
+
+fn interval(s: &str) -> Value {
+    Interval(s.parse::<Interval>().unwrap())
+}
+
+fn dec(s: &str) -> Value {
+    Decimal(decimal::parse(s).unwrap())
+}
+
+fn json(s: &str) -> Value {
+    Json(s.to_string())
+}
+
 test_expr! {
     // Constants and literals
     const_case: "TrUe" => Ok(Boolean(true)),
@@ -459,6 +487,238 @@ test_expr! {
     op_like_null: "'abc' LIKE NULL" => Ok(Null),
     op_like_null_lhs: "NULL LIKE 'abc'" => Ok(Null),
 
+    // Temporal literals, operators and functions
+    lit_date: "DATE '2024-02-29'" => Ok(date("2024-02-29")),
+    lit_date_invalid: "DATE '2023-02-29'" => Err(Error::Parse("Invalid date 2023-02-29: input is out of range".into())),
+    lit_date_not_string: "DATE 2024" => Err(Error::Parse("Expected string, got 2024".into())),
+    lit_time: "TIME '13:45:01.5'" => Ok(time("13:45:01.5")),
+    lit_time_invalid: "TIME '25:00'" => Err(Error::Parse("Invalid time 25:00".into())),
+    lit_timestamp: "TIMESTAMP '2024-01-31 13:45:00'" => Ok(ts("2024-01-31 13:45:00")),
+    lit_timestamp_without_zone: "TIMESTAMP WITHOUT TIME ZONE '2024-01-31T13:45:00'" => Ok(ts("2024-01-31 13:45:00")),
+    lit_timestamp_invalid: "TIMESTAMP '2024-01-31 13'" => Err(Error::Parse("Invalid timestamp 2024-01-31 13".into())),
+    lit_timestamptz: "TIMESTAMP WITH TIME ZONE '2024-01-31 13:45:00+02:00'" => Ok(tstz("2024-01-31 11:45:00")),
+    lit_timestamptz_utc: "TIMESTAMP WITH TIME ZONE '2024-01-31 13:45:00'" => Ok(tstz("2024-01-31 13:45:00Z")),
+    lit_interval: "INTERVAL '1 year 2 mons 3 days 04:05:06'" => Ok(interval("14 months 3 days 4 hours 5 minutes 6 seconds")),
+    lit_interval_invalid: "INTERVAL '1 fortnight'" => Err(Error::Parse("Invalid interval 1 fortnight".into())),
+
+    op_add_date_int: "DATE '2024-02-28' + 2" => Ok(date("2024-03-01")),
+    op_add_int_date: "2 + DATE '2024-02-28'" => Ok(date("2024-03-01")),
+    op_add_date_time: "DATE '2024-02-28' + TIME '13:45'" => Ok(ts("2024-02-28 13:45")),
+    op_add_date_interval: "DATE '2024-01-31' + INTERVAL '1 month'" => Ok(ts("2024-02-29")),
+    op_add_time_interval: "TIME '23:00' + INTERVAL '2 hours'" => Ok(time("01:00")),
+    op_add_timestamp_interval: "TIMESTAMP '2024-01-31 12:00' + INTERVAL '1 month 1 day 1 hour'" => Ok(ts("2024-03-01 13:00")),
+    op_add_timestamptz_interval: "INTERVAL '90 minutes' + TIMESTAMP WITH TIME ZONE '2024-01-31 12:00:00+01:00'" => Ok(tstz("2024-01-31 12:30:00")),
+    op_add_interval_interval: "INTERVAL '1 day' + INTERVAL '2 hours'" => Ok(interval("1 day 2 hours")),
+    op_add_timestamp_timestamp: "TIMESTAMP '2024-01-31' + TIMESTAMP '2024-01-31'" => Err(Error::Value("Can't add 2024-01-31 00:00:00 and 2024-01-31 00:00:00".into())),
+    op_add_timestamp_overflow: "TIMESTAMP '2024-01-31' + INTERVAL '1000000 years'" => Err(Error::Value("Timestamp out of range".into())),
+    op_add_date_null: "DATE '2024-01-31' + NULL" => Ok(Null),
+    op_sub_date_int: "DATE '2024-03-01' - 1" => Ok(date("2024-02-29")),
+    op_sub_date_date: "DATE '2024-03-01' - DATE '2024-01-01'" => Ok(Integer(60)),
+    op_sub_date_interval: "DATE '2024-03-31' - INTERVAL '1 month'" => Ok(ts("2024-02-29")),
+    op_sub_time_time: "TIME '13:45' - TIME '12:00:30'" => Ok(interval("01:44:30")),
+    op_sub_timestamp_timestamp: "TIMESTAMP '2024-03-01 01:00' - TIMESTAMP '2024-02-28'" => Ok(interval("2 days 1 hour")),
+    op_sub_timestamptz_interval: "TIMESTAMP WITH TIME ZONE '2024-01-31 12:00:00Z' - INTERVAL '1 week'" => Ok(tstz("2024-01-24 12:00:00")),
+    op_sub_interval_interval: "INTERVAL '1 day' - INTERVAL '2 hours'" => Ok(interval("1 day -2 hours")),
+    op_mul_interval: "INTERVAL '1 day' * 1.5" => Ok(interval("36 hours")),
+    op_div_interval: "INTERVAL '1 month' / 4" => Ok(interval("7 days 12 hours")),
+    op_div_interval_zero: "INTERVAL '1 month' / 0" => Err(Error::Value("Can't divide by zero".into())),
+    op_negate_interval: "-INTERVAL '1 day 02:00'" => Ok(interval("-1 day -2 hours")),
+    op_eq_date: "DATE '2024-01-31' = DATE '2024-01-31'" => Ok(Boolean(true)),
+    op_eq_date_timestamp: "DATE '2024-01-31' = TIMESTAMP '2024-01-31 00:00'" => Ok(Boolean(true)),
+    op_eq_interval: "INTERVAL '1 month' = INTERVAL '30 days'" => Ok(Boolean(true)),
+    op_eq_date_string: "DATE '2024-01-31' = '2024-01-31'" => Err(Error::Value("Can't compare 2024-01-31 and 2024-01-31".into())),
+    op_gt_timestamp: "TIMESTAMP '2024-01-31 00:00:01' > TIMESTAMP '2024-01-31'" => Ok(Boolean(true)),
+    op_gt_timestamptz_timestamp: "TIMESTAMP WITH TIME ZONE '2024-01-31 00:00:00+01:00' > TIMESTAMP '2024-01-31'" => Ok(Boolean(false)),
+    op_lt_time: "TIME '12:00' < TIME '13:00'" => Ok(Boolean(true)),
+    op_lt_interval: "INTERVAL '23:59:59' < INTERVAL '1 day'" => Ok(Boolean(true)),
+    op_lt_date_null: "DATE '2024-01-31' < NULL" => Ok(Null),
+
+    func_date_trunc: "date_trunc('month', TIMESTAMP '2024-03-31 13:45')" => Ok(ts("2024-03-01")),
+    func_date_trunc_date: "DATE_TRUNC('week', DATE '2024-03-31')" => Ok(ts("2024-03-25")),
+    func_date_trunc_timestamptz: "date_trunc('hour', TIMESTAMP WITH TIME ZONE '2024-03-31 13:45:00Z')" => Ok(tstz("2024-03-31 13:00:00")),
+    func_date_trunc_invalid: "date_trunc('fortnight', DATE '2024-03-31')" => Err(Error::Value("Can't truncate to fortnight".into())),
+    func_date_trunc_null: "date_trunc('day', NULL)" => Ok(Null),
+    func_date_trunc_args: "date_trunc('day')" => Err(Error::Value("Function date_trunc takes 2 arguments, got 1".into())),
+    func_extract: "extract(year FROM DATE '2024-03-31')" => Ok(Integer(2024)),
+    func_extract_case: "EXTRACT(HOUR FROM TIMESTAMP '2024-03-31 13:45')" => Ok(Integer(13)),
+    func_extract_second: "extract(second FROM TIME '13:45:01.5')" => Ok(Float(1.5)),
+    func_extract_epoch: "extract(epoch FROM TIMESTAMP WITH TIME ZONE '1970-01-02 00:00:00+01:00')" => Ok(Float(82800.0)),
+    func_extract_interval: "extract(day FROM INTERVAL '1 month 3 days')" => Ok(Integer(3)),
+    func_extract_string: "extract('month' FROM DATE '2024-03-31')" => Ok(Integer(3)),
+    func_extract_invalid: "extract(hour FROM INTEGER)" => Err(Error::Parse("Expected expression atom, found INTEGER".into())),
+    func_extract_no_from: "extract(year, DATE '2024-03-31')" => Err(Error::Parse("Expected token FROM, found ,".into())),
+    func_extract_type: "extract(year FROM 1)" => Err(Error::Value("Function extract expects a temporal value for argument 2, got INTEGER".into())),
+    func_now: "now() > TIMESTAMP WITH TIME ZONE '2024-01-01 00:00:00Z'" => Ok(Boolean(true)),
+    func_now_args: "now(1)" => Err(Error::Value("Unknown function now".into())),
+
+    // Decimal literals and operators
+    lit_decimal: "DECIMAL '3.14'" => Ok(dec("3.14")),
+    lit_decimal_numeric: "NUMERIC '-0.001'" => Ok(dec("-0.001")),
+    lit_decimal_integer: "DECIMAL '42'" => Ok(dec("42")),
+    lit_decimal_invalid: "DECIMAL '1.2.3'" => Err(Error::Parse("Invalid decimal 1.2.3".into())),
+    lit_decimal_not_string: "DECIMAL 3.14" => Err(Error::Parse("Expected string, got 3.14".into())),
+
+    op_add_decimal: "DECIMAL '0.1' + DECIMAL '0.2'" => Ok(dec("0.3")),
+    op_add_decimal_int: "DECIMAL '0.1' + 2" => Ok(dec("2.1")),
+    op_add_int_decimal: "2 + DECIMAL '0.1'" => Ok(dec("2.1")),
+    op_add_decimal_float: "DECIMAL '0.5' + 0.25" => Ok(Float(0.75)),
+    op_add_decimal_null: "DECIMAL '0.1' + NULL" => Ok(Null),
+    op_add_decimal_overflow: "DECIMAL '79228162514264337593543950335' + 1" => Err(Error::Value("Decimal overflow".into())),
+    op_add_decimal_string: "DECIMAL '0.1' + 'a'" => Err(Error::Value("Can't add 0.1 and a".into())),
+    op_sub_decimal: "DECIMAL '0.3' - DECIMAL '0.1'" => Ok(dec("0.2")),
+    op_sub_int_decimal: "1 - DECIMAL '0.01'" => Ok(dec("0.99")),
+    op_sub_decimal_float: "DECIMAL '1.5' - 0.5" => Ok(Float(1.0)),
+    op_mul_decimal: "DECIMAL '1.1' * DECIMAL '1.1'" => Ok(dec("1.21")),
+    op_mul_decimal_int: "3 * DECIMAL '0.1'" => Ok(dec("0.3")),
+    op_mul_decimal_overflow: "DECIMAL '79228162514264337593543950335' * 2" => Err(Error::Value("Decimal overflow".into())),
+    op_div_decimal: "DECIMAL '1' / DECIMAL '8'" => Ok(dec("0.125")),
+    op_div_decimal_int: "DECIMAL '10.5' / 3" => Ok(dec("3.5")),
+    op_div_int_decimal: "1 / DECIMAL '3'" => Ok(dec("0.3333333333333333333333333333")),
+    op_div_decimal_zero: "DECIMAL '1' / DECIMAL '0.00'" => Err(Error::Value("Can't divide by zero".into())),
+    op_div_decimal_int_zero: "DECIMAL '1' / 0" => Err(Error::Value("Can't divide by zero".into())),
+    op_mod_decimal: "DECIMAL '10.5' % 3" => Ok(dec("1.5")),
+    op_mod_decimal_negative: "DECIMAL '-10.5' % DECIMAL '3'" => Ok(dec("-1.5")),
+    op_mod_decimal_zero: "DECIMAL '10.5' % DECIMAL '0'" => Err(Error::Value("Can't divide by zero".into())),
+    op_exp_decimal: "DECIMAL '1.5' ^ 2" => Ok(dec("2.25")),
+    op_exp_decimal_decimal: "DECIMAL '4' ^ DECIMAL '0.5'" => Ok(Float(2.0)),
+    op_negate_decimal: "-DECIMAL '1.5'" => Ok(dec("-1.5")),
+    op_assert_decimal: "+DECIMAL '1.5'" => Ok(dec("1.5")),
+    op_eq_decimal: "DECIMAL '1.50' = DECIMAL '1.5'" => Ok(Boolean(true)),
+    op_eq_decimal_int: "DECIMAL '2.0' = 2" => Ok(Boolean(true)),
+    op_eq_decimal_float: "DECIMAL '0.5' = 0.5" => Ok(Boolean(true)),
+    op_gt_decimal: "DECIMAL '0.3' > DECIMAL '0.29'" => Ok(Boolean(true)),
+    op_lt_int_decimal: "1 < DECIMAL '1.01'" => Ok(Boolean(true)),
+    op_lt_decimal_null: "DECIMAL '1' < NULL" => Ok(Null),
+
+    // Byte string literals, operators and functions
+    lit_bytes: "x'00ff10'" => Ok(Bytes(vec![0x00, 0xff, 0x10])),
+    lit_bytes_upper: "X'ABcd'" => Ok(Bytes(vec![0xab, 0xcd])),
+    lit_bytes_empty: "x''" => Ok(Bytes(vec![])),
+    lit_bytes_invalid: "x'0g'" => Err(Error::Parse("Invalid hex string x'0g'".into())),
+    lit_bytes_odd: "x'012'" => Err(Error::Parse("Invalid hex string x'012'".into())),
+    lit_bytes_unterminated: "x'01" => Err(Error::Parse("Unexpected end of string literal".into())),
+
+    op_eq_bytes: "x'0102' = x'0102'" => Ok(Boolean(true)),
+    op_eq_bytes_string: "x'61' = 'a'" => Err(Error::Value("Can't compare \\x61 and a".into())),
+    op_gt_bytes: "x'02' > x'0100'" => Ok(Boolean(true)),
+    op_lt_bytes_prefix: "x'01' < x'0100'" => Ok(Boolean(true)),
+    op_lt_bytes_null: "x'01' < NULL" => Ok(Null),
+    op_add_bytes: "x'01' + x'02'" => Err(Error::Value("Can't add \\x01 and \\x02".into())),
+
+    func_length: "length('héllo')" => Ok(Integer(5)),
+    func_length_bytes: "length(x'00ff10')" => Ok(Integer(3)),
+    func_length_null: "length(NULL)" => Ok(Null),
+    func_length_integer: "length(1)" => Err(Error::Value("Function length expects STRING or BYTEA for argument 1, got INTEGER".into())),
+    func_length_args: "length('a', 'b')" => Err(Error::Value("Function length takes 1 arguments, got 2".into())),
+    func_substring: "substring('héllo', 2, 3)" => Ok(String("éll".into())),
+    func_substring_rest: "substring('hello', 3)" => Ok(String("llo".into())),
+    func_substring_before: "substring('hello', 0, 2)" => Ok(String("h".into())),
+    func_substring_past: "substring('hello', 9)" => Ok(String("".into())),
+    func_substring_bytes: "substring(x'00ff1020', 2, 2)" => Ok(Bytes(vec![0xff, 0x10])),
+    func_substring_negative: "substring('hello', 1, -1)" => Err(Error::Value("Substring length can't be negative".into())),
+    func_substring_null: "substring('hello', NULL)" => Ok(Null),
+    func_substring_args: "substring('hello')" => Err(Error::Value("Function substring takes 2 to 3 arguments, got 1".into())),
+    func_substring_type: "substring('hello', '2')" => Err(Error::Value("Function substring expects INTEGER for argument 2, got STRING".into())),
+    func_substr: "substr('hello', 2, 2)" => Ok(String("el".into())),
+
+    // JSON literals, operators and functions
+    lit_json: r#"JSON '{"b": [1, 2.5], "a": null}'"# => Ok(json(r#"{"a":null,"b":[1,2.5]}"#)),
+    lit_json_scalar: r#"JSON '"text"'"# => Ok(json(r#""text""#)),
+    lit_json_invalid: "JSON '{a: 1}'" => Err(Error::Value("Invalid JSON: key must be a string at line 1 column 2".into())),
+    lit_json_not_string: "JSON 1" => Err(Error::Parse("Expected string, got 1".into())),
+
+    op_get_json: r#"JSON '{"a": {"b": [1, 2]}}' -> 'a'"# => Ok(json(r#"{"b":[1,2]}"#)),
+    op_get_json_chain: r#"JSON '{"a": {"b": [1, 2]}}' -> 'a' -> 'b' -> 1"# => Ok(json("2")),
+    op_get_json_index_negative: "JSON '[1, 2, 3]' -> -1" => Ok(json("3")),
+    op_get_json_index_missing: "JSON '[1, 2, 3]' -> 3" => Ok(Null),
+    op_get_json_missing: r#"JSON '{"a": 1}' -> 'b'"# => Ok(Null),
+    op_get_json_mismatch: r#"JSON '{"a": 1}' -> 0"# => Ok(Null),
+    op_get_json_null: r#"JSON '{"a": 1}' -> NULL"# => Ok(Null),
+    op_get_json_key: r#"JSON '{"a": 1}' -> TRUE"# => Err(Error::Value("Can't use TRUE as a JSON key".into())),
+    op_get_string: "'{}' -> 'a'" => Err(Error::Value("Can't get a from {}".into())),
+    op_get_text_json: r#"JSON '{"a": "text", "b": [1], "c": null}' ->> 'a'"# => Ok(String("text".into())),
+    op_get_text_json_array: r#"JSON '{"a": "text", "b": [1], "c": null}' ->> 'b'"# => Ok(String("[1]".into())),
+    op_get_text_json_null: r#"JSON '{"a": "text", "b": [1], "c": null}' ->> 'c'"# => Ok(Null),
+    op_contains_json: r#"JSON '{"a": 1, "tags": ["x", "y"]}' @> JSON '{"tags": ["y"]}'"# => Ok(Boolean(true)),
+    op_contains_json_false: r#"JSON '{"a": 1, "tags": ["x", "y"]}' @> JSON '{"a": 2}'"# => Ok(Boolean(false)),
+    op_contains_json_null: "JSON '{}' @> NULL" => Ok(Null),
+    op_contains_json_string: "JSON '{}' @> '{}'" => Err(Error::Value("Can't check if {} contains {}".into())),
+    op_eq_json: r#"JSON '{"a": 1, "b": 2}' = JSON '{"b":2,"a":1}'"# => Ok(Boolean(true)),
+    op_prec_get_is_null: r#"JSON '{"a": 1}' -> 'b' IS NULL"# => Ok(Boolean(true)),
+    op_prec_get_text_add: r#"JSON '{"a": 1}' ->> 'a' = '1'"# => Ok(Boolean(true)),
+    op_prec_get_compare: r#"JSON '{"a": {"b": 1}}' -> 'a' @> JSON '{"b": 1}' = TRUE"# => Ok(Boolean(true)),
+
+    func_json_extract: r#"json_extract(JSON '{"a": {"b": [10, 20.5, "x", true, null, {}]}}', '$.a.b[1]')"# => Ok(Float(20.5)),
+    func_json_extract_integer: r#"json_extract(JSON '{"a": {"b": [10]}}', '$.a.b[0]')"# => Ok(Integer(10)),
+    func_json_extract_string: r#"json_extract(JSON '["x"]', '$[0]')"# => Ok(String("x".into())),
+    func_json_extract_object: r#"json_extract(JSON '{"a": {"b": true}}', '$.a')"# => Ok(json(r#"{"b":true}"#)),
+    func_json_extract_root: "json_extract(JSON '[1]', '$')" => Ok(json("[1]")),
+    func_json_extract_missing: r#"json_extract(JSON '{"a": 1}', '$.a.b')"# => Ok(Null),
+    func_json_extract_invalid_path: r#"json_extract(JSON '{"a": 1}', 'a')"# => Err(Error::Value("Invalid JSON path a".into())),
+    func_json_extract_string_arg: r#"json_extract('{"a": 1}', '$.a')"# => Err(Error::Value("Function json_extract expects JSON for argument 1, got STRING".into())),
+    func_json_extract_null: "json_extract(NULL, '$.a')" => Ok(Null),
+    func_json_array_length: "json_array_length(JSON '[1, [2, 3], {}]')" => Ok(Integer(3)),
+    func_json_array_length_object: "json_array_length(JSON '{}')" => Err(Error::Value("Can't take array length of {}".into())),
+
+    // String functions
+    func_lower: "lower('HéLLO')" => Ok(String("héllo".into())),
+    func_lower_null: "lower(NULL)" => Ok(Null),
+    func_lower_integer: "lower(1)" => Err(Error::Value("Function lower expects STRING for argument 1, got INTEGER".into())),
+    func_upper: "upper('héllo')" => Ok(String("HÉLLO".into())),
+    func_upper_args: "upper()" => Err(Error::Value("Function upper takes 1 arguments, got 0".into())),
+    func_trim: "trim('  a b \t\n')" => Ok(String("a b".into())),
+    func_replace: "replace('banana', 'an', 'AN')" => Ok(String("bANANa".into())),
+    func_replace_empty: "replace('abc', '', 'x')" => Ok(String("abc".into())),
+    func_replace_null: "replace('abc', 'b', NULL)" => Ok(Null),
+    func_concat: "concat('a', 1, NULL, 2.5, TRUE)" => Ok(String("a12.5TRUE".into())),
+    func_concat_null: "concat(NULL, NULL)" => Ok(String("".into())),
+    func_concat_args: "concat()" => Err(Error::Value("Function concat takes at least 1 arguments, got 0".into())),
+
+    // Math functions
+    func_abs: "abs(-3)" => Ok(Integer(3)),
+    func_abs_float: "abs(-3.5)" => Ok(Float(3.5)),
+    func_abs_decimal: "abs(DECIMAL '-1.25')" => Ok(dec("1.25")),
+    func_abs_overflow: "abs(-9223372036854775807 - 1)" => Err(Error::Value("Integer overflow".into())),
+    func_abs_string: "abs('1')" => Err(Error::Value("Function abs expects a number for argument 1, got STRING".into())),
+    func_round: "round(2.5)" => Ok(Float(3.0)),
+    func_round_negative: "round(-2.5)" => Ok(Float(-3.0)),
+    func_round_digits: "round(1.23456, 2)" => Ok(Float(1.23)),
+    func_round_integer: "round(1234, 1)" => Ok(Integer(1234)),
+    func_round_integer_negative: "round(-1250, -2)" => Ok(Integer(-1300)),
+    func_round_integer_overflow: "round(9223372036854775807, -1)" => Err(Error::Value("Integer overflow".into())),
+    func_round_decimal: "round(DECIMAL '2.345', 2)" => Ok(dec("2.35")),
+    func_round_decimal_negative: "round(DECIMAL '150', -2)" => Ok(dec("200")),
+    func_round_digits_large: "round(1.5, 400)" => Ok(Float(1.5)),
+    func_round_digits_precision: "round(1.5, 20)" => Ok(Float(1.5)),
+    func_round_digits_small: "round(1.5, -400)" => Ok(Float(0.0)),
+    func_round_digits_float: "round(1.5, 1.0)" => Err(Error::Value("Function round expects INTEGER for argument 2, got FLOAT".into())),
+    func_floor: "floor(-1.5)" => Ok(Float(-2.0)),
+    func_floor_integer: "floor(3)" => Ok(Integer(3)),
+    func_floor_decimal: "floor(DECIMAL '1.9')" => Ok(dec("1")),
+    func_ceil: "ceil(1.1)" => Ok(Float(2.0)),
+    func_ceil_decimal: "ceil(DECIMAL '-1.9')" => Ok(dec("-1")),
+    func_ceiling: "ceiling(1)" => Ok(Integer(1)),
+    func_sqrt: "sqrt(16)" => Ok(Float(4.0)),
+    func_sqrt_decimal: "sqrt(DECIMAL '2.25')" => Ok(Float(1.5)),
+    func_sqrt_negative: "sqrt(-1)" => Err(Error::Value("Can't take square root of negative number -1".into())),
+    func_power: "power(2, 10)" => Ok(Integer(1024)),
+    func_power_float: "power(4, 0.5)" => Ok(Float(2.0)),
+    func_power_overflow: "power(2, 64)" => Err(Error::Value("Integer overflow".into())),
+    func_power_null: "power(NULL, 2)" => Ok(Null),
+
+    // Null handling functions
+    func_coalesce: "coalesce(NULL, 1, 2)" => Ok(Integer(1)),
+    func_coalesce_null: "coalesce(NULL, NULL)" => Ok(Null),
+    func_coalesce_mixed: "coalesce(NULL, 'a', 1)" => Err(Error::Value("Function coalesce expects arguments of a common type, got STRING and INTEGER".into())),
+    func_coalesce_widen_float: "coalesce(NULL, 1, 2.5)" => Ok(Float(1.0)),
+    func_coalesce_widen_decimal: "coalesce(1, DECIMAL '2.5')" => Ok(dec("1")),
+    func_coalesce_args: "coalesce()" => Err(Error::Value("Function coalesce takes at least 1 arguments, got 0".into())),
+    func_nullif: "nullif(1, 1)" => Ok(Null),
+    func_nullif_float: "nullif(1, 1.0)" => Ok(Null),
+    func_nullif_unequal: "nullif(1, 2)" => Ok(Integer(1)),
+    func_nullif_null: "nullif(1, NULL)" => Ok(Integer(1)),
+    func_nullif_incompatible: "nullif(1, 'a')" => Err(Error::Value("Can't compare 1 and a".into())),
+
     // Operator precedence, testing each operator against the ones at the same level and immediately
     // below it in order.
     op_prec_negate_factorial: "-3!" => Err(Error::Value("Can't take factorial of negative number".into())),
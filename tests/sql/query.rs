@@ -228,6 +228,15 @@ test_query! {
     order_aggregate: "SELECT studio_id, MAX(rating) FROM movies GROUP BY studio_id ORDER BY MAX(rating)",
     order_aggregate_noselect: "SELECT studio_id, MAX(rating) FROM movies GROUP BY studio_id ORDER BY MIN(rating)",
     order_group_by_noselect: "SELECT MAX(rating) FROM movies GROUP BY studio_id ORDER BY studio_id",
+
+    func_string: "SELECT id, upper(title), lower(title), length(title), substr(title, 2, 3), replace(title, 'i', 'I') FROM movies WHERE id <= 3",
+    func_concat: "SELECT concat(title, ' (', released, ')') AS title FROM movies WHERE genre_id = 3 ORDER BY id",
+    func_math: "SELECT id, rating, round(rating), floor(rating), ceil(rating), abs(released - 2000), round(sqrt(released), 2), power(id, 3) FROM movies WHERE id <= 3",
+    func_null: "SELECT id, ultrahd, coalesce(ultrahd, FALSE), nullif(studio_id, 4) FROM movies ORDER BY id",
+    func_fold: "SELECT id, title FROM movies WHERE upper(title) = upper('heat') OR released > coalesce(NULL, 2014, released) ORDER BY id",
+    func_fold_partial: "SELECT id, round(rating, NULL), coalesce(NULL, ultrahd, NULL, FALSE, ultrahd) FROM movies WHERE id <= 3",
+    func_type: "SELECT upper(id) FROM movies",
+    func_type_constant: "SELECT upper(title), lower(1) FROM movies",
 }
 test_query! { with [
         "CREATE TABLE booleans (id INTEGER PRIMARY KEY, value BOOLEAN)",
@@ -266,6 +275,84 @@ test_query! { with [
     order_string_asc: "SELECT * FROM strings ORDER BY value ASC",
     order_string_desc: "SELECT * FROM strings ORDER BY value DESC",
 }
+test_query! { with [
+        "CREATE TABLE accounts (id INTEGER PRIMARY KEY, balance DECIMAL(10,2) INDEX, rate FLOAT)",
+        "INSERT INTO accounts VALUES
+            (1, DECIMAL '0.10', 0.1),
+            (2, DECIMAL '0.20', 0.2),
+            (3, DECIMAL '-5.25', -5.25),
+            (4, NULL, NULL),
+            (5, DECIMAL '1000000.01', 1000000.01),
+            (6, DECIMAL '0.10', 0.1)
+        ",
+    ];
+    agg_decimal: "SELECT SUM(balance), AVG(balance), MIN(balance), MAX(balance), COUNT(balance) FROM accounts WHERE balance IS NOT NULL",
+    agg_decimal_exact: "SELECT SUM(balance), SUM(rate), AVG(balance), AVG(rate) FROM accounts WHERE id < 3",
+    group_decimal: "SELECT balance, COUNT(*) FROM accounts GROUP BY balance ORDER BY balance",
+    order_decimal_desc: "SELECT * FROM accounts ORDER BY balance DESC",
+    where_decimal_index: "SELECT id, balance * 2 FROM accounts WHERE balance = DECIMAL '0.1' ORDER BY id",
+    where_decimal_range: "SELECT id, balance FROM accounts WHERE balance > 0 ORDER BY id",
+}
+test_query! { with [
+        "CREATE TABLE prices (id DECIMAL(5,2) PRIMARY KEY, amount DECIMAL(10,2) INDEX)",
+        "INSERT INTO prices VALUES (2, 100), (DECIMAL '3.5', DECIMAL '100.00'), (4, 50)",
+    ];
+    where_decimal_pk_integer: "SELECT * FROM prices WHERE id = 2",
+    where_decimal_pk_integer_or: "SELECT * FROM prices WHERE id = 2 OR id = 4 ORDER BY id",
+    where_decimal_pk_float: "SELECT * FROM prices WHERE id = 3.5",
+    where_decimal_index_integer: "SELECT * FROM prices WHERE amount = 100 ORDER BY id",
+}
+test_query! { with [
+        "CREATE TABLE blobs (id BYTEA PRIMARY KEY, name STRING, data BLOB INDEX)",
+        "INSERT INTO blobs VALUES
+            (x'01', 'one', x'c0ffee'),
+            (x'0100', 'two', x''),
+            (x'', 'empty', NULL),
+            (x'ff', 'max', x'c0ffee')
+        ",
+    ];
+    order_bytes: "SELECT * FROM blobs ORDER BY id",
+    order_bytes_desc: "SELECT id, name FROM blobs ORDER BY data DESC, id",
+    func_bytes: "SELECT id, length(data), substring(data, 2, 1) FROM blobs ORDER BY id",
+    where_bytes_pk: "SELECT name FROM blobs WHERE id = x'0100'",
+    where_bytes_index: "SELECT id, name FROM blobs WHERE data = x'C0FFEE' ORDER BY id",
+}
+test_query! { with [
+        "CREATE TABLE orders (id INTEGER PRIMARY KEY, payload JSON INDEX ON '$.customer.id')",
+        r#"INSERT INTO orders VALUES
+            (1, '{"customer": {"id": 7, "name": "Ann"}, "items": [{"sku": "a", "qty": 2}], "tags": ["rush"]}'),
+            (2, '{"customer": {"id": 8, "name": "Bob"}, "items": [], "tags": []}'),
+            (3, '{"customer": {"id": 7, "name": "Ann"}, "items": [{"sku": "b", "qty": 1}, {"sku": "a", "qty": 5}]}'),
+            (4, NULL)
+        "#,
+    ];
+    select_json_get: "SELECT id, payload -> 'customer', payload -> 'items' -> 0 ->> 'sku' FROM orders ORDER BY id",
+    select_json_extract: "SELECT id, json_extract(payload, '$.customer.name'), json_array_length(payload -> 'items') FROM orders ORDER BY id",
+    where_json_contains: r#"SELECT id FROM orders WHERE payload @> JSON '{"items": [{"sku": "a"}]}' ORDER BY id"#,
+    where_json_get_text: "SELECT id FROM orders WHERE payload -> 'customer' ->> 'name' = 'Bob'",
+    where_json_path_index: "SELECT id, payload ->> 'tags' FROM orders WHERE json_extract(payload, '$.customer.id') = 7 ORDER BY id",
+    where_json_path_index_null: "SELECT id FROM orders WHERE json_extract(payload, '$.customer.id') IS NULL",
+    where_json_path_other: "SELECT id FROM orders WHERE json_extract(payload, '$.customer.name') = 'Ann' ORDER BY id",
+    group_json_path: "SELECT json_extract(payload, '$.customer.id') AS customer, COUNT(*) FROM orders GROUP BY customer ORDER BY customer",
+}
+
+test_query! { with [
+        "CREATE TABLE events (id INTEGER PRIMARY KEY, at TIMESTAMP INDEX, duration INTERVAL)",
+        "INSERT INTO events VALUES
+            (1, TIMESTAMP '2024-01-31 13:45:00', INTERVAL '1 day'),
+            (2, TIMESTAMP '1969-12-31 23:59:59', INTERVAL '-1 hour'),
+            (3, NULL, NULL),
+            (4, TIMESTAMP '2024-01-31 09:00:00', INTERVAL '1 mon'),
+            (5, TIMESTAMP '2024-03-01 00:00:00', INTERVAL '30 days')
+        ",
+    ];
+    order_temporal_asc: "SELECT * FROM events ORDER BY at ASC",
+    order_temporal_desc: "SELECT * FROM events ORDER BY duration DESC, id",
+    agg_temporal: "SELECT MIN(at), MAX(at), COUNT(at), MIN(duration), MAX(duration) FROM events WHERE at IS NOT NULL",
+    group_temporal: "SELECT date_trunc('day', at) AS day, COUNT(*) FROM events GROUP BY day ORDER BY day",
+    where_temporal_index: "SELECT id, at + duration FROM events WHERE at = TIMESTAMP '2024-01-31 09:00'",
+    where_temporal_range: "SELECT id, extract(month FROM at) FROM events WHERE at > TIMESTAMP '2024-01-01' - INTERVAL '1 week' ORDER BY id",
+}
 test_query! {
     limit: "SELECT * FROM movies LIMIT 3",
     limit_zero: "SELECT * FROM movies LIMIT 0",
@@ -410,3 +497,11 @@ test_query! {
     having_noselect: "SELECT studio_id FROM movies GROUP BY studio_id HAVING MAX(rating) > 8 ORDER BY studio_id",
     having_noaggr: "SELECT studio_id, MAX(rating) AS rating FROM movies GROUP BY studio_id HAVING studio_id >= 3 ORDER BY studio_id",
 }
+test_query! {
+    lock_update: "SELECT * FROM movies WHERE rating > 8 FOR UPDATE",
+    lock_share_nowait: "SELECT title FROM movies WHERE id = 1 FOR SHARE NOWAIT",
+    lock_order_limit: "SELECT * FROM genres ORDER BY name LIMIT 1 FOR UPDATE",
+    lock_join: "SELECT * FROM movies, genres FOR UPDATE",
+    lock_nothing: "SELECT 1 FOR UPDATE",
+    lock_invalid: "SELECT * FROM movies FOR DELETE",
+}
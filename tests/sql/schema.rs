@@ -70,6 +70,43 @@ test_schema! {
             "varchar" VARCHAR
         )
     "#,
+    create_table_datatype_temporal: r#"
+        CREATE TABLE name (
+            id DATE PRIMARY KEY,
+            "time" TIME,
+            "timestamp" TIMESTAMP INDEX,
+            "timestamp_without" TIMESTAMP WITHOUT TIME ZONE,
+            "timestamptz" TIMESTAMP WITH TIME ZONE DEFAULT TIMESTAMP WITH TIME ZONE '2024-01-31 13:45:00+01:00',
+            "interval" INTERVAL DEFAULT INTERVAL '1 day'
+        )
+    "#,
+    create_table_datatype_decimal: r#"
+        CREATE TABLE name (
+            id DECIMAL(10,2) PRIMARY KEY,
+            "decimal" DECIMAL INDEX,
+            "numeric" NUMERIC(5),
+            "default" DECIMAL(4,2) DEFAULT DECIMAL '12.5'
+        )
+    "#,
+    create_table_datatype_decimal_precision: "CREATE TABLE name (id INTEGER PRIMARY KEY, value DECIMAL(29,2))",
+    create_table_datatype_decimal_scale: "CREATE TABLE name (id INTEGER PRIMARY KEY, value DECIMAL(2,3))",
+    create_table_datatype_decimal_float: "CREATE TABLE name (id INTEGER PRIMARY KEY, value DECIMAL(2.5))",
+    create_table_datatype_bytes: r#"
+        CREATE TABLE name (
+            id BYTEA PRIMARY KEY,
+            "blob" BLOB INDEX,
+            "default" BYTEA DEFAULT x'c0ffee'
+        )
+    "#,
+    create_table_datatype_json: r#"
+        CREATE TABLE name (
+            id INTEGER PRIMARY KEY,
+            "json" JSON INDEX,
+            "path" JSON INDEX ON '$.user.ids[0]',
+            "default" JSON DEFAULT '{"b": [], "a": 1}'
+        )
+    "#,
+    create_table_datatype_json_default_invalid: "CREATE TABLE name (id INTEGER PRIMARY KEY, value JSON DEFAULT '{')",
     create_table_datatype_missing: "CREATE TABLE name (id)",
     create_table_datatype_null: "CREATE TABLE name (id INTEGER PRIMARY KEY, value NULL)",
 
@@ -96,6 +133,7 @@ test_schema! {
     create_table_pk_nullable: "CREATE TABLE name (id INTEGER PRIMARY KEY NULL)",
     create_table_pk_default: "CREATE TABLE name (id INTEGER PRIMARY KEY DEFAULT 1)",
     create_table_pk_unique: "CREATE TABLE name (id INTEGER PRIMARY KEY UNIQUE)",
+    create_table_pk_interval: "CREATE TABLE name (id INTERVAL PRIMARY KEY)",
 
     create_table_null: "CREATE TABLE name (id INTEGER PRIMARY KEY, value STRING NULL)",
     create_table_null_not: "CREATE TABLE name (id INTEGER PRIMARY KEY, value STRING NOT NULL)",
@@ -112,9 +150,16 @@ test_schema! {
     create_table_default_conflict: "CREATE TABLE name (id INTEGER PRIMARY KEY, value STRING DEFAULT 7)",
     create_table_default_conflict_float_integer: "CREATE TABLE name (id INTEGER PRIMARY KEY, value FLOAT DEFAULT 7)",
     create_table_default_conflict_integer_float: "CREATE TABLE name (id INTEGER PRIMARY KEY, value INTEGER DEFAULT 3.14)",
+    create_table_default_conflict_decimal: "CREATE TABLE name (id INTEGER PRIMARY KEY, value DECIMAL(4,2) DEFAULT DECIMAL '1.234')",
+    create_table_default_decimal_integer: "CREATE TABLE name (id INTEGER PRIMARY KEY, value DECIMAL(4,2) DEFAULT 1)",
 
     create_table_index: "CREATE TABLE name (id INTEGER PRIMARY KEY, value STRING INDEX)",
     create_table_index_pk: "CREATE TABLE name (id INTEGER PRIMARY KEY INDEX, value STRING)",
+    create_table_index_interval: "CREATE TABLE name (id INTEGER PRIMARY KEY, value INTERVAL INDEX)",
+    create_table_index_path_invalid: "CREATE TABLE name (id INTEGER PRIMARY KEY, value JSON INDEX ON 'user')",
+    create_table_index_path_no_index: "CREATE TABLE name (id INTEGER PRIMARY KEY, value JSON ON '$.user')",
+    create_table_index_path_quote: "CREATE TABLE name (id INTEGER PRIMARY KEY, value JSON INDEX ON '$.it''s')",
+    create_table_index_path_string: "CREATE TABLE name (id INTEGER PRIMARY KEY, value STRING INDEX ON '$.user')",
 
     create_table_unique: "CREATE TABLE name (id INTEGER PRIMARY KEY, value STRING UNIQUE)",
     create_table_unique_null: "CREATE TABLE name (id INTEGER PRIMARY KEY, value STRING NULL UNIQUE)",
@@ -446,3 +491,55 @@ test_schema! { with [
     update_index_pk: "UPDATE test SET id = 4 WHERE id = 1",
     update_index_null: "UPDATE test SET name = NULL WHERE id = 3",
 }
+
+test_schema! { with [
+        "CREATE TABLE test (id DATE PRIMARY KEY, ts TIMESTAMP INDEX, duration INTERVAL NULL)",
+        "INSERT INTO test VALUES (DATE '2024-01-01', TIMESTAMP '2024-01-01 12:00', INTERVAL '1 day')",
+        "INSERT INTO test VALUES (DATE '1969-12-31', TIMESTAMP '2024-01-01 12:00', NULL)",
+    ];
+
+    insert_temporal: "INSERT INTO test VALUES (DATE '2023-06-30', TIMESTAMP '1969-12-31 23:59:59.999999', INTERVAL '-1 mon')",
+    insert_temporal_pk_exists: "INSERT INTO test VALUES (DATE '2024-01-01', TIMESTAMP '2024-01-01 12:00', NULL)",
+    insert_temporal_datatype: "INSERT INTO test VALUES (DATE '2023-06-30', DATE '2023-06-30', NULL)",
+    update_temporal: "UPDATE test SET ts = ts + duration, duration = duration * 2 WHERE id = DATE '2024-01-01'",
+}
+
+test_schema! { with [
+        "CREATE TABLE test (id DECIMAL(5,2) PRIMARY KEY, amount DECIMAL(10,2) INDEX)",
+        "INSERT INTO test VALUES (DECIMAL '1.5', DECIMAL '100.25'), (DECIMAL '-1.05', DECIMAL '-0.01')",
+    ];
+
+    insert_decimal: "INSERT INTO test VALUES (DECIMAL '999.99', DECIMAL '12345678.90')",
+    insert_decimal_pk_exists: "INSERT INTO test VALUES (DECIMAL '1.50', DECIMAL '0')",
+    insert_decimal_scale: "INSERT INTO test VALUES (DECIMAL '2', DECIMAL '1.005')",
+    insert_decimal_precision: "INSERT INTO test VALUES (DECIMAL '1000', DECIMAL '0')",
+    insert_decimal_datatype: "INSERT INTO test VALUES (DECIMAL '2', 1.5)",
+    insert_decimal_integer: "INSERT INTO test VALUES (2, 100)",
+    insert_decimal_integer_precision: "INSERT INTO test VALUES (1000, 0)",
+    update_decimal: "UPDATE test SET amount = amount * 2 + 1 WHERE id = DECIMAL '1.5'",
+    update_decimal_overflow: "UPDATE test SET amount = amount * 1000000",
+}
+
+test_schema! { with [
+        "CREATE TABLE test (id BYTEA PRIMARY KEY, data BLOB INDEX)",
+        "INSERT INTO test VALUES (x'01', x'c0ffee'), (x'0100', NULL)",
+    ];
+
+    insert_bytes: "INSERT INTO test VALUES (x'', x'00ff')",
+    insert_bytes_pk_exists: "INSERT INTO test VALUES (X'01', x'')",
+    insert_bytes_datatype: "INSERT INTO test VALUES (x'02', 'c0ffee')",
+    update_bytes: "UPDATE test SET data = substring(data, 2) WHERE id = x'01'",
+}
+
+test_schema! { with [
+        "CREATE TABLE test (id INTEGER PRIMARY KEY, doc JSON INDEX, customer JSON INDEX ON '$.customer.id')",
+        r#"INSERT INTO test VALUES (1, JSON '[1, 2]', JSON '{"customer": {"id": 7}}'), (2, NULL, JSON '{}')"#,
+    ];
+
+    insert_json: r#"INSERT INTO test VALUES (3, '{"b": 1, "a": [true]}', '{"customer": {"id": "x"}}')"#,
+    insert_json_invalid: r#"INSERT INTO test VALUES (3, '{"a": }', NULL)"#,
+    insert_json_datatype: "INSERT INTO test VALUES (3, 1, NULL)",
+    update_json: r#"UPDATE test SET customer = '{"customer": {"id": 8, "name": "y"}}' WHERE id = 1"#,
+    update_json_path_unchanged: r#"UPDATE test SET customer = '{"customer": {"id": 7, "name": "y"}}' WHERE id = 1"#,
+    delete_json: "DELETE FROM test WHERE id = 1",
+}